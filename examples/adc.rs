@@ -102,6 +102,11 @@ fn main() -> ! {
     // for use in embedded drivers). Channels for EH usage are included: `stm32hal2::adc::AdcChannel::C3`
     let reading = adc.read(chan_num);
 
+    // Or, on L4, G4, WB, and WL, let the pin determine the channel, instead of looking it up in
+    // the datasheet:
+    let pb0 = Pin::new(Port::B, 0, PinMode::Input).into_analog(AdcDevice::One);
+    let reading = adc.read(&pb0);
+
     // Convert a reading to voltage, which includes compensation for the built-in VDDA
     // reference voltage
     let voltage = adc.reading_to_voltage(reading);
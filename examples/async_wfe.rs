@@ -0,0 +1,95 @@
+//! This example demonstrates awaitable DMA, USART, and timer events, using the `async_wfe`
+//! feature. There's no executor; `block_on` sleeps with `WFE` until the awaited event occurs.
+//! For project structure and debugging boilerplate, see the `synax_overview` example.
+
+#![no_main]
+#![no_std]
+
+use core::pin::Pin;
+
+use cortex_m_rt::entry;
+
+use stm32_hal2::{
+    async_wfe::{self, Signal},
+    clocks::Clocks,
+    dma::{Dma, DmaChannel},
+    gpio::{Pin as GpioPin, PinMode, Port},
+    pac::{self, interrupt},
+    timer::Timer,
+    usart::Usart,
+};
+
+static BUTTON_PRESSED: Signal = Signal::new();
+
+/// Read a command byte, then wait for 10 timer ticks.
+async fn read_command(uart: &mut Usart<pac::USART1>, timer: &mut Timer<pac::TIM3>) -> u8 {
+    let cmd = uart.read_async(pac::Interrupt::USART1).await;
+
+    for _ in 0..10 {
+        timer.wait_update(pac::Interrupt::TIM3).await;
+    }
+
+    cmd
+}
+
+#[entry]
+fn main() -> ! {
+    let dp = pac::Peripherals::take().unwrap();
+
+    let clock_cfg = Clocks::default();
+    clock_cfg.setup().unwrap();
+
+    let _uart_tx = GpioPin::new(Port::A, 9, PinMode::Alt(7));
+    let _uart_rx = GpioPin::new(Port::A, 10, PinMode::Alt(7));
+
+    let mut uart = Usart::new(dp.USART1, 115_200, Default::default(), &clock_cfg);
+    let mut timer = Timer::new_tim3(dp.TIM3, 1_000., Default::default(), &clock_cfg);
+    timer.enable();
+
+    let mut dma = Dma::new(dp.DMA1);
+
+    // Note that we don't unmask the USART1, TIM3, or DMA1_CH2 interrupts in the NVIC: Their
+    // pending bits wake the core from `WFE`, without running an interrupt handler.
+
+    // Block until a command byte is received, and the timer has ticked 10 times.
+    let _cmd = async_wfe::block_on(read_command(&mut uart, &mut timer));
+
+    // Start a DMA transfer here (eg `uart.write_dma(...)` or `spi.transfer_dma(...)`), then wait
+    // for it to complete.
+    if async_wfe::block_on(dma.wait_transfer_complete(DmaChannel::C2, pac::Interrupt::DMA1_CH2))
+        .is_err()
+    {
+        // Handle the transfer error.
+    }
+
+    // Unlike the above, the button uses an interrupt handler that sets a `Signal`. (Set up the
+    // EXTI line for the button's pin here)
+    unsafe { cortex_m::peripheral::NVIC::unmask(pac::Interrupt::EXTI0) };
+
+    // In a super-loop, poll several tasks in turn instead of blocking on one.
+    let mut button = BUTTON_PRESSED.wait();
+    loop {
+        if async_wfe::poll_once(Pin::new(&mut button)).is_some() {
+            button = BUTTON_PRESSED.wait();
+            // Handle the button press.
+        }
+
+        // Other super-loop work here.
+
+        cortex_m::asm::wfe();
+    }
+}
+
+#[interrupt]
+/// A button interrupt handler, eg from EXTI.
+fn EXTI0() {
+    // (Clear the EXTI interrupt flag here)
+    BUTTON_PRESSED.signal();
+}
+
+// same panicking *behavior* as `panic-probe` but doesn't print a panic message
+// this prevents the panic message being printed *twice* when `defmt::panic` is invoked
+#[defmt::panic_handler]
+fn panic() -> ! {
+    cortex_m::asm::udf()
+}
@@ -78,6 +78,10 @@ fn main() -> ! {
         ..Default::default()
     };
 
+    // On H7, a PLL's `fracn` field puts it in fractional mode. This, combined with
+    // `SaiConfig::best_mckdiv`, lets you hit audio sample rates like 44.1kHz exactly.
+    // clock_cfg.pll2.fracn = 2_048;
+
     // Or on L4 or WB, using the PLLSAI:
     let clock_cfg = Clocks {
         pllsai1: PllCfg {
@@ -98,14 +102,20 @@ fn main() -> ! {
     // Change the default wakeup from Stop mode to be HSI instead of MSI. (L4 and L5 only)
     clock_cfg.stop_wuck = StopWuck::Hsi;
 
+    // Use an MSI input source, automatically calibrated by a 32.768kHz LSE crystal. (L4 and L5 only)
+    clock_cfg.input_src = InputSrc::Msi(MsiRange::R4M);
+    clock_cfg.msi_pll = true;
+
     // Set up PLL using a 4Mhz HSE:
     clock_cfg.input_src = InputSrc::Pll(PllSrc::Hse(4_000_000));
 
     // Enable the Clock Security System (CSS)
     clock_cfg.security_system = true;
 
-    // Bypass HSE output
+    // Bypass the HSE oscillator, eg when using a TCXO instead of a crystal.
     clock_cfg.hse_bypass = true;
+    // (H7B3 only) Select a digital external clock in bypass mode, instead of an analog one.
+    // clock_cfg.hse_bypass_digital = true;
 
     // Enable HSI48 (eg L4, L5, G4 etc)
     clock_cfg.hse48_on = true;
@@ -0,0 +1,123 @@
+//! Hardware-in-loop (HIL) test firmware, using the `testsuite` module. Build with the `testsuite`
+//! feature. Accepts single-character commands over USART2 (the ST-Link virtual COM port on Nucleo
+//! boards, at 115,200 baud), runs the corresponding test, and reports `PASS` or `FAIL` for it.
+//!
+//! Commands:
+//! - `g`: GPIO, using both `Pin` and `Port` APIs
+//! - `u`: USART1 internal loopback
+//! - `s`: SPI1 loopback
+//! - `d`: SPI1 loopback using DMA
+//! - `i`: I2C1 device acknowledge
+//! - `a`: ADC1 reading
+//! - `*`: All of the above
+//!
+//! Required jumpers, using the L4 pinout; adjust pins and AF numbers for your MCU:
+//! - PB0 to PB1
+//! - PA6 (SPI1 MISO) to PA7 (SPI1 MOSI)
+//! - PB6 (I2C1 SCL) and PB7 (I2C1 SDA) to a device at `I2C_ADDR`, with pull-up resistors
+//! - PA0 (ADC1 channel 5) to 3V3
+//!
+//! For project structure and debugging boilerplate, see the `synax_overview` example.
+
+#![no_main]
+#![no_std]
+
+use cortex_m_rt::entry;
+
+use stm32_hal2::{
+    adc::{Adc, AdcDevice},
+    clocks::Clocks,
+    dma::{Dma, DmaChannel},
+    gpio::{OutputType, Pin, PinMode, Port},
+    i2c::I2c,
+    pac,
+    spi::{BaudRate, Spi},
+    testsuite::{self, Test},
+    usart::Usart,
+};
+
+/// The address of a device on the I2C bus; eg an EEPROM.
+const I2C_ADDR: u8 = 0x50;
+
+/// ADC readings above this indicate the input is tied to VDDA. (12-bit, right-aligned)
+const ADC_HIGH_THRESH: u16 = 4_000;
+
+#[entry]
+fn main() -> ! {
+    // Set up microcontroller peripherals
+    let dp = pac::Peripherals::take().unwrap();
+
+    let clock_cfg = Clocks::default();
+    clock_cfg.setup().unwrap();
+
+    // Host link.
+    let _host_tx = Pin::new(Port::A, 2, PinMode::Alt(7));
+    let _host_rx = Pin::new(Port::A, 3, PinMode::Alt(7));
+    let mut host = Usart::new(dp.USART2, 115_200, Default::default(), &clock_cfg);
+
+    // USART1 under test. Internal loopback only uses its TX pin.
+    let _usart_tx = Pin::new(Port::A, 9, PinMode::Alt(7));
+    let mut usart = Usart::new(dp.USART1, 115_200, Default::default(), &clock_cfg);
+
+    let _sck = Pin::new(Port::A, 5, PinMode::Alt(5));
+    let _miso = Pin::new(Port::A, 6, PinMode::Alt(5));
+    let _mosi = Pin::new(Port::A, 7, PinMode::Alt(5));
+    let mut spi = Spi::new(dp.SPI1, Default::default(), BaudRate::Div32);
+
+    let mut dma = Dma::new(dp.DMA1);
+
+    let mut scl = Pin::new(Port::B, 6, PinMode::Alt(4));
+    scl.output_type(OutputType::OpenDrain);
+    let mut sda = Pin::new(Port::B, 7, PinMode::Alt(4));
+    sda.output_type(OutputType::OpenDrain);
+    let mut i2c = I2c::new(dp.I2C1, Default::default(), &clock_cfg);
+
+    let _adc_pin = Pin::new(Port::A, 0, PinMode::Analog);
+    let mut adc = Adc::new_adc1(dp.ADC1, AdcDevice::One, Default::default(), &clock_cfg);
+
+    // SPI1 and its DMA test both use SPI1, so share it between their closures.
+    let spi = core::cell::RefCell::new(&mut spi);
+
+    testsuite::run(
+        &mut host,
+        &mut [
+            Test {
+                cmd: b'g',
+                name: "GPIO",
+                run: &mut || testsuite::test_gpio(Port::B, 0, 1),
+            },
+            Test {
+                cmd: b'u',
+                name: "USART",
+                run: &mut || usart.self_test(true).is_ok(),
+            },
+            Test {
+                cmd: b's',
+                name: "SPI",
+                run: &mut || spi.borrow_mut().self_test().is_ok(),
+            },
+            Test {
+                cmd: b'd',
+                name: "SPI DMA",
+                run: &mut || {
+                    testsuite::test_spi_dma(
+                        &mut **spi.borrow_mut(),
+                        &mut dma,
+                        DmaChannel::C2,
+                        DmaChannel::C3,
+                    )
+                },
+            },
+            Test {
+                cmd: b'i',
+                name: "I2C",
+                run: &mut || i2c.self_test(I2C_ADDR).is_ok(),
+            },
+            Test {
+                cmd: b'a',
+                name: "ADC",
+                run: &mut || adc.read(5) > ADC_HIGH_THRESH,
+            },
+        ],
+    )
+}
@@ -14,7 +14,7 @@ use cortex_m_rt::entry;
 use stm32_hal::{
     adc::{Adc, AdcChannel, AdcDevice},
     clocks::Clocks,
-    gpio::{Edge, Pin, PinMode, Port},
+    gpio::{self, Edge, Pin, PinMode, Port},
     low_power,
     pac::{self, interrupt, ADC1, EXTI},
     rtc::{Rtc, RtcClockSource, RtcConfig},
@@ -99,10 +99,8 @@ fn main() -> ! {
 #[interrupt]
 /// GPIO interrupt
 fn EXTI0() {
-    unsafe {
-        // Clear the interrupt flag, to prevent continous firing.
-        (*pac::EXTI::ptr()).pr1.modify(|_, w| w.pr0().set_bit());
-    }
+    // Clear the interrupt flag, to prevent continous firing.
+    gpio::clear_exti_interrupt(0).unwrap();
 
     free(|cs| {
         let bouncing = BOUNCING.borrow(cs);
@@ -221,7 +221,8 @@ mod app {
                     ..Default::default()
                 },
                 &mut dma,
-            );
+            )
+            .unwrap();
         }
 
         dfsdm.enable_filter(Filter::F0, DfsdmChannel::C1);
@@ -130,7 +130,8 @@ fn main() -> ! {
             DmaChannel::C3,
             channel_cfg,
             &mut dma,
-        );
+        )
+        .unwrap();
     }
 
     dac.enable(DacChannel::C1);
@@ -21,19 +21,32 @@ use crate::pac::dma as dma_p;
 #[cfg(any(
     feature = "f3",
     feature = "l4",
+    feature = "l5",
     feature = "g4",
     feature = "h7",
     feature = "wb"
 ))]
 use crate::pac::dma1 as dma_p;
 
-#[cfg(not(any(feature = "f4", feature = "l5")))]
+#[cfg(not(feature = "f4"))]
 use crate::dma::{self, ChannelCfg, Dma, DmaChannel};
 
-#[cfg(any(feature = "f3", feature = "l4"))]
+#[cfg(any(feature = "f3", feature = "l4", feature = "wb", feature = "wl"))]
 use crate::dma::DmaInput;
 
+#[cfg(feature = "g4")]
+use crate::interconnect::{self, TriggerSource};
+
+pub use crate::util::RouteError;
+
+#[cfg(any(feature = "l4", feature = "g4", feature = "wb", feature = "wl"))]
+use crate::gpio::{Pin, Port};
+
 const MAX_ADVREGEN_STARTUP_US: u32 = 10;
+/// The maximum startup time of the temperature sensor, which is the slowest internal channel.
+/// (eg L476 DS, table 30: tSTART)
+#[cfg(any(feature = "f3", feature = "l4", feature = "l5", feature = "g4"))]
+const MAX_INTERNAL_STARTUP_US: u32 = 120;
 
 /// https://github.com/rust-embedded/embedded-hal/issues/267
 /// We are simulating an enum due to how the `embedded-hal` trait is set up.
@@ -73,7 +86,191 @@ pub enum AdcDevice {
     Five,
 }
 
-#[derive(Copy, Clone)]
+/// An input to `Adc::read`: Either a channel number, or an `AnalogPin`.
+pub trait AdcInput {
+    /// The channel number to read, on a given ADC.
+    fn channel(&self, device: AdcDevice) -> u8;
+}
+
+impl AdcInput for u8 {
+    fn channel(&self, _device: AdcDevice) -> u8 {
+        *self
+    }
+}
+
+#[cfg(any(feature = "l4", feature = "g4", feature = "wb", feature = "wl"))]
+/// A GPIO pin in analog mode, associated with the ADC and channel it's connected to. Create it
+/// using `Pin::into_analog`, and pass a reference to it to `Adc::read` instead of a channel number.
+/// This avoids looking up channel numbers manually in the datasheet.
+pub struct AnalogPin {
+    pub pin: Pin,
+    device: AdcDevice,
+    channel: u8,
+}
+
+#[cfg(any(feature = "l4", feature = "g4", feature = "wb", feature = "wl"))]
+impl AnalogPin {
+    /// Associate a pin with its channel on a given ADC. Panics if the pin isn't an input to that ADC.
+    /// Doesn't change the pin's mode; use `Pin::into_analog` for that.
+    pub(crate) fn new(pin: Pin, device: AdcDevice) -> Self {
+        let channel = match pin_channel(pin.port, pin.pin, device) {
+            Some(c) => c,
+            None => panic!("This pin isn't an input to this ADC."),
+        };
+
+        Self {
+            pin,
+            device,
+            channel,
+        }
+    }
+
+    /// The ADC this pin is associated with.
+    pub fn device(&self) -> AdcDevice {
+        self.device
+    }
+
+    /// The ADC channel this pin is connected to.
+    pub fn channel(&self) -> u8 {
+        self.channel
+    }
+}
+
+#[cfg(any(feature = "l4", feature = "g4", feature = "wb", feature = "wl"))]
+impl AdcInput for &AnalogPin {
+    fn channel(&self, device: AdcDevice) -> u8 {
+        assert!(
+            self.device == device,
+            "This pin is associated with a different ADC."
+        );
+        self.channel
+    }
+}
+
+#[cfg(any(feature = "l4", feature = "g4", feature = "wb", feature = "wl"))]
+/// Find the channel a pin is connected to, on a given ADC. Returns `None` if it's not an input to
+/// that ADC. See the datasheet's pin definitions table; eg L476 DS, Table 16. Channels that
+/// aren't connected to pins, eg VREFINT and the temperature sensor, aren't included.
+// todo: F3, L5, G0, and H7.
+fn pin_channel(port: Port, pin: u8, device: AdcDevice) -> Option<u8> {
+    cfg_if! {
+        if #[cfg(feature = "l4")] {
+            // ADC1 and ADC2 share these inputs (ADC12_INx).
+            // todo: ADC3 on L4x5 and L4x6.
+            if device == AdcDevice::Three {
+                return None;
+            }
+
+            match (port, pin) {
+                (Port::C, 0) => Some(1),
+                (Port::C, 1) => Some(2),
+                (Port::C, 2) => Some(3),
+                (Port::C, 3) => Some(4),
+                (Port::A, 0) => Some(5),
+                (Port::A, 1) => Some(6),
+                (Port::A, 2) => Some(7),
+                (Port::A, 3) => Some(8),
+                (Port::A, 4) => Some(9),
+                (Port::A, 5) => Some(10),
+                (Port::A, 6) => Some(11),
+                (Port::A, 7) => Some(12),
+                (Port::C, 4) => Some(13),
+                (Port::C, 5) => Some(14),
+                (Port::B, 0) => Some(15),
+                (Port::B, 1) => Some(16),
+                _ => None,
+            }
+        } else if #[cfg(feature = "g4")] {
+            // todo: ADC3 - 5.
+            match device {
+                AdcDevice::One => match (port, pin) {
+                    (Port::A, 0) => Some(1),
+                    (Port::A, 1) => Some(2),
+                    (Port::A, 2) => Some(3),
+                    (Port::A, 3) => Some(4),
+                    (Port::B, 14) => Some(5),
+                    (Port::C, 0) => Some(6),
+                    (Port::C, 1) => Some(7),
+                    (Port::C, 2) => Some(8),
+                    (Port::C, 3) => Some(9),
+                    (Port::F, 0) => Some(10),
+                    (Port::B, 12) => Some(11),
+                    (Port::B, 1) => Some(12),
+                    (Port::B, 11) => Some(14),
+                    (Port::B, 0) => Some(15),
+                    _ => None,
+                },
+                AdcDevice::Two => match (port, pin) {
+                    (Port::A, 0) => Some(1),
+                    (Port::A, 1) => Some(2),
+                    (Port::A, 6) => Some(3),
+                    (Port::A, 7) => Some(4),
+                    (Port::C, 4) => Some(5),
+                    (Port::C, 0) => Some(6),
+                    (Port::C, 1) => Some(7),
+                    (Port::C, 2) => Some(8),
+                    (Port::C, 3) => Some(9),
+                    (Port::F, 1) => Some(10),
+                    (Port::C, 5) => Some(11),
+                    (Port::B, 2) => Some(12),
+                    (Port::A, 5) => Some(13),
+                    (Port::B, 11) => Some(14),
+                    (Port::B, 15) => Some(15),
+                    (Port::A, 4) => Some(17),
+                    _ => None,
+                },
+                _ => None,
+            }
+        } else if #[cfg(feature = "wb")] {
+            if device != AdcDevice::One {
+                return None;
+            }
+
+            match (port, pin) {
+                (Port::C, 0) => Some(1),
+                (Port::C, 1) => Some(2),
+                (Port::C, 2) => Some(3),
+                (Port::C, 3) => Some(4),
+                (Port::A, 0) => Some(5),
+                (Port::A, 1) => Some(6),
+                (Port::A, 2) => Some(7),
+                (Port::A, 3) => Some(8),
+                (Port::A, 4) => Some(9),
+                (Port::A, 5) => Some(10),
+                (Port::A, 6) => Some(11),
+                (Port::A, 7) => Some(12),
+                (Port::C, 4) => Some(13),
+                (Port::C, 5) => Some(14),
+                (Port::A, 8) => Some(15),
+                (Port::A, 9) => Some(16),
+                _ => None,
+            }
+        } else {
+            // WL
+            if device != AdcDevice::One {
+                return None;
+            }
+
+            match (port, pin) {
+                (Port::B, 13) => Some(0),
+                (Port::B, 14) => Some(1),
+                (Port::B, 3) => Some(2),
+                (Port::B, 4) => Some(3),
+                (Port::B, 2) => Some(4),
+                (Port::B, 1) => Some(5),
+                (Port::A, 10) => Some(6),
+                (Port::A, 11) => Some(7),
+                (Port::A, 12) => Some(8),
+                (Port::A, 13) => Some(9),
+                (Port::A, 14) => Some(10),
+                (Port::A, 15) => Some(11),
+                _ => None,
+            }
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
 #[repr(u8)]
 /// ADC interrupts. See L44 RM, section 16.5: ADC interrupts. Set in the IER register, and cleared
 /// in the ISR register.
@@ -155,6 +352,204 @@ pub enum OperationMode {
     Continuous = 1,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(u8)]
+/// Selects which edge of a trigger source starts an ADC conversion. Sets the CFGR register, EXTEN
+/// field.
+pub enum TriggerEdge {
+    Rising = 0b01,
+    Falling = 0b10,
+    Both = 0b11,
+}
+
+#[cfg(any(feature = "f3", feature = "l4", feature = "l5", feature = "h7"))]
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// Timer and EXTI events that can trigger regular conversions, eg for periodic sampling at a
+/// rate set by a timer, without jitter from software. Configure the timer's TRGO using
+/// `Timer::set_mastermode`. See L4 RM, section 16.4.18: "Conversion on external trigger and
+/// trigger polarity", or the equivalent section for other families. (On G4, use
+/// `interconnect::TriggerSource`.)
+pub enum RegularTrigger {
+    Tim1Cc1,
+    Tim1Cc2,
+    Tim1Cc3,
+    Tim1Trgo,
+    Tim1Trgo2,
+    Tim2Cc2,
+    Tim2Trgo,
+    Tim3Trgo,
+    Tim3Cc4,
+    Tim4Cc4,
+    Tim4Trgo,
+    Tim6Trgo,
+    Tim8Trgo,
+    Tim8Trgo2,
+    Tim15Trgo,
+    Exti11,
+    // These are only connected to ADC3 and ADC4 on F3.
+    #[cfg(feature = "f3")]
+    Tim2Cc1,
+    #[cfg(feature = "f3")]
+    Tim2Cc3,
+    #[cfg(feature = "f3")]
+    Tim3Cc1,
+    #[cfg(feature = "f3")]
+    Tim4Cc1,
+    #[cfg(feature = "f3")]
+    Tim7Trgo,
+    #[cfg(feature = "f3")]
+    Tim8Cc1,
+    #[cfg(feature = "f3")]
+    Exti2,
+    #[cfg(feature = "h7")]
+    Hrtim1AdcTrg1,
+    #[cfg(feature = "h7")]
+    Hrtim1AdcTrg3,
+    #[cfg(feature = "h7")]
+    Lptim1Out,
+    #[cfg(feature = "h7")]
+    Lptim2Out,
+    #[cfg(feature = "h7")]
+    Lptim3Out,
+}
+
+#[cfg(any(feature = "f3", feature = "l4", feature = "l5", feature = "h7"))]
+impl RegularTrigger {
+    /// The EXTSEL value for this trigger, or `None` if it's not connected to this ADC. F3's ADC3
+    /// and ADC4 use a different table from ADC1 and ADC2; see F303 RM, tables 88 and 89.
+    fn extsel(&self, device: AdcDevice) -> Option<u8> {
+        use RegularTrigger::*;
+
+        #[cfg(feature = "f3")]
+        if device == AdcDevice::Three || device == AdcDevice::Four {
+            return match self {
+                Tim3Cc1 => Some(0),
+                Tim2Cc3 => Some(1),
+                Tim1Cc3 => Some(2),
+                Tim8Cc1 => Some(3),
+                Tim8Trgo => Some(4),
+                Exti2 => Some(5),
+                Tim4Cc1 => Some(6),
+                Tim2Trgo => Some(7),
+                Tim8Trgo2 => Some(8),
+                Tim1Trgo => Some(9),
+                Tim1Trgo2 => Some(10),
+                Tim3Trgo => Some(11),
+                Tim4Trgo => Some(12),
+                Tim7Trgo => Some(13),
+                Tim15Trgo => Some(14),
+                Tim2Cc1 => Some(15),
+                _ => None,
+            };
+        }
+        #[cfg(not(feature = "f3"))]
+        let _ = device;
+
+        match self {
+            Tim1Cc1 => Some(0),
+            Tim1Cc2 => Some(1),
+            Tim1Cc3 => Some(2),
+            Tim2Cc2 => Some(3),
+            Tim3Trgo => Some(4),
+            Tim4Cc4 => Some(5),
+            Exti11 => Some(6),
+            Tim8Trgo => Some(7),
+            Tim8Trgo2 => Some(8),
+            Tim1Trgo => Some(9),
+            Tim1Trgo2 => Some(10),
+            Tim2Trgo => Some(11),
+            Tim4Trgo => Some(12),
+            Tim6Trgo => Some(13),
+            Tim15Trgo => Some(14),
+            Tim3Cc4 => Some(15),
+            #[cfg(feature = "h7")]
+            Hrtim1AdcTrg1 => Some(16),
+            #[cfg(feature = "h7")]
+            Hrtim1AdcTrg3 => Some(17),
+            #[cfg(feature = "h7")]
+            Lptim1Out => Some(18),
+            #[cfg(feature = "h7")]
+            Lptim2Out => Some(19),
+            #[cfg(feature = "h7")]
+            Lptim3Out => Some(20),
+            #[cfg(feature = "f3")]
+            _ => None,
+        }
+    }
+}
+
+#[cfg(any(feature = "f3", feature = "g4"))]
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(u8)]
+/// Advanced timer events that can trigger injected conversions on ADC1 and ADC2, eg to sample
+/// motor phase currents in sync with PWM. Conversions start on the event's rising edge. Sets the
+/// JSQR register, JEXTSEL field. These values are shared by F3 and G4; see F303 RM, section
+/// 15.3.18, and G4 RM, section 21.4.19: "Conversion on external trigger and trigger polarity".
+pub enum InjectedTrigger {
+    Tim1Trgo = 0,
+    Tim1Cc4 = 1,
+    Tim8Cc4 = 7,
+    Tim1Trgo2 = 8,
+    Tim8Trgo = 9,
+    Tim8Trgo2 = 10,
+}
+
+#[cfg(any(feature = "f3", feature = "l4", feature = "g4", feature = "h7"))]
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(u8)]
+/// Dual ADC mode, where a master ADC (ADC1, or ADC3 for the ADC3/4 pair on F3) controls its
+/// slave (ADC2, or ADC4). Sets the ADC_CCR register, DUAL field (MULT on F3). See L44 RM,
+/// section 16.4.31: "Dual ADC modes".
+pub enum DualMode {
+    /// Each ADC works independently.
+    Independent = 0b00000,
+    /// Both ADCs convert their injected sequences simultaneously, on the master's trigger.
+    InjectedSimultaneous = 0b00101,
+    /// Both ADCs convert their regular sequences simultaneously, on the master's trigger. Eg, to
+    /// sample a motor's phase current and voltage at exactly the same time. The sequences must
+    /// be the same length, and should use different channels.
+    RegularSimultaneous = 0b00110,
+    /// Both ADCs convert the same channel, with the slave starting `delay` ADC clock cycles after
+    /// the master, up to doubling the sample rate of one channel. Use continuous mode, with a
+    /// single-channel sequence.
+    Interleaved = 0b00111,
+    /// The master's injected trigger alternately starts the master's and slave's injected
+    /// sequences.
+    AlternateTrigger = 0b01001,
+}
+
+#[cfg(any(feature = "f3", feature = "l4", feature = "g4", feature = "h7"))]
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(u8)]
+/// How the master and slave results are packed in the common data register (ADC_CDR), for DMA
+/// in dual mode. Sets the ADC_CCR register, MDMA field (DAMDF field on H7).
+pub enum DualDataFormat {
+    /// No packing; each ADC uses its own DMA request, as in independent mode.
+    Separate = 0b00,
+    /// For 10 and 12-bit resolution (16-bit on H7): One 32-bit DMA transfer per pair of
+    /// conversions, with the master's result in the low half-word, and the slave's in the high.
+    HalfWords = 0b10,
+    /// For 6 and 8-bit resolution: One 16-bit DMA transfer per pair, with the master's result
+    /// in the low byte, and the slave's in the high.
+    Bytes = 0b11,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+#[repr(u8)]
+/// What happens to the data register when a conversion completes before the previous result
+/// was read, eg because DMA didn't keep up. Sets ADC_CFGR register, OVRMOD field.
+/// See L44 RM, section 16.4.26: "ADC overrun (OVR, OVRMOD)".
+pub enum OverrunMode {
+    /// "The overrun event preserves the data register from being overrun: the old data is
+    /// maintained and the new conversion is discarded and lost." Further conversions are
+    /// discarded until the OVR flag is cleared. Suits DMA acquisition where a gap in the data
+    /// must be detectable.
+    Preserve = 0,
+    /// "The data register is overwritten with the last conversion result and the previous unread
+    /// data is lost." Suits polling, where only the latest reading matters.
+    Overwrite = 1,
+}
+
 // todo: Check the diff ways of configuring clock; i don't think teh enum below covers all.(?)
 
 #[derive(Clone, Copy, PartialEq)]
@@ -249,6 +644,11 @@ pub struct AdcConfig {
     // Most families use u8 values for calibration, but H7 uses u16.
     pub cal_single_ended: Option<u16>, // Stored calibration value for single-ended
     pub cal_differential: Option<u16>, // Stored calibration value for differential
+    /// Data register behavior on overrun. Defaults to `Preserve`.
+    pub overrun_mode: OverrunMode,
+    /// Auto-delayed conversion mode: Don't start a conversion until the previous result has
+    /// been read, to save power, and prevent overruns. Defaults to `false`.
+    pub auto_delay: bool,
 }
 
 impl Default for AdcConfig {
@@ -258,6 +658,8 @@ impl Default for AdcConfig {
             operation_mode: OperationMode::OneShot,
             cal_single_ended: None,
             cal_differential: None,
+            overrun_mode: OverrunMode::Preserve,
+            auto_delay: false,
         }
     }
 }
@@ -274,6 +676,243 @@ pub struct Adc<R> {
     vdda_calibrated: f32,
 }
 
+/// A view into a DMA buffer filled by a conversion sequence of `N` channels (ranks). The buffer is
+/// interleaved by rank: `[rank 1, rank 2, ..., rank N, rank 1, rank 2, ...]`. Each group of `N`
+/// readings is a frame. A partial frame at the end of the buffer is ignored.
+///
+/// Example, with a 3-channel sequence, and a circular DMA buffer:
+/// ```
+/// static mut ADC_BUF: [u16; 3 * 64] = [0; 3 * 64];
+///
+/// let view = InterleavedBuf::<3>::new(unsafe { &ADC_BUF });
+/// let [current, voltage, temp] = view.average();
+/// let voltage_latest = view.channel(1).last();
+/// ```
+pub struct InterleavedBuf<'a, const N: usize> {
+    buf: &'a [u16],
+}
+
+impl<'a, const N: usize> InterleavedBuf<'a, N> {
+    pub fn new(buf: &'a [u16]) -> Self {
+        if N == 0 {
+            panic!("The sequence must have at least 1 channel.");
+        }
+        Self { buf }
+    }
+
+    /// The number of complete frames in the buffer.
+    pub fn num_frames(&self) -> usize {
+        self.buf.len() / N
+    }
+
+    /// Get a frame: one reading from each rank.
+    pub fn frame(&self, i: usize) -> [u16; N] {
+        let mut result = [0; N];
+        result.copy_from_slice(&self.buf[i * N..(i + 1) * N]);
+        result
+    }
+
+    /// Iterate over frames.
+    pub fn frames(&self) -> impl Iterator<Item = [u16; N]> + 'a {
+        self.buf.chunks_exact(N).map(|f| {
+            let mut result = [0; N];
+            result.copy_from_slice(f);
+            result
+        })
+    }
+
+    /// Iterate over the readings from one rank. `rank` is 0-indexed; ie the first channel in the
+    /// sequence is rank 0.
+    pub fn channel(&self, rank: usize) -> impl Iterator<Item = u16> + 'a {
+        assert!(rank < N);
+        self.buf[..self.num_frames() * N]
+            .iter()
+            .skip(rank)
+            .step_by(N)
+            .copied()
+    }
+
+    /// Copy the readings from one rank into a contiguous buffer, eg for filtering. Returns the
+    /// number of readings copied; this is the smaller of `out`'s length, and the number of frames.
+    pub fn copy_channel(&self, rank: usize, out: &mut [u16]) -> usize {
+        let mut count = 0;
+        for (o, reading) in out.iter_mut().zip(self.channel(rank)) {
+            *o = reading;
+            count += 1;
+        }
+        count
+    }
+
+    /// The mean reading of each rank, across all frames. Uses integer math, so it doesn't require
+    /// an FPU.
+    pub fn average(&self) -> [u16; N] {
+        let mut sums = [0_u32; N];
+        for frame in self.frames() {
+            for (sum, reading) in sums.iter_mut().zip(frame) {
+                *sum += reading as u32;
+            }
+        }
+
+        let num_frames = self.num_frames() as u32;
+        let mut result = [0; N];
+        if num_frames == 0 {
+            return result;
+        }
+        for (r, sum) in result.iter_mut().zip(sums) {
+            *r = (sum / num_frames) as u16;
+        }
+        result
+    }
+
+    /// Decimate by `factor`: Average each group of `factor` consecutive frames into one frame in
+    /// `out`. This is a simple low pass filter, and reduces the sample rate by `factor`. Returns
+    /// the number of frames written. A partial group at the end is ignored.
+    pub fn decimate(&self, factor: usize, out: &mut [[u16; N]]) -> usize {
+        assert!(factor > 0);
+
+        let mut count = 0;
+        for (o, group) in out.iter_mut().zip(self.buf.chunks_exact(N * factor)) {
+            let mut sums = [0_u32; N];
+            for (i, reading) in group.iter().enumerate() {
+                sums[i % N] += *reading as u32;
+            }
+            for (r, sum) in o.iter_mut().zip(sums) {
+                *r = (sum / factor as u32) as u16;
+            }
+            count += 1;
+        }
+        count
+    }
+}
+
+/// Scale a right-aligned reading taken at `bits` of resolution to the full 16-bit range. Use
+/// this to combine readings from sequences at different resolutions, eg when oversampling is
+/// enabled on some ADCs or injected channels but not others.
+pub fn normalize(reading: u16, bits: u8) -> u16 {
+    if bits >= 16 {
+        reading
+    } else {
+        reading << (16 - bits)
+    }
+}
+
+// Factory calibration values, stored in system memory, and the conditions they were measured at.
+// See the datasheet's "Temperature sensor calibration values", and "Internal voltage reference
+// calibration values" tables; eg L476 DS, tables 6 and 7. These are raw 12-bit readings.
+cfg_if! {
+    if #[cfg(feature = "f3")] {
+        const VREFINT_CAL: *const u16 = 0x1FFF_F7BA as *const u16;
+        const TS_CAL1: *const u16 = 0x1FFF_F7B8 as *const u16;
+        const TS_CAL2: *const u16 = 0x1FFF_F7C2 as *const u16;
+        const TS_CAL2_TEMP: i32 = 110;
+        const CAL_VDDA_MV: u32 = 3_300;
+        const VBAT_DIV: u32 = 2;
+    } else if #[cfg(feature = "l5")] {
+        const VREFINT_CAL: *const u16 = 0x0BFA_05AA as *const u16;
+        const TS_CAL1: *const u16 = 0x0BFA_05A8 as *const u16;
+        const TS_CAL2: *const u16 = 0x0BFA_05CA as *const u16;
+        const TS_CAL2_TEMP: i32 = 130;
+        const CAL_VDDA_MV: u32 = 3_000;
+        const VBAT_DIV: u32 = 3;
+    } else if #[cfg(any(feature = "l4", feature = "g4"))] {
+        const VREFINT_CAL: *const u16 = 0x1FFF_75AA as *const u16;
+        const TS_CAL1: *const u16 = 0x1FFF_75A8 as *const u16;
+        const TS_CAL2: *const u16 = 0x1FFF_75CA as *const u16;
+        // todo: 130°C on L4+.
+        #[cfg(feature = "l4")]
+        const TS_CAL2_TEMP: i32 = 110;
+        #[cfg(feature = "g4")]
+        const TS_CAL2_TEMP: i32 = 130;
+        const CAL_VDDA_MV: u32 = 3_000;
+        const VBAT_DIV: u32 = 3;
+    }
+    // todo: H7, once ADC3 is supported; its internal channels are only on ADC3.
+}
+
+#[cfg(any(feature = "f3", feature = "l4", feature = "l5", feature = "g4"))]
+const TS_CAL1_TEMP: i32 = 30;
+
+#[cfg(any(feature = "f3", feature = "l4", feature = "l5", feature = "g4"))]
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// ADC inputs that are internal to the MCU, instead of connected to pins. Pass these to
+/// `Adc::read`, after enabling them with `Adc::enable_internal`, or use `Adc::read_vdda_mv`,
+/// `Adc::read_temp`, and `Adc::read_vbat_mv`, which handle this. They're connected to ADC1; on
+/// L4x5 and L4x6, the temperature sensor and VBAT are also connected to ADC3.
+pub enum InternalChannel {
+    /// The internal voltage reference, used to measure VDDA.
+    Vrefint,
+    TempSensor,
+    /// VBAT, through a resistor divider: /2 on F3, and /3 on other families.
+    Vbat,
+}
+
+#[cfg(any(feature = "f3", feature = "l4", feature = "l5", feature = "g4"))]
+impl InternalChannel {
+    /// Is this channel wired to a given ADC?
+    fn is_connected(&self, device: AdcDevice) -> bool {
+        match device {
+            AdcDevice::One => true,
+            #[cfg(any(feature = "l4x5", feature = "l4x6"))]
+            AdcDevice::Three => !matches!(self, Self::Vrefint),
+            _ => false,
+        }
+    }
+}
+
+#[cfg(any(feature = "f3", feature = "l4", feature = "l5", feature = "g4"))]
+impl AdcInput for InternalChannel {
+    fn channel(&self, _device: AdcDevice) -> u8 {
+        cfg_if! {
+            if #[cfg(any(feature = "f3", feature = "g4"))] {
+                match self {
+                    Self::TempSensor => 16,
+                    Self::Vbat => 17,
+                    Self::Vrefint => 18,
+                }
+            } else {
+                match self {
+                    Self::Vrefint => 0,
+                    Self::TempSensor => 17,
+                    Self::Vbat => 18,
+                }
+            }
+        }
+    }
+}
+
+#[cfg(any(feature = "f3", feature = "l4", feature = "l5", feature = "g4"))]
+/// Calculate VDDA in mV, from a 12-bit reading of `InternalChannel::Vrefint`, using its factory
+/// calibration value. See L4 RM, section 16.4.34: "Monitoring the internal voltage reference".
+pub fn vdda_mv(vrefint_reading: u16) -> u32 {
+    let vrefint_cal = unsafe { ptr::read_volatile(VREFINT_CAL) };
+    CAL_VDDA_MV * vrefint_cal as u32 / (vrefint_reading as u32).max(1)
+}
+
+#[cfg(any(feature = "f3", feature = "l4", feature = "l5", feature = "g4"))]
+/// Convert a 12-bit, right-aligned reading to mV, given VDDA in mV, eg from `vdda_mv`.
+pub fn reading_to_mv(reading: u16, vdda_mv: u32) -> u32 {
+    vdda_mv * reading as u32 / 4_095
+}
+
+#[cfg(any(feature = "f3", feature = "l4", feature = "l5", feature = "g4"))]
+/// Calculate the temperature in °C, from a 12-bit reading of `InternalChannel::TempSensor`, and
+/// VDDA in mV, using the sensor's factory calibration values. See L4 RM, section 16.4.32:
+/// "Temperature sensor".
+pub fn temp_c(ts_reading: u16, vdda_mv: u32) -> f32 {
+    let (cal1, cal2) = unsafe { (ptr::read_volatile(TS_CAL1), ptr::read_volatile(TS_CAL2)) };
+    // Scale the reading to the VDDA the calibration values were measured at.
+    let reading = ts_reading as f32 * vdda_mv as f32 / CAL_VDDA_MV as f32;
+
+    (TS_CAL2_TEMP - TS_CAL1_TEMP) as f32 / (cal2 as f32 - cal1 as f32) * (reading - cal1 as f32)
+        + TS_CAL1_TEMP as f32
+}
+
+#[cfg(any(feature = "f3", feature = "l4", feature = "l5", feature = "g4"))]
+/// Calculate VBAT in mV, from a 12-bit reading of `InternalChannel::Vbat`, and VDDA in mV.
+pub fn vbat_mv(vbat_reading: u16, vdda_mv: u32) -> u32 {
+    reading_to_mv(vbat_reading, vdda_mv) * VBAT_DIV
+}
+
 // todo: Remove this macro, and replace using a `regs` fn like you use in GPIO.
 macro_rules! hal {
     ($ADC:ident, $ADC_COMMON:ident, $adc:ident, $rcc_num:tt) => {
@@ -325,16 +964,6 @@ macro_rules! hal {
                     result.calibrate(InputType::SingleEnded, clock_cfg);
                     result.calibrate(InputType::Differential, clock_cfg);
 
-                    // Reference Manual: "ADEN bit cannot be set during ADCAL=1
-                    // and 4 ADC clock cycle after the ADCAL
-                    // bit is cleared by hardware."
-                    let adc_per_cpu_cycles = match result.cfg.clock_mode {
-                        ClockMode::Async => unimplemented!(),
-                        ClockMode::SyncDiv1 => 1,
-                        ClockMode::SyncDiv2 => 2,
-                        ClockMode::SyncDiv4 => 4,
-                    };
-                    asm::delay(adc_per_cpu_cycles * 4);
                     result.enable();
 
                     match result.cfg.operation_mode {
@@ -342,6 +971,10 @@ macro_rules! hal {
                         OperationMode::Continuous => (),// todo: Setup Continuous
                     }
 
+                    if result.cfg.auto_delay {
+                        result.set_auto_delay(true);
+                    }
+
                     // Set up VDDA only after the ADC is otherwise enabled.
                     result.setup_vdda(clock_cfg);
 
@@ -352,7 +985,8 @@ macro_rules! hal {
             /// Sets up adc in one shot mode for a single channel
             pub fn setup_oneshot(&mut self) {
                 self.regs.cr.modify(|_, w| w.adstp().set_bit());
-                self.regs.isr.modify(|_, w| w.ovr().clear_bit());
+                // OVR is cleared by writing 1 to it.
+                self.regs.isr.write(|w| w.ovr().set_bit());
 
                 self.regs.cfgr.modify(|_, w| w
                     .cont().clear_bit()  // single conversion mode.
@@ -363,7 +997,7 @@ macro_rules! hal {
                     // • OVRMOD=1: The data register is overwritten with the last conversion result and the
                     // previous unread data is lost. If OVR remains at 1, any further conversions will operate
                     // normally and the ADC_DR register will always contain the latest converted data.
-                    .ovrmod().clear_bit()  // preserve DR data
+                    .ovrmod().bit(self.cfg.overrun_mode as u8 != 0)
                 );
 
                 self.set_sequence_len(1);
@@ -371,6 +1005,169 @@ macro_rules! hal {
                 self.cfg.operation_mode = OperationMode::OneShot;
             }
 
+            #[cfg(any(feature = "f3", feature = "g4"))]
+            /// Set up the injected sequence: Between 1 and 4 channels, converted in order each
+            /// time `trigger` fires. Stops any ongoing conversions. Call `start_injected` to arm
+            /// the trigger, and read results with `read_injected`. Only ADC1 and ADC2 are
+            /// supported. See G4 RM, section 21.4.12: "Channel selection (SQRx, JSQRx)".
+            pub fn setup_injected(&mut self, channels: &[u8], trigger: InjectedTrigger) {
+                if channels.is_empty() || channels.len() > 4 {
+                    panic!("The injected sequence must have between 1 and 4 channels.");
+                }
+                match self.device {
+                    AdcDevice::One | AdcDevice::Two => (),
+                    _ => panic!("Injected triggers are only supported on ADC1 and ADC2."),
+                }
+
+                self.stop_conversions();
+
+                for channel in channels {
+                    assert!(*channel <= 18);
+                }
+                // Unused ranks are ignored, based on JL.
+                let chan = |rank: usize| channels.get(rank).copied().unwrap_or(0);
+
+                // JSQR is written as a whole, since it's treated as a single context.
+                self.regs.jsqr.write(|w| unsafe {
+                    w.jl().bits(channels.len() as u8 - 1);
+                    w.jextsel().bits(trigger as u8);
+                    // Rising edge.
+                    w.jexten().bits(0b01);
+                    w.jsq1().bits(chan(0));
+                    w.jsq2().bits(chan(1));
+                    w.jsq3().bits(chan(2));
+                    w.jsq4().bits(chan(3))
+                });
+            }
+
+            #[cfg(any(feature = "f3", feature = "g4"))]
+            /// Arm injected conversions: They start each time the trigger set in
+            /// `setup_injected` fires. Sets the CR register, JADSTART field.
+            pub fn start_injected(&mut self) {
+                self.regs.cr.modify(|_, w| w.jadstart().set_bit());
+            }
+
+            #[cfg(any(feature = "f3", feature = "g4"))]
+            /// Read the most recent result of an injected conversion. `rank` is the channel's
+            /// position in the injected sequence, between 1 and 4. Reads the JDRx register.
+            pub fn read_injected(&self, rank: u8) -> u16 {
+                let jdr = match rank {
+                    1 => self.regs.jdr1.read().bits(),
+                    2 => self.regs.jdr2.read().bits(),
+                    3 => self.regs.jdr3.read().bits(),
+                    4 => self.regs.jdr4.read().bits(),
+                    _ => panic!("Injected rank must be between 1 and 4."),
+                };
+                jdr as u16
+            }
+
+            /// Set the data register's behavior on overrun. Stops any ongoing conversions, since
+            /// OVRMOD can only be written when ADSTART = 0.
+            pub fn set_overrun_mode(&mut self, mode: OverrunMode) {
+                self.stop_conversions();
+                self.regs.cfgr.modify(|_, w| w.ovrmod().bit(mode as u8 != 0));
+                self.cfg.overrun_mode = mode;
+            }
+
+            /// Enable or disable auto-delayed conversion mode. In this mode, a new conversion starts
+            /// only once the previous result has been read from the data register (or the
+            /// injected data registers), so the ADC idles between conversions, instead of
+            /// overrunning. This is useful when the application reads results slower than
+            /// the ADC produces them, eg in continuous mode. Sets the CFGR register, AUTDLY field.
+            /// See L4 RM, section 16.4.26: "Auto-delayed conversion mode".
+            ///
+            /// These families don't have the auto-off mode (AUTOFF) found on G0 and WL; to power
+            /// the ADC down between infrequent conversions, use `power_down` and `resume`.
+            pub fn set_auto_delay(&mut self, enabled: bool) {
+                // AUTDLY can only be written when ADSTART = 0 and JADSTART = 0.
+                self.stop_conversions();
+                self.regs.cfgr.modify(|_, w| w.autdly().bit(enabled));
+                self.cfg.auto_delay = enabled;
+            }
+
+            /// Returns true if an overrun has occurred since the OVR flag was last cleared: A
+            /// conversion completed before the previous result was read. Clear the flag with
+            /// `clear_interrupt(AdcInterrupt::Overrun)`; to be notified instead of polling, use
+            /// `enable_interrupt(AdcInterrupt::Overrun)`.
+            pub fn overrun_occurred(&self) -> bool {
+                self.regs.isr.read().ovr().bit_is_set()
+            }
+
+            /// Monitor a channel using analog watchdog 1, converting continuously without CPU
+            /// involvement, and raising the `Watchdog1` interrupt only when a reading is outside
+            /// `low..=high`. Thresholds are in ADC counts, compared against raw, right-aligned
+            /// readings. This suits eg battery monitoring: Sleep the core with
+            /// `low_power::sleep_now`, and handle the low-battery case in the ADC ISR, where you
+            /// clear the flag with `clear_interrupt(AdcInterrupt::Watchdog1)`.
+            ///
+            /// If `auto_delay` is false, conversions run back-to-back, overwriting the data
+            /// register, which never needs to be read. If it's true, auto-delayed conversion mode
+            /// (AUTDLY) is used: Each conversion waits until the previous result is read with
+            /// `read_result`, and the ADC idles in between. Read it from a periodic wakeup, eg the
+            /// RTC's, to pace conversions and save power.
+            ///
+            /// To save power, use a long sample time, and a slow ADC clock. The ADC isn't
+            /// functional in Stop mode on the families this module supports, so use Sleep (or
+            /// low-power sleep) modes. Stop monitoring with `stop_window_monitor`.
+            pub fn start_window_monitor(
+                &mut self,
+                channel: u8,
+                low: u16,
+                high: u16,
+                auto_delay: bool,
+            ) {
+                assert!(low <= high);
+
+                // CFGR fields can only be written with ADSTART = 0.
+                self.stop_conversions();
+
+                self.set_sequence_len(1);
+                self.set_sequence(channel, 1);
+
+                // Watch a single channel, in continuous mode.
+                self.regs.cfgr.modify(|_, w| unsafe {
+                    w.ovrmod().set_bit();
+                    w.cont().set_bit();
+                    w.autdly().bit(auto_delay);
+                    w.awd1sgl().set_bit();
+                    w.awd1en().set_bit();
+                    w.awd1ch().bits(channel)
+                });
+                self.cfg.overrun_mode = OverrunMode::Overwrite;
+                self.cfg.auto_delay = auto_delay;
+
+                // H7 has separate low and high threshold registers, since its thresholds are up
+                // to 26 bits; F3, L4, L5 and G4 combine them in TR1.
+                cfg_if! {
+                    if #[cfg(feature = "h7")] {
+                        self.regs.ltr1.write(|w| unsafe { w.ltr1().bits(low as u32) });
+                        self.regs.htr1.write(|w| unsafe { w.htr1().bits(high as u32) });
+                    } else {
+                        self.regs.tr1.write(|w| unsafe {
+                            w.lt1().bits(low);
+                            w.ht1().bits(high)
+                        });
+                    }
+                }
+
+                self.clear_interrupt(AdcInterrupt::Watchdog1);
+                self.enable_interrupt(AdcInterrupt::Watchdog1);
+
+                self.regs.cr.modify(|_, w| w.adstart().set_bit());
+            }
+
+            /// Stop monitoring started with `start_window_monitor`, and return to single
+            /// conversion mode.
+            pub fn stop_window_monitor(&mut self) {
+                self.stop_conversions();
+
+                self.regs.cfgr.modify(|_, w| {
+                    w.awd1en().clear_bit();
+                    w.cont().clear_bit()
+                });
+                self.regs.ier.modify(|_, w| w.awd1ie().clear_bit());
+            }
+
             /// Set the ADC conversion sequence length, between 1 and 16.
             pub fn set_sequence_len(&mut self, len: u8) {
                 if len - 1 >= 16 {
@@ -389,6 +1186,78 @@ macro_rules! hal {
                 self.regs.cfgr.modify(|_, w| w.align().bit(align as u8 != 0));
             }
 
+            #[cfg(feature = "g4")]
+            /// Select an internal event to trigger regular conversions, and the edge it triggers
+            /// on. Returns an error if the source isn't connected to this ADC. Sets the CFGR
+            /// register, EXTSEL and EXTEN fields.
+            pub fn set_trigger(
+                &mut self,
+                source: TriggerSource,
+                edge: TriggerEdge,
+            ) -> Result<(), RouteError> {
+                let extsel = interconnect::adc_extsel(self.device, source)?;
+
+                // Software is allowed to write these bits only when ADSTART=0.
+                self.stop_conversions();
+
+                self.regs.cfgr.modify(|_, w| unsafe {
+                    w.extsel().bits(extsel);
+                    w.exten().bits(edge as u8)
+                });
+
+                Ok(())
+            }
+
+            #[cfg(any(feature = "f3", feature = "l4", feature = "l5", feature = "h7"))]
+            /// Select a timer or EXTI event to trigger regular conversions, and the edge it triggers
+            /// on. Sets the CFGR register, EXTSEL and EXTEN fields. Conversions start on each
+            /// trigger after `start_conversion`, or a DMA read function, sets ADSTART. Returns an
+            /// error if the trigger isn't connected to this ADC.
+            ///
+            /// Example: Sample a 3-channel sequence at 10kHz, into a circular DMA buffer:
+            /// ```
+            /// static mut ADC_BUF: [u16; 3 * 64] = [0; 3 * 64];
+            ///
+            /// let mut timer = Timer::new_tim2(dp.TIM2, 10_000., Default::default(), &clock_cfg);
+            /// timer.set_mastermode(MasterModeSelection::Update);
+            ///
+            /// adc.set_trigger(RegularTrigger::Tim2Trgo, TriggerEdge::Rising).unwrap();
+            /// unsafe {
+            ///     adc.read_sequence_dma(
+            ///         &[1, 2, 3],
+            ///         &mut ADC_BUF,
+            ///         DmaChannel::C1,
+            ///         ChannelCfg { circular: Circular::Enabled, ..Default::default() },
+            ///         &mut dma,
+            ///     );
+            /// }
+            /// timer.enable();
+            /// ```
+            pub fn set_trigger(
+                &mut self,
+                trigger: RegularTrigger,
+                edge: TriggerEdge,
+            ) -> Result<(), RouteError> {
+                let extsel = trigger.extsel(self.device).ok_or(RouteError {})?;
+
+                // Software is allowed to write these bits only when ADSTART=0.
+                self.stop_conversions();
+
+                self.regs.cfgr.modify(|_, w| unsafe {
+                    w.extsel().bits(extsel);
+                    w.exten().bits(edge as u8)
+                });
+
+                Ok(())
+            }
+
+            /// Disable hardware triggers for regular conversions, so they start when software
+            /// sets ADSTART. Sets the CFGR register, EXTEN field.
+            pub fn clear_trigger(&mut self) {
+                self.stop_conversions();
+                self.regs.cfgr.modify(|_, w| unsafe { w.exten().bits(0) });
+            }
+
             /// Enable the ADC.
             /// ADEN=1 enables the ADC. The flag ADRDY will be set once the ADC is ready for
             /// operation.
@@ -487,7 +1356,7 @@ macro_rules! hal {
 
             /// Disable power, eg to save power in low power modes. Inferred from RM,
             /// we should run this before entering `STOP` mode, in conjunction with with
-            /// disabling the ADC.
+            /// disabling the ADC. Use `resume` to restore the ADC afterwards.
             pub fn advregen_disable(&mut self){
                 cfg_if! {
                     if #[cfg(feature = "f3")] {
@@ -515,7 +1384,7 @@ macro_rules! hal {
             ///
             /// This is based on the MAX_ADVREGEN_STARTUP_US of the device.
             fn wait_advregen_startup(&self, clocks: &Clocks) {
-                let mut delay = (MAX_ADVREGEN_STARTUP_US * 1_000_000) / clocks.sysclk();
+                let mut delay = clocks.sysclk() / 1_000_000 * MAX_ADVREGEN_STARTUP_US;
                 // https://github.com/rust-embedded/cortex-m/pull/328
                 if delay < 2 {  // Work around a bug in cortex-m.
                     delay = 2;
@@ -523,7 +1392,34 @@ macro_rules! hal {
                 asm::delay(delay);
             }
 
-            /// Calibrate. See L4 RM, 16.5.8, or F404 RM, section 15.3.8.
+            /// Wait 4 ADC clock cycles, as required after calibration, before setting ADEN. In
+            /// asynchronous clock mode, we don't know the ADC clock speed, so wait 4 cycles of
+            /// the slowest ADC clock allowed: 0.14Mhz on L4, and faster on other families.
+            fn wait_after_calibration(&self, clocks: &Clocks) {
+                let ahb_per_adc_cycles = match self.cfg.clock_mode {
+                    ClockMode::Async => {
+                        // 4 cycles at 0.14Mhz is 28.6us.
+                        let mut delay = clocks.sysclk() / 1_000_000 * 29;
+                        if delay < 2 {  // Work around a bug in cortex-m.
+                            delay = 2;
+                        }
+                        asm::delay(delay);
+                        return;
+                    }
+                    ClockMode::SyncDiv1 => 1,
+                    ClockMode::SyncDiv2 => 2,
+                    ClockMode::SyncDiv4 => 4,
+                };
+                let cpu_per_ahb_cycles = (clocks.sysclk() / clocks.hclk()).max(1);
+
+                asm::delay(ahb_per_adc_cycles * cpu_per_ahb_cycles * 4);
+            }
+
+            /// Calibrate. See L4 RM, 16.5.8, or F404 RM, section 15.3.8. `input_type` selects
+            /// whether the calibration applies to single-ended, or differential conversions; they
+            /// use separate calibration factors. On H7, single-ended calibration includes
+            /// linearity calibration. Enables the voltage regulator if required, and disables the
+            /// ADC during calibration.
             /// Stores calibration values, which can be re-inserted later,
             /// eg after entering ADC deep sleep mode, or MCU STANDBY or VBAT.
             pub fn calibrate(&mut self, input_type: InputType, clocks: &Clocks) {
@@ -538,8 +1434,16 @@ macro_rules! hal {
                 // 2. Ensure that ADEN=0
                 if was_enabled {
                     self.disable();
+                    while self.is_enabled() {}
                 }
 
+                // H7 RM, 25.4.8: Linearity calibration is launched with ADCAL, if ADCALLIN is
+                // set. It only applies to single-ended calibration.
+                #[cfg(feature = "h7")]
+                self.regs.cr.modify(|_, w| {
+                    w.adcallin().bit(matches!(input_type, InputType::SingleEnded))
+                });
+
                 self.regs.cr.modify(|_, w| w
                     // RM:
                     // The calibration factor to be applied for single-ended input conversions is different from the
@@ -562,20 +1466,16 @@ macro_rules! hal {
                 // 5. Wait until ADCAL=0.
                 while self.regs.cr.read().adcal().bit_is_set() {}
 
+                // Reference Manual: "ADEN bit cannot be set during ADCAL=1
+                // and 4 ADC clock cycle after the ADCAL
+                // bit is cleared by hardware."
+                self.wait_after_calibration(clocks);
+
                 // 6. The calibration factor can be read from ADC_CALFACT register.
+                let val = self.calibration_factor(input_type);
                 match input_type {
-                    InputType::SingleEnded => {
-                        let val = self.regs.calfact.read().calfact_s().bits();
-                        #[cfg(not(feature = "h7"))]
-                        let val = val as u16;
-                        self.cfg.cal_single_ended = Some(val);
-                    }
-                    InputType::Differential => {
-                         let val = self.regs.calfact.read().calfact_d().bits();
-                         #[cfg(not(feature = "h7"))]
-                         let val = val as u16;
-                         self.cfg.cal_differential = Some(val);
-                    }
+                    InputType::SingleEnded => self.cfg.cal_single_ended = Some(val),
+                    InputType::Differential => self.cfg.cal_differential = Some(val),
                 }
 
                 if was_enabled {
@@ -596,14 +1496,10 @@ macro_rules! hal {
 
                 // 2. Write CALFACT_S and CALFACT_D with the new calibration factors.
                 if let Some(cal) = self.cfg.cal_single_ended {
-                    #[cfg(not(feature = "h7"))]
-                    let cal = cal as u8;
-                    self.regs.calfact.modify(|_, w| unsafe { w.calfact_s().bits(cal) });
+                    self.write_calfact(InputType::SingleEnded, cal);
                 }
                 if let Some(cal) = self.cfg.cal_differential {
-                    #[cfg(not(feature = "h7"))]
-                    let cal = cal as u8;
-                    self.regs.calfact.modify(|_, w| unsafe { w.calfact_d().bits(cal) });
+                    self.write_calfact(InputType::Differential, cal);
                 }
 
                 // 3. When a conversion is launched, the calibration factor will be injected into the analog
@@ -612,6 +1508,76 @@ macro_rules! hal {
                 // channel.
             }
 
+            /// Read the calibration factor currently in use, from the CALFACT register. This is 7
+            /// bits, or 11 bits on H7.
+            pub fn calibration_factor(&self, input_type: InputType) -> u16 {
+                let calfact = self.regs.calfact.read();
+                match input_type {
+                    InputType::SingleEnded => calfact.calfact_s().bits() as u16,
+                    InputType::Differential => calfact.calfact_d().bits() as u16,
+                }
+            }
+
+            /// Set a calibration factor, eg one saved from a previous `calibrate`, and store it in
+            /// the config, for `inject_calibration`. Enables the ADC if required, and stops any
+            /// ongoing conversions.
+            pub fn set_calibration_factor(&mut self, input_type: InputType, factor: u16) {
+                match input_type {
+                    InputType::SingleEnded => self.cfg.cal_single_ended = Some(factor),
+                    InputType::Differential => self.cfg.cal_differential = Some(factor),
+                }
+                // The calibration factor can only be written when ADEN=1, and ADSTART=0 and JADSTART=0.
+                if !self.is_enabled() {
+                    self.enable();
+                }
+                self.stop_conversions();
+
+                self.write_calfact(input_type, factor);
+            }
+
+            fn write_calfact(&mut self, input_type: InputType, factor: u16) {
+                #[cfg(not(feature = "h7"))]
+                let factor = factor as u8;
+                match input_type {
+                    InputType::SingleEnded => {
+                        self.regs.calfact.modify(|_, w| unsafe { w.calfact_s().bits(factor) })
+                    }
+                    InputType::Differential => {
+                        self.regs.calfact.modify(|_, w| unsafe { w.calfact_d().bits(factor) })
+                    }
+                }
+            }
+
+            /// Disable the ADC, and its voltage regulator, so it draws no power between
+            /// conversions, eg in battery-powered designs, or before entering Stop mode. The
+            /// calibration is lost, but the factors stored in `cfg` are re-applied by `resume`,
+            /// which takes the ADC regulator startup time, plus the ADC startup time.
+            pub fn power_down(&mut self) {
+                self.disable();
+                while self.is_enabled() {}
+                self.advregen_disable();
+            }
+
+            /// Restore the ADC after `power_down` or `advregen_disable`, eg on exiting Stop mode:
+            /// Re-enable the voltage regulator, and enable the ADC. Deep-power-down mode loses the
+            /// calibration, so re-apply the stored calibration factors, or if there are none,
+            /// calibrate.
+            pub fn resume(&mut self, clocks: &Clocks) {
+                if !self.is_advregen_enabled() {
+                    self.advregen_enable(clocks);
+                }
+
+                if self.cfg.cal_single_ended.is_none() {
+                    self.calibrate(InputType::SingleEnded, clocks);
+                }
+                if self.cfg.cal_differential.is_none() {
+                    self.calibrate(InputType::Differential, clocks);
+                }
+
+                // Enables the ADC if required.
+                self.inject_calibration();
+            }
+
             /// Select single-ended, or differential conversions for a given channel.
             pub fn set_input_type(&mut self, channel: u8, input_type: InputType) {
                 // L44 RM, 16.4.7:
@@ -840,17 +1806,122 @@ macro_rules! hal {
                 return self.regs.dr.read().rdata().bits() as u16;
             }
 
-            /// Take a single reading, in OneShot mode
-            pub fn read(&mut self, channel: u8) -> u16 {
+            /// Take a single reading, in OneShot mode. `channel` is either a channel number, or
+            /// a reference to an `AnalogPin`.
+            pub fn read<C: AdcInput>(&mut self, channel: C) -> u16 {
+                let channel = channel.channel(self.device);
                 self.start_conversion(&[channel], OperationMode::OneShot);
                 self.read_result()
             }
 
-            // todo: fn read_voltage, using vrefint and L4xx-hal style calibration?
+            #[cfg(any(feature = "f3", feature = "l4", feature = "l5", feature = "g4"))]
+            /// Enable an internal channel, so it can be read. Wait for its startup time (up to
+            /// 120µs for the temperature sensor) before reading it. Disable VBAT when not in use,
+            /// since its divider drains the battery.
+            pub fn enable_internal(&mut self, channel: InternalChannel) {
+                self.set_internal(channel, true);
+            }
+
+            #[cfg(any(feature = "f3", feature = "l4", feature = "l5", feature = "g4"))]
+            /// Disable an internal channel.
+            pub fn disable_internal(&mut self, channel: InternalChannel) {
+                self.set_internal(channel, false);
+            }
+
+            #[cfg(any(feature = "f3", feature = "l4", feature = "l5", feature = "g4"))]
+            /// Set the common CCR register's bit for an internal channel. These are named
+            /// differently on each family.
+            fn set_internal(&mut self, channel: InternalChannel, enabled: bool) {
+                let common_regs = unsafe { &*pac::$ADC_COMMON::ptr() };
+                common_regs.ccr.modify(|_, w| match channel {
+                    InternalChannel::Vrefint => w.vrefen().bit(enabled),
+                    #[cfg(feature = "f3")]
+                    InternalChannel::TempSensor => w.tsen().bit(enabled),
+                    #[cfg(feature = "f3")]
+                    InternalChannel::Vbat => w.vbaten().bit(enabled),
+                    #[cfg(any(feature = "l4", feature = "l5"))]
+                    InternalChannel::TempSensor => w.ch17sel().bit(enabled),
+                    #[cfg(any(feature = "l4", feature = "l5"))]
+                    InternalChannel::Vbat => w.ch18sel().bit(enabled),
+                    #[cfg(feature = "g4")]
+                    InternalChannel::TempSensor => w.vsensesel().bit(enabled),
+                    #[cfg(feature = "g4")]
+                    InternalChannel::Vbat => w.vbatsel().bit(enabled),
+                });
+            }
+
+            #[cfg(any(feature = "f3", feature = "l4", feature = "l5", feature = "g4"))]
+            /// Take a reading of an internal channel: Enable it, wait for it to start, read it with
+            /// the longest sample time (the temperature sensor and VREFINT require at least 4 -
+            /// 5µs), then disable it, and restore the channel's sample time. Assumes 12-bit
+            /// resolution. Returns an error if the channel isn't connected to this ADC.
+            pub fn read_internal(
+                &mut self,
+                channel: InternalChannel,
+                clocks: &Clocks,
+            ) -> Result<u16, RouteError> {
+                if !channel.is_connected(self.device) {
+                    return Err(RouteError {});
+                }
+
+                self.enable_internal(channel);
 
-            #[cfg(not(any(feature = "g0", feature = "f4", feature = "l5")))]
+                let mut delay = clocks.sysclk() / 1_000_000 * MAX_INTERNAL_STARTUP_US;
+                // https://github.com/rust-embedded/cortex-m/pull/328
+                if delay < 2 {  // Work around a bug in cortex-m.
+                    delay = 2;
+                }
+                asm::delay(delay);
+
+                // Internal channels may be in either sample time register, depending on family.
+                let smpr1 = self.regs.smpr1.read().bits();
+                let smpr2 = self.regs.smpr2.read().bits();
+
+                let chan = channel.channel(self.device);
+                self.set_sample_time(chan, SampleTime::T601);
+                let reading = self.read(channel);
+
+                self.disable_internal(channel);
+                self.regs.smpr1.write(|w| unsafe { w.bits(smpr1) });
+                self.regs.smpr2.write(|w| unsafe { w.bits(smpr2) });
+                Ok(reading)
+            }
+
+            #[cfg(any(feature = "f3", feature = "l4", feature = "l5", feature = "g4"))]
+            /// Measure VDDA, in mV, using the internal voltage reference and its factory
+            /// calibration value. Returns an error if not run on ADC1.
+            pub fn read_vdda_mv(&mut self, clocks: &Clocks) -> Result<u32, RouteError> {
+                Ok(vdda_mv(self.read_internal(InternalChannel::Vrefint, clocks)?))
+            }
+
+            #[cfg(any(feature = "f3", feature = "l4", feature = "l5", feature = "g4"))]
+            /// Measure the MCU's temperature, in °C, using the internal temperature sensor and its
+            /// factory calibration values. Measures VDDA too, to compensate for it. Returns an
+            /// error if not run on ADC1.
+            pub fn read_temp(&mut self, clocks: &Clocks) -> Result<f32, RouteError> {
+                let vdda = self.read_vdda_mv(clocks)?;
+                Ok(temp_c(self.read_internal(InternalChannel::TempSensor, clocks)?, vdda))
+            }
+
+            #[cfg(any(feature = "f3", feature = "l4", feature = "l5", feature = "g4"))]
+            /// Measure VBAT, in mV. Measures VDDA too, as the reference. Returns an error if not
+            /// run on ADC1.
+            pub fn read_vbat_mv(&mut self, clocks: &Clocks) -> Result<u32, RouteError> {
+                let vdda = self.read_vdda_mv(clocks)?;
+                Ok(vbat_mv(self.read_internal(InternalChannel::Vbat, clocks)?, vdda))
+            }
+
+            #[cfg(not(any(feature = "g0", feature = "f4")))]
             /// Take a one shot reading, using DMA. See L44 RM, 16.4.27: "DMA one shot mode".
-            /// Note that the `channel` argument is only used on F3 and L4.
+            /// Note that the `dma_channel` argument has no effect on F3 and L4. On WB and WL, the DMAMUX
+            /// is configured to route this ADC's request to it.
+            ///
+            /// If `channel_cfg` sets circular mode, the ADC keeps requesting DMA transfers
+            /// indefinitely. If the DMA falls behind, an overrun occurs, and the ADC stops
+            /// issuing requests until the OVR flag is cleared, regardless of `OverrunMode`, so
+            /// the data in the buffer stays valid, but has a gap. For high-rate acquisition,
+            /// enable the `Overrun` interrupt; in its handler, clear the flag, and restart with
+            /// this function if the buffer's ordering matters.
             pub unsafe fn read_dma<D>(
                 &mut self, buf: &mut [u16],
                 adc_channel: u8,
@@ -860,6 +1931,45 @@ macro_rules! hal {
             ) where
                 D: Deref<Target = dma_p::RegisterBlock>,
             {
+                self.read_sequence_dma(&[adc_channel], buf, dma_channel, channel_cfg, dma);
+            }
+
+            #[cfg(not(any(feature = "g0", feature = "f4")))]
+            /// Read a sequence of up to 16 channels, using DMA: Sets the regular sequence and its
+            /// length (SQR1 - SQR4 registers), sets up DMA, and starts conversions. `buf` is filled
+            /// in sequence order, repeating: `[seq[0], seq[1], ..., seq[n-1], seq[0], ...]`; view it
+            /// with `InterleavedBuf`. Its length should be a multiple of the sequence length. See
+            /// `read_dma` for details on DMA modes.
+            ///
+            /// Example, reading 3 channels into a circular buffer, on each trigger:
+            /// ```
+            /// static mut ADC_BUF: [u16; 3 * 64] = [0; 3 * 64];
+            ///
+            /// unsafe {
+            ///     adc.read_sequence_dma(
+            ///         &[1, 2, 17],
+            ///         &mut ADC_BUF,
+            ///         DmaChannel::C1,
+            ///         ChannelCfg { circular: Circular::Enabled, ..Default::default() },
+            ///         &mut dma,
+            ///     );
+            /// }
+            /// ```
+            pub unsafe fn read_sequence_dma<D>(
+                &mut self,
+                sequence: &[u8],
+                buf: &mut [u16],
+                dma_channel: DmaChannel,
+                channel_cfg: ChannelCfg,
+                dma: &mut Dma<D>
+            ) where
+                D: Deref<Target = dma_p::RegisterBlock>,
+            {
+                assert!(
+                    !sequence.is_empty() && sequence.len() <= 16,
+                    "The ADC sequence must have 1 - 16 channels."
+                );
+
                 let (ptr, len) = (buf.as_mut_ptr(), buf.len());
                 // The software is allowed to write (dmaen and dmacfg) only when ADSTART=0 and JADSTART=0 (which
                 // ensures that no conversion is ongoing)
@@ -895,8 +2005,13 @@ macro_rules! hal {
                     _ => unimplemented!(),
                 }
 
-                self.set_sequence(adc_channel, 1);
-                // todo: Support sequences.
+                #[cfg(any(feature = "wb", feature = "wl"))]
+                dma::mux_dma(dma, dma_channel, DmaInput::Adc1);
+
+                for (i, channel) in sequence.iter().enumerate() {
+                    self.set_sequence(*channel, i as u8 + 1); // + 1, since sequences start at 1.
+                }
+                self.set_sequence_len(sequence.len() as u8);
 
                 self.regs.cr.modify(|_, w| w.adstart().set_bit());  // Start
 
@@ -953,6 +2068,97 @@ macro_rules! hal {
                 );
             }
 
+            #[cfg(any(feature = "f3", feature = "l4", feature = "g4", feature = "h7"))]
+            /// Set the dual ADC mode. Call this on the master ADC, with both ADCs enabled, and no
+            /// conversions in progress; configure the slave's sequence and sample times as usual,
+            /// then start conversions on the master only. `delay` is the minimum delay between
+            /// the master's and slave's sampling phases in interleaved mode, in ADC clock cycles
+            /// minus 1 (0 - 15). Sets the ADC_CCR register, DUAL and DELAY fields.
+            pub fn set_dual_mode(&mut self, mode: DualMode, delay: u8) {
+                assert!(delay <= 0xf, "The interleaved delay must be 0 - 15.");
+
+                let common_regs = unsafe { &*pac::$ADC_COMMON::ptr() };
+
+                // F3 calls the DUAL field MULT.
+                common_regs.ccr.modify(|_, w| unsafe {
+                    #[cfg(feature = "f3")]
+                    w.mult().bits(mode as u8);
+                    #[cfg(not(feature = "f3"))]
+                    w.dual().bits(mode as u8);
+                    w.delay().bits(delay)
+                });
+            }
+
+            #[cfg(any(feature = "f3", feature = "l4", feature = "g4", feature = "h7"))]
+            /// Read conversions from both ADCs in dual mode, using DMA: Each word of `buf` holds a
+            /// pair, with the master's result in the low half-word, and the slave's in the high.
+            /// Call this on the master ADC, after `set_dual_mode`, and after configuring both ADCs'
+            /// sequences. Reads the common data register (ADC_CDR), and sets the ADC_CCR register,
+            /// MDMA and DMACFG fields (DAMDF on H7). See L44 RM, section 16.4.31: "DMA requests in
+            /// dual ADC mode".
+            ///
+            /// Note that the `dma_channel` argument has no effect on F3 and L4, where the master
+            /// ADC's channel is used. On G4 and H7, route the master ADC's request (eg
+            /// `DmaInput::Adc1`) to `dma_channel` using `dma::mux` before calling this; in dual
+            /// mode, the slave ADC doesn't generate DMA requests.
+            pub unsafe fn read_dma_dual<D>(
+                &mut self,
+                buf: &mut [u32],
+                dma_channel: DmaChannel,
+                channel_cfg: ChannelCfg,
+                dma: &mut Dma<D>
+            ) where
+                D: Deref<Target = dma_p::RegisterBlock>,
+            {
+                let common_regs = &*pac::$ADC_COMMON::ptr();
+                let (ptr, len) = (buf.as_mut_ptr(), buf.len());
+
+                // MDMA and DMACFG can only be written with no conversions in progress.
+                self.stop_conversions();
+
+                let circular = channel_cfg.circular == dma::Circular::Enabled;
+
+                #[cfg(not(feature = "h7"))]
+                common_regs.ccr.modify(|_, w| {
+                    w.mdma().bits(DualDataFormat::HalfWords as u8);
+                    w.dmacfg().bit(circular)
+                });
+
+                #[cfg(feature = "h7")]
+                common_regs.ccr.modify(|_, w| w.damdf().bits(DualDataFormat::HalfWords as u8));
+                // On H7, the master's DMNGT field selects one-shot or circular mode.
+                #[cfg(feature = "h7")]
+                self.regs.cfgr.modify(|_, w| w.dmngt().bits(if circular { 0b11 } else { 0b01 }));
+
+                #[cfg(any(feature = "f3", feature = "l4"))]
+                let dma_channel = match self.device {
+                    AdcDevice::One => DmaInput::Adc1.dma1_channel(),
+                    _ => panic!("Dual mode DMA is only supported with ADC1 as the master.")
+                };
+
+                #[cfg(feature = "l4")]
+                dma.channel_select(DmaInput::Adc1);
+
+                // In dual mode, starting the master also starts the slave.
+                self.regs.cr.modify(|_, w| w.adstart().set_bit());
+
+                #[cfg(feature = "h7")]
+                let num_data = len as u32;
+                #[cfg(not(feature = "h7"))]
+                let num_data = len as u16;
+
+                dma.cfg_channel(
+                    dma_channel,
+                    &common_regs.cdr as *const _ as u32,
+                    ptr as u32,
+                    num_data,
+                    dma::Direction::ReadFromPeriph,
+                    dma::DataSize::S32,
+                    dma::DataSize::S32,
+                    channel_cfg,
+                );
+            }
+
             /// Enable a specific type of ADC interrupt.
             pub fn enable_interrupt(&mut self, interrupt: AdcInterrupt) {
                 self.regs.ier.modify(|_, w| match interrupt {
@@ -970,6 +2176,57 @@ macro_rules! hal {
                 });
             }
 
+            /// Disable a specific type of interrupt.
+            pub fn disable_interrupt(&mut self, interrupt: AdcInterrupt) {
+                self.regs.ier.modify(|_, w| match interrupt {
+                    AdcInterrupt::Ready => w.adrdyie().clear_bit(),
+                    AdcInterrupt::EndOfConversion => w.eocie().clear_bit(),
+                    AdcInterrupt::EndOfSequence => w.eosie().clear_bit(),
+                    AdcInterrupt::EndofConversionInjected => w.jeocie().clear_bit(),
+                    AdcInterrupt::EndOfSequenceInjected => w.jeosie().clear_bit(),
+                    AdcInterrupt::Watchdog1 => w.awd1ie().clear_bit(),
+                    AdcInterrupt::Watchdog2 => w.awd2ie().clear_bit(),
+                    AdcInterrupt::Watchdog3 => w.awd3ie().clear_bit(),
+                    AdcInterrupt::EndOfSamplingPhase => w.eosmpie().clear_bit(),
+                    AdcInterrupt::Overrun => w.ovrie().clear_bit(),
+                    AdcInterrupt::InjectedOverflow => w.jqovfie().clear_bit(),
+                });
+            }
+
+            /// Check if an interrupt's flag is set, eg to find which event caused the ADC
+            /// interrupt, since they share an interrupt line. Flags are set regardless of whether
+            /// the interrupt is enabled, so this can also be used to poll. Note that reading the
+            /// data register, eg with `read_result`, clears the `EndOfConversion` flag.
+            ///
+            /// Example, in the ADC interrupt handler:
+            /// ```
+            /// if adc.interrupt_is_set(AdcInterrupt::EndOfConversion) {
+            ///     let reading = adc.read_result();
+            /// }
+            /// if adc.interrupt_is_set(AdcInterrupt::EndOfSequence) {
+            ///     adc.clear_interrupt(AdcInterrupt::EndOfSequence);
+            /// }
+            /// if adc.interrupt_is_set(AdcInterrupt::Overrun) {
+            ///     adc.clear_interrupt(AdcInterrupt::Overrun);
+            /// }
+            /// ```
+            pub fn interrupt_is_set(&self, interrupt: AdcInterrupt) -> bool {
+                let isr = self.regs.isr.read();
+                match interrupt {
+                    AdcInterrupt::Ready => isr.adrdy().bit_is_set(),
+                    AdcInterrupt::EndOfConversion => isr.eoc().bit_is_set(),
+                    AdcInterrupt::EndOfSequence => isr.eos().bit_is_set(),
+                    AdcInterrupt::EndofConversionInjected => isr.jeoc().bit_is_set(),
+                    AdcInterrupt::EndOfSequenceInjected => isr.jeos().bit_is_set(),
+                    AdcInterrupt::Watchdog1 => isr.awd1().bit_is_set(),
+                    AdcInterrupt::Watchdog2 => isr.awd2().bit_is_set(),
+                    AdcInterrupt::Watchdog3 => isr.awd3().bit_is_set(),
+                    AdcInterrupt::EndOfSamplingPhase => isr.eosmp().bit_is_set(),
+                    AdcInterrupt::Overrun => isr.ovr().bit_is_set(),
+                    AdcInterrupt::InjectedOverflow => isr.jqovf().bit_is_set(),
+                }
+            }
+
             /// Clear an interrupt flag of the specified type. Consider running this in the
             /// corresponding ISR.
             pub fn clear_interrupt(&mut self, interrupt: AdcInterrupt) {
@@ -0,0 +1,168 @@
+//! A minimal "wait for event" layer, for awaitable I/O without an executor dependency. Futures
+//! here check a hardware flag when polled, and `block_on` puts the core to sleep with `WFE`
+//! between polls. Suitable for simple super-loop firmware that wants `async` / `await` syntax
+//! without pulling in a full executor like Embassy or RTIC.
+//!
+//! Wakeup works using the SEVONPEND bit in the System Control Register: when a peripheral
+//! interrupt becomes pending, an event is generated that wakes the core from `WFE`, even if that
+//! interrupt is masked in the NVIC. So, enable the peripheral's interrupt (eg TCIE for DMA), but
+//! leave it masked in the NVIC; the futures clear the peripheral's flag, then its pending bit.
+//! The driver-level futures, such as `Dma::wait_transfer_complete`, handle this for you.
+//!
+//! Alternatively, set a `Signal` from an interrupt handler, and await it from the main loop.
+//!
+//! Example:
+//! ```rust
+//! let byte = async_wfe::block_on(uart.read_async(pac::Interrupt::USART1));
+//! ```
+
+use core::{
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicBool, Ordering},
+    task::{Context, Poll, RawWaker, RawWakerVTable, Waker},
+};
+
+use cortex_m::{asm, interrupt::free, peripheral::NVIC};
+
+use crate::{low_power, pac::Interrupt};
+
+/// A future that completes when `f` returns `Some`. If `interrupt` is set, its NVIC pending bit
+/// is cleared on completion, so it can wake `WFE` again next time. `f` must clear the peripheral
+/// flag that requested the interrupt before returning `Some`; otherwise, the interrupt pends
+/// again immediately, and the next `WFE` never sees a new pending edge.
+pub struct WaitFor<F> {
+    interrupt: Option<Interrupt>,
+    f: F,
+}
+
+impl<T, F> Future for WaitFor<F>
+where
+    F: FnMut() -> Option<T> + Unpin,
+{
+    type Output = T;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        match (self.f)() {
+            Some(v) => {
+                if let Some(interrupt) = self.interrupt {
+                    NVIC::unpend(interrupt);
+                }
+                Poll::Ready(v)
+            }
+            None => {
+                // We don't have a way to register a waker from hardware; ask to be polled again.
+                // With `block_on`, this doesn't spin, since it sleeps with `WFE` between polls.
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// Create a future that completes when `f` returns `Some`, with its value. `interrupt` is the
+/// NVIC interrupt whose pending bit wakes the core; see the module-level documentation. `f`
+/// must clear the peripheral's flag when it returns `Some`.
+pub fn wait_for<T, F>(interrupt: Option<Interrupt>, f: F) -> WaitFor<F>
+where
+    F: FnMut() -> Option<T> + Unpin,
+{
+    WaitFor { interrupt, f }
+}
+
+/// A flag that can be set from an interrupt handler, and awaited from the main loop.
+///
+/// Example:
+/// ```rust
+/// static ADC_DONE: Signal = Signal::new();
+///
+/// #[interrupt]
+/// fn ADC1_2() {
+///     // (Clear the interrupt flag here)
+///     ADC_DONE.signal();
+/// }
+///
+/// // In main:
+/// async_wfe::block_on(ADC_DONE.wait());
+/// ```
+pub struct Signal {
+    flag: AtomicBool,
+}
+
+impl Signal {
+    pub const fn new() -> Self {
+        Self {
+            flag: AtomicBool::new(false),
+        }
+    }
+
+    /// Set the flag, and send an event to wake the core from `WFE`.
+    pub fn signal(&self) {
+        self.flag.store(true, Ordering::Release);
+        asm::sev();
+    }
+
+    /// Returns true, and clears the flag if it's set.
+    pub fn take(&self) -> bool {
+        // Not all cores we support have atomic swap (eg Cortex-M0+ on G0), so use a critical section.
+        free(|_| {
+            let result = self.flag.load(Ordering::Acquire);
+            self.flag.store(false, Ordering::Release);
+            result
+        })
+    }
+
+    /// Create a future that completes (and clears the flag) once the flag is set.
+    pub fn wait(&self) -> WaitFor<impl FnMut() -> Option<()> + Unpin + '_> {
+        wait_for(None, move || if self.take() { Some(()) } else { None })
+    }
+}
+
+impl Default for Signal {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn noop_waker() -> Waker {
+    fn clone(_: *const ()) -> RawWaker {
+        RawWaker::new(core::ptr::null(), &VTABLE)
+    }
+    fn noop(_: *const ()) {}
+
+    static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+
+    unsafe { Waker::from_raw(RawWaker::new(core::ptr::null(), &VTABLE)) }
+}
+
+/// Run a future to completion, sleeping with `WFE` between polls. This sets the SEVONPEND bit,
+/// so pending interrupts wake the core, even if masked in the NVIC.
+pub fn block_on<F: Future>(mut fut: F) -> F::Output {
+    low_power::set_sev_on_pend(true);
+
+    let waker = noop_waker();
+    let mut cx = Context::from_waker(&waker);
+    // Safety: `fut` is shadowed, so it can't be moved after pinning.
+    let mut fut = unsafe { Pin::new_unchecked(&mut fut) };
+
+    loop {
+        if let Poll::Ready(v) = fut.as_mut().poll(&mut cx) {
+            return v;
+        }
+        // If the event occurred after polling, the event register is already set, and `WFE`
+        // returns immediately.
+        asm::wfe();
+    }
+}
+
+/// Poll a future once, without blocking. Useful in super-loops that check on several tasks in
+/// turn. Returns `None` if the future isn't ready yet.
+pub fn poll_once<F: Future>(fut: Pin<&mut F>) -> Option<F::Output> {
+    let waker = noop_waker();
+    let mut cx = Context::from_waker(&waker);
+
+    match fut.poll(&mut cx) {
+        Poll::Ready(v) => Some(v),
+        Poll::Pending => None,
+    }
+}
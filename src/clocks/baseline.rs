@@ -498,6 +498,21 @@ pub enum SaiSrc {
     ExtClk = 0b11,
 }
 
+#[derive(Clone, Copy, PartialEq)]
+#[repr(u8)]
+/// LPUART kernel clock source. Sets RCC_CCIPR register, LPUARTxSEL fields. HSI16 and LSE allow
+/// the LPUART to operate in Stop mode.
+pub enum LpuartSrc {
+    /// PCLK (APB1) selected as LPUART clock
+    Pclk = 0b00,
+    /// System clock (SYSCLK) selected as LPUART clock
+    Sysclk = 0b01,
+    /// HSI16 clock selected as LPUART clock
+    Hsi16 = 0b10,
+    /// LSE clock selected as LPUART clock
+    Lse = 0b11,
+}
+
 /// Settings used to configure clocks. Create this struct by using its `Default::default()`
 /// implementation, then modify as required, referencing your RM's clock tree,
 /// or Stm32Cube IDE's interactive clock manager. Apply settings by running `.setup()`.
@@ -532,8 +547,14 @@ pub struct Clocks {
     #[cfg(not(any(feature = "g0", feature = "wl")))]
     /// The input source for the 48Mhz clock used by USB.
     pub clk48_src: Clk48Src,
-    /// Bypass the HSE output, for use with oscillators that don't need it. Saves power, and
-    /// frees up the pin for use as GPIO.
+    #[cfg(not(feature = "l5"))]
+    /// Enable the flash prefetch buffer. Improves performance when running from flash with wait
+    /// states, at the cost of slightly higher power consumption.
+    pub flash_prefetch: bool,
+    /// Bypass the HSE oscillator, for use with an external clock source, such as a TCXO or
+    /// another MCU's clock output, fed into OSC_IN. Saves power, and frees up OSC_OUT for use
+    /// as GPIO. Note that G0 and G4 only support the analog bypass mode (HSEBYP); there is no
+    /// separate digital bypass setting on these families.
     pub hse_bypass: bool,
     pub security_system: bool,
     #[cfg(not(any(feature = "g0", feature = "wl")))]
@@ -542,12 +563,24 @@ pub struct Clocks {
     #[cfg(any(feature = "l4", feature = "l5", feature = "wb", feature = "wl"))]
     /// Select the input source to use after waking up from `stop` mode. Eg HSI or MSI.
     pub stop_wuck: StopWuck,
+    #[cfg(any(feature = "l4", feature = "l5"))]
+    /// Enable MSI PLL-mode, where the MSI is automatically calibrated using the LSE. Requires a
+    /// 32.768kHz crystal. This enables the LSE if it's not already running.
+    pub msi_pll: bool,
     #[cfg(feature = "wb")]
     /// Select the RF wakeup source.
     pub rf_wakeup_src: RfWakeupSrc,
     #[cfg(not(any(feature = "g0", feature = "g4", feature = "wl")))]
     /// SAI1 kernel clock source selection
     pub sai1_src: SaiSrc,
+    #[cfg(not(any(
+        feature = "g030",
+        feature = "g050",
+        feature = "g070",
+        feature = "g0b0"
+    )))]
+    /// LPUART kernel clock source selection. On G0, this applies to both LPUART1 and LPUART2.
+    pub lpuart_src: LpuartSrc,
 }
 
 // todo: On L4/5, add a way to enable the MSI for use as CLK48.
@@ -572,103 +605,28 @@ impl Clocks {
         rcc_en_reset!(apb2, syscfg, rcc);
 
         // Adjust flash wait states according to the HCLK frequency.
-        // We need to do this before enabling PLL, or it won't enable.
-        let sysclk = self.sysclk();
-
-        cfg_if! {
-            if #[cfg(feature = "wb")] {
-                let hclk = sysclk / self.hclk4_prescaler.value() as u32;
-            } else if #[cfg(feature = "wl")] {
-                let hclk = sysclk / self.hclk3_prescaler.value() as u32;
-            } else {
-                let hclk = sysclk / self.hclk_prescaler.value() as u32;
-            }
+        let wait_state = self.wait_state();
+
+        // L4 RM, Increasing the CPU frequency: "Program the new number of wait states to the LATENCY
+        // bits in the Flash access control register (FLASH_ACR). Check that the new number of wait
+        // states is taken into account to access the Flash memory by reading the FLASH_ACR register."
+        // Decreasing the CPU frequency: "Modify the CPU clock source by writing the SW bits in the
+        // RCC_CFGR register. [...] Program the new number of wait states." So, set wait states
+        // now if increasing, and after the clock switch if decreasing.
+        let wait_states_increasing = wait_state as u8 > flash.acr.read().latency().bits();
+        if wait_states_increasing {
+            set_wait_states(wait_state);
         }
 
-        // TODO: these are only implemented for Vcore Rnage 1 (Normal mode as applicable)
-        // todo: Other modes, like MODE 2 (For lower max system clocks) on L4.
-
-        cfg_if! {
-            if #[cfg(feature = "l4")] {  // RM section 3.3.3
-                flash.acr.modify(|_, w| unsafe {
-                    if hclk <= 16_000_000 {
-                        w.latency().bits(WaitState::W0 as u8)
-                    } else if hclk <= 32_000_000 {
-                        w.latency().bits(WaitState::W1 as u8)
-                    } else if hclk <= 48_000_000 {
-                        w.latency().bits(WaitState::W2 as u8)
-                    } else if hclk <= 64_000_000 {
-                        w.latency().bits(WaitState::W3 as u8)
-                    } else {
-                        w.latency().bits(WaitState::W4 as u8)
-                    }
-                });
-            } else if #[cfg(feature = "l5")] {  // RM section 6.3.3
-                flash.acr.modify(|_, w| unsafe {
-                    if hclk <= 20_000_000 {
-                        w.latency().bits(WaitState::W0 as u8)
-                    } else if hclk <= 40_000_000 {
-                        w.latency().bits(WaitState::W1 as u8)
-                    } else if hclk <= 60_000_000 {
-                        w.latency().bits(WaitState::W2 as u8)
-                    } else if hclk <= 80_000_000 {
-                        w.latency().bits(WaitState::W3 as u8)
-                    } else if hclk <= 100_000_000 {
-                        w.latency().bits(WaitState::W4 as u8)
-                    } else {
-                        w.latency().bits(WaitState::W5 as u8)
-                    }
-                });
-            } else if #[cfg(feature = "g0")] {  // G0. RM section 3.3.4
-                flash.acr.modify(|_, w| unsafe {
-                    if hclk <= 24_000_000 {
-                        w.latency().bits(WaitState::W0 as u8)
-                    } else if hclk <= 48_000_000 {
-                        w.latency().bits(WaitState::W1 as u8)
-                    } else {
-                        w.latency().bits(WaitState::W2 as u8)
-                    }
-                });
-            } else if #[cfg(feature = "wb")] {  // WB. RM section 3.3.4, Table 4.
-            // Note: This applies to HCLK4 HCLK. (See HCLK4 used above for hclk var.)
-                flash.acr.modify(|_, w| unsafe {
-                    if hclk <= 18_000_000 {
-                        w.latency().bits(WaitState::W0 as u8)
-                    } else if hclk <= 36_000_000 {
-                        w.latency().bits(WaitState::W1 as u8)
-                    } else if hclk <= 54_000_000 {
-                        w.latency().bits(WaitState::W2 as u8)
-                    } else {
-                        w.latency().bits(WaitState::W3 as u8)
-                    }
-                });
-            } else if #[cfg(any(feature = "wb", feature = "wl"))] {  // WL. RM section 3.3.4, Table 5.
-            // Note: This applies to HCLK3 HCLK. (See HCLK3 used above for hclk var.)
-                flash.acr.modify(|_, w| unsafe {
-                    if hclk <= 18_000_000 {
-                        w.latency().bits(WaitState::W0 as u8)
-                    } else if hclk <= 36_000_000 {
-                        w.latency().bits(WaitState::W1 as u8)
-                    } else {
-                        w.latency().bits(WaitState::W2 as u8)
-                    }
-                });
-            } else {  // G4. RM section 3.3.3
-                flash.acr.modify(|_, w| unsafe {
-                    if hclk <= 34_000_000 {
-                        w.latency().bits(WaitState::W0 as u8)
-                    } else if hclk <= 68_000_000 {
-                        w.latency().bits(WaitState::W1 as u8)
-                    } else if hclk <= 102_000_000 {
-                        w.latency().bits(WaitState::W2 as u8)
-                    } else if hclk <= 136_000_000 {
-                        w.latency().bits(WaitState::W3 as u8)
-                    } else {
-                        w.latency().bits(WaitState::W4 as u8)
-                    }
-                });
-            }
-        }
+        // Enable the prefetch buffer if configured, and the instruction and data caches.
+        // L5 has no prefetch buffer or ART caches in FLASH_ACR; it uses the separate ICACHE peripheral.
+        #[cfg(not(feature = "l5"))]
+        flash.acr.modify(|_, w| {
+            w.prften().bit(self.flash_prefetch);
+            #[cfg(not(feature = "g0"))]
+            w.dcen().set_bit();
+            w.icen().set_bit()
+        });
 
         // Reference Manual, 6.2.5:
         // The device embeds 3 PLLs: PLL, PLLSAI1, PLLSAI2. Each PLL provides up to three
@@ -688,6 +646,17 @@ impl Clocks {
         // 5. Enable the desired PLL outputs by configuring PLLPEN, PLLQEN, PLLREN in PLL
         // configuration register (RCC_PLLCFGR).
 
+        // RM: "The HSEBYP bit can be written only if the HSE oscillator is disabled." Set it
+        // before enabling the HSE below; if the HSE is already running, it's left as-is.
+        if rcc.cr.read().hseon().bit_is_clear() {
+            rcc.cr.modify(|_, w| {
+                #[cfg(feature = "wl")]
+                return w.hsebyppwr().bit(self.hse_bypass);
+                #[cfg(not(feature = "wl"))]
+                w.hsebyp().bit(self.hse_bypass)
+            });
+        }
+
         // Enable oscillators, and wait until ready.
         match self.input_src {
             #[cfg(not(any(feature = "g0", feature = "g4")))]
@@ -705,7 +674,11 @@ impl Clocks {
                 });
                 // Wait for the MSI to be ready.
                 while rcc.cr.read().msirdy().bit_is_clear() {}
-                // todo: If LSE is enabled, calibrate MSI.
+
+                #[cfg(any(feature = "l4", feature = "l5"))]
+                if self.msi_pll {
+                    enable_msi_pll();
+                }
             }
             InputSrc::Hse(_) => {
                 rcc.cr.modify(|_, w| w.hseon().set_bit());
@@ -728,6 +701,11 @@ impl Clocks {
                             w.msion().set_bit()
                         });
                         while rcc.cr.read().msirdy().bit_is_clear() {}
+
+                        #[cfg(any(feature = "l4", feature = "l5"))]
+                        if self.msi_pll {
+                            enable_msi_pll();
+                        }
                     }
                     PllSrc::Hse(_) => {
                         rcc.cr.modify(|_, w| w.hseon().set_bit());
@@ -747,19 +725,13 @@ impl Clocks {
             }
             #[cfg(feature = "g0")]
             InputSrc::Lse => {
+                // LSEON is in the backup domain, which is write-protected.
+                let _bd = crate::power::enable_backup_domain_access();
                 rcc.bdcr.modify(|_, w| w.lseon().set_bit());
                 while rcc.bdcr.read().lserdy().bit_is_clear() {}
             }
         }
 
-        rcc.cr.modify(|_, w| {
-            // Enable bypass mode on HSE, since we're using a ceramic oscillator.
-            #[cfg(feature = "wl")]
-            return w.hsebyppwr().bit(self.hse_bypass);
-            #[cfg(not(feature = "wl"))]
-            w.hsebyp().bit(self.hse_bypass)
-        });
-
         rcc.cfgr.modify(|_, w| unsafe {
             w.sw().bits(self.input_src.bits());
             w.hpre().bits(self.hclk_prescaler as u8);
@@ -917,6 +889,26 @@ impl Clocks {
         rcc.ccipr2
             .modify(|_, w| unsafe { w.sai1sel().bits(self.sai1_src as u8) });
 
+        cfg_if! {
+            if #[cfg(feature = "l5")] {
+                rcc.ccipr1
+                    .modify(|_, w| unsafe { w.lpuart1sel().bits(self.lpuart_src as u8) });
+            } else if #[cfg(any(feature = "g0b1", feature = "g0c1"))] {
+                rcc.ccipr.modify(|_, w| unsafe {
+                    w.lpuart1sel().bits(self.lpuart_src as u8);
+                    w.lpuart2sel().bits(self.lpuart_src as u8)
+                });
+            } else if #[cfg(not(any(
+                feature = "g030",
+                feature = "g050",
+                feature = "g070",
+                feature = "g0b0"
+            )))] {
+                rcc.ccipr
+                    .modify(|_, w| unsafe { w.lpuart1sel().bits(self.lpuart_src as u8) });
+            }
+        }
+
         // If we're not using the default clock source as input source or for PLL, turn it off.
         cfg_if! {
             if #[cfg(any(feature = "l4", feature = "l5"))] {
@@ -957,14 +949,117 @@ impl Clocks {
         rcc.csr
             .modify(|_, w| unsafe { w.rfwkpsel().bits(self.rf_wakeup_src as u8) });
 
+        if !wait_states_increasing {
+            // Wait until the new clock source is in use before reducing wait states.
+            while rcc.cfgr.read().sws().bits() != self.input_src.bits() {}
+            set_wait_states(wait_state);
+        }
+
         Ok(())
     }
 
+    /// Find the flash wait states required for the configured HCLK frequency.
+    fn wait_state(&self) -> WaitState {
+        let sysclk = self.sysclk();
+
+        cfg_if! {
+            if #[cfg(feature = "wb")] {
+                let hclk = sysclk / self.hclk4_prescaler.value() as u32;
+            } else if #[cfg(feature = "wl")] {
+                let hclk = sysclk / self.hclk3_prescaler.value() as u32;
+            } else {
+                let hclk = sysclk / self.hclk_prescaler.value() as u32;
+            }
+        }
+
+        // TODO: these are only implemented for Vcore Rnage 1 (Normal mode as applicable)
+        // todo: Other modes, like MODE 2 (For lower max system clocks) on L4.
+
+        cfg_if! {
+            if #[cfg(feature = "l4")] {  // RM section 3.3.3
+                let wait_state = if hclk <= 16_000_000 {
+                    WaitState::W0
+                } else if hclk <= 32_000_000 {
+                    WaitState::W1
+                } else if hclk <= 48_000_000 {
+                    WaitState::W2
+                } else if hclk <= 64_000_000 {
+                    WaitState::W3
+                } else {
+                    WaitState::W4
+                };
+            } else if #[cfg(feature = "l5")] {  // RM section 6.3.3
+                let wait_state = if hclk <= 20_000_000 {
+                    WaitState::W0
+                } else if hclk <= 40_000_000 {
+                    WaitState::W1
+                } else if hclk <= 60_000_000 {
+                    WaitState::W2
+                } else if hclk <= 80_000_000 {
+                    WaitState::W3
+                } else if hclk <= 100_000_000 {
+                    WaitState::W4
+                } else {
+                    WaitState::W5
+                };
+            } else if #[cfg(feature = "g0")] {  // G0. RM section 3.3.4
+                let wait_state = if hclk <= 24_000_000 {
+                    WaitState::W0
+                } else if hclk <= 48_000_000 {
+                    WaitState::W1
+                } else {
+                    WaitState::W2
+                };
+            } else if #[cfg(feature = "wb")] {  // WB. RM section 3.3.4, Table 4.
+            // Note: This applies to HCLK4 HCLK. (See HCLK4 used above for hclk var.)
+                let wait_state = if hclk <= 18_000_000 {
+                    WaitState::W0
+                } else if hclk <= 36_000_000 {
+                    WaitState::W1
+                } else if hclk <= 54_000_000 {
+                    WaitState::W2
+                } else {
+                    WaitState::W3
+                };
+            } else if #[cfg(any(feature = "wb", feature = "wl"))] {  // WL. RM section 3.3.4, Table 5.
+            // Note: This applies to HCLK3 HCLK. (See HCLK3 used above for hclk var.)
+                let wait_state = if hclk <= 18_000_000 {
+                    WaitState::W0
+                } else if hclk <= 36_000_000 {
+                    WaitState::W1
+                } else {
+                    WaitState::W2
+                };
+            } else {  // G4. RM section 3.3.3
+                let wait_state = if hclk <= 34_000_000 {
+                    WaitState::W0
+                } else if hclk <= 68_000_000 {
+                    WaitState::W1
+                } else if hclk <= 102_000_000 {
+                    WaitState::W2
+                } else if hclk <= 136_000_000 {
+                    WaitState::W3
+                } else {
+                    WaitState::W4
+                };
+            }
+        }
+
+        wait_state
+    }
+
     /// Re-select input source; used after Stop and Standby modes, where the system reverts
     /// to MSI or HSI after wake.
     pub fn reselect_input(&self) {
         let rcc = unsafe { &(*RCC::ptr()) };
 
+        // MSIPLLEN is cleared by hardware if the LSE clock security system detects a failure.
+        // Re-enable it before the MSI is re-selected, so it resumes tracking the LSE.
+        #[cfg(any(feature = "l4", feature = "l5"))]
+        if self.msi_pll && rcc.cr.read().msipllen().bit_is_clear() {
+            enable_msi_pll();
+        }
+
         // Re-select the input source; useful for changing input source, or reverting
         // from stop or standby mode. This assumes we're on a clean init,
         // or waking up from stop mode etc.
@@ -1073,6 +1168,8 @@ impl Clocks {
             }
             #[cfg(feature = "g0")]
             InputSrc::Lse => {
+                // LSEON is in the backup domain, which is write-protected.
+                let _bd = crate::power::enable_backup_domain_access();
                 rcc.bdcr.modify(|_, w| w.lseon().set_bit());
                 while rcc.bdcr.read().lserdy().bit_is_clear() {}
                 rcc.cfgr
@@ -1099,11 +1196,24 @@ impl Clocks {
         // So, we can change MSI range while it's running.
         while rcc.cr.read().msirdy().bit_is_clear() {}
 
-        rcc.cr
-            .modify(|_, w| unsafe { w.msirange().bits(range as u8).msirgsel().set_bit() });
+        let flash = unsafe { &(*FLASH::ptr()) };
+        let wait_state_prev = flash.acr.read().latency().bits();
 
         // Update our config to reflect the new speed.
         self.input_src = InputSrc::Msi(range);
+
+        // Set wait states before increasing the speed, or after decreasing it.
+        let wait_state = self.wait_state();
+        if wait_state as u8 > wait_state_prev {
+            set_wait_states(wait_state);
+        }
+
+        rcc.cr
+            .modify(|_, w| unsafe { w.msirange().bits(range as u8).msirgsel().set_bit() });
+
+        if (wait_state as u8) < wait_state_prev {
+            set_wait_states(wait_state);
+        }
     }
 
     #[cfg(any(feature = "l4", feature = "l5"))]
@@ -1228,6 +1338,22 @@ impl Clocks {
         self.hclk() / self.apb1_prescaler.value() as u32
     }
 
+    #[cfg(not(any(
+        feature = "g030",
+        feature = "g050",
+        feature = "g070",
+        feature = "g0b0"
+    )))]
+    /// Get the LPUART kernel clock frequency, in hz
+    pub fn lpuart(&self) -> u32 {
+        match self.lpuart_src {
+            LpuartSrc::Pclk => self.apb1(),
+            LpuartSrc::Sysclk => self.sysclk(),
+            LpuartSrc::Hsi16 => 16_000_000,
+            LpuartSrc::Lse => 32_768,
+        }
+    }
+
     /// Get the frequency used by APB1 timers, in hz
     pub fn apb1_timer(&self) -> u32 {
         // L4 RM, 6.2.14: The timer clock frequencies are automatically defined by hardware. There are two cases:
@@ -1398,20 +1524,60 @@ impl Default for Clocks {
             apb2_prescaler: ApbPrescaler::Div1,
             #[cfg(not(any(feature = "g0", feature = "wl")))]
             clk48_src: Clk48Src::Hsi48,
+            #[cfg(not(feature = "l5"))]
+            flash_prefetch: true,
             hse_bypass: false,
             security_system: false,
             #[cfg(not(any(feature = "g0", feature = "wl")))]
             hsi48_on: false,
             #[cfg(any(feature = "l4", feature = "l5", feature = "wb", feature = "wl"))]
             stop_wuck: StopWuck::Msi,
+            #[cfg(any(feature = "l4", feature = "l5"))]
+            msi_pll: false,
             #[cfg(feature = "wb")]
             rf_wakeup_src: RfWakeupSrc::Lse,
             #[cfg(not(any(feature = "g0", feature = "g4", feature = "wl")))]
             sai1_src: SaiSrc::Pllp,
+            #[cfg(not(any(
+                feature = "g030",
+                feature = "g050",
+                feature = "g070",
+                feature = "g0b0"
+            )))]
+            lpuart_src: LpuartSrc::Pclk,
         }
     }
 }
 
+/// Set the flash wait states (FLASH_ACR, LATENCY field), and wait until the new value is in effect.
+fn set_wait_states(wait_state: WaitState) {
+    let flash = unsafe { &(*FLASH::ptr()) };
+
+    flash
+        .acr
+        .modify(|_, w| unsafe { w.latency().bits(wait_state as u8) });
+    while flash.acr.read().latency().bits() != wait_state as u8 {}
+}
+
+#[cfg(any(feature = "l4", feature = "l5"))]
+/// Enable the LSE if it's not already running, then enable MSI PLL-mode. L44 RM, section 6.2.3:
+/// "When a 32.768 kHz external oscillator is present in the application, it is possible to configure
+/// the MSI in a PLL-mode by setting the MSIPLLEN bit in the Clock control register (RCC_CR).
+/// When configured in PLL-mode, the MSI automatically calibrates itself thanks to the LSE."
+/// MSIPLLEN must be enabled after LSE is enabled (LSEON=1) and ready (LSERDY=1).
+fn enable_msi_pll() {
+    let rcc = unsafe { &(*RCC::ptr()) };
+
+    if rcc.bdcr.read().lserdy().bit_is_clear() {
+        // LSEON is in the backup domain, which is write-protected.
+        let _bd = crate::power::enable_backup_domain_access();
+        rcc.bdcr.modify(|_, w| w.lseon().set_bit());
+        while rcc.bdcr.read().lserdy().bit_is_clear() {}
+    }
+
+    rcc.cr.modify(|_, w| w.msipllen().set_bit());
+}
+
 #[cfg(any(feature = "l4", feature = "l5", feature = "g4", feature = "wb"))]
 /// Enable the Clock Recovery System. L443 User manual:
 /// "The STM32L443xx devices embed a special block which allows automatic trimming of the
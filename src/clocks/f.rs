@@ -380,8 +380,12 @@ pub struct Clocks {
     pub apb1_prescaler: ApbPrescaler,
     /// The divider of HCLK to get the APB2 peripheral clock
     pub apb2_prescaler: ApbPrescaler,
-    /// Bypass the HSE output, for use with oscillators that don't need it. Saves power, and
-    /// frees up the pin for use as GPIO.
+    /// Enable the flash prefetch buffer. Improves performance when running from flash with wait
+    /// states, at the cost of slightly higher power consumption.
+    pub flash_prefetch: bool,
+    /// Bypass the HSE oscillator, for use with an external clock source, such as a TCXO or
+    /// another MCU's clock output, fed into OSC_IN. Saves power, and frees up OSC_OUT for use
+    /// as GPIO.
     pub hse_bypass: bool,
     pub security_system: bool,
 }
@@ -400,41 +404,32 @@ impl Clocks {
         let flash = unsafe { &(*FLASH::ptr()) };
 
         // Adjust flash wait states according to the HCLK frequency.
-        // We need to do this before enabling PLL, or it won't enable.
-        let sysclk = self.sysclk();
-
-        // todo: We don't yet take into account other voltage settings for f4 wait states.
-        let hclk = sysclk / self.hclk_prescaler.value() as u32;
-        cfg_if! {
-            if #[cfg(feature = "f3")] {  // RM section 4.5.1
-                flash.acr.modify(|_, w| unsafe {
-                    if hclk <= 24_000_000 {
-                        w.latency().bits(WaitState::W0 as u8)
-                    } else if hclk <= 48_000_000 {
-                        w.latency().bits(WaitState::W1 as u8)
-                    } else {
-                        w.latency().bits(WaitState::W2 as u8)
-                    }
-                });
-            } else {  // F4
-                flash.acr.modify(|_, w| unsafe {
-                    if hclk <= 30_000_000 {
-                        w.latency().bits(WaitState::W0 as u8)
-                    } else if hclk <= 60_000_000 {
-                        w.latency().bits(WaitState::W1 as u8)
-                    } else if hclk <= 90_000_000 {
-                        w.latency().bits(WaitState::W2 as u8)
-                    } else if hclk <= 120_000_000 {
-                        w.latency().bits(WaitState::W3 as u8)
-                    } else if hclk <= 150_000_000 {
-                        w.latency().bits(WaitState::W4 as u8)
-                    } else {
-                        w.latency().bits(WaitState::W5 as u8)
-                    }
-                });
-            }
+        let wait_state = self.wait_state();
+
+        // F303 RM, Flash memory: "To correctly read data from Flash memory, the number of wait
+        // states (LATENCY) must be correctly programmed in the Flash access control register
+        // (FLASH_ACR) according to the frequency of the CPU clock (HCLK) and the supply voltage
+        // of the device." Set wait states now if increasing the frequency, and after the clock
+        // switch if decreasing it.
+        let wait_states_increasing = wait_state as u8 > flash.acr.read().latency().bits();
+        if wait_states_increasing {
+            set_wait_states(wait_state);
         }
 
+        // Enable the prefetch buffer if configured, and (F4) the instruction and data caches.
+        flash.acr.modify(|_, w| {
+            #[cfg(feature = "f3")]
+            return w.prftbe().bit(self.flash_prefetch);
+            #[cfg(feature = "f4")]
+            return w
+                .prften()
+                .bit(self.flash_prefetch)
+                .dcen()
+                .set_bit()
+                .icen()
+                .set_bit();
+        });
+
         // 303 RM, 9.2.3:
         // The internal PLL can be used to multiply the HSI or HSE output clock frequency. Refer to
         // Figure 13 and Clock control register (RCC_CR).
@@ -450,6 +445,12 @@ impl Clocks {
         // The PLL output frequency must be set in the range 16-72 MHz.
         // Set up the HSE if required.
 
+        // RM: "The HSEBYP bit can be written only if the HSE oscillator is disabled." Set it
+        // before enabling the HSE below; if the HSE is already running, it's left as-is.
+        if rcc.cr.read().hseon().bit_is_clear() {
+            rcc.cr.modify(|_, w| w.hsebyp().bit(self.hse_bypass));
+        }
+
         // Enable oscillators, and wait until ready.
         match self.input_src {
             InputSrc::Hse(_) => {
@@ -476,11 +477,6 @@ impl Clocks {
                 }
             }
         }
-        rcc.cr.modify(|_, w| {
-            // Enable bypass mode on HSE, since we're using a ceramic oscillator.
-            w.hsebyp().bit(self.hse_bypass)
-        });
-
         if let InputSrc::Pll(pll_src) = self.input_src {
             // Turn off the PLL: Required for modifying some of the settings below.
             rcc.cr.modify(|_, w| w.pllon().off());
@@ -552,9 +548,50 @@ impl Clocks {
         // todo: Is this the right module to do this in?
         rcc_en_reset!(apb2, syscfg, rcc);
 
+        if !wait_states_increasing {
+            // Wait until the new clock source is in use before reducing wait states.
+            while rcc.cfgr.read().sws().bits() != self.input_src.bits() {}
+            set_wait_states(wait_state);
+        }
+
         Ok(())
     }
 
+    /// Find the flash wait states required for the configured HCLK frequency.
+    fn wait_state(&self) -> WaitState {
+        let sysclk = self.sysclk();
+
+        // todo: We don't yet take into account other voltage settings for f4 wait states.
+        let hclk = sysclk / self.hclk_prescaler.value() as u32;
+        cfg_if! {
+            if #[cfg(feature = "f3")] {  // RM section 4.5.1
+                let wait_state = if hclk <= 24_000_000 {
+                    WaitState::W0
+                } else if hclk <= 48_000_000 {
+                    WaitState::W1
+                } else {
+                    WaitState::W2
+                };
+            } else {  // F4
+                let wait_state = if hclk <= 30_000_000 {
+                    WaitState::W0
+                } else if hclk <= 60_000_000 {
+                    WaitState::W1
+                } else if hclk <= 90_000_000 {
+                    WaitState::W2
+                } else if hclk <= 120_000_000 {
+                    WaitState::W3
+                } else if hclk <= 150_000_000 {
+                    WaitState::W4
+                } else {
+                    WaitState::W5
+                };
+            }
+        }
+
+        wait_state
+    }
+
     /// Re-select innput source; used on Stop and Standby modes, where the system reverts
     /// to HSI after wake.
     pub fn reselect_input(&self) {
@@ -738,6 +775,7 @@ impl Default for Clocks {
             hclk_prescaler: HclkPrescaler::Div1,
             apb1_prescaler: ApbPrescaler::Div2,
             apb2_prescaler: ApbPrescaler::Div1,
+            flash_prefetch: true,
             hse_bypass: false,
             security_system: false,
         }
@@ -768,8 +806,19 @@ impl Default for Clocks {
             apb2_prescaler: ApbPrescaler::Div1,
             #[cfg(not(any(feature = "f401", feature = "f410", feature = "f411")))]
             apb2_prescaler: ApbPrescaler::Div2,
+            flash_prefetch: true,
             hse_bypass: false,
             security_system: false,
         }
     }
 }
+
+/// Set the flash wait states (FLASH_ACR, LATENCY field), and wait until the new value is in effect.
+fn set_wait_states(wait_state: WaitState) {
+    let flash = unsafe { &(*FLASH::ptr()) };
+
+    flash
+        .acr
+        .modify(|_, w| unsafe { w.latency().bits(wait_state as u8) });
+    while flash.acr.read().latency().bits() != wait_state as u8 {}
+}
@@ -9,6 +9,12 @@ use crate::{
     pac::{FLASH, PWR, RCC, SYSCFG},
 };
 
+#[cfg(any(feature = "h747cm4", feature = "h747cm7"))]
+use crate::power::SupplyConfig;
+
+#[cfg(any(feature = "h747cm4", feature = "h747cm7", feature = "h7b3"))]
+use crate::power::VoltageLevel;
+
 use cfg_if::cfg_if;
 
 #[derive(Clone, Copy, PartialEq)]
@@ -80,7 +86,6 @@ impl InputSrc {
 /// field has no effect for PLL1.
 pub struct PllCfg {
     pub enabled: bool,
-    // pub fractional: bool,
     pub pllp_en: bool,
     pub pllq_en: bool,
     pub pllr_en: bool,
@@ -89,6 +94,11 @@ pub struct PllCfg {
     pub divp: u8,
     pub divq: u8,
     pub divr: u8,
+    /// Fractional part of the multiplication factor, from 0 to 8191. The VCO runs at
+    /// `refx_ck x (DIVN + FRACN / 2^13)`. If nonzero, the PLL is put in fractional mode, which
+    /// allows hitting audio sample rates like 44.1kHz and 48kHz exactly. Sets RCC_PLLxFRACR
+    /// register, FRACNx field.
+    pub fracn: u16,
 }
 
 impl Default for PllCfg {
@@ -96,7 +106,6 @@ impl Default for PllCfg {
         // todo: Different defaults for different variants.
         Self {
             enabled: true,
-            // fractional: false,
             pllp_en: true,
             pllq_en: false,
             pllr_en: false,
@@ -106,6 +115,7 @@ impl Default for PllCfg {
             divp: 2,
             divq: 2, // Allows <150Mhz SAI clock, if it's configureud for PLL1Q.
             divr: 2,
+            fracn: 0,
         }
     }
 }
@@ -308,9 +318,19 @@ pub struct Clocks {
     pub d2_prescaler2: ApbPrescaler,
     /// APB4 peripheral clocks
     pub d3_prescaler: ApbPrescaler,
-    /// Bypass the HSE output, for use with oscillators that don't need it. Saves power, and
-    /// frees up the pin for use as GPIO.
+    /// Bypass the HSE oscillator, for use with an external clock source, such as a TCXO or
+    /// another MCU's clock output, fed into OSC_IN. Saves power, and frees up OSC_OUT for use
+    /// as GPIO.
     pub hse_bypass: bool,
+    #[cfg(feature = "h7b3")]
+    /// When `hse_bypass` is set, select a digital (square wave) external clock instead of an
+    /// analog (eg sine wave) one. Sets RCC_CR register, HSEEXT field.
+    pub hse_bypass_digital: bool,
+    #[cfg(any(feature = "h747cm4", feature = "h747cm7", feature = "h7b3"))]
+    /// The SMPS step-down converter's output level, when it supplies the LDO, ie with the
+    /// `h7_smps_ldo` feature. Sets the PWR_CR3 register, SDLEVEL field (SMPSLEVEL on H7B3).
+    /// Defaults to 1.8V.
+    pub smps_level: VoltageLevel,
     pub security_system: bool,
     pub hsi48_on: bool,
     pub stop_wuck: StopWuck,
@@ -330,8 +350,9 @@ impl Clocks {
     /// are invalid.
     /// Use the STM32CubeIDE Clock Configuration tab to identify valid configs.
     /// Use the `default()` implementation as a safe baseline.
-    /// This method also configures the PWR VOS setting, and can be used to enable VOS boost,
-    /// if `vos_range` is set to `VosRange::VOS0`.
+    /// This method also applies the supply configuration selected by the `h7_smps`, `h7_smps_ldo`,
+    /// or `h7_bypass` features, and configures the PWR VOS setting. It can be used to enable
+    /// VOS boost, if `vos_range` is set to `VosRange::VOS0`.
     pub fn setup(&self) -> Result<(), SpeedError> {
         if let Err(e) = self.validate_speeds() {
             return Err(e);
@@ -342,11 +363,65 @@ impl Clocks {
         let pwr = unsafe { &(*PWR::ptr()) };
         let syscfg = unsafe { &(*SYSCFG::ptr()) };
 
-        // Enable and reset System Configuration Controller, ie for interrupts.
+        // Enable the System Configuration Controller, ie for interrupts, and the VOS0 ODEN bit.
+        // We don't reset it: That would clear ODEN, leaving VOS0 before the system frequency is
+        // lowered, and would lose the state the deactivation sequence below checks.
         // todo: Is this the right module to do this in?
         rcc.apb4enr.modify(|_, w| w.syscfgen().set_bit());
-        rcc.apb4rstr.modify(|_, w| w.syscfgrst().set_bit());
-        rcc.apb4rstr.modify(|_, w| w.syscfgrst().clear_bit());
+
+        // RM0399, section 7.4: Power supply configuration. The supply configuration must be
+        // written once after a power-on reset, before changing the voltage scaling; it can't be
+        // changed again until the next POR. The topology is selected at compile time, using the
+        // `h7_smps`, `h7_smps_ldo` and `h7_bypass` features. The LDO is used if none are set.
+        cfg_if! {
+            if #[cfg(any(feature = "h747cm4", feature = "h747cm7"))] {
+                let supply = if cfg!(feature = "h7_bypass") {
+                    // LDO and SMPS disabled; VCORE is supplied externally.
+                    SupplyConfig::SmpsStepdownDisabledBypass
+                } else if cfg!(feature = "h7_smps") {
+                    SupplyConfig::DirectSmps
+                } else if cfg!(feature = "h7_smps_ldo") {
+                    SupplyConfig::SmpsStepdownLdo
+                } else {
+                    SupplyConfig::Ldo
+                };
+                supply.setup(pwr, self.smps_level);
+            } else if #[cfg(feature = "h7_bypass")] {
+                pwr.cr3.modify(|_, w| {
+                    #[cfg(feature = "h7b3")]
+                    w.smpsen().clear_bit();
+                    w.ldoen().clear_bit();
+                    w.bypass().set_bit()
+                });
+            } else if #[cfg(all(feature = "h7b3", feature = "h7_smps"))] {
+                pwr.cr3.modify(|_, w| {
+                    w.smpsexthp().clear_bit();
+                    w.smpsen().set_bit();
+                    w.ldoen().clear_bit();
+                    w.bypass().clear_bit()
+                });
+            } else if #[cfg(all(feature = "h7b3", feature = "h7_smps_ldo"))] {
+                pwr.cr3.modify(|_, w| unsafe {
+                    w.smpslevel().bits(self.smps_level as u8);
+                    w.smpsexthp().clear_bit();
+                    w.smpsen().set_bit();
+                    w.ldoen().set_bit();
+                    w.bypass().clear_bit()
+                });
+            } else {
+                // LDO supply.
+                pwr.cr3.modify(|_, w| {
+                    #[cfg(feature = "h7b3")]
+                    w.smpsen().clear_bit();
+                    w.ldoen().set_bit();
+                    w.bypass().clear_bit()
+                });
+            }
+        }
+
+        // Wait until the voltage level for the currently used VOS is ready, ie the supply
+        // configuration has been applied.
+        while pwr.csr1.read().actvosrdy().bit_is_clear() {}
 
         // H743 RM, sefction 6.8.6, and section 6.6.2: Voltage Scaling
         //  Voltage scaling selection according to performance
@@ -396,19 +471,38 @@ impl Clocks {
                 // in the RCC_APB4ENR register.
                 // 3. Reset the ODEN bit in the SYSCFG_PWRCR register to disable VOS0.
             }
-            _ => pwr
-                .d3cr
-                .modify(|_, w| unsafe { w.vos().bits(self.vos_range as u8) }),
+            _ => {
+                // Raise the voltage scale now if increasing it. If decreasing it, including leaving
+                // VOS0, this happens after the clock switch and wait state reduction below.
+                if self.vos_range as u8 > pwr.d3cr.read().vos().bits() {
+                    pwr.d3cr
+                        .modify(|_, w| unsafe { w.vos().bits(self.vos_range as u8) });
+                    while pwr.d3cr.read().vosrdy().bit_is_clear() {}
+                }
+            }
         }
 
-        // Adjust flash wait states according to the HCLK frequency.
-        // We need to do this before enabling PLL, or it won't enable.
+        // Adjust flash wait states according to the HCLK frequency, and voltage scale.
         // H742 RM, Table 17.
         let wait_states = self.vos_range.wait_states(self.hclk());
-        flash.acr.modify(|_, w| unsafe {
-            w.latency().bits(wait_states.0);
-            w.wrhighfreq().bits(wait_states.1)
-        });
+
+        // Set wait states now if increasing the frequency, and after the clock switch if
+        // decreasing it; the flash must be able to keep up with AXI clock at all times.
+        let wait_states_increasing = wait_states.0 > flash.acr.read().latency().bits();
+        if wait_states_increasing {
+            set_wait_states(wait_states);
+        }
+
+        // RM: "The HSEBYP bit can be written only if the HSE oscillator is disabled." The same
+        // applies to HSEEXT. Set them before enabling the HSE below; if the HSE is already running,
+        // they're left as-is.
+        if rcc.cr.read().hseon().bit_is_clear() {
+            rcc.cr.modify(|_, w| {
+                #[cfg(feature = "h7b3")]
+                w.hseext().bit(self.hse_bypass_digital);
+                w.hsebyp().bit(self.hse_bypass)
+            });
+        }
 
         // Enable oscillators, and wait until ready.
         match self.input_src {
@@ -451,11 +545,6 @@ impl Clocks {
             }
         }
 
-        rcc.cr.modify(|_, w| {
-            // Enable bypass mode on HSE, since we're using a ceramic oscillator.
-            w.hsebyp().bit(self.hse_bypass)
-        });
-
         rcc.cfgr.modify(|_, w| unsafe {
             w.sw().bits(self.input_src.bits());
             w.stopwuck().bit(self.stop_wuck as u8 != 0)
@@ -498,8 +587,6 @@ impl Clocks {
 
         rcc.cr.modify(|_, w| w.hsecsson().bit(self.security_system));
 
-        // todo: Allow configuring the PLL in fractional mode.
-
         rcc.pllckselr
             .modify(|_, w| w.pllsrc().bits(self.pll_src.bits()));
 
@@ -566,6 +653,14 @@ impl Clocks {
                 w.divr1().bits(self.pll1.divr - 1)
             });
 
+            // H743 RM, RCC_PLLxFRACR register: FRACN can only be loaded while PLLxFRACEN is 0; setting it
+            // to 1 latches the value into the sigma-delta modulator.
+            rcc.pllcfgr.modify(|_, w| w.pll1fracen().clear_bit());
+            rcc.pll1fracr
+                .modify(|_, w| unsafe { w.fracn1().bits(self.pll1.fracn) });
+            rcc.pllcfgr
+                .modify(|_, w| w.pll1fracen().bit(self.pll1.fracn != 0));
+
             // Now turn PLL back on, once we're configured things that can only be set with it off.
             rcc.cr.modify(|_, w| w.pll1on().set_bit());
             while rcc.cr.read().pll1rdy().bit_is_clear() {}
@@ -606,6 +701,14 @@ impl Clocks {
                 w.divr2().bits(self.pll2.divr - 1)
             });
 
+            // H743 RM, RCC_PLLxFRACR register: FRACN can only be loaded while PLLxFRACEN is 0; setting it
+            // to 1 latches the value into the sigma-delta modulator.
+            rcc.pllcfgr.modify(|_, w| w.pll2fracen().clear_bit());
+            rcc.pll2fracr
+                .modify(|_, w| unsafe { w.fracn2().bits(self.pll2.fracn) });
+            rcc.pllcfgr
+                .modify(|_, w| w.pll2fracen().bit(self.pll2.fracn != 0));
+
             rcc.cr.modify(|_, w| w.pll2on().set_bit());
             while rcc.cr.read().pll2rdy().bit_is_clear() {}
         }
@@ -644,6 +747,14 @@ impl Clocks {
                 w.divr3().bits(self.pll3.divr - 1)
             });
 
+            // H743 RM, RCC_PLLxFRACR register: FRACN can only be loaded while PLLxFRACEN is 0; setting it
+            // to 1 latches the value into the sigma-delta modulator.
+            rcc.pllcfgr.modify(|_, w| w.pll3fracen().clear_bit());
+            rcc.pll3fracr
+                .modify(|_, w| unsafe { w.fracn3().bits(self.pll3.fracn) });
+            rcc.pllcfgr
+                .modify(|_, w| w.pll3fracen().bit(self.pll3.fracn != 0));
+
             rcc.cr.modify(|_, w| w.pll3on().set_bit());
             while rcc.cr.read().pll3rdy().bit_is_clear() {}
         }
@@ -653,6 +764,44 @@ impl Clocks {
             while rcc.cr.read().hsi48rdy().bit_is_clear() {}
         }
 
+        if !wait_states_increasing {
+            // Wait until the new clock source is in use before reducing wait states.
+            while rcc.cfgr.read().sws().bits() != self.input_src.bits() {}
+            set_wait_states(wait_states);
+        }
+
+        // Now that the system frequency and wait states have been reduced, lower the voltage
+        // scale if required. If we were previously in VOS0, run its deactivation sequence
+        // (described above) first.
+        #[cfg(not(feature = "h7b3"))]
+        let lowering = !matches!(self.vos_range, VosRange::VOS0);
+        #[cfg(feature = "h7b3")]
+        let lowering = true;
+
+        if lowering {
+            while rcc.cfgr.read().sws().bits() != self.input_src.bits() {}
+
+            cfg_if! {
+                if #[cfg(any(feature = "h747cm4", feature = "h747cm7"))] {
+                    if syscfg.pwrcr.read().oden().bit_is_set() {
+                        syscfg.pwrcr.modify(|_, w| w.oden().clear_bit());
+                        while pwr.d3cr.read().vosrdy().bit_is_clear() {}
+                    }
+                } else if #[cfg(not(feature = "h7b3"))] {
+                    if syscfg.pwrcr.read().oden().bits() != 0 {
+                        syscfg.pwrcr.modify(|_, w| unsafe { w.oden().bits(0) });
+                        while pwr.d3cr.read().vosrdy().bit_is_clear() {}
+                    }
+                }
+            }
+
+            if (self.vos_range as u8) < pwr.d3cr.read().vos().bits() {
+                pwr.d3cr
+                    .modify(|_, w| unsafe { w.vos().bits(self.vos_range as u8) });
+                while pwr.d3cr.read().vosrdy().bit_is_clear() {}
+            }
+        }
+
         Ok(())
     }
 
@@ -738,15 +887,17 @@ impl Clocks {
         }
     }
 
-    /// Calculate VCO output frequency: = Fref1_ck x DIVN1
+    /// Calculate VCO output frequency: = Fref1_ck x (DIVN1 + FRACN1 / 2^13)
     pub fn vco_output_freq(&self, pll_src: PllSrc, pll_num: u8) -> u32 {
-        let input_speed = self.pll_input_speed(pll_src, pll_num);
-        match pll_num {
-            1 => input_speed * self.pll1.divn as u32,
-            2 => input_speed * self.pll2.divn as u32,
-            3 => input_speed * self.pll3.divn as u32,
+        let input_speed = self.pll_input_speed(pll_src, pll_num) as u64;
+        let pll = match pll_num {
+            1 => &self.pll1,
+            2 => &self.pll2,
+            3 => &self.pll3,
             _ => panic!("Pll num must be between 1 and 3."),
-        }
+        };
+
+        ((input_speed * (((pll.divn as u64) << 13) + pll.fracn as u64)) >> 13) as u32
     }
 
     /// Check if the PLL is enabled. This is useful if checking whether to re-enable the PLL
@@ -768,8 +919,7 @@ impl Clocks {
         match self.input_src {
             InputSrc::Pll1 => {
                 // divm1 is included in `pll_input_speed`.
-                self.pll_input_speed(self.pll_src, 1) * self.pll1.divn as u32
-                    / self.pll1.divp as u32
+                self.vco_output_freq(self.pll_src, 1) / self.pll1.divp as u32
             }
             InputSrc::Csi => 4_000_000,
             InputSrc::Hsi(div) => 64_000_000 / (div.value() as u32),
@@ -827,6 +977,11 @@ impl Clocks {
         }
     }
 
+    /// Get the APB4 frequency, in hz. This is the default LPUART1 kernel clock.
+    pub fn apb4(&self) -> u32 {
+        self.hclk() / self.d3_prescaler.value() as u32
+    }
+
     /// Get the SAI1 audio clock frequency, in hz
     pub fn sai1_speed(&self) -> u32 {
         let pll_src = match self.input_src {
@@ -837,15 +992,9 @@ impl Clocks {
         };
 
         match self.sai1_src {
-            SaiSrc::Pll1Q => {
-                self.pll_input_speed(pll_src, 1) * self.pll1.divn as u32 / self.pll1.divq as u32
-            }
-            SaiSrc::Pll2P => {
-                self.pll_input_speed(pll_src, 1) * self.pll2.divn as u32 / self.pll2.divp as u32
-            }
-            SaiSrc::Pll3P => {
-                self.pll_input_speed(pll_src, 1) * self.pll3.divn as u32 / self.pll3.divp as u32
-            }
+            SaiSrc::Pll1Q => self.vco_output_freq(pll_src, 1) / self.pll1.divq as u32,
+            SaiSrc::Pll2P => self.vco_output_freq(pll_src, 2) / self.pll2.divp as u32,
+            SaiSrc::Pll3P => self.vco_output_freq(pll_src, 3) / self.pll3.divp as u32,
             SaiSrc::I2sCkin => unimplemented!(),
             SaiSrc::PerClk => unimplemented!(),
         }
@@ -928,6 +1077,21 @@ impl Clocks {
 
         // todo: Apb3/4?
 
+        // H743 datasheet, Table 23: General operating conditions. Sysclk above 400Mhz is only
+        // available in VOS0.
+        #[cfg(not(feature = "h7b3"))]
+        if self.vos_range != VosRange::VOS0 && self.sysclk() > 400_000_000 {
+            return Err(SpeedError::new("Sysclk above 400Mhz requires VOS0"));
+        }
+
+        // RM0399, section 7.4: VOS0 isn't available when VCORE is supplied directly by the SMPS.
+        #[cfg(all(not(feature = "h7b3"), feature = "h7_smps"))]
+        if self.vos_range == VosRange::VOS0 {
+            return Err(SpeedError::new(
+                "VOS0 is unavailable with a direct SMPS supply",
+            ));
+        }
+
         Ok(())
     }
 }
@@ -957,6 +1121,10 @@ impl Default for Clocks {
             /// Bypass the HSE output, for use with oscillators that don't need it. Saves power, and
             /// frees up the pin for use as GPIO.
             hse_bypass: false,
+            #[cfg(feature = "h7b3")]
+            hse_bypass_digital: false,
+            #[cfg(any(feature = "h747cm4", feature = "h747cm7", feature = "h7b3"))]
+            smps_level: VoltageLevel::V1_8,
             security_system: false,
             /// Enable the HSI48.
             hsi48_on: false,
@@ -1014,3 +1182,15 @@ impl Clocks {
 //         w.cen().set_bit()
 //     });
 // }
+
+/// Set the flash wait states (FLASH_ACR, LATENCY and WRHIGHFREQ fields), and wait until the new
+/// value is in effect.
+fn set_wait_states(wait_states: (u8, u8)) {
+    let flash = unsafe { &(*FLASH::ptr()) };
+
+    flash.acr.modify(|_, w| unsafe {
+        w.latency().bits(wait_states.0);
+        w.wrhighfreq().bits(wait_states.1)
+    });
+    while flash.acr.read().latency().bits() != wait_states.0 {}
+}
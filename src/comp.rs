@@ -0,0 +1,194 @@
+//! Support for the comparator (COMP) peripheral. Currently supports window mode, where
+//! COMP1 and COMP2 share a non-inverting input, and compare it against an upper and lower
+//! threshold respectively. This is useful for voltage supervision. See L4 RM, COMP section:
+//! Window comparator. L4 only, for now: L5 names its COMP register fields differently.
+
+use crate::pac::COMP;
+
+#[derive(Clone, Copy, PartialEq)]
+#[repr(u8)]
+/// The shared input for the window comparator. This is COMP1's non-inverting input, which
+/// COMP2's non-inverting input is connected to in window mode. Sets COMP1_CSR register, INPSEL
+/// field.
+pub enum WindowInput {
+    /// PC5
+    Io1 = 0,
+    /// PB2
+    Io2 = 1,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+#[repr(u8)]
+/// The threshold a comparator's non-inverting input is compared to. Sets COMPx_CSR register,
+/// INMSEL field. See L4 RM, COMP1 and COMP2 input minus assignment tables.
+pub enum Threshold {
+    /// 1/4 VREFINT
+    QuarterVref = 0b000,
+    /// 1/2 VREFINT
+    HalfVref = 0b001,
+    /// 3/4 VREFINT
+    ThreeQuarterVref = 0b010,
+    /// VREFINT
+    Vref = 0b011,
+    /// DAC channel 1
+    DacCh1 = 0b100,
+    /// DAC channel 2
+    DacCh2 = 0b101,
+    /// COMP1: PB1, COMP2: PB3
+    Io1 = 0b110,
+    /// COMP1: PC4, COMP2: PB7
+    Io2 = 0b111,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+#[repr(u8)]
+/// Comparator hysteresis. Sets COMPx_CSR register, HYST field.
+pub enum Hysteresis {
+    None = 0b00,
+    Low = 0b01,
+    Medium = 0b10,
+    High = 0b11,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+#[repr(u8)]
+/// Trade off power consumption against propagation delay. Sets COMPx_CSR register, PWRMODE field.
+pub enum PowerMode {
+    HighSpeed = 0b00,
+    MediumSpeed = 0b01,
+    UltraLowPower = 0b11,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+/// Position of the input relative to the window.
+pub enum WindowState {
+    /// The input is below the lower threshold.
+    Below,
+    /// The input is between the lower and upper thresholds.
+    Inside,
+    /// The input is above the upper threshold.
+    Above,
+}
+
+/// Configuration for the window comparator.
+pub struct WindowCfg {
+    pub input: WindowInput,
+    /// The upper threshold; compared against by COMP1.
+    pub upper: Threshold,
+    /// The lower threshold; compared against by COMP2.
+    pub lower: Threshold,
+    pub hysteresis: Hysteresis,
+    pub power_mode: PowerMode,
+}
+
+impl Default for WindowCfg {
+    fn default() -> Self {
+        Self {
+            input: WindowInput::Io1,
+            upper: Threshold::DacCh1,
+            lower: Threshold::DacCh2,
+            hysteresis: Hysteresis::Low,
+            power_mode: PowerMode::HighSpeed,
+        }
+    }
+}
+
+/// Represents COMP1 and COMP2, configured as a window comparator.
+pub struct WindowComparator {
+    pub regs: COMP,
+    pub cfg: WindowCfg,
+}
+
+impl WindowComparator {
+    /// Configure COMP1 and COMP2 in window mode, and enable them. Note that COMP shares its
+    /// peripheral clock with SYSCFG, which is enabled in `Clocks::setup`. If using DAC thresholds,
+    /// the DAC must be configured separately.
+    pub fn new(regs: COMP, cfg: WindowCfg) -> Self {
+        // L4 RM: SCALEN enables the VREFINT scaler outputs on the minus input. BRGEN enables the
+        // bridge of the scaler. If SCALEN is set and BRGEN is reset, the minus input is VREFINT.
+        let (upper_scalen, upper_brgen) = scaler_bits(cfg.upper);
+        let (lower_scalen, lower_brgen) = scaler_bits(cfg.lower);
+
+        regs.comp1_csr.modify(|_, w| unsafe {
+            w.comp1_pwrmode().bits(cfg.power_mode as u8);
+            w.comp1_inmsel().bits(cfg.upper as u8);
+            #[cfg(any(feature = "l4x1", feature = "l4x2", feature = "l412"))]
+            w.comp1_inpsel().bits(cfg.input as u8);
+            #[cfg(not(any(feature = "l4x1", feature = "l4x2", feature = "l412")))]
+            w.comp1_inpsel().bit(cfg.input as u8 != 0);
+            w.comp1_polarity().clear_bit();
+            w.comp1_hyst().bits(cfg.hysteresis as u8);
+            w.comp1_scalen().bit(upper_scalen);
+            w.comp1_brgen().bit(upper_brgen)
+        });
+
+        // L4 RM, COMP2_CSR: WINMODE: 1: COMP2 input plus is connected to COMP1 input plus.
+        regs.comp2_csr.modify(|_, w| unsafe {
+            w.comp2_pwrmode().bits(cfg.power_mode as u8);
+            w.comp2_inmsel().bits(cfg.lower as u8);
+            w.comp2_winmode().set_bit();
+            w.comp2_polarity().clear_bit();
+            w.comp2_hyst().bits(cfg.hysteresis as u8);
+            w.comp2_scalen().bit(lower_scalen);
+            w.comp2_brgen().bit(lower_brgen)
+        });
+
+        let mut result = Self { regs, cfg };
+        result.enable();
+        result
+    }
+
+    /// Enable both comparators.
+    pub fn enable(&mut self) {
+        self.regs.comp1_csr.modify(|_, w| w.comp1_en().set_bit());
+        self.regs.comp2_csr.modify(|_, w| w.comp2_en().set_bit());
+    }
+
+    /// Disable both comparators.
+    pub fn disable(&mut self) {
+        self.regs.comp1_csr.modify(|_, w| w.comp1_en().clear_bit());
+        self.regs.comp2_csr.modify(|_, w| w.comp2_en().clear_bit());
+    }
+
+    /// Returns true if the input is above the upper threshold. Reads COMP1_CSR, VALUE.
+    pub fn above_upper(&self) -> bool {
+        self.regs.comp1_csr.read().comp1_value().bit_is_set()
+    }
+
+    /// Returns true if the input is above the lower threshold. Reads COMP2_CSR, VALUE.
+    pub fn above_lower(&self) -> bool {
+        self.regs.comp2_csr.read().comp2_value().bit_is_set()
+    }
+
+    /// Read both comparator outputs, and determine where the input is relative to the window.
+    pub fn state(&self) -> WindowState {
+        if self.above_upper() {
+            WindowState::Above
+        } else if self.above_lower() {
+            WindowState::Inside
+        } else {
+            WindowState::Below
+        }
+    }
+
+    /// Returns true if the input is between the lower and upper thresholds.
+    pub fn in_window(&self) -> bool {
+        self.state() == WindowState::Inside
+    }
+
+    /// Lock the configuration of both comparators. Once set, the COMPx_CSR registers are
+    /// read-only until the next system reset.
+    pub fn lock(&mut self) {
+        self.regs.comp1_csr.modify(|_, w| w.comp1_lock().set_bit());
+        self.regs.comp2_csr.modify(|_, w| w.comp2_lock().set_bit());
+    }
+}
+
+/// Determine the (SCALEN, BRGEN) bits required by a threshold.
+fn scaler_bits(threshold: Threshold) -> (bool, bool) {
+    match threshold {
+        Threshold::QuarterVref | Threshold::HalfVref | Threshold::ThreeQuarterVref => (true, true),
+        Threshold::Vref => (true, false),
+        _ => (false, false),
+    }
+}
@@ -76,26 +76,27 @@ impl Crc {
     /// Write data to the CRC unit. Note that CRC calculation works
     /// faster if more data is given at once.
     pub fn update(&mut self, data: &[u8]) {
+        // DR is at offset 0, and accepts 32, 16, and 8-bit writes. We write through a pointer,
+        // since the PACs' accessors for the narrower writes are inconsistent.
+        let dr = &*self.reg as *const crc::RegisterBlock as *mut u8;
+
         // write 4 bytes at once, then 2, then 1, as appropriate
         // in the case of a single large slice this improves speed by >3x
         let mut words = data.chunks_exact(4);
         for word in words.by_ref() {
             let word = u32::from_be_bytes(word.try_into().unwrap());
-            // todo: Put back once PAC settles. Currently causing error on H7
-            // self.reg.dr_mut().write(|w| w.dr().bits(word));
+            unsafe { core::ptr::write_volatile(dr as *mut u32, word) };
         }
 
         // there will be at most 3 bytes remaining, so 1 half-word and 1 byte
         let mut half_word = words.remainder().chunks_exact(2);
         if let Some(half_word) = half_word.next() {
-            let _half_word = u16::from_be_bytes(half_word.try_into().unwrap());
-            // todo: Put back once PAC settles. Currently causing error on H7
-            // self.reg.dr16_mut().write(|w| w.dr16().bits(half_word));
+            let half_word = u16::from_be_bytes(half_word.try_into().unwrap());
+            unsafe { core::ptr::write_volatile(dr as *mut u16, half_word) };
         }
 
         if let Some(byte) = half_word.remainder().first() {
-            // todo: Put back once PAC settles. Currently causing error on H7
-            // self.reg.dr8_mut().write(|w| w.dr8().bits(*byte));
+            unsafe { core::ptr::write_volatile(dr, *byte) };
         }
     }
 
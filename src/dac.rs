@@ -22,6 +22,7 @@ use pac::dma as dma_p;
 #[cfg(any(
     feature = "f3",
     feature = "l4",
+    feature = "l5",
     feature = "g4",
     feature = "h7",
     feature = "wb",
@@ -29,11 +30,17 @@ use pac::dma as dma_p;
 ))]
 use pac::dma1 as dma_p;
 
-#[cfg(not(any(feature = "f4", feature = "l5")))]
+#[cfg(not(feature = "f4"))]
 use crate::dma::{self, ChannelCfg, Dma, DmaChannel};
 
-#[cfg(any(feature = "f3", feature = "l4"))]
-use crate::dma::DmaInput;
+#[cfg(not(any(feature = "f4", feature = "g0")))]
+use crate::{
+    dma::DmaInput,
+    util::{DacChannelDma, RouteError},
+};
+
+#[cfg(feature = "g4")]
+use crate::interconnect::{self, TriggerSource};
 
 #[derive(Clone, Copy)]
 #[repr(u8)]
@@ -309,10 +316,23 @@ where
         }
     }
 
+    #[cfg(not(any(feature = "g0", feature = "f4")))]
+    /// The DMA input (request) for one of this DAC's channels. On G4, L5 and H7, pass this to
+    /// `dma::mux` to route it to the DMA channel used with `write_dma`. Returns an error if this
+    /// DAC doesn't have the channel.
+    pub fn dma_input(&self, channel: DacChannel) -> Result<DmaInput, RouteError>
+    where
+        R: DacChannelDma,
+    {
+        R::dma_input(channel)
+    }
+
     /// Send values to the DAC using DMA. Each trigger (Eg using a timer; the basic timers Tim6
     /// and Tim7 are designed for DAC triggering) sends one word from the buffer to the DAC's
-    /// output.
-    #[cfg(not(any(feature = "g0", feature = "f4", feature = "l5")))]
+    /// output. Note that the `dma_channel` argument has no effect on F3 and L4. On WL, the
+    /// DMAMUX is configured to route this channel's request to it. Returns an error if this DAC
+    /// doesn't have the channel.
+    #[cfg(not(any(feature = "g0", feature = "f4")))]
     pub unsafe fn write_dma<D>(
         &mut self,
         buf: &[u16],
@@ -320,22 +340,43 @@ where
         dma_channel: DmaChannel,
         channel_cfg: ChannelCfg,
         dma: &mut Dma<D>,
-    ) where
+    ) -> Result<(), RouteError>
+    where
         D: Deref<Target = dma_p::RegisterBlock>,
+        R: DacChannelDma,
     {
         let (ptr, len) = (buf.as_ptr(), buf.len());
 
+        // On families with DMAMUX, the input is routed by the caller; this still rejects
+        // channels the DAC doesn't have.
+        #[cfg(not(any(feature = "f3", feature = "l4", feature = "wl")))]
+        R::dma_input(dac_channel)?;
+        #[cfg(any(feature = "f3", feature = "l4", feature = "wl"))]
+        let dma_input = R::dma_input(dac_channel)?;
+
         #[cfg(any(feature = "f3", feature = "l4"))]
-        let dma_channel = match dac_channel {
-            DacChannel::C1 => DmaInput::Dac1Ch1.dma1_channel(),
-            DacChannel::C2 => DmaInput::Dac1Ch2.dma1_channel(),
-        };
+        let dma_channel = dma_input.dma1_channel();
 
         #[cfg(feature = "l4")]
-        match dac_channel {
-            DacChannel::C1 => dma.channel_select(DmaInput::Dac1Ch1),
-            DacChannel::C2 => dma.channel_select(DmaInput::Dac1Ch2),
-        };
+        dma.channel_select(dma_input);
+
+        #[cfg(feature = "wl")]
+        dma::mux_dma(dma, dma_channel, dma_input);
+
+        // F303 RM, section 13.4.5: DAC requests are on DMA2 channels 3 and 4 by default. Remap
+        // them to DMA1 channels 3 and 4, which `dma1_channel` returns. (SYSCFG_CFGR1
+        // TIM6_DAC1_DMA_RMP, and TIM7_DAC2_DMA_RMP)
+        #[cfg(feature = "f3")]
+        free(|_| {
+            let rcc = unsafe { &(*RCC::ptr()) };
+            let syscfg = unsafe { &(*pac::SYSCFG::ptr()) };
+
+            rcc.apb2enr.modify(|_, w| w.syscfgen().set_bit());
+            syscfg.cfgr1.modify(|_, w| match dac_channel {
+                DacChannel::C1 => w.tim6_dac1_dma_rmp().set_bit(),
+                DacChannel::C2 => w.tim7_dac2_dma_rmp().set_bit(),
+            });
+        });
 
         // H743 RM, section 26.4.8: DMA requests
         // Each DAC channel has a DMA capability. Two DMA channels are used to service DAC
@@ -426,17 +467,22 @@ where
             dma::DataSize::S16,
             channel_cfg,
         );
+
+        Ok(())
+    }
+
+    /// The maximum output word, for the configured number of bits.
+    pub fn max_word(&self) -> u16 {
+        match self.bits {
+            DacBits::EightR => 255,
+            DacBits::TwelveL => 4_095,
+            DacBits::TwelveR => 4_095,
+        }
     }
 
     /// Set the DAC output voltage.
     pub fn write_voltage(&mut self, channel: DacChannel, volts: f32) {
-        let max_word = match self.bits {
-            DacBits::EightR => 255.,
-            DacBits::TwelveL => 4_095.,
-            DacBits::TwelveR => 4_095.,
-        };
-
-        let val = ((volts / self.vref) * max_word) as u16;
+        let val = ((volts / self.vref) * self.max_word() as f32) as u16;
         self.write(channel, val);
     }
 
@@ -471,6 +517,35 @@ where
         }
     }
 
+    #[cfg(feature = "g4")]
+    /// Select and activate a trigger, using the G4 trigger selection table. Returns an error if
+    /// the source isn't connected to the DAC. Sets the DAC_CR register, TSELx and TENx fields.
+    pub fn set_trigger_source(
+        &mut self,
+        channel: DacChannel,
+        source: TriggerSource,
+    ) -> Result<(), RouteError> {
+        let tsel = interconnect::dac_tsel(source)?;
+        let cr = &self.regs.dac_cr;
+
+        match channel {
+            DacChannel::C1 => {
+                cr.modify(|_, w| unsafe {
+                    w.ten1().set_bit();
+                    w.tsel1().bits(tsel)
+                });
+            }
+            DacChannel::C2 => {
+                cr.modify(|_, w| unsafe {
+                    w.ten2().set_bit();
+                    w.tsel2().bits(tsel)
+                });
+            }
+        }
+
+        Ok(())
+    }
+
     #[cfg(not(any(feature = "l5", feature = "wl")))] // See note on `set_trigger`.
     /// Independent trigger with single LFSR generation
     /// See f303 Reference Manual section 16.5.2
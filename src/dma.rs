@@ -13,19 +13,22 @@ use cortex_m::interrupt::free;
 
 use crate::{
     pac::{self, RCC},
-    rcc_en_reset,
+    util::RccPeriph,
 };
 
+#[cfg(any(feature = "wb", feature = "wl"))]
+use crate::rcc_en_reset;
+
 #[cfg(feature = "g0")]
 use crate::pac::dma;
 #[cfg(not(feature = "g0"))]
 use crate::pac::dma1 as dma;
 
-#[cfg(any(feature = "l5", feature = "g0", feature = "g4", feature = "wl"))]
+#[cfg(any(feature = "g0", feature = "g4", feature = "wl"))]
 use pac::DMAMUX;
 
 // todo: DMAMUX2 support (Not sure if WB has it, but H7 has both).
-#[cfg(any(feature = "wb", feature = "h7"))]
+#[cfg(any(feature = "l5", feature = "wb", feature = "h7"))]
 use pac::DMAMUX1 as DMAMUX;
 
 #[cfg(feature = "h7")]
@@ -39,7 +42,7 @@ use cfg_if::cfg_if;
 
 #[derive(Copy, Clone)]
 #[repr(usize)]
-#[cfg(not(feature = "h7"))]
+#[cfg(not(any(feature = "h7", feature = "wb", feature = "wl")))]
 /// A list of DMA input sources. The integer values represent their DMAMUX register value, on
 /// MCUs that use this. G4 RM, Table 91: DMAMUX: Assignment of multiplexer inputs to resources.
 pub enum DmaInput {
@@ -86,7 +89,7 @@ pub enum DmaInput {
     Tim1Ch2 = 43,
     Tim1Ch3 = 44,
     Tim1Ch4 = 45,
-    TimUp = 46,
+    Tim1Up = 46,
     Tim1Trig = 47,
     Tim1Com = 48,
     Tim8Ch1 = 49,
@@ -112,6 +115,21 @@ pub enum DmaInput {
     Tim4Ch3 = 69,
     Tim4Ch4 = 70,
     Tim4Up = 71,
+    Tim5Ch1 = 72,
+    Tim5Ch2 = 73,
+    Tim5Ch3 = 74,
+    Tim5Ch4 = 75,
+    Tim5Up = 76,
+    Tim15Ch1 = 78,
+    Tim15Up = 79,
+    Tim16Ch1 = 82,
+    Tim16Up = 83,
+    Tim17Ch1 = 84,
+    Tim17Up = 85,
+    Dac3Ch1 = 102,
+    Dac3Ch2 = 103,
+    Dac4Ch1 = 104,
+    Dac4Ch2 = 105,
     Sai1A = 108,
     Sai1B = 109,
     // todo: These SAI2 values are bogus; can't find on G4 DMA mux.
@@ -122,6 +140,96 @@ pub enum DmaInput {
     Dfsdm1F1 = 201,
 }
 
+#[derive(Copy, Clone)]
+#[repr(usize)]
+#[cfg(feature = "wb")]
+/// A list of DMA input sources. The integer values represent their DMAMUX register value.
+/// WB55 RM, DMAMUX: Assignment of multiplexer inputs to resources.
+pub enum DmaInput {
+    Adc1 = 5,
+    Spi1Rx = 6,
+    Spi1Tx = 7,
+    Spi2Rx = 8,
+    Spi2Tx = 9,
+    I2c1Rx = 10,
+    I2c1Tx = 11,
+    I2c3Rx = 12,
+    I2c3Tx = 13,
+    Usart1Rx = 14,
+    Usart1Tx = 15,
+    Lpuart1Rx = 16,
+    Lpuart1Tx = 17,
+    Sai1A = 18,
+    Sai1B = 19,
+    Quadspi = 20,
+    Tim1Ch1 = 21,
+    Tim1Ch2 = 22,
+    Tim1Ch3 = 23,
+    Tim1Ch4 = 24,
+    Tim1Up = 25,
+    Tim1Trig = 26,
+    Tim1Com = 27,
+    Tim2Ch1 = 28,
+    Tim2Ch2 = 29,
+    Tim2Ch3 = 30,
+    Tim2Ch4 = 31,
+    Tim2Up = 32,
+    Tim16Ch1 = 33,
+    Tim16Up = 34,
+    Tim17Ch1 = 35,
+    Tim17Up = 36,
+    Aes1In = 37,
+    Aes1Out = 38,
+    Aes2In = 39,
+    Aes2Out = 40,
+}
+
+#[derive(Copy, Clone)]
+#[repr(usize)]
+#[cfg(feature = "wl")]
+/// A list of DMA input sources. The integer values represent their DMAMUX register value.
+/// RM0453, DMAMUX: Assignment of multiplexer inputs to resources.
+pub enum DmaInput {
+    Adc1 = 5,
+    Dac1Ch1 = 6,
+    Spi1Rx = 7,
+    Spi1Tx = 8,
+    Spi2Rx = 9,
+    Spi2Tx = 10,
+    I2c1Rx = 11,
+    I2c1Tx = 12,
+    I2c2Rx = 13,
+    I2c2Tx = 14,
+    I2c3Rx = 15,
+    I2c3Tx = 16,
+    Usart1Rx = 17,
+    Usart1Tx = 18,
+    Usart2Rx = 19,
+    Usart2Tx = 20,
+    Lpuart1Rx = 21,
+    Lpuart1Tx = 22,
+    Tim1Ch1 = 23,
+    Tim1Ch2 = 24,
+    Tim1Ch3 = 25,
+    Tim1Ch4 = 26,
+    Tim1Up = 27,
+    Tim1Trig = 28,
+    Tim1Com = 29,
+    Tim2Ch1 = 30,
+    Tim2Ch2 = 31,
+    Tim2Ch3 = 32,
+    Tim2Ch4 = 33,
+    Tim2Up = 34,
+    Tim16Ch1 = 35,
+    Tim16Up = 36,
+    Tim17Ch1 = 37,
+    Tim17Up = 38,
+    AesIn = 39,
+    AesOut = 40,
+    SubghzSpiRx = 41,
+    SubghzSpiTx = 42,
+}
+
 // todo: Trigger, synchronization etc mappings. Perhaps DmaTrigger, DmaSync enums etc.
 
 #[derive(Copy, Clone)]
@@ -169,6 +277,11 @@ pub enum DmaInput {
     Usart2Tx = 44,
     Usart3Rx = 45,
     Usart3Tx = 46,
+    Tim8Ch1 = 47,
+    Tim8Ch2 = 48,
+    Tim8Ch3 = 49,
+    Tim8Ch4 = 50,
+    Tim8Up = 51,
     Tim5Ch1 = 55,
     Tim5Ch2 = 56,
     Tim5Ch3 = 57,
@@ -195,6 +308,12 @@ pub enum DmaInput {
     Dfsdm1F1 = 102,
     Dfsdm1F2 = 103,
     Dfsdm1F3 = 104,
+    Tim15Ch1 = 105,
+    Tim15Up = 106,
+    Tim16Ch1 = 109,
+    Tim16Up = 110,
+    Tim17Ch1 = 111,
+    Tim17Up = 112,
     Sai3A = 113,
     Sai3B = 114,
 }
@@ -220,8 +339,10 @@ impl DmaInput {
     pub fn dma1_channel(&self) -> DmaChannel {
         match self {
             Self::Adc1 => DmaChannel::C1,
-            // Self::Dac1Ch1 => 6,
-            // Self::Dac1Ch2 => 7,
+            // Note: On F3, DAC requests are only on DMA1 if remapped in SYSCFG_CFGR1; `Dac::write_dma`
+            // does this.
+            Self::Dac1Ch1 => DmaChannel::C3,
+            Self::Dac1Ch2 => DmaChannel::C4,
             // Self::Tim6Up => 8,
             // Self::Tim7Up => 9,
             Self::Spi1Rx => DmaChannel::C2,
@@ -269,8 +390,8 @@ impl DmaInput {
     pub fn dma1_channel_select(&self) -> u8 {
         match self {
             Self::Adc1 => 0b000,
-            // Self::Dac1Ch1 => 6,
-            // Self::Dac1Ch2 => 7,
+            Self::Dac1Ch1 => 0b110,
+            Self::Dac1Ch2 => 0b101,
             // Self::Tim6Up => 8,
             // Self::Tim7Up => 9,
             Self::Spi1Rx => 0b001,
@@ -330,7 +451,7 @@ pub enum Priority {
     VeryHigh = 0b11,
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 #[repr(u8)]
 /// Represents a DMA channel to select, eg when configuring for use with a peripheral.
 /// u8 representation is used to index registers on H7 PAC (And hopefully on future PACs if they
@@ -364,6 +485,8 @@ pub enum Direction {
     ReadFromPeriph = 0,
     /// DIR = 1 defines typically a memory-to-peripheral transfer.
     ReadFromMem = 1,
+    /// Memory-to-memory: Transfers from the `periph_addr` passed to `cfg_channel`, to `mem_addr`,
+    /// without waiting for a peripheral request. Can't be used with circular mode. (MEM2MEM)
     MemToMem = 2,
 }
 
@@ -407,6 +530,62 @@ pub enum DmaInterrupt {
     DirectModeError,
     #[cfg(feature = "h7")]
     FifoError,
+    #[cfg(not(feature = "h7"))]
+    /// The channel's global flag (GIF), which is set when any of its other flags are. Enabling
+    /// this enables the transfer error, half transfer, and transfer complete interrupts; clearing
+    /// it clears all of the channel's flags.
+    Global,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+/// The event that caused a channel's interrupt to fire. Returned by `Dma::on_interrupt`, and
+/// the `on_interrupt_channelX` functions.
+pub enum DmaEvent {
+    TransferComplete,
+    HalfTransfer,
+    TransferError,
+    #[cfg(feature = "h7")]
+    DirectModeError,
+    #[cfg(feature = "h7")]
+    FifoError,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+/// All of a channel's interrupt flags. Returned by `Dma::pending`.
+pub struct DmaEventFlags {
+    pub transfer_complete: bool,
+    pub half_transfer: bool,
+    pub transfer_error: bool,
+    #[cfg(feature = "h7")]
+    pub direct_mode_error: bool,
+    #[cfg(feature = "h7")]
+    pub fifo_error: bool,
+}
+
+impl From<DmaEvent> for DmaInterrupt {
+    fn from(event: DmaEvent) -> Self {
+        match event {
+            DmaEvent::TransferComplete => Self::TransferComplete,
+            DmaEvent::HalfTransfer => Self::HalfTransfer,
+            DmaEvent::TransferError => Self::TransferError,
+            #[cfg(feature = "h7")]
+            DmaEvent::DirectModeError => Self::DirectModeError,
+            #[cfg(feature = "h7")]
+            DmaEvent::FifoError => Self::FifoError,
+        }
+    }
+}
+
+/// Work around the L5 PAC's channel register types: Those for channels 2 - 8 are incorrect; eg
+/// `ccr2` has `CNDTR`'s fields. (https://github.com/stm32-rs/stm32-rs/issues/551) The channels'
+/// register sets share a layout, and are spaced 0x14 bytes apart, so we access a channel's
+/// register using channel 1's type, offset by the channel number.
+#[cfg(feature = "l5")]
+fn l5_chan_reg<T>(ch1_reg: &T, channel: DmaChannel) -> &T {
+    const CHANNEL_STRIDE: usize = 0x14;
+    let offset = (channel as usize - 1) * CHANNEL_STRIDE;
+
+    unsafe { &*((ch1_reg as *const T as *const u8).add(offset) as *const T) }
 }
 
 /// Reduce DRY over channels when configuring a channel's CCR.
@@ -420,9 +599,8 @@ macro_rules! set_ccr {
         $ccr.modify(|_, w| w.en().clear_bit());
         while $ccr.read().en().bit_is_set() {}
 
-        if let Circular::Enabled = $circular {
-            $ccr.modify(|_, w| w.mem2mem().clear_bit());
-        }
+        // In memory-to-memory mode, DIR = 0 reads from the peripheral address, as on H7.
+        $ccr.modify(|_, w| w.mem2mem().bit($direction as u8 == 2));
 
         $ccr.modify(|_, w| unsafe {
             // – the channel priority
@@ -430,7 +608,7 @@ macro_rules! set_ccr {
             // – the data transfer direction
             // This bit [DIR] must be set only in memory-to-peripheral and peripheral-to-memory modes.
             // 0: read from peripheral
-            w.dir().bit($direction as u8 != 0);
+            w.dir().bit($direction as u8 == 1);
             // – the circular mode
             w.circ().bit($circular as u8 != 0);
             // – the peripheral and memory incremented mode
@@ -460,6 +638,11 @@ macro_rules! enable_interrupt {
             DmaInterrupt::TransferError => w.teie().set_bit(),
             DmaInterrupt::HalfTransfer => w.htie().set_bit(),
             DmaInterrupt::TransferComplete => w.tcie().set_bit(),
+            DmaInterrupt::Global => {
+                w.teie().set_bit();
+                w.htie().set_bit();
+                w.tcie().set_bit()
+            }
         });
 
         if originally_enabled {
@@ -469,6 +652,74 @@ macro_rules! enable_interrupt {
     };
 }
 
+/// A buffer aligned to 32 bytes: The Cortex-M7 (H7) data cache line size. This keeps cache
+/// maintenance operations on the buffer from affecting neighbouring data, and satisfies the
+/// alignment requirements of all DMA transfer sizes. Usually created with `dma_buffer!`.
+#[repr(C, align(32))]
+pub struct DmaAligned<T>(pub T);
+
+#[doc(hidden)]
+/// Used by `dma_buffer!`: Set each element of a static buffer to `init`, and return a reference
+/// to it. Panics if called more than once for the same buffer.
+pub unsafe fn take_buffer<T: Copy, const N: usize>(
+    buf: *mut [T; N],
+    taken: *mut bool,
+    init: T,
+) -> &'static mut [T; N] {
+    free(|_| {
+        if *taken {
+            panic!("This DMA buffer has already been taken.");
+        }
+        *taken = true;
+    });
+
+    // Sections like `.axisram` are often `NOLOAD`, so the static's initializer isn't applied
+    // at startup. We write the elements in place, instead of the whole array, so large buffers
+    // aren't built on the stack first.
+    let first = buf as *mut T;
+    for i in 0..N {
+        core::ptr::write_volatile(first.add(i), init);
+    }
+    &mut *buf
+}
+
+/// Create a statically-allocated, zeroed, 32-byte-aligned buffer for DMA, of a numeric type, and
+/// return it as `&'static mut [T; N]`. This can be passed directly to the DMA transfer methods,
+/// eg `Usart::read_dma` or `Adc::read_dma`; its static lifetime ensures it outlives the transfer.
+/// Optionally, place it in a specific link section, eg one in a DMA-accessible RAM region, as
+/// defined in your `memory.x`. Panics if the same invocation runs more than once.
+///
+/// On H7, DMA1 and DMA2 can't access DTCM, which is where `cortex-m-rt` places statics by
+/// default with the usual H7 memory layouts; use a section in AXI SRAM or SRAM1-3 instead. With
+/// the data cache enabled, clean or invalidate the buffer around transfers, and make its size
+/// a multiple of 32 bytes.
+///
+/// Example:
+/// ```
+/// let rx_buf = dma_buffer!(u8, 64);
+/// let adc_buf = dma_buffer!(u16, 256, ".axisram");
+///
+/// unsafe { adc.read_dma(adc_buf, 1, DmaChannel::C1, Default::default(), &mut dma) };
+/// ```
+#[macro_export]
+macro_rules! dma_buffer {
+    ($T:ty, $N:expr $(, $section:literal)?) => {{
+        $(#[link_section = $section])?
+        static mut BUF: $crate::dma::DmaAligned<[$T; $N]> =
+            $crate::dma::DmaAligned([0 as $T; $N]);
+        static mut TAKEN: bool = false;
+
+        unsafe {
+            $crate::dma::take_buffer(
+                core::ptr::addr_of_mut!(BUF.0),
+                core::ptr::addr_of_mut!(TAKEN),
+                0 as $T,
+            )
+        }
+    }};
+}
+
+#[derive(Clone, Copy)]
 /// This struct is used to pass common (non-peripheral and non-use-specific) data when configuring
 /// a channel.
 pub struct ChannelCfg {
@@ -478,6 +729,43 @@ pub struct ChannelCfg {
     pub mem_incr: IncrMode,
 }
 
+impl ChannelCfg {
+    /// Continuously receive from a peripheral into a ring buffer, eg for ADC sampling, or UART
+    /// reception. Use with `Direction::ReadFromPeriph`. High priority, since the peripheral's
+    /// data is lost if it isn't read in time.
+    pub fn periph_to_mem_circular() -> Self {
+        Self {
+            priority: Priority::High,
+            circular: Circular::Enabled,
+            periph_incr: IncrMode::Disabled,
+            mem_incr: IncrMode::Enabled,
+        }
+    }
+
+    /// Transmit a buffer to a peripheral once, eg for a UART or SPI write. Use with
+    /// `Direction::ReadFromMem`.
+    pub fn mem_to_periph_once() -> Self {
+        Self {
+            priority: Priority::Medium,
+            circular: Circular::Disabled,
+            periph_incr: IncrMode::Disabled,
+            mem_incr: IncrMode::Enabled,
+        }
+    }
+
+    /// Copy a block of memory, eg with 32-bit words, using `DataSize::S32` for both sizes. Use
+    /// with `Direction::MemToMem`. Both addresses increment. Low priority, so peripheral
+    /// transfers on other channels are served first.
+    pub fn mem_to_mem() -> Self {
+        Self {
+            priority: Priority::Low,
+            circular: Circular::Disabled,
+            periph_incr: IncrMode::Enabled,
+            mem_incr: IncrMode::Enabled,
+        }
+    }
+}
+
 impl Default for ChannelCfg {
     fn default() -> Self {
         Self {
@@ -495,25 +783,223 @@ pub struct Dma<D> {
     pub regs: D,
 }
 
+#[cfg(feature = "wb")]
+/// The WB PAC gives DMA2 its own register block type, although its layout is the same as DMA1's.
+/// Wrap it in this to use it with `Dma`. Example: `let mut dma2 = Dma::new(Dma2(dp.DMA2));`
+pub struct Dma2(pub pac::DMA2);
+
+#[cfg(feature = "wb")]
+impl Deref for Dma2 {
+    type Target = dma::RegisterBlock;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*(pac::DMA2::ptr() as *const dma::RegisterBlock) }
+    }
+}
+
+#[cfg(feature = "wb")]
+impl RccPeriph for Dma2 {
+    fn en_reset(rcc: &pac::rcc::RegisterBlock) {
+        pac::DMA2::en_reset(rcc);
+    }
+}
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// A DMA controller that can be used with `Dma`, eg DMA1 or DMA2. Along with its register block,
+/// it knows its RCC enable and reset bits (from `RccPeriph`), its channels' interrupts, and which
+/// DMAMUX channels it's connected to, so code can be generic over controllers. This trait is
+/// sealed; it's implemented for each controller in the PAC. H7's BDMA has a different register
+/// layout, and isn't supported by `Dma`.
+pub trait DmaInstance:
+    Deref<Target = dma::RegisterBlock> + RccPeriph + sealed::Sealed
+{
+    #[cfg(not(any(feature = "g0", feature = "l5")))]
+    /// The NVIC interrupt for a channel, eg to unmask, or to pass to `wait_transfer_complete`.
+    /// Panics if the controller doesn't have the channel. (Not on G0, where channels share
+    /// interrupts in ways that vary by variant, or L5)
+    fn interrupt(channel: DmaChannel) -> pac::Interrupt;
+
+    #[cfg(any(
+        feature = "g0",
+        feature = "g4",
+        feature = "l5",
+        feature = "wb",
+        feature = "wl",
+        feature = "h7"
+    ))]
+    /// The DMAMUX channel connected to the controller's first channel. DMAMUX channels start at 0.
+    const MUX_CHANNEL_OFFSET: u8;
+}
+
+/// Implement `DmaInstance::interrupt`, from a list of channels and their interrupts.
+#[cfg(not(any(feature = "g0", feature = "l5")))]
+macro_rules! dma_interrupts {
+    ($($(#[$meta:meta])* $channel:ident => $interrupt:ident),+ $(,)?) => {
+        #[allow(unreachable_patterns)]
+        fn interrupt(channel: DmaChannel) -> pac::Interrupt {
+            match channel {
+                $($(#[$meta])* DmaChannel::$channel => pac::Interrupt::$interrupt,)+
+                _ => panic!("This DMA controller doesn't have this channel."),
+            }
+        }
+    };
+}
+
+cfg_if! {
+    if #[cfg(feature = "f3")] {
+        impl sealed::Sealed for pac::DMA1 {}
+        impl DmaInstance for pac::DMA1 {
+            dma_interrupts!(
+                C1 => DMA1_CH1, C2 => DMA1_CH2, C3 => DMA1_CH3, C4 => DMA1_CH4,
+                C5 => DMA1_CH5, C6 => DMA1_CH6, C7 => DMA1_CH7,
+            );
+        }
+
+        #[cfg(not(any(feature = "f301", feature = "f3x4")))]
+        impl sealed::Sealed for pac::DMA2 {}
+        #[cfg(not(any(feature = "f301", feature = "f3x4")))]
+        impl DmaInstance for pac::DMA2 {
+            dma_interrupts!(
+                C1 => DMA2_CH1, C2 => DMA2_CH2, C3 => DMA2_CH3, C4 => DMA2_CH4, C5 => DMA2_CH5,
+            );
+        }
+    } else if #[cfg(feature = "l4")] {
+        impl sealed::Sealed for pac::DMA1 {}
+        impl DmaInstance for pac::DMA1 {
+            dma_interrupts!(
+                C1 => DMA1_CH1, C2 => DMA1_CH2, C3 => DMA1_CH3, C4 => DMA1_CH4,
+                C5 => DMA1_CH5, C6 => DMA1_CH6, C7 => DMA1_CH7,
+            );
+        }
+
+        impl sealed::Sealed for pac::DMA2 {}
+        impl DmaInstance for pac::DMA2 {
+            dma_interrupts!(
+                C1 => DMA2_CH1, C2 => DMA2_CH2, C3 => DMA2_CH3, C4 => DMA2_CH4,
+                C5 => DMA2_CH5, C6 => DMA2_CH6, C7 => DMA2_CH7,
+            );
+        }
+    } else if #[cfg(feature = "g4")] {
+        // G431 and G441 (category 2 devices) have 6 channels per controller; the others, 8.
+        impl sealed::Sealed for pac::DMA1 {}
+        impl DmaInstance for pac::DMA1 {
+            dma_interrupts!(
+                C1 => DMA1_CH1, C2 => DMA1_CH2, C3 => DMA1_CH3, C4 => DMA1_CH4,
+                C5 => DMA1_CH5, C6 => DMA1_CH6,
+                #[cfg(not(any(feature = "g431", feature = "g441")))]
+                C7 => DMA1_CH7,
+                #[cfg(not(any(feature = "g431", feature = "g441")))]
+                C8 => DMA1_CH8,
+            );
+            const MUX_CHANNEL_OFFSET: u8 = 0;
+        }
+
+        impl sealed::Sealed for pac::DMA2 {}
+        impl DmaInstance for pac::DMA2 {
+            dma_interrupts!(
+                C1 => DMA2_CH1, C2 => DMA2_CH2, C3 => DMA2_CH3, C4 => DMA2_CH4,
+                C5 => DMA2_CH5, C6 => DMA2_CH6,
+                #[cfg(not(any(feature = "g431", feature = "g441")))]
+                C7 => DMA2_CH7,
+                #[cfg(not(any(feature = "g431", feature = "g441")))]
+                C8 => DMA2_CH8,
+            );
+            #[cfg(any(feature = "g431", feature = "g441"))]
+            const MUX_CHANNEL_OFFSET: u8 = 6;
+            #[cfg(not(any(feature = "g431", feature = "g441")))]
+            const MUX_CHANNEL_OFFSET: u8 = 8;
+        }
+    } else if #[cfg(feature = "h7")] {
+        // DMA1 and DMA2 are on DMAMUX1. (BDMA is on DMAMUX2)
+        impl sealed::Sealed for pac::DMA1 {}
+        impl DmaInstance for pac::DMA1 {
+            dma_interrupts!(
+                C0 => DMA1_STR0, C1 => DMA1_STR1, C2 => DMA1_STR2, C3 => DMA1_STR3,
+                C4 => DMA1_STR4, C5 => DMA1_STR5, C6 => DMA1_STR6, C7 => DMA1_STR7,
+            );
+            const MUX_CHANNEL_OFFSET: u8 = 0;
+        }
+
+        impl sealed::Sealed for pac::DMA2 {}
+        impl DmaInstance for pac::DMA2 {
+            dma_interrupts!(
+                C0 => DMA2_STR0, C1 => DMA2_STR1, C2 => DMA2_STR2, C3 => DMA2_STR3,
+                C4 => DMA2_STR4, C5 => DMA2_STR5, C6 => DMA2_STR6, C7 => DMA2_STR7,
+            );
+            const MUX_CHANNEL_OFFSET: u8 = 8;
+        }
+    } else if #[cfg(feature = "wb")] {
+        // Note that the WB PAC names DMA1's interrupts differently from DMA2's.
+        impl sealed::Sealed for pac::DMA1 {}
+        impl DmaInstance for pac::DMA1 {
+            dma_interrupts!(
+                C1 => DMA1_CHANNEL1, C2 => DMA1_CHANNEL2, C3 => DMA1_CHANNEL3, C4 => DMA1_CHANNEL4,
+                C5 => DMA1_CHANNEL5, C6 => DMA1_CHANNEL6, C7 => DMA1_CHANNEL7,
+            );
+            const MUX_CHANNEL_OFFSET: u8 = 0;
+        }
+
+        impl sealed::Sealed for Dma2 {}
+        impl DmaInstance for Dma2 {
+            dma_interrupts!(
+                C1 => DMA2_CH1, C2 => DMA2_CH2, C3 => DMA2_CH3, C4 => DMA2_CH4,
+                C5 => DMA2_CH5, C6 => DMA2_CH6, C7 => DMA2_CH7,
+            );
+            const MUX_CHANNEL_OFFSET: u8 = 7;
+        }
+    } else if #[cfg(feature = "wl")] {
+        impl sealed::Sealed for pac::DMA1 {}
+        impl DmaInstance for pac::DMA1 {
+            dma_interrupts!(
+                C1 => DMA1_CH1, C2 => DMA1_CH2, C3 => DMA1_CH3, C4 => DMA1_CH4,
+                C5 => DMA1_CH5, C6 => DMA1_CH6, C7 => DMA1_CH7,
+            );
+            const MUX_CHANNEL_OFFSET: u8 = 0;
+        }
+
+        impl sealed::Sealed for pac::DMA2 {}
+        impl DmaInstance for pac::DMA2 {
+            dma_interrupts!(
+                C1 => DMA2_CH1, C2 => DMA2_CH2, C3 => DMA2_CH3, C4 => DMA2_CH4,
+                C5 => DMA2_CH5, C6 => DMA2_CH6, C7 => DMA2_CH7,
+            );
+            const MUX_CHANNEL_OFFSET: u8 = 7;
+        }
+    } else if #[cfg(feature = "l5")] {
+        impl sealed::Sealed for pac::DMA1 {}
+        impl DmaInstance for pac::DMA1 {
+            const MUX_CHANNEL_OFFSET: u8 = 0;
+        }
+
+        impl sealed::Sealed for pac::DMA2 {}
+        impl DmaInstance for pac::DMA2 {
+            const MUX_CHANNEL_OFFSET: u8 = 8;
+        }
+    } else {
+        // G0
+        impl sealed::Sealed for pac::DMA {}
+        impl DmaInstance for pac::DMA {
+            const MUX_CHANNEL_OFFSET: u8 = 0;
+        }
+    }
+}
+
 impl<D> Dma<D>
 where
     D: Deref<Target = dma::RegisterBlock>,
 {
     /// Initialize a DMA peripheral, including enabling and resetting
     /// its RCC peripheral clock.
-    pub fn new(regs: D) -> Self {
-        // todo: Enable RCC for DMA 2 etc!
+    pub fn new(regs: D) -> Self
+    where
+        D: DmaInstance,
+    {
         free(|_| {
             let rcc = unsafe { &(*RCC::ptr()) };
-            cfg_if! {
-                if #[cfg(feature = "f3")] {
-                    rcc.ahbenr.modify(|_, w| w.dma1en().set_bit()); // no dmarst on F3.
-                } else if #[cfg(feature = "g0")] {
-                    rcc_en_reset!(ahb1, dma, rcc);
-                } else {
-                    rcc_en_reset!(ahb1, dma1, rcc);
-                }
-            }
+            D::en_reset(rcc);
         });
 
         Self { regs }
@@ -535,12 +1021,18 @@ where
     ) {
         // See the comments in the H7 variant for a description of what's going on.
 
+        if let (Direction::MemToMem, Circular::Enabled) = (direction, cfg.circular) {
+            panic!("Circular mode can't be used with memory-to-memory transfers.");
+        }
+
         unsafe {
             match channel {
                 DmaChannel::C1 => {
                     cfg_if! {
                         if #[cfg(any(feature = "f3", feature = "g0"))] {
                             let cpar = &self.regs.ch1.par;
+                        } else if #[cfg(feature = "l5")] {
+                            let cpar = l5_chan_reg(&self.regs.cpar1, DmaChannel::C1);
                         } else {
                             let cpar = &self.regs.cpar1;
                         }
@@ -551,6 +1043,8 @@ where
                     cfg_if! {
                         if #[cfg(any(feature = "f3", feature = "g0"))] {
                             let cpar = &self.regs.ch2.par;
+                        } else if #[cfg(feature = "l5")] {
+                            let cpar = l5_chan_reg(&self.regs.cpar1, DmaChannel::C2);
                         } else {
                             let cpar = &self.regs.cpar2;
                         }
@@ -561,6 +1055,8 @@ where
                     cfg_if! {
                         if #[cfg(any(feature = "f3", feature = "g0"))] {
                             let cpar = &self.regs.ch3.par;
+                        } else if #[cfg(feature = "l5")] {
+                            let cpar = l5_chan_reg(&self.regs.cpar1, DmaChannel::C3);
                         } else {
                             let cpar = &self.regs.cpar3;
                         }
@@ -571,6 +1067,8 @@ where
                     cfg_if! {
                         if #[cfg(any(feature = "f3", feature = "g0"))] {
                             let cpar = &self.regs.ch4.par;
+                        } else if #[cfg(feature = "l5")] {
+                            let cpar = l5_chan_reg(&self.regs.cpar1, DmaChannel::C4);
                         } else {
                             let cpar = &self.regs.cpar4;
                         }
@@ -581,6 +1079,8 @@ where
                     cfg_if! {
                         if #[cfg(any(feature = "f3", feature = "g0"))] {
                             let cpar = &self.regs.ch5.par;
+                        } else if #[cfg(feature = "l5")] {
+                            let cpar = l5_chan_reg(&self.regs.cpar1, DmaChannel::C5);
                         } else {
                             let cpar = &self.regs.cpar5;
                         }
@@ -592,6 +1092,8 @@ where
                     cfg_if! {
                         if #[cfg(any(feature = "f3", feature = "g0"))] {
                             let cpar = &self.regs.ch6.par;
+                        } else if #[cfg(feature = "l5")] {
+                            let cpar = l5_chan_reg(&self.regs.cpar1, DmaChannel::C6);
                         } else {
                             let cpar = &self.regs.cpar6;
                         }
@@ -603,6 +1105,8 @@ where
                     cfg_if! {
                         if #[cfg(any(feature = "f3", feature = "g0"))] {
                             let cpar = &self.regs.ch7.par;
+                        } else if #[cfg(feature = "l5")] {
+                            let cpar = l5_chan_reg(&self.regs.cpar1, DmaChannel::C7);
                         } else {
                             let cpar = &self.regs.cpar7;
                         }
@@ -611,7 +1115,13 @@ where
                 }
                 #[cfg(any(feature = "l5", feature = "g4"))]
                 DmaChannel::C8 => {
-                    let cpar = &self.regs.cpar8;
+                    cfg_if! {
+                        if #[cfg(feature = "l5")] {
+                            let cpar = l5_chan_reg(&self.regs.cpar1, DmaChannel::C8);
+                        } else {
+                            let cpar = &self.regs.cpar8;
+                        }
+                    }
                     cpar.write(|w| w.bits(periph_addr));
                 }
             }
@@ -624,6 +1134,8 @@ where
                     cfg_if! {
                         if #[cfg(any(feature = "f3", feature = "g0"))] {
                             let cmar = &self.regs.ch1.mar;
+                        } else if #[cfg(feature = "l5")] {
+                            let cmar = l5_chan_reg(&self.regs.cm0ar1, DmaChannel::C1);
                         } else {
                             let cmar = &self.regs.cmar1;
                         }
@@ -634,6 +1146,8 @@ where
                     cfg_if! {
                         if #[cfg(any(feature = "f3", feature = "g0"))] {
                             let cmar = &self.regs.ch2.mar;
+                        } else if #[cfg(feature = "l5")] {
+                            let cmar = l5_chan_reg(&self.regs.cm0ar1, DmaChannel::C2);
                         } else {
                             let cmar = &self.regs.cmar2;
                         }
@@ -644,6 +1158,8 @@ where
                     cfg_if! {
                         if #[cfg(any(feature = "f3", feature = "g0"))] {
                             let cmar = &self.regs.ch3.mar;
+                        } else if #[cfg(feature = "l5")] {
+                            let cmar = l5_chan_reg(&self.regs.cm0ar1, DmaChannel::C3);
                         } else {
                             let cmar = &self.regs.cmar3;
                         }
@@ -654,6 +1170,8 @@ where
                     cfg_if! {
                         if #[cfg(any(feature = "f3", feature = "g0"))] {
                             let cmar = &self.regs.ch4.mar;
+                        } else if #[cfg(feature = "l5")] {
+                            let cmar = l5_chan_reg(&self.regs.cm0ar1, DmaChannel::C4);
                         } else {
                             let cmar = &self.regs.cmar4;
                         }
@@ -664,6 +1182,8 @@ where
                     cfg_if! {
                         if #[cfg(any(feature = "f3", feature = "g0"))] {
                             let cmar = &self.regs.ch5.mar;
+                        } else if #[cfg(feature = "l5")] {
+                            let cmar = l5_chan_reg(&self.regs.cm0ar1, DmaChannel::C5);
                         } else {
                             let cmar = &self.regs.cmar5;
                         }
@@ -675,6 +1195,8 @@ where
                     cfg_if! {
                         if #[cfg(any(feature = "f3", feature = "g0"))] {
                             let cmar = &self.regs.ch6.mar;
+                        } else if #[cfg(feature = "l5")] {
+                            let cmar = l5_chan_reg(&self.regs.cm0ar1, DmaChannel::C6);
                         } else {
                             let cmar = &self.regs.cmar6;
                         }
@@ -686,6 +1208,8 @@ where
                     cfg_if! {
                         if #[cfg(any(feature = "f3", feature = "g0"))] {
                             let cmar = &self.regs.ch7.mar;
+                        } else if #[cfg(feature = "l5")] {
+                            let cmar = l5_chan_reg(&self.regs.cm0ar1, DmaChannel::C7);
                         } else {
                             let cmar = &self.regs.cmar7;
                         }
@@ -694,13 +1218,19 @@ where
                 }
                 #[cfg(any(feature = "l5", feature = "g4"))]
                 DmaChannel::C8 => {
-                    let cmar = &self.regs.cmar8;
+                    cfg_if! {
+                        if #[cfg(feature = "l5")] {
+                            let cmar = l5_chan_reg(&self.regs.cm0ar1, DmaChannel::C8);
+                        } else {
+                            let cmar = &self.regs.cmar8;
+                        }
+                    }
                     cmar.write(|w| w.bits(mem_addr));
                 }
             }
         }
 
-        #[cfg(feature = "wl")]
+        #[cfg(any(feature = "l5", feature = "wl"))]
         let num_data = num_data as u32;
 
         unsafe {
@@ -709,6 +1239,8 @@ where
                     cfg_if! {
                         if #[cfg(any(feature = "f3", feature = "g0"))] {
                             let cndtr = &self.regs.ch1.ndtr;
+                        } else if #[cfg(feature = "l5")] {
+                            let cndtr = l5_chan_reg(&self.regs.cndtr1, DmaChannel::C1);
                         } else {
                             let cndtr = &self.regs.cndtr1;
                         }
@@ -719,6 +1251,8 @@ where
                     cfg_if! {
                         if #[cfg(any(feature = "f3", feature = "g0"))] {
                             let cndtr = &self.regs.ch2.ndtr;
+                        } else if #[cfg(feature = "l5")] {
+                            let cndtr = l5_chan_reg(&self.regs.cndtr1, DmaChannel::C2);
                         } else {
                             let cndtr = &self.regs.cndtr2;
                         }
@@ -729,6 +1263,8 @@ where
                     cfg_if! {
                         if #[cfg(any(feature = "f3", feature = "g0"))] {
                             let cndtr = &self.regs.ch3.ndtr;
+                        } else if #[cfg(feature = "l5")] {
+                            let cndtr = l5_chan_reg(&self.regs.cndtr1, DmaChannel::C3);
                         } else {
                             let cndtr = &self.regs.cndtr3;
                         }
@@ -739,6 +1275,8 @@ where
                     cfg_if! {
                         if #[cfg(any(feature = "f3", feature = "g0"))] {
                             let cndtr = &self.regs.ch4.ndtr;
+                        } else if #[cfg(feature = "l5")] {
+                            let cndtr = l5_chan_reg(&self.regs.cndtr1, DmaChannel::C4);
                         } else {
                             let cndtr = &self.regs.cndtr4;
                         }
@@ -749,6 +1287,8 @@ where
                     cfg_if! {
                         if #[cfg(any(feature = "f3", feature = "g0"))] {
                             let cndtr = &self.regs.ch5.ndtr;
+                        } else if #[cfg(feature = "l5")] {
+                            let cndtr = l5_chan_reg(&self.regs.cndtr1, DmaChannel::C5);
                         } else {
                             let cndtr = &self.regs.cndtr5;
                         }
@@ -760,6 +1300,8 @@ where
                     cfg_if! {
                         if #[cfg(any(feature = "f3", feature = "g0"))] {
                             let cndtr = &self.regs.ch6.ndtr;
+                        } else if #[cfg(feature = "l5")] {
+                            let cndtr = l5_chan_reg(&self.regs.cndtr1, DmaChannel::C6);
                         } else {
                             let cndtr = &self.regs.cndtr6;
                         }
@@ -771,6 +1313,8 @@ where
                     cfg_if! {
                         if #[cfg(any(feature = "f3", feature = "g0"))] {
                             let cndtr = &self.regs.ch7.ndtr;
+                        } else if #[cfg(feature = "l5")] {
+                            let cndtr = l5_chan_reg(&self.regs.cndtr1, DmaChannel::C7);
                         } else {
                             let cndtr = &self.regs.cndtr7;
                         }
@@ -779,7 +1323,13 @@ where
                 }
                 #[cfg(any(feature = "l5", feature = "g4"))]
                 DmaChannel::C8 => {
-                    let cndtr = &self.regs.cndtr8;
+                    cfg_if! {
+                        if #[cfg(feature = "l5")] {
+                            let cndtr = l5_chan_reg(&self.regs.cndtr1, DmaChannel::C8);
+                        } else {
+                            let cndtr = &self.regs.cndtr8;
+                        }
+                    }
                     cndtr.write(|w| w.ndt().bits(num_data));
                 }
             }
@@ -790,6 +1340,8 @@ where
                 cfg_if! {
                     if #[cfg(any(feature = "f3", feature = "g0"))] {
                         let ccr = &self.regs.ch1.cr;
+                    } else if #[cfg(feature = "l5")] {
+                        let ccr = l5_chan_reg(&self.regs.ccr1, DmaChannel::C1);
                     } else {
                         let ccr = &self.regs.ccr1;
                     }
@@ -809,6 +1361,8 @@ where
                 cfg_if! {
                     if #[cfg(any(feature = "f3", feature = "g0"))] {
                         let ccr = &self.regs.ch2.cr;
+                    } else if #[cfg(feature = "l5")] {
+                        let ccr = l5_chan_reg(&self.regs.ccr1, DmaChannel::C2);
                     } else {
                         let ccr = &self.regs.ccr2;
                     }
@@ -828,6 +1382,8 @@ where
                 cfg_if! {
                     if #[cfg(any(feature = "f3", feature = "g0"))] {
                         let ccr = &self.regs.ch3.cr;
+                    } else if #[cfg(feature = "l5")] {
+                        let ccr = l5_chan_reg(&self.regs.ccr1, DmaChannel::C3);
                     } else {
                         let ccr = &self.regs.ccr3;
                     }
@@ -847,6 +1403,8 @@ where
                 cfg_if! {
                     if #[cfg(any(feature = "f3", feature = "g0"))] {
                         let ccr = &self.regs.ch4.cr;
+                    } else if #[cfg(feature = "l5")] {
+                        let ccr = l5_chan_reg(&self.regs.ccr1, DmaChannel::C4);
                     } else {
                         let ccr = &self.regs.ccr4;
                     }
@@ -866,6 +1424,8 @@ where
                 cfg_if! {
                     if #[cfg(any(feature = "f3", feature = "g0"))] {
                         let ccr = &self.regs.ch5.cr;
+                    } else if #[cfg(feature = "l5")] {
+                        let ccr = l5_chan_reg(&self.regs.ccr1, DmaChannel::C5);
                     } else {
                         let ccr = &self.regs.ccr5;
                     }
@@ -886,6 +1446,8 @@ where
                 cfg_if! {
                     if #[cfg(any(feature = "f3", feature = "g0"))] {
                         let ccr = &self.regs.ch6.cr;
+                    } else if #[cfg(feature = "l5")] {
+                        let ccr = l5_chan_reg(&self.regs.ccr1, DmaChannel::C6);
                     } else {
                         let ccr = &self.regs.ccr6;
                     }
@@ -906,6 +1468,8 @@ where
                 cfg_if! {
                     if #[cfg(any(feature = "f3", feature = "g0"))] {
                         let ccr = &self.regs.ch7.cr;
+                    } else if #[cfg(feature = "l5")] {
+                        let ccr = l5_chan_reg(&self.regs.ccr1, DmaChannel::C7);
                     } else {
                         let ccr = &self.regs.ccr7;
                     }
@@ -923,7 +1487,13 @@ where
             }
             #[cfg(any(feature = "l5", feature = "g4"))]
             DmaChannel::C8 => {
-                let mut ccr = &self.regs.ccr8;
+                cfg_if! {
+                    if #[cfg(feature = "l5")] {
+                        let ccr = l5_chan_reg(&self.regs.ccr1, DmaChannel::C8);
+                    } else {
+                        let ccr = &self.regs.ccr8;
+                    }
+                }
                 set_ccr!(
                     ccr,
                     cfg.priority,
@@ -1008,7 +1578,9 @@ where
 
         // (See remainder of steps in `set_ccr()!` macro.
 
-        // todo: Let user set mem2mem mode?
+        if let (Direction::MemToMem, Circular::Enabled) = (direction, cfg.circular) {
+            panic!("Circular mode can't be used with memory-to-memory transfers.");
+        }
 
         // See the [Embedonomicon section on DMA](https://docs.rust-embedded.org/embedonomicon/dma.html)
         // for info on why we use `compiler_fence` here:
@@ -1056,6 +1628,8 @@ where
                 cfg_if! {
                     if #[cfg(any(feature = "f3", feature = "g0"))] {
                         let ccr = &self.regs.ch1.cr;
+                    } else if #[cfg(feature = "l5")] {
+                        let ccr = l5_chan_reg(&self.regs.ccr1, DmaChannel::C1);
                     } else {
                         let ccr = &self.regs.ccr1;
                     }
@@ -1067,6 +1641,8 @@ where
                 cfg_if! {
                     if #[cfg(any(feature = "f3", feature = "g0"))] {
                         let ccr = &self.regs.ch2.cr;
+                    } else if #[cfg(feature = "l5")] {
+                        let ccr = l5_chan_reg(&self.regs.ccr1, DmaChannel::C2);
                     } else {
                         let ccr = &self.regs.ccr2;
                     }
@@ -1078,6 +1654,8 @@ where
                 cfg_if! {
                     if #[cfg(any(feature = "f3", feature = "g0"))] {
                         let ccr = &self.regs.ch3.cr;
+                    } else if #[cfg(feature = "l5")] {
+                        let ccr = l5_chan_reg(&self.regs.ccr1, DmaChannel::C3);
                     } else {
                         let ccr = &self.regs.ccr3;
                     }
@@ -1089,6 +1667,8 @@ where
                 cfg_if! {
                     if #[cfg(any(feature = "f3", feature = "g0"))] {
                         let ccr = &self.regs.ch4.cr;
+                    } else if #[cfg(feature = "l5")] {
+                        let ccr = l5_chan_reg(&self.regs.ccr1, DmaChannel::C4);
                     } else {
                         let ccr = &self.regs.ccr4;
                     }
@@ -1100,6 +1680,8 @@ where
                 cfg_if! {
                     if #[cfg(any(feature = "f3", feature = "g0"))] {
                         let ccr = &self.regs.ch5.cr;
+                    } else if #[cfg(feature = "l5")] {
+                        let ccr = l5_chan_reg(&self.regs.ccr1, DmaChannel::C5);
                     } else {
                         let ccr = &self.regs.ccr5;
                     }
@@ -1112,6 +1694,8 @@ where
                 cfg_if! {
                     if #[cfg(any(feature = "f3", feature = "g0"))] {
                         let ccr = &self.regs.ch6.cr;
+                    } else if #[cfg(feature = "l5")] {
+                        let ccr = l5_chan_reg(&self.regs.ccr1, DmaChannel::C6);
                     } else {
                         let ccr = &self.regs.ccr6;
                     }
@@ -1124,6 +1708,8 @@ where
                 cfg_if! {
                     if #[cfg(any(feature = "f3", feature = "g0"))] {
                         let ccr = &self.regs.ch7.cr;
+                    } else if #[cfg(feature = "l5")] {
+                        let ccr = l5_chan_reg(&self.regs.ccr1, DmaChannel::C7);
                     } else {
                         let ccr = &self.regs.ccr7;
                     }
@@ -1133,7 +1719,13 @@ where
             }
             #[cfg(any(feature = "l5", feature = "g4"))]
             DmaChannel::C8 => {
-                let ccr = &self.regs.ccr8;
+                cfg_if! {
+                    if #[cfg(feature = "l5")] {
+                        let ccr = l5_chan_reg(&self.regs.ccr1, DmaChannel::C8);
+                    } else {
+                        let ccr = &self.regs.ccr8;
+                    }
+                }
                 ccr.modify(|_, w| w.en().clear_bit());
                 while ccr.read().en().bit_is_set() {}
             }
@@ -1241,6 +1833,8 @@ where
                 cfg_if! {
                     if #[cfg(any(feature = "f3", feature = "g0"))] {
                         let ccr = &self.regs.ch1.cr;
+                    } else if #[cfg(feature = "l5")] {
+                        let ccr = l5_chan_reg(&self.regs.ccr1, DmaChannel::C1);
                     } else {
                         let ccr = &self.regs.ccr1;
                     }
@@ -1251,6 +1845,8 @@ where
                 cfg_if! {
                     if #[cfg(any(feature = "f3", feature = "g0"))] {
                         let ccr = &self.regs.ch2.cr;
+                    } else if #[cfg(feature = "l5")] {
+                        let ccr = l5_chan_reg(&self.regs.ccr1, DmaChannel::C2);
                     } else {
                         let ccr = &self.regs.ccr2;
                     }
@@ -1261,6 +1857,8 @@ where
                 cfg_if! {
                     if #[cfg(any(feature = "f3", feature = "g0"))] {
                         let ccr = &self.regs.ch3.cr;
+                    } else if #[cfg(feature = "l5")] {
+                        let ccr = l5_chan_reg(&self.regs.ccr1, DmaChannel::C3);
                     } else {
                         let ccr = &self.regs.ccr3;
                     }
@@ -1271,6 +1869,8 @@ where
                 cfg_if! {
                     if #[cfg(any(feature = "f3", feature = "g0"))] {
                         let ccr = &self.regs.ch4.cr;
+                    } else if #[cfg(feature = "l5")] {
+                        let ccr = l5_chan_reg(&self.regs.ccr1, DmaChannel::C4);
                     } else {
                         let ccr = &self.regs.ccr4;
                     }
@@ -1281,6 +1881,8 @@ where
                 cfg_if! {
                     if #[cfg(any(feature = "f3", feature = "g0"))] {
                         let ccr = &self.regs.ch5.cr;
+                    } else if #[cfg(feature = "l5")] {
+                        let ccr = l5_chan_reg(&self.regs.ccr1, DmaChannel::C5);
                     } else {
                         let ccr = &self.regs.ccr5;
                     }
@@ -1292,6 +1894,8 @@ where
                 cfg_if! {
                     if #[cfg(any(feature = "f3", feature = "g0"))] {
                         let ccr = &self.regs.ch6.cr;
+                    } else if #[cfg(feature = "l5")] {
+                        let ccr = l5_chan_reg(&self.regs.ccr1, DmaChannel::C6);
                     } else {
                         let ccr = &self.regs.ccr6;
                     }
@@ -1303,6 +1907,8 @@ where
                 cfg_if! {
                     if #[cfg(any(feature = "f3", feature = "g0"))] {
                         let ccr = &self.regs.ch7.cr;
+                    } else if #[cfg(feature = "l5")] {
+                        let ccr = l5_chan_reg(&self.regs.ccr1, DmaChannel::C7);
                     } else {
                         let ccr = &self.regs.ccr7;
                     }
@@ -1311,7 +1917,13 @@ where
             }
             #[cfg(any(feature = "l5", feature = "g4"))]
             DmaChannel::C8 => {
-                let ccr = &self.regs.ccr8;
+                cfg_if! {
+                    if #[cfg(feature = "l5")] {
+                        let ccr = l5_chan_reg(&self.regs.ccr1, DmaChannel::C8);
+                    } else {
+                        let ccr = &self.regs.ccr8;
+                    }
+                }
                 enable_interrupt!(ccr, interrupt);
             }
         };
@@ -1387,42 +1999,50 @@ where
                         DmaInterrupt::TransferError => w.teif1().set_bit(),
                         DmaInterrupt::HalfTransfer => w.htif1().set_bit(),
                         DmaInterrupt::TransferComplete => w.tcif1().set_bit(),
+                        DmaInterrupt::Global => w.gif1().set_bit(),
                     }
                     DmaChannel::C2 => match interrupt {
                         DmaInterrupt::TransferError => w.teif2().set_bit(),
                         DmaInterrupt::HalfTransfer => w.htif2().set_bit(),
                         DmaInterrupt::TransferComplete => w.tcif2().set_bit(),
+                        DmaInterrupt::Global => w.gif2().set_bit(),
                     }
                     DmaChannel::C3 => match interrupt {
                         DmaInterrupt::TransferError => w.teif3().set_bit(),
                         DmaInterrupt::HalfTransfer => w.htif3().set_bit(),
                         DmaInterrupt::TransferComplete => w.tcif3().set_bit(),
+                        DmaInterrupt::Global => w.gif3().set_bit(),
                     }
                     DmaChannel::C4 => match interrupt {
                         DmaInterrupt::TransferError => w.teif4().set_bit(),
                         DmaInterrupt::HalfTransfer => w.htif4().set_bit(),
                         DmaInterrupt::TransferComplete => w.tcif4().set_bit(),
+                        DmaInterrupt::Global => w.gif4().set_bit(),
                     }
                     DmaChannel::C5 => match interrupt {
                         DmaInterrupt::TransferError => w.teif5().set_bit(),
                         DmaInterrupt::HalfTransfer => w.htif5().set_bit(),
                         DmaInterrupt::TransferComplete => w.tcif5().set_bit(),
+                        DmaInterrupt::Global => w.gif5().set_bit(),
                     }
                     DmaChannel::C6 => match interrupt {
                         DmaInterrupt::TransferError => w.teif6().set_bit(),
                         DmaInterrupt::HalfTransfer => w.htif6().set_bit(),
                         DmaInterrupt::TransferComplete => w.tcif6().set_bit(),
+                        DmaInterrupt::Global => w.gif6().set_bit(),
                     }
                     DmaChannel::C7 => match interrupt {
                         DmaInterrupt::TransferError => w.teif7().set_bit(),
                         DmaInterrupt::HalfTransfer => w.htif7().set_bit(),
                         DmaInterrupt::TransferComplete => w.tcif7().set_bit(),
+                        DmaInterrupt::Global => w.gif7().set_bit(),
                     }
                     #[cfg(not(feature = "wl"))]
                     DmaChannel::C8 => match interrupt {
                         DmaInterrupt::TransferError => w.teif8().set_bit(),
                         DmaInterrupt::HalfTransfer => w.htif8().set_bit(),
                         DmaInterrupt::TransferComplete => w.tcif8().set_bit(),
+                        DmaInterrupt::Global => w.gif8().set_bit(),
                     }
                 });
             } else if #[cfg(feature = "h7")] {
@@ -1491,49 +2111,222 @@ where
                         DmaInterrupt::TransferError => w.cteif1().set_bit(),
                         DmaInterrupt::HalfTransfer => w.chtif1().set_bit(),
                         DmaInterrupt::TransferComplete => w.ctcif1().set_bit(),
+                        DmaInterrupt::Global => w.cgif1().set_bit(),
                     }
                     DmaChannel::C2 => match interrupt {
                         DmaInterrupt::TransferError => w.cteif2().set_bit(),
                         DmaInterrupt::HalfTransfer => w.chtif2().set_bit(),
                         DmaInterrupt::TransferComplete => w.ctcif2().set_bit(),
+                        DmaInterrupt::Global => w.cgif2().set_bit(),
                     }
                     DmaChannel::C3 => match interrupt {
                         DmaInterrupt::TransferError => w.cteif3().set_bit(),
                         DmaInterrupt::HalfTransfer => w.chtif3().set_bit(),
                         DmaInterrupt::TransferComplete => w.ctcif3().set_bit(),
+                        DmaInterrupt::Global => w.cgif3().set_bit(),
                     }
                     DmaChannel::C4 => match interrupt {
                         DmaInterrupt::TransferError => w.cteif4().set_bit(),
                         DmaInterrupt::HalfTransfer => w.chtif4().set_bit(),
                         DmaInterrupt::TransferComplete => w.ctcif4().set_bit(),
+                        DmaInterrupt::Global => w.cgif4().set_bit(),
                     }
                     DmaChannel::C5 => match interrupt {
                         DmaInterrupt::TransferError => w.cteif5().set_bit(),
                         DmaInterrupt::HalfTransfer => w.chtif5().set_bit(),
                         DmaInterrupt::TransferComplete => w.ctcif5().set_bit(),
+                        DmaInterrupt::Global => w.cgif5().set_bit(),
                     }
                     #[cfg(not(feature = "g0"))]
                     DmaChannel::C6 => match interrupt {
                         DmaInterrupt::TransferError => w.cteif6().set_bit(),
                         DmaInterrupt::HalfTransfer => w.chtif6().set_bit(),
                         DmaInterrupt::TransferComplete => w.ctcif6().set_bit(),
+                        DmaInterrupt::Global => w.cgif6().set_bit(),
                     }
                     #[cfg(not(feature = "g0"))]
                     DmaChannel::C7 => match interrupt {
                         DmaInterrupt::TransferError => w.cteif7().set_bit(),
                         DmaInterrupt::HalfTransfer => w.chtif7().set_bit(),
                         DmaInterrupt::TransferComplete => w.ctcif7().set_bit(),
+                        DmaInterrupt::Global => w.cgif7().set_bit(),
                     }
                     #[cfg(any(feature = "l5", feature = "g4"))]
                     DmaChannel::C8 => match interrupt {
                         DmaInterrupt::TransferError => w.cteif8().set_bit(),
                         DmaInterrupt::HalfTransfer => w.chtif8().set_bit(),
                         DmaInterrupt::TransferComplete => w.ctcif8().set_bit(),
+                        DmaInterrupt::Global => w.cgif8().set_bit(),
                     }
                 });
             }
         }
     }
+
+    /// Read all of a channel's interrupt flags. Unlike `pending_event`, this reports every flag
+    /// that's set, eg both half transfer and transfer complete, if the ISR ran late.
+    #[cfg(not(feature = "g0"))]
+    pub fn pending(&self, channel: DmaChannel) -> DmaEventFlags {
+        cfg_if! {
+            if #[cfg(feature = "h7")] {
+                // See `pending_event` for the flag layout.
+                let ch = channel as u32;
+                let isr = if ch < 4 {
+                    self.regs.lisr.read().bits()
+                } else {
+                    self.regs.hisr.read().bits()
+                };
+                let flags = isr >> [0, 6, 16, 22][(ch % 4) as usize];
+
+                DmaEventFlags {
+                    transfer_complete: flags & 0b10_0000 != 0,
+                    half_transfer: flags & 0b01_0000 != 0,
+                    transfer_error: flags & 0b00_1000 != 0,
+                    direct_mode_error: flags & 0b00_0100 != 0,
+                    fifo_error: flags & 0b00_0001 != 0,
+                }
+            } else {
+                let flags = self.regs.isr.read().bits() >> (4 * (channel as u32 - 1));
+
+                DmaEventFlags {
+                    transfer_complete: flags & 0b0010 != 0,
+                    half_transfer: flags & 0b0100 != 0,
+                    transfer_error: flags & 0b1000 != 0,
+                }
+            }
+        }
+    }
+
+    /// Clear all of a channel's interrupt flags, in a single write to the flag clear register.
+    #[cfg(not(feature = "g0"))]
+    pub fn clear_all(&mut self, channel: DmaChannel) {
+        cfg_if! {
+            if #[cfg(feature = "h7")] {
+                // TCIF, HTIF, TEIF, DMEIF, and FEIF; bit 1 is reserved.
+                let ch = channel as u32;
+                let bits = 0b11_1101 << [0, 6, 16, 22][(ch % 4) as usize];
+                if ch < 4 {
+                    self.regs.lifcr.write(|w| unsafe { w.bits(bits) });
+                } else {
+                    self.regs.hifcr.write(|w| unsafe { w.bits(bits) });
+                }
+            } else {
+                // "CGIFx: global interrupt flag clear for channel x: Setting this bit clears the
+                // GIFx, TEIFx, HTIFx and TCIFx flags in the DMA_ISR register."
+                self.regs
+                    .ifcr
+                    .write(|w| unsafe { w.bits(1 << (4 * (channel as u32 - 1))) });
+            }
+        }
+    }
+
+    /// Read a channel's interrupt flags, and return the event that's pending, if any. Error
+    /// events take priority, followed by transfer complete, then half transfer. This doesn't
+    /// clear the flag; see `on_interrupt`.
+    #[cfg(not(feature = "g0"))]
+    pub fn pending_event(&self, channel: DmaChannel) -> Option<DmaEvent> {
+        cfg_if! {
+            if #[cfg(feature = "h7")] {
+                // Each stream has 6 flag bits in DMA_LISR (streams 0-3) or DMA_HISR (streams 4-7),
+                // at offsets 0, 6, 16, and 22: FEIF, (reserved), DMEIF, TEIF, HTIF, TCIF.
+                let ch = channel as u32;
+                let isr = if ch < 4 {
+                    self.regs.lisr.read().bits()
+                } else {
+                    self.regs.hisr.read().bits()
+                };
+                let flags = isr >> [0, 6, 16, 22][(ch % 4) as usize];
+
+                if flags & 0b00_1000 != 0 {
+                    Some(DmaEvent::TransferError)
+                } else if flags & 0b00_0100 != 0 {
+                    Some(DmaEvent::DirectModeError)
+                } else if flags & 0b00_0001 != 0 {
+                    Some(DmaEvent::FifoError)
+                } else if flags & 0b10_0000 != 0 {
+                    Some(DmaEvent::TransferComplete)
+                } else if flags & 0b01_0000 != 0 {
+                    Some(DmaEvent::HalfTransfer)
+                } else {
+                    None
+                }
+            } else {
+                // Each channel has 4 flag bits in DMA_ISR, starting at bit 4 * (x - 1):
+                // GIFx, TCIFx, HTIFx, TEIFx.
+                let flags = self.regs.isr.read().bits() >> (4 * (channel as u32 - 1));
+
+                if flags & 0b1000 != 0 {
+                    Some(DmaEvent::TransferError)
+                } else if flags & 0b0010 != 0 {
+                    Some(DmaEvent::TransferComplete)
+                } else if flags & 0b0100 != 0 {
+                    Some(DmaEvent::HalfTransfer)
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    /// Call this at the top of a channel's interrupt handler. Reads the channel's interrupt flags,
+    /// clears the highest-priority one that's set, and returns its event. (See `pending_event`
+    /// for priority) If more than one flag is set, the interrupt fires again for the others.
+    /// Returns `None` if no flag is set for this channel.
+    #[cfg(not(feature = "g0"))]
+    pub fn on_interrupt(&mut self, channel: DmaChannel) -> Option<DmaEvent> {
+        let event = self.pending_event(channel)?;
+        self.clear_interrupt(channel, event.into());
+        Some(event)
+    }
+
+    /// Read the number of data items a channel has left to transfer. (DMA_CNDTRx, or DMA_SxNDTR
+    /// on H7) In circular mode, this counts down from the `num_data` passed to `cfg_channel`, and
+    /// reloads at the end of each pass.
+    pub fn transfer_remaining(&self, channel: DmaChannel) -> u16 {
+        cfg_if! {
+            if #[cfg(feature = "h7")] {
+                self.regs.st[channel as usize].ndtr.read().bits() as u16
+            } else {
+                // Each channel's CNDTR is at offset 0x0c + 0x14 * (x - 1), on all families. We read
+                // it this way due to the inconsistent channel register names across PACs.
+                let base = &*self.regs as *const dma::RegisterBlock as *const u8;
+                let offset = 0x0c + 0x14 * (channel as usize - 1);
+                let cndtr = unsafe { core::ptr::read_volatile(base.add(offset) as *const u32) };
+                (cndtr & 0xffff) as u16
+            }
+        }
+    }
+
+    /// Wait for a channel's transfer to complete, sleeping with `WFE` until it does. Enables the
+    /// channel's transfer complete and transfer error interrupts; `interrupt` is the channel's
+    /// NVIC interrupt, which should be left masked. See the `async_wfe` module for details.
+    /// Returns the error event if the transfer fails.
+    #[cfg(all(feature = "async_wfe", not(feature = "g0")))]
+    pub async fn wait_transfer_complete(
+        &mut self,
+        channel: DmaChannel,
+        interrupt: pac::Interrupt,
+    ) -> Result<(), DmaEvent> {
+        self.enable_interrupt(channel, DmaInterrupt::TransferComplete);
+        self.enable_interrupt(channel, DmaInterrupt::TransferError);
+
+        let event = crate::async_wfe::wait_for(Some(interrupt), || {
+            match self.pending_event(channel) {
+                Some(DmaEvent::HalfTransfer) | None => None,
+                Some(event) => {
+                    // Clear the flag before the NVIC pending bit is cleared.
+                    self.clear_interrupt(channel, event.into());
+                    Some(event)
+                }
+            }
+        })
+        .await;
+
+        match event {
+            DmaEvent::TransferComplete => Ok(()),
+            _ => Err(event),
+        }
+    }
 }
 
 #[cfg(any(
@@ -1592,6 +2385,44 @@ pub fn mux(channel: DmaChannel, input: DmaInput, mux: &mut DMAMUX) {
     }
 }
 
+#[cfg(any(feature = "wb", feature = "wl"))]
+/// Configure a DMA channel on either DMA1 or DMA2 to work with a specific peripheral. DMAMUX
+/// channels 0 - 6 are connected to DMA1 channels 1 - 7, and DMAMUX channels 7 - 13 to DMA2
+/// channels 1 - 7. This is called by peripheral modules' DMA methods. Make sure the DMAMUX clock
+/// is enabled first, using `enable_mux1()`.
+pub fn mux_dma<D>(dma: &Dma<D>, channel: DmaChannel, input: DmaInput)
+where
+    D: Deref<Target = dma::RegisterBlock>,
+{
+    let mux = unsafe { &(*DMAMUX::ptr()) };
+
+    let is_dma2 = &*dma.regs as *const _ as u32 == pac::DMA2::ptr() as u32;
+
+    unsafe {
+        if is_dma2 {
+            match channel {
+                DmaChannel::C1 => mux.c7cr.modify(|_, w| w.dmareq_id().bits(input as u8)),
+                DmaChannel::C2 => mux.c8cr.modify(|_, w| w.dmareq_id().bits(input as u8)),
+                DmaChannel::C3 => mux.c9cr.modify(|_, w| w.dmareq_id().bits(input as u8)),
+                DmaChannel::C4 => mux.c10cr.modify(|_, w| w.dmareq_id().bits(input as u8)),
+                DmaChannel::C5 => mux.c11cr.modify(|_, w| w.dmareq_id().bits(input as u8)),
+                DmaChannel::C6 => mux.c12cr.modify(|_, w| w.dmareq_id().bits(input as u8)),
+                DmaChannel::C7 => mux.c13cr.modify(|_, w| w.dmareq_id().bits(input as u8)),
+            }
+        } else {
+            match channel {
+                DmaChannel::C1 => mux.c0cr.modify(|_, w| w.dmareq_id().bits(input as u8)),
+                DmaChannel::C2 => mux.c1cr.modify(|_, w| w.dmareq_id().bits(input as u8)),
+                DmaChannel::C3 => mux.c2cr.modify(|_, w| w.dmareq_id().bits(input as u8)),
+                DmaChannel::C4 => mux.c3cr.modify(|_, w| w.dmareq_id().bits(input as u8)),
+                DmaChannel::C5 => mux.c4cr.modify(|_, w| w.dmareq_id().bits(input as u8)),
+                DmaChannel::C6 => mux.c5cr.modify(|_, w| w.dmareq_id().bits(input as u8)),
+                DmaChannel::C7 => mux.c6cr.modify(|_, w| w.dmareq_id().bits(input as u8)),
+            }
+        }
+    }
+}
+
 #[cfg(feature = "h7")]
 /// Configure a specific DMA channel to work with a specific peripheral, on DMAMUX2.
 pub fn mux2(channel: DmaChannel, input: DmaInput2, mux: &mut DMAMUX2) {
@@ -1601,7 +2432,7 @@ pub fn mux2(channel: DmaChannel, input: DmaInput2, mux: &mut DMAMUX2) {
 // todo: Enable this for other MCUs as requried
 /// Enable the DMA mux RCC clock. Applicable to some variants, but no others. (H7 and G0 don't use it,
 /// for example)
-#[cfg(any(feature = "g4", feature = "wb"))]
+#[cfg(any(feature = "g4", feature = "wb", feature = "wl"))]
 pub fn enable_mux1() {
     free(|_| {
         let rcc = unsafe { &(*RCC::ptr()) };
@@ -1612,9 +2443,435 @@ pub fn enable_mux1() {
                 rcc.ahb1enr.modify(|_, w| w.dmamuxen().set_bit());
                 rcc.ahb1rstr.modify(|_, w| w.dmamux1rst().set_bit());
                 rcc.ahb1rstr.modify(|_, w| w.dmamux1rst().clear_bit());
-            } else {
+            } else if #[cfg(feature = "wb")] {
                 rcc_en_reset!(ahb1, dmamux, rcc);
+            } else {
+                rcc_en_reset!(ahb1, dmamux1, rcc);
             }
         }
     });
 }
+
+/// Create a free function that services a DMA1 channel's interrupt, for use in interrupt handlers
+/// where the `Dma` struct isn't available.
+#[cfg(not(feature = "g0"))]
+macro_rules! on_interrupt_fn {
+    ($fn_name:ident, $channel:expr) => {
+        /// Call this at the top of this DMA1 channel's interrupt handler. Reads and clears
+        /// the channel's highest-priority interrupt flag, and returns its event. See `Dma::on_interrupt`.
+        pub fn $fn_name() -> Option<DmaEvent> {
+            let mut dma = Dma {
+                regs: unsafe { &(*pac::DMA1::ptr()) },
+            };
+            dma.on_interrupt($channel)
+        }
+    };
+}
+
+#[cfg(feature = "h7")]
+on_interrupt_fn!(on_interrupt_channel0, DmaChannel::C0);
+#[cfg(not(feature = "g0"))]
+on_interrupt_fn!(on_interrupt_channel1, DmaChannel::C1);
+#[cfg(not(feature = "g0"))]
+on_interrupt_fn!(on_interrupt_channel2, DmaChannel::C2);
+#[cfg(not(feature = "g0"))]
+on_interrupt_fn!(on_interrupt_channel3, DmaChannel::C3);
+#[cfg(not(feature = "g0"))]
+on_interrupt_fn!(on_interrupt_channel4, DmaChannel::C4);
+#[cfg(not(feature = "g0"))]
+on_interrupt_fn!(on_interrupt_channel5, DmaChannel::C5);
+#[cfg(not(feature = "g0"))]
+on_interrupt_fn!(on_interrupt_channel6, DmaChannel::C6);
+#[cfg(not(feature = "g0"))]
+on_interrupt_fn!(on_interrupt_channel7, DmaChannel::C7);
+#[cfg(any(feature = "l5", feature = "g4"))]
+on_interrupt_fn!(on_interrupt_channel8, DmaChannel::C8);
+
+/// Copies shorter than this, in bytes, are done by the CPU: For these, configuring the channel takes
+/// longer than the copy itself.
+#[cfg(not(any(feature = "h7", feature = "g0")))]
+pub const MEMCPY_DMA_THRESHOLD: usize = 64;
+
+/// A memory copy service, using a DMA channel reserved for it in memory-to-memory mode. This frees
+/// the CPU during large copies, eg of framebuffers or packets. Word-aligned copies are transferred
+/// 32 bits at a time; others, a byte at a time.
+///
+/// Example, with a completion callback:
+/// ```
+/// fn frame_copied(result: Result<(), MemcpyError>) {
+///     // ...
+/// }
+///
+/// let mut memcpy = DmaMemcpy::new(DmaChannel::C7);
+///
+/// unsafe { memcpy.copy(&mut dma, &mut FRAME_OUT, &FRAME_IN, Some(frame_copied)) };
+///
+/// // In the channel's interrupt handler:
+/// memcpy.on_interrupt(&mut dma);
+/// ```
+///
+/// todo: H7, where memory-to-memory transfers require FIFO mode, and G0.
+#[cfg(not(any(feature = "h7", feature = "g0")))]
+pub struct DmaMemcpy {
+    channel: DmaChannel,
+    callback: Option<fn(Result<(), MemcpyError>)>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// A `DmaMemcpy` copy was aborted by a DMA transfer error, eg due to an address the DMA controller
+/// can't access. `dst`'s contents are undefined.
+#[cfg(not(any(feature = "h7", feature = "g0")))]
+pub struct MemcpyError {}
+
+#[cfg(not(any(feature = "h7", feature = "g0")))]
+impl DmaMemcpy {
+    /// Create the service, using a DMA channel that isn't used for anything else.
+    pub fn new(channel: DmaChannel) -> Self {
+        Self {
+            channel,
+            callback: None,
+        }
+    }
+
+    /// Start copying `src` into `dst`, which must be the same length, and return immediately.
+    /// `callback` runs from `on_interrupt` once the copy is complete, or has failed. Returns
+    /// `false` if the copy was small enough to be done by the CPU, in which case it's already
+    /// complete, and `callback` has already run.
+    ///
+    /// Safety: `src` and `dst` must remain valid, and `dst` must not be accessed, until the copy is
+    /// complete. Eg, use buffers created with `dma_buffer!`, or other statics.
+    pub unsafe fn copy<D>(
+        &mut self,
+        dma: &mut Dma<D>,
+        dst: &mut [u8],
+        src: &[u8],
+        callback: Option<fn(Result<(), MemcpyError>)>,
+    ) -> bool
+    where
+        D: Deref<Target = dma::RegisterBlock>,
+    {
+        assert_eq!(dst.len(), src.len());
+
+        if src.len() < MEMCPY_DMA_THRESHOLD {
+            dst.copy_from_slice(src);
+            if let Some(cb) = callback {
+                cb(Ok(()));
+            }
+            return false;
+        }
+
+        self.callback = callback;
+        self.start(dma, dst, src);
+        true
+    }
+
+    /// Copy `src` into `dst`, which must be the same length, blocking until complete, or until
+    /// a transfer error.
+    pub fn copy_blocking<D>(
+        &mut self,
+        dma: &mut Dma<D>,
+        dst: &mut [u8],
+        src: &[u8],
+    ) -> Result<(), MemcpyError>
+    where
+        D: Deref<Target = dma::RegisterBlock>,
+    {
+        assert_eq!(dst.len(), src.len());
+
+        if src.len() < MEMCPY_DMA_THRESHOLD {
+            dst.copy_from_slice(src);
+            return Ok(());
+        }
+
+        self.callback = None;
+        unsafe { self.start(dma, dst, src) };
+
+        // `cfg_channel` enables the transfer complete interrupt, so an interrupt handler that
+        // calls `on_interrupt` may clear TCIF before we see it. The count reaching 0 isn't
+        // affected by that. On a transfer error, the hardware disables the channel, and the count
+        // stops where it was.
+        while dma.transfer_remaining(self.channel) != 0 {
+            if dma.pending(self.channel).transfer_error {
+                self.finish(dma);
+                return Err(MemcpyError {});
+            }
+        }
+        self.finish(dma);
+
+        Ok(())
+    }
+
+    /// Call this from the channel's interrupt handler. If the copy is complete, or has failed,
+    /// clears the channel's flags, and runs the callback passed to `copy` with the result.
+    pub fn on_interrupt<D>(&mut self, dma: &mut Dma<D>)
+    where
+        D: Deref<Target = dma::RegisterBlock>,
+    {
+        let flags = dma.pending(self.channel);
+        if !flags.transfer_complete && !flags.transfer_error {
+            return;
+        }
+        self.finish(dma);
+
+        let result = if flags.transfer_error {
+            Err(MemcpyError {})
+        } else {
+            Ok(())
+        };
+
+        if let Some(cb) = self.callback.take() {
+            cb(result);
+        }
+    }
+
+    unsafe fn start<D>(&mut self, dma: &mut Dma<D>, dst: &mut [u8], src: &[u8])
+    where
+        D: Deref<Target = dma::RegisterBlock>,
+    {
+        let (src_addr, dst_addr) = (src.as_ptr() as u32, dst.as_mut_ptr() as u32);
+
+        let size = if src_addr % 4 == 0 && dst_addr % 4 == 0 && src.len() % 4 == 0 {
+            DataSize::S32
+        } else {
+            DataSize::S8
+        };
+        let num_data = match size {
+            DataSize::S32 => src.len() / 4,
+            _ => src.len(),
+        };
+        assert!(
+            num_data <= u16::MAX as usize,
+            "Copy is too large for a single transfer."
+        );
+
+        dma.clear_all(self.channel);
+        // `cfg_channel` only enables the transfer complete interrupt. Enable the transfer error
+        // interrupt too, so `on_interrupt` runs if the copy fails. The channel is disabled here.
+        dma.enable_interrupt(self.channel, DmaInterrupt::TransferError);
+
+        atomic::compiler_fence(Ordering::Release);
+
+        // In memory-to-memory mode, the peripheral address is the source.
+        dma.cfg_channel(
+            self.channel,
+            src_addr,
+            dst_addr,
+            num_data as u16,
+            Direction::MemToMem,
+            size,
+            size,
+            ChannelCfg::mem_to_mem(),
+        );
+    }
+
+    fn finish<D>(&mut self, dma: &mut Dma<D>)
+    where
+        D: Deref<Target = dma::RegisterBlock>,
+    {
+        dma.stop(self.channel);
+        dma.clear_all(self.channel);
+
+        atomic::compiler_fence(Ordering::Acquire);
+    }
+}
+
+/// Estimated AHB cycles the DMA controller takes per transfer: Arbitration, then a read and a
+/// write of at least 2 cycles each. See L4 RM, section 11.4.3: "DMA transfers". Accesses to APB
+/// peripherals, or slow memory, take longer.
+const DMA_CYCLES_PER_TRANSFER: f32 = 5.;
+
+#[derive(Clone, Copy)]
+/// A DMA channel's expected load, for `check_bandwidth`.
+pub struct ChannelLoad {
+    pub channel: DmaChannel,
+    pub priority: Priority,
+    /// The size of each transfer; the smaller of the peripheral and memory sizes.
+    pub size: DataSize,
+    /// The rate the peripheral requests data at, in bytes per second. Eg, 2 channels of 12-bit
+    /// ADC readings at 100kHz, in 16-bit words, are 400,000. `None` for memory-to-memory
+    /// transfers, which run back-to-back, without waiting for requests.
+    pub bytes_per_sec: Option<f32>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// A likely cause of overruns, or dropped data, found by `check_bandwidth`.
+pub enum BandwidthWarning {
+    /// The peripheral channels' requests exceed the DMA controller's capacity. Contains the
+    /// estimated occupancy, as a fraction.
+    Saturated(f32),
+    /// A memory-to-memory channel (`hog`) outranks `channel`, so `channel` isn't serviced
+    /// until the memory-to-memory transfer completes. Give `channel` a higher priority, or use a
+    /// channel with a lower index.
+    Starved {
+        channel: DmaChannel,
+        hog: DmaChannel,
+    },
+    /// The worst-case time `channel` waits for service, in seconds, exceeds the interval between
+    /// its requests, so the peripheral may overrun.
+    Overrun {
+        channel: DmaChannel,
+        latency: f32,
+        interval: f32,
+    },
+}
+
+/// Returns true if `a` is serviced before `b` when both request at once: By priority, then by
+/// lowest channel index.
+fn outranks(a: &ChannelLoad, b: &ChannelLoad) -> bool {
+    (a.priority as u8, b.channel as u8) > (b.priority as u8, a.channel as u8)
+}
+
+/// Estimate how heavily the channels of one DMA controller are loaded, and check for likely
+/// overrun scenarios, eg a high-rate ADC channel blocked by a memory-to-memory copy, before
+/// debugging dropped samples on hardware. `ahb_freq` is the AHB clock frequency, in Hz, eg from
+/// `Clocks::hclk()`. Returns the estimated fraction of the controller's time the peripheral
+/// channels occupy, or the first problem found.
+///
+/// This is a coarse model: It assumes each transfer takes a fixed number of AHB cycles, and
+/// ignores contention with the CPU for the bus. On H7, the stream FIFOs absorb some latency, so
+/// `Overrun` warnings are conservative there. Treat an occupancy over about 0.5 as a risk.
+///
+/// Example:
+/// ```
+/// let loads = [
+///     ChannelLoad { channel: DmaChannel::C1, priority: Priority::High, size: DataSize::S16, bytes_per_sec: Some(2_000_000.) },
+///     ChannelLoad { channel: DmaChannel::C2, priority: Priority::VeryHigh, size: DataSize::S32, bytes_per_sec: None },
+/// ];
+/// if let Err(w) = dma::check_bandwidth(&loads, clock_cfg.hclk()) {
+///     defmt::warn!("DMA plan: {}", defmt::Debug2Format(&w));
+/// }
+/// ```
+pub fn check_bandwidth(loads: &[ChannelLoad], ahb_freq: u32) -> Result<f32, BandwidthWarning> {
+    let transfer_time = DMA_CYCLES_PER_TRANSFER / ahb_freq as f32;
+
+    let size_bytes = |size: DataSize| match size {
+        DataSize::S8 => 1.,
+        DataSize::S16 => 2.,
+        DataSize::S32 => 4.,
+    };
+
+    let mut occupancy = 0.;
+    for load in loads {
+        if let Some(rate) = load.bytes_per_sec {
+            occupancy += rate / size_bytes(load.size) * transfer_time;
+        }
+    }
+
+    if occupancy > 1. {
+        return Err(BandwidthWarning::Saturated(occupancy));
+    }
+
+    for load in loads {
+        let rate = match load.bytes_per_sec {
+            Some(r) => r,
+            None => continue,
+        };
+
+        if let Some(hog) = loads
+            .iter()
+            .find(|l| l.bytes_per_sec.is_none() && outranks(l, load))
+        {
+            return Err(BandwidthWarning::Starved {
+                channel: load.channel,
+                hog: hog.channel,
+            });
+        }
+
+        // Worst case: The transfer in progress completes, then each channel that outranks this
+        // one is serviced once.
+        let outranking = loads.iter().filter(|l| outranks(l, load)).count();
+        let latency = (1 + outranking) as f32 * transfer_time;
+        let interval = size_bytes(load.size) / rate;
+
+        if latency > interval {
+            return Err(BandwidthWarning::Overrun {
+                channel: load.channel,
+                latency,
+                interval,
+            });
+        }
+    }
+
+    Ok(occupancy)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(channel: DmaChannel, priority: Priority, bytes_per_sec: Option<f32>) -> ChannelLoad {
+        ChannelLoad {
+            channel,
+            priority,
+            size: DataSize::S8,
+            bytes_per_sec,
+        }
+    }
+
+    #[test]
+    fn bandwidth_ok() {
+        let loads = [ChannelLoad {
+            channel: DmaChannel::C1,
+            priority: Priority::High,
+            size: DataSize::S32,
+            bytes_per_sec: Some(400_000.),
+        }];
+
+        // 100k transfers per second, of 5 cycles each, at 80Mhz.
+        let occupancy = check_bandwidth(&loads, 80_000_000).unwrap();
+        assert!((occupancy - 0.00625).abs() < 1e-6);
+
+        assert_eq!(check_bandwidth(&[], 80_000_000), Ok(0.));
+    }
+
+    #[test]
+    fn bandwidth_saturated() {
+        let loads = [load(DmaChannel::C1, Priority::High, Some(300_000.))];
+
+        assert!(matches!(
+            check_bandwidth(&loads, 1_000_000),
+            Err(BandwidthWarning::Saturated(_))
+        ));
+    }
+
+    #[test]
+    fn bandwidth_starved() {
+        let loads = [
+            load(DmaChannel::C1, Priority::VeryHigh, None),
+            load(DmaChannel::C2, Priority::High, Some(1_000.)),
+        ];
+
+        assert_eq!(
+            check_bandwidth(&loads, 80_000_000),
+            Err(BandwidthWarning::Starved {
+                channel: DmaChannel::C2,
+                hog: DmaChannel::C1,
+            })
+        );
+
+        // A lower-priority memory-to-memory channel doesn't starve it.
+        let loads = [
+            load(DmaChannel::C1, Priority::Low, None),
+            load(DmaChannel::C2, Priority::High, Some(1_000.)),
+        ];
+        assert!(check_bandwidth(&loads, 80_000_000).is_ok());
+    }
+
+    #[test]
+    fn bandwidth_overrun() {
+        // At 1Mhz, each transfer takes 5µs. C2 requests every 8.3µs, but may wait 10µs: For the
+        // transfer in progress, and for C1.
+        let loads = [
+            load(DmaChannel::C1, Priority::VeryHigh, Some(50_000.)),
+            load(DmaChannel::C2, Priority::Low, Some(120_000.)),
+        ];
+
+        assert!(matches!(
+            check_bandwidth(&loads, 1_000_000),
+            Err(BandwidthWarning::Overrun {
+                channel: DmaChannel::C2,
+                ..
+            })
+        ));
+    }
+}
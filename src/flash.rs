@@ -1,5 +1,10 @@
 //! Read and write onboard flash memory.
 //! Note that on dual bank variants, only bank 1 is supported for now.
+//!
+//! On WB, program and erase operations are coordinated with CPU2 (the radio core) using the
+//! HSEM and the PESD flag, so they don't disrupt radio activity. Note that ST's wireless stack may
+//! also require you to notify it before and after erasing, using the `SHCI_C2_FLASH_EraseActivity`
+//! system command; send this over IPCC before and after calls to `erase_page`.
 
 // Note that most of the code for L5 and U5 is feature-gated due to different
 // register names, differentiating secure and non-secure. We keep them in the same file
@@ -19,13 +24,45 @@ use crate::pac::FLASH;
 #[cfg(feature = "h7")]
 use crate::pac::flash::BANK;
 
+#[cfg(feature = "wb")]
+use crate::{hsem, pac::RCC};
+
+#[cfg(feature = "wb")]
+use cortex_m::asm;
+#[cfg(any(feature = "wb", feature = "wl"))]
+use cortex_m::interrupt::free;
+
 use core;
 
 use cfg_if::cfg_if;
 
+#[cfg(not(feature = "h7"))]
+/// The size of a flash page, in bytes, as used by `erase_page` and `write_page`. Note that some
+/// variants have larger pages, eg L4R and L4S (4 or 8 KB); this driver doesn't support them yet.
+pub const PAGE_SIZE: usize = 2_048;
+
 const FLASH_KEY1: u32 = 0x4567_0123;
 const FLASH_KEY2: u32 = 0xCDEF_89AB;
 
+#[cfg(feature = "wb")]
+/// HSEM semaphore that CPU1 and CPU2 use to arbitrate ownership of the flash controller. This
+/// and `SEM_BLOCK_FLASH_REQ_BY_CPU2` follow the convention used by ST's CPU2 wireless firmware.
+const SEM_FLASH: u8 = 2;
+
+#[cfg(feature = "wb")]
+/// The number of attempts `unlock` makes to take `SEM_FLASH`, before returning `Error::Busy`.
+const SEM_FLASH_ATTEMPTS: u32 = 1_000_000;
+
+#[cfg(feature = "wb")]
+/// HSEM semaphore CPU2 holds to prevent CPU1 from programming or erasing flash, eg during radio
+/// events. CPU1 takes it around each single program or erase operation.
+const SEM_BLOCK_FLASH_REQ_BY_CPU2: u8 = 7;
+
+#[cfg(feature = "wb")]
+/// After releasing `SEM_BLOCK_FLASH_REQ_BY_CPU2`, CPU1 must wait at least 1us before taking it
+/// again, so CPU2 has a chance to take it. This is 1us at the maximum HCLK of 64Mhz.
+const CPU2_RELEASE_CYCLES: u32 = 64;
+
 #[cfg(feature = "l5")]
 #[derive(Clone, Copy)]
 /// Cortex-M33 secure programming, or nonsecure.
@@ -66,6 +103,49 @@ pub enum Error {
 
 // todo: Bank 2 support on H7 and others.
 
+#[cfg(any(feature = "wb", feature = "wl"))]
+/// Run a single program or erase operation (eg one double word, or one page erase) without
+/// disrupting CPU2. Flash operations stall CPU2 when it fetches from flash, which can break its
+/// timing-critical (eg radio) activity. CPU2 protects this activity by setting PES in
+/// FLASH_C2ACR, which suspends new operations, and is reported by PESD in FLASH_SR. On WB, ST's
+/// wireless firmware may hold `SEM_BLOCK_FLASH_REQ_BY_CPU2` instead.
+fn cpu2_coordinated<T>(regs: &FLASH, mut op: impl FnMut() -> T) -> T {
+    loop {
+        // Wait until CPU2 allows flash operations.
+        while regs.sr.read().pesd().bit_is_set() {}
+
+        // Disable interrupts, so the operation runs as soon as CPU2 allows it, and we don't hold
+        // the semaphore longer than required.
+        let result = free(|_| {
+            #[cfg(feature = "wb")]
+            if !hsem::try_lock(SEM_BLOCK_FLASH_REQ_BY_CPU2) {
+                return None;
+            }
+
+            // CPU2 may have set PES since we checked.
+            if regs.sr.read().pesd().bit_is_set() {
+                #[cfg(feature = "wb")]
+                hsem::release(SEM_BLOCK_FLASH_REQ_BY_CPU2);
+                return None;
+            }
+
+            let result = op();
+
+            #[cfg(feature = "wb")]
+            hsem::release(SEM_BLOCK_FLASH_REQ_BY_CPU2);
+
+            Some(result)
+        });
+
+        #[cfg(feature = "wb")]
+        asm::delay(CPU2_RELEASE_CYCLES);
+
+        if let Some(r) = result {
+            return r;
+        }
+    }
+}
+
 #[cfg(not(any(feature = "l5", feature = "h7")))]
 /// Check and clear all non-secure error programming flags due to a previous
 /// programming. If not, PGSERR is set.
@@ -143,6 +223,15 @@ impl Flash {
     /// Create a struct used to perform operations on Flash.
     pub fn new(regs: FLASH) -> Self {
         // todo: Implement and configure dual bank mode.
+
+        // Enable the HSEM clock, used to coordinate with CPU2. We don't reset the HSEM, since CPU2
+        // may be holding semaphores.
+        #[cfg(feature = "wb")]
+        free(|_| {
+            let rcc = unsafe { &(*RCC::ptr()) };
+            rcc.ahb3enr.modify(|_, w| w.hsemen().set_bit());
+        });
+
         Self { regs }
     }
 
@@ -175,28 +264,47 @@ impl Flash {
 
     #[cfg(not(feature = "l5"))]
     /// Unlock the flash memory, allowing writes. See L4 Reference manual, section 3.3.5.
+    /// On WB, this waits for CPU2 to release the flash semaphore, returning `Error::Busy` if it
+    /// doesn't; it's held until `lock`.
     pub fn unlock(&mut self) -> Result<(), Error> {
         #[cfg(not(feature = "h7"))]
         let regs = &self.regs;
         #[cfg(feature = "h7")]
         let regs = self.regs.bank1();
 
+        #[cfg(feature = "wb")]
+        {
+            let mut attempts = 0;
+            while !hsem::try_lock(SEM_FLASH) {
+                attempts += 1;
+                if attempts >= SEM_FLASH_ATTEMPTS {
+                    return Err(Error::Busy);
+                }
+            }
+        }
+
         regs.keyr.write(|w| unsafe { w.bits(FLASH_KEY1) });
         regs.keyr.write(|w| unsafe { w.bits(FLASH_KEY2) });
 
         if regs.cr.read().lock().bit_is_clear() {
             Ok(())
         } else {
+            #[cfg(feature = "wb")]
+            hsem::release(SEM_FLASH);
             Err(Error::Failure)
         }
     }
 
     #[cfg(not(feature = "l5"))]
+    /// Lock the flash memory, preventing writes. On WB, this releases the flash semaphore.
     pub fn lock(&mut self) {
         #[cfg(not(feature = "h7"))]
         self.regs.cr.modify(|_, w| w.lock().set_bit());
         #[cfg(feature = "h7")]
         self.regs.bank1().cr.modify(|_, w| w.lock().set_bit());
+
+        #[cfg(feature = "wb")]
+        hsem::release(SEM_FLASH);
     }
 
     #[cfg(feature = "l5")]
@@ -292,20 +400,27 @@ impl Flash {
             }
         }
 
-        // 4. Set the STRT bit in the FLASH_CR register.
-        cfg_if! {
-            if #[cfg(any(feature = "f3", feature = "f4", feature = "wb", feature = "wl"))] {
-                regs.cr.modify(|_, w| w.strt().set_bit());
-            } else {
-                #[cfg(any(feature = "g0", feature = "g4"))]
-                regs.cr.modify(|_, w| w.strt().set_bit());
-                #[cfg(not(any(feature = "g0", feature = "g4")))]
-                regs.cr.modify(|_, w| w.start().set_bit());
+        let start = || {
+            // 4. Set the STRT bit in the FLASH_CR register.
+            cfg_if! {
+                if #[cfg(any(feature = "f3", feature = "f4", feature = "wb", feature = "wl"))] {
+                    regs.cr.modify(|_, w| w.strt().set_bit());
+                } else {
+                    #[cfg(any(feature = "g0", feature = "g4"))]
+                    regs.cr.modify(|_, w| w.strt().set_bit());
+                    #[cfg(not(any(feature = "g0", feature = "g4")))]
+                    regs.cr.modify(|_, w| w.start().set_bit());
+                }
             }
-        }
 
-        // 5. Wait for the BSY bit to be cleared in the FLASH_SR register.
-        while regs.sr.read().bsy().bit_is_set() {}
+            // 5. Wait for the BSY bit to be cleared in the FLASH_SR register.
+            while regs.sr.read().bsy().bit_is_set() {}
+        };
+
+        #[cfg(any(feature = "wb", feature = "wl"))]
+        cpu2_coordinated(regs, start);
+        #[cfg(not(any(feature = "wb", feature = "wl")))]
+        start();
 
         // todo on F3: "Read the erased option bytes and verify" as final step
         cfg_if! {
@@ -490,8 +605,9 @@ impl Flash {
                 // 3. Set the BER1/2 bit in the FLASH_CR1/2 register corresponding to the targeted bank.
                 regs.cr.modify(|_, w| w.ber().clear_bit());
 
-                // 4. Set the STRT bit in the FLASH_CR register.
-                #[cfg(not(feature = "h7"))]
+                // 4. Set the STRT bit in the FLASH_CR register. (On WB and WL, this is
+                // coordinated with CPU2 below)
+                #[cfg(not(any(feature = "h7", feature = "wb", feature = "wl")))]
                 regs.cr.modify(|_, w| w.strt().set_bit());
                 #[cfg(feature = "h7")]
                 // Set the START1/2 bit in the FLASH_CR1/2 register to start the bank erase operation.
@@ -517,6 +633,12 @@ impl Flash {
         }
 
         // 5. Wait for the BSY bit to be cleared in the FLASH_SR register.
+        #[cfg(any(feature = "wb", feature = "wl"))]
+        cpu2_coordinated(regs, || {
+            regs.cr.modify(|_, w| w.strt().set_bit());
+            while regs.sr.read().bsy().bit_is_set() {}
+        });
+        #[cfg(not(any(feature = "wb", feature = "wl")))]
         while regs.sr.read().bsy().bit_is_set() {}
 
         self.lock();
@@ -633,17 +755,24 @@ impl Flash {
         let mut address = sector_to_address(page, Bank::B1) as *mut u32;
 
         for dword in data {
-            unsafe {
-                // – Write a first word in an address aligned with double word
-                core::ptr::write_volatile(address, *dword as u32);
-                // – Write the second word
-                core::ptr::write_volatile(address.add(1), (*dword >> 32) as u32);
+            let program = || {
+                unsafe {
+                    // – Write a first word in an address aligned with double word
+                    core::ptr::write_volatile(address, *dword as u32);
+                    // – Write the second word
+                    core::ptr::write_volatile(address.add(1), (*dword >> 32) as u32);
+                }
 
-                address = address.add(2);
-            }
+                // 5. Wait until the BSY bit is cleared in the FLASH_SR register.
+                while regs.sr.read().bsy().bit_is_set() {}
+            };
 
-            // 5. Wait until the BSY bit is cleared in the FLASH_SR register.
-            while regs.sr.read().bsy().bit_is_set() {}
+            #[cfg(any(feature = "wb", feature = "wl"))]
+            cpu2_coordinated(regs, program);
+            #[cfg(not(any(feature = "wb", feature = "wl")))]
+            program();
+
+            address = unsafe { address.add(2) };
 
             // 6. Check that EOP flag is set in the FLASH_SR register (meaning that the programming
             // operation has succeed), and clear it by software.
@@ -822,7 +951,7 @@ impl Flash {
 /// Calculate the address of the start of a given page. Each page is 2,048 Kb for non-H7.
 /// For H7, sectors are 128Kb, with 8 sectors per bank.
 fn page_to_address(page: usize) -> usize {
-    0x0800_0000 + page * 2048
+    0x0800_0000 + page * PAGE_SIZE
 }
 
 #[cfg(feature = "h7")]
@@ -0,0 +1,107 @@
+//! Support for sampling motor phase currents in sync with PWM, for field-oriented control (FOC).
+//! Configures an advanced timer (TIM1 or TIM8) running center-aligned PWM to trigger the ADC's
+//! injected conversions at the center of each PWM period, and limits phase duties so the
+//! switching edges stay clear of the sampling window.
+//!
+//! In center-aligned PWM mode 1, all high-side switches are off at the counter peak, so the
+//! low-side shunts conduct the phase currents. We use OC4REF, through TRGO2, to trigger the ADC
+//! slightly before the peak, so the sample is centered on it. If a phase's duty is high, its
+//! low-side on-time shrinks around the peak, and the sample catches the switching transient;
+//! `CenterSampling::set_duties` clamps duties to prevent this.
+//!
+//! Example, with TIM1 driving 3 phases, and 2 shunts on ADC1 channels 1 and 2:
+//! ```
+//! let mut timer = Timer::new_tim1(dp.TIM1, 20_000., TimerConfig {
+//!     alignment: Alignment::Center1,
+//!     ..Default::default()
+//! }, &clock_cfg);
+//!
+//! // Allow 60 timer counts of low-side conduction on each side of the peak, and trigger 10 counts
+//! // early to center ADC sampling on it.
+//! let sampling = CenterSampling::new(&timer.regs, 60, 10).unwrap();
+//!
+//! adc.setup_injected(&[1, 2], InjectedTrigger::Tim1Trgo2);
+//! adc.enable_interrupt(AdcInterrupt::EndOfSequenceInjected);
+//! adc.start_injected();
+//! timer.enable();
+//!
+//! // In the ADC ISR:
+//! adc.clear_interrupt(AdcInterrupt::EndOfSequenceInjected);
+//! let (i_a, i_b) = (adc.read_injected(1), adc.read_injected(2));
+//! // (Run the control loop)
+//! sampling.set_duties(&timer.regs, [duty_a, duty_b, duty_c]);
+//! ```
+
+use crate::pac::tim1;
+
+#[derive(Clone, Copy, Debug)]
+/// Returned by `CenterSampling::new` if the timer isn't in a center-aligned mode.
+pub struct AlignmentError {}
+
+/// Center-of-period phase current sampling, for an advanced timer in center-aligned mode.
+pub struct CenterSampling {
+    /// The timer's auto-reload value, read on construction.
+    arr: u32,
+    /// The minimum low-side conduction time on each side of the counter peak, in timer counts.
+    blanking: u32,
+}
+
+impl CenterSampling {
+    /// Configure the timer to generate the ADC trigger on TRGO2. `blanking` is the minimum time,
+    /// in timer counts, each phase's low side conducts on each side of the counter peak; set it
+    /// to cover the switching transient, plus the ADC sampling time. `trigger_advance` is how many
+    /// counts before the peak to trigger, eg to center the ADC's sampling time on it.
+    ///
+    /// The timer must already be configured in a center-aligned mode, with its period set;
+    /// returns `AlignmentError` if it isn't center-aligned. If you change the period later,
+    /// construct this again.
+    pub fn new(
+        regs: &tim1::RegisterBlock,
+        blanking: u32,
+        trigger_advance: u32,
+    ) -> Result<Self, AlignmentError> {
+        if regs.cr1.read().cms().bits() == 0 {
+            return Err(AlignmentError {});
+        }
+
+        let arr = regs.arr.read().arr().bits() as u32;
+        assert!(trigger_advance < arr && blanking < arr);
+
+        // CCR4 sets the trigger point. With PWM mode 2, OC4REF goes high when the counter
+        // reaches it counting up, ie once per period.
+        regs.ccr4
+            .write(|w| unsafe { w.ccr().bits((arr - 1 - trigger_advance) as u16) });
+
+        // Channel 4 as an output, in PWM mode 2 (0b0111), with preload.
+        regs.ccmr2_output().modify(|_, w| unsafe {
+            w.cc4s().bits(0b00);
+            w.oc4pe().set_bit();
+            w.oc4m_3().clear_bit();
+            w.oc4m().bits(0b111)
+        });
+
+        // OC4REF is used as TRGO2.
+        regs.cr2.modify(|_, w| unsafe { w.mms2().bits(0b0111) });
+
+        Ok(Self { arr, blanking })
+    }
+
+    /// The highest duty a phase can be set to, in timer counts, while leaving the sampling
+    /// window clear of switching edges.
+    pub fn max_duty(&self) -> u32 {
+        self.arr - self.blanking
+    }
+
+    /// Set the duties of the 3 phases, on channels 1 - 3, in timer counts. Each is clamped to
+    /// `max_duty()`. With preload enabled, these take effect at the next update event.
+    pub fn set_duties(&self, regs: &tim1::RegisterBlock, duties: [u32; 3]) {
+        let max = self.max_duty();
+
+        regs.ccr1
+            .write(|w| unsafe { w.ccr().bits(duties[0].min(max) as u16) });
+        regs.ccr2
+            .write(|w| unsafe { w.ccr().bits(duties[1].min(max) as u16) });
+        regs.ccr3
+            .write(|w| unsafe { w.ccr().bits(duties[2].min(max) as u16) });
+    }
+}
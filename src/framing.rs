@@ -0,0 +1,497 @@
+//! A frame-oriented protocol engine, for serial links. Extracts frames from a byte stream, eg
+//! received with `Usart::read_dma_circular`, either by delimiter, with optional byte stuffing
+//! (as in SLIP and HDLC), or by length prefix. Frames can carry a CRC, which is checked before
+//! they're queued. Complete frames are held in a fixed-size queue until the application takes
+//! them. `FrameConfig::encode` builds frames in the same format, for transmission.
+//!
+//! The CRC is CRC-16/CCITT-FALSE (polynomial 0x1021, initial value 0xffff), calculated over the
+//! payload, and sent after it, little-endian.
+//!
+//! Example, with HDLC-style framing, and reception using circular DMA:
+//! ```
+//! static mut RX_BUF: [u8; 256] = [0; 256];
+//!
+//! let cfg = FrameConfig {
+//!     framing: Framing::Delimited {
+//!         delimiter: 0x7e,
+//!         escape: Some(Escape { escape: 0x7d, xor: 0x20 }),
+//!     },
+//!     crc: true,
+//! };
+//! let mut decoder: FrameDecoder<64, 4> = FrameDecoder::new(cfg);
+//!
+//! unsafe { uart.read_dma_circular(&mut RX_BUF, DmaChannel::C1, &mut dma) };
+//!
+//! // In the USART's Idle interrupt handler:
+//! uart.clear_interrupt(UsartInterrupt::Idle);
+//! decoder.poll(&mut uart, &dma);
+//!
+//! // Elsewhere:
+//! while let Some(frame) = decoder.pop() {
+//!     handle(frame.data());
+//! }
+//! ```
+
+#[cfg(not(any(feature = "g0", feature = "f4")))]
+use core::ops::Deref;
+
+#[cfg(not(any(feature = "g0", feature = "f4")))]
+use crate::{
+    dma::Dma,
+    pac::{self, dma1 as dma_p},
+    usart::Usart,
+    util::{BaudPeriph, RccPeriph},
+};
+
+/// Byte stuffing, for delimited frames: Delimiter and escape bytes in the payload are sent as
+/// the escape byte, followed by the original byte XORed with `xor`.
+#[derive(Clone, Copy, Debug)]
+pub struct Escape {
+    /// Eg 0x7d for HDLC, or 0xdb for SLIP.
+    pub escape: u8,
+    /// Eg 0x20 for HDLC.
+    pub xor: u8,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// The size of a length prefix. Lengths are little-endian.
+pub enum LenSize {
+    U8,
+    U16,
+}
+
+#[derive(Clone, Copy, Debug)]
+/// How frames are delimited in the byte stream.
+pub enum Framing {
+    /// Frames end with `delimiter`. Empty frames, eg from a delimiter sent at the start of each
+    /// frame as well as the end, are ignored. Without escaping, the payload must not contain the
+    /// delimiter.
+    Delimited {
+        delimiter: u8,
+        escape: Option<Escape>,
+    },
+    /// Frames start with `sync`, followed by the payload length, not including the CRC. After
+    /// an invalid frame, the decoder resynchronizes on the next `sync` byte.
+    LengthPrefixed { sync: u8, len_size: LenSize },
+}
+
+#[derive(Clone, Copy, Debug)]
+/// Frame format configuration, shared by the encoder and decoder.
+pub struct FrameConfig {
+    pub framing: Framing,
+    /// Append a CRC to each frame, and check it on reception.
+    pub crc: bool,
+}
+
+impl FrameConfig {
+    /// Encode `payload` as a frame into `out`, and return the frame's length, or `None` if it
+    /// doesn't fit.
+    pub fn encode(&self, payload: &[u8], out: &mut [u8]) -> Option<usize> {
+        let crc = crc16(payload).to_le_bytes();
+        let crc: &[u8] = if self.crc { &crc } else { &[] };
+
+        let mut i = 0;
+        let mut put = |byte: u8| {
+            *out.get_mut(i)? = byte;
+            i += 1;
+            Some(())
+        };
+
+        match self.framing {
+            Framing::Delimited { delimiter, escape } => {
+                for &byte in payload.iter().chain(crc) {
+                    match escape {
+                        Some(e) if byte == delimiter || byte == e.escape => {
+                            put(e.escape)?;
+                            put(byte ^ e.xor)?;
+                        }
+                        _ => put(byte)?,
+                    }
+                }
+                put(delimiter)?;
+            }
+            Framing::LengthPrefixed { sync, len_size } => {
+                put(sync)?;
+                match len_size {
+                    LenSize::U8 => put(u8::try_from(payload.len()).ok()?)?,
+                    LenSize::U16 => {
+                        for byte in u16::try_from(payload.len()).ok()?.to_le_bytes() {
+                            put(byte)?;
+                        }
+                    }
+                }
+                for &byte in payload.iter().chain(crc) {
+                    put(byte)?;
+                }
+            }
+        }
+
+        Some(i)
+    }
+}
+
+/// A received frame's payload, without framing, or CRC.
+pub struct Frame<const N: usize> {
+    buf: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> Frame<N> {
+    pub fn data(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+/// Counts of frames the decoder has discarded.
+pub struct FrameStats {
+    /// Frames with an incorrect CRC.
+    pub crc_errors: u32,
+    /// Frames longer than the decoder's buffer, or, for length-prefixed frames, with a length
+    /// that couldn't fit in it.
+    pub overflows: u32,
+    /// Valid frames discarded because the queue was full.
+    pub dropped: u32,
+}
+
+#[derive(Clone, Copy)]
+enum State {
+    /// Delimited: Receiving a frame. Length-prefixed: Waiting for the sync byte.
+    Idle,
+    /// Delimited: The previous byte was the escape byte.
+    Escaped,
+    /// Delimited: The frame is too long; discard bytes until the next delimiter.
+    Discarding,
+    /// Length-prefixed: Receiving the length; the number of length bytes received so far.
+    Len(usize),
+    /// Length-prefixed: Receiving the payload and CRC; the number of bytes remaining.
+    Payload(usize),
+}
+
+/// Extracts frames of up to `N` bytes, including the CRC, from a byte stream, and queues up to
+/// `Q` of them.
+pub struct FrameDecoder<const N: usize, const Q: usize> {
+    cfg: FrameConfig,
+    state: State,
+    /// The frame being received.
+    buf: [u8; N],
+    len: usize,
+    queue: [Frame<N>; Q],
+    /// The index of the oldest frame in the queue.
+    head: usize,
+    queued: usize,
+    pub stats: FrameStats,
+}
+
+impl<const N: usize, const Q: usize> FrameDecoder<N, Q> {
+    /// Panics if `N` is too small to hold a length-prefixed frame's length field, which is
+    /// stored in the frame buffer while it's received.
+    pub fn new(cfg: FrameConfig) -> Self {
+        if let Framing::LengthPrefixed { len_size, .. } = cfg.framing {
+            let len_bytes = match len_size {
+                LenSize::U8 => 1,
+                LenSize::U16 => 2,
+            };
+            assert!(N >= len_bytes, "The frame buffer is smaller than the length field.");
+        }
+
+        Self {
+            cfg,
+            state: State::Idle,
+            buf: [0; N],
+            len: 0,
+            queue: [(); Q].map(|_| Frame { buf: [0; N], len: 0 }),
+            head: 0,
+            queued: 0,
+            stats: Default::default(),
+        }
+    }
+
+    /// Process received bytes, queueing any frames they complete.
+    pub fn push(&mut self, data: &[u8]) {
+        for &byte in data {
+            match self.cfg.framing {
+                Framing::Delimited { delimiter, escape } => {
+                    self.push_delimited(byte, delimiter, escape)
+                }
+                Framing::LengthPrefixed { sync, len_size } => {
+                    self.push_length_prefixed(byte, sync, len_size)
+                }
+            }
+        }
+    }
+
+    #[cfg(not(any(feature = "g0", feature = "f4")))]
+    /// Process all bytes received by `usart.read_dma_circular` since the last call. Call this
+    /// from the USART's Idle interrupt handler, and, for long bursts, the DMA channel's half
+    /// transfer and transfer complete handlers.
+    pub fn poll<R, D>(&mut self, usart: &mut Usart<R>, dma: &Dma<D>)
+    where
+        R: Deref<Target = pac::usart1::RegisterBlock> + RccPeriph + BaudPeriph,
+        D: Deref<Target = dma_p::RegisterBlock>,
+    {
+        let mut chunk = [0; 32];
+        loop {
+            let count = usart.read_available(&mut chunk, dma);
+            if count == 0 {
+                break;
+            }
+            self.push(&chunk[..count]);
+        }
+    }
+
+    /// Take the oldest complete frame from the queue.
+    pub fn pop(&mut self) -> Option<&Frame<N>> {
+        if self.queued == 0 {
+            return None;
+        }
+
+        let i = self.head;
+        self.head = (self.head + 1) % Q;
+        self.queued -= 1;
+
+        // The slot isn't reused until the next `push`, which requires `&mut self`.
+        Some(&self.queue[i])
+    }
+
+    /// The number of complete frames in the queue.
+    pub fn queued(&self) -> usize {
+        self.queued
+    }
+
+    /// Discard any partial frame, eg after a timeout, or a line error.
+    pub fn reset(&mut self) {
+        self.state = State::Idle;
+        self.len = 0;
+    }
+
+    fn push_delimited(&mut self, byte: u8, delimiter: u8, escape: Option<Escape>) {
+        if byte == delimiter {
+            if let State::Discarding = self.state {
+                self.stats.overflows += 1;
+            } else if self.len > 0 {
+                self.finish_frame();
+            }
+            self.reset();
+            return;
+        }
+
+        let byte = match (self.state, escape) {
+            (State::Discarding, _) => return,
+            (State::Escaped, Some(e)) => {
+                self.state = State::Idle;
+                byte ^ e.xor
+            }
+            (_, Some(e)) if byte == e.escape => {
+                self.state = State::Escaped;
+                return;
+            }
+            _ => byte,
+        };
+
+        if self.len == N {
+            self.state = State::Discarding;
+            return;
+        }
+        self.buf[self.len] = byte;
+        self.len += 1;
+    }
+
+    fn push_length_prefixed(&mut self, byte: u8, sync: u8, len_size: LenSize) {
+        let crc_len = if self.cfg.crc { 2 } else { 0 };
+
+        match self.state {
+            State::Idle => {
+                if byte == sync {
+                    self.len = 0;
+                    self.state = State::Len(0);
+                }
+            }
+            State::Len(received) => {
+                // Store the length bytes temporarily at the start of the buffer.
+                self.buf[received] = byte;
+                let received = received + 1;
+
+                let payload_len = match len_size {
+                    LenSize::U8 => self.buf[0] as usize,
+                    LenSize::U16 if received == 2 => {
+                        u16::from_le_bytes([self.buf[0], self.buf[1]]) as usize
+                    }
+                    LenSize::U16 => {
+                        self.state = State::Len(received);
+                        return;
+                    }
+                };
+
+                let total = payload_len + crc_len;
+                self.len = 0;
+                self.state = if total > N {
+                    self.stats.overflows += 1;
+                    State::Idle
+                } else if total == 0 {
+                    self.finish_frame();
+                    State::Idle
+                } else {
+                    State::Payload(total)
+                };
+            }
+            State::Payload(remaining) => {
+                self.buf[self.len] = byte;
+                self.len += 1;
+
+                if remaining == 1 {
+                    self.finish_frame();
+                    self.reset();
+                } else {
+                    self.state = State::Payload(remaining - 1);
+                }
+            }
+            _ => self.reset(),
+        }
+    }
+
+    /// Check the CRC of the frame in `buf`, and queue it if valid.
+    fn finish_frame(&mut self) {
+        let mut len = self.len;
+
+        if self.cfg.crc {
+            if len < 2 {
+                self.stats.crc_errors += 1;
+                return;
+            }
+            len -= 2;
+            let received = u16::from_le_bytes([self.buf[len], self.buf[len + 1]]);
+            if crc16(&self.buf[..len]) != received {
+                self.stats.crc_errors += 1;
+                return;
+            }
+        }
+
+        if self.queued == Q {
+            self.stats.dropped += 1;
+            return;
+        }
+
+        let frame = &mut self.queue[(self.head + self.queued) % Q];
+        frame.buf[..len].copy_from_slice(&self.buf[..len]);
+        frame.len = len;
+        self.queued += 1;
+    }
+}
+
+/// CRC-16/CCITT-FALSE.
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0xffff;
+
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HDLC: FrameConfig = FrameConfig {
+        framing: Framing::Delimited {
+            delimiter: 0x7e,
+            escape: Some(Escape {
+                escape: 0x7d,
+                xor: 0x20,
+            }),
+        },
+        crc: true,
+    };
+
+    const LEN_PREFIXED: FrameConfig = FrameConfig {
+        framing: Framing::LengthPrefixed {
+            sync: 0xaa,
+            len_size: LenSize::U16,
+        },
+        crc: true,
+    };
+
+    #[test]
+    fn crc16_check_value() {
+        assert_eq!(crc16(b"123456789"), 0x29b1);
+    }
+
+    #[test]
+    fn delimited_round_trip() {
+        let payload = [0x01, 0x7e, 0x02, 0x7d, 0x03];
+        let mut buf = [0; 32];
+        let len = HDLC.encode(&payload, &mut buf).unwrap();
+
+        // Both special bytes are escaped.
+        assert!(!buf[..len - 1].contains(&0x7e));
+
+        let mut decoder: FrameDecoder<16, 2> = FrameDecoder::new(HDLC);
+        decoder.push(&buf[..len]);
+
+        assert_eq!(decoder.pop().unwrap().data(), &payload);
+        assert!(decoder.pop().is_none());
+    }
+
+    #[test]
+    fn length_prefixed_round_trip() {
+        let payload = [0xaa, 0x00, 0x10];
+        let mut buf = [0; 32];
+        let len = LEN_PREFIXED.encode(&payload, &mut buf).unwrap();
+        assert_eq!(len, 1 + 2 + payload.len() + 2);
+
+        let mut decoder: FrameDecoder<16, 2> = FrameDecoder::new(LEN_PREFIXED);
+        // Garbage before the sync byte is skipped.
+        decoder.push(&[0x12, 0x34]);
+        decoder.push(&buf[..len]);
+
+        assert_eq!(decoder.pop().unwrap().data(), &payload);
+    }
+
+    #[test]
+    fn crc_error() {
+        let mut buf = [0; 32];
+        let len = HDLC.encode(&[1, 2, 3], &mut buf).unwrap();
+        buf[0] ^= 0xff;
+
+        let mut decoder: FrameDecoder<16, 2> = FrameDecoder::new(HDLC);
+        decoder.push(&buf[..len]);
+
+        assert!(decoder.pop().is_none());
+        assert_eq!(decoder.stats.crc_errors, 1);
+    }
+
+    #[test]
+    fn overflow_then_recover() {
+        let mut decoder: FrameDecoder<4, 2> = FrameDecoder::new(HDLC);
+        decoder.push(&[0; 8]);
+        decoder.push(&[0x7e]);
+        assert_eq!(decoder.stats.overflows, 1);
+
+        let mut buf = [0; 16];
+        let len = HDLC.encode(&[5, 6], &mut buf).unwrap();
+        decoder.push(&buf[..len]);
+
+        assert_eq!(decoder.pop().unwrap().data(), &[5, 6]);
+    }
+
+    #[test]
+    fn queue_full() {
+        let mut decoder: FrameDecoder<16, 1> = FrameDecoder::new(HDLC);
+        let mut buf = [0; 16];
+        let len = HDLC.encode(&[1], &mut buf).unwrap();
+        decoder.push(&buf[..len]);
+        decoder.push(&buf[..len]);
+
+        assert_eq!(decoder.queued(), 1);
+        assert_eq!(decoder.stats.dropped, 1);
+    }
+}
@@ -29,18 +29,22 @@ use crate::pac::dma as dma_p;
 #[cfg(any(
     feature = "f3",
     feature = "l4",
+    feature = "l5",
     feature = "g4",
     feature = "h7",
     feature = "wb"
 ))]
 use crate::pac::dma1 as dma_p;
 
-#[cfg(not(any(feature = "f4", feature = "l5")))]
+#[cfg(not(feature = "f4"))]
 use crate::dma::{self, ChannelCfg, Dma, DmaChannel};
 
 #[cfg(any(feature = "f3", feature = "l4"))]
 use crate::dma::DmaInput;
 
+#[cfg(any(feature = "l4", feature = "g4", feature = "wb", feature = "wl"))]
+use crate::adc::{AdcDevice, AnalogPin};
+
 use cfg_if::cfg_if;
 use paste::paste;
 
@@ -50,6 +54,10 @@ use paste::paste;
 pub enum PinMode {
     Input,
     Output,
+    /// Alternate function. The value is the AF number (0 - 15) from the "Alternate function"
+    /// table in your MCU's datasheet. It isn't checked against the peripheral the pin is used with;
+    /// an incorrect AF number results in the peripheral not driving or reading the pin. To have it
+    /// checked, pass `PinToken`s to a constructor like `Usart::new_with_pins`.
     Alt(u8),
     Analog,
 }
@@ -97,6 +105,25 @@ pub enum Pull {
     Dn = 0b10,
 }
 
+/// Output drive configuration: speed, output type, and pull resistor, for applying together with
+/// `Pin::output_cfg`. Eg for high-speed SPI, SDMMC, or QSPI pins, set `speed` to `High`.
+pub struct OutputCfg {
+    pub speed: OutputSpeed,
+    pub output_type: OutputType,
+    pub pull: Pull,
+}
+
+impl Default for OutputCfg {
+    /// The reset configuration for most pins.
+    fn default() -> Self {
+        Self {
+            speed: OutputSpeed::Low,
+            output_type: OutputType::PushPull,
+            pull: Pull::Floating,
+        }
+    }
+}
+
 #[derive(Copy, Clone)]
 #[repr(u8)]
 /// Values for `GPIOx_IDR` and `GPIOx_ODR`.
@@ -239,6 +266,288 @@ impl Port {
             Self::H => 7,
         }
     }
+
+    /// Read the input level of all 16 pins on this port at once; bit `n` is pin `n`. Reads from
+    /// the `IDR` register.
+    pub fn read(&self) -> u16 {
+        unsafe { (*regs(*self)).idr.read().bits() as u16 }
+    }
+
+    /// Set the output level of all 16 pins on this port at once; bit `n` is pin `n`. Sets the
+    /// `BSRR` register. Atomic: All pins change in the same cycle, which is useful for parallel
+    /// buses. Only affects pins configured as outputs.
+    pub fn write(&mut self, value: u16) {
+        self.write_masked(0xffff, value);
+    }
+
+    /// Set the output level of the pins selected by `mask`, leaving the others unchanged. Bit `n`
+    /// of `mask` and `value` is pin `n`. For example, to drive an 8-bit bus on pins 4 - 11:
+    /// `port.write_masked(0xff << 4, (byte as u16) << 4)`. Sets the `BSRR` register. Atomic, so it's
+    /// safe to use if other pins on the port are modified concurrently, eg in interrupts.
+    pub fn write_masked(&mut self, mask: u16, value: u16) {
+        let set = (value & mask) as u32;
+        let reset = (!value & mask) as u32;
+
+        unsafe { (*regs(*self)).bsrr.write(|w| w.bits((reset << 16) | set)) }
+    }
+
+    /// Lock the configuration of the pins selected by `mask` (bit `n` is pin `n`) until the next
+    /// MCU reset. Their `MODER`, `OTYPER`, `OSPEEDR`, `PUPDR`, `AFRL`, and `AFRH` bits can't be
+    /// modified; output levels can still be changed. Useful for safety-critical outputs, eg gate
+    /// driver enables. Returns `true` if the lock is active. Sets the `LCKR` register.
+    ///
+    /// The lock key can only be applied once per reset; after that, the lock state of this
+    /// port's pins can't be changed.
+    pub fn lock(&mut self, mask: u16) -> bool {
+        let regs = unsafe { &(*regs(*self)) };
+
+        // RM: "LOCK key write sequence: WR LCKR[16] = '1' + LCKR[15:0]; WR LCKR[16] = '0' +
+        // LCKR[15:0]; WR LCKR[16] = '1' + LCKR[15:0]; RD LCKR". The value of LCKR[15:0] must not
+        // change during the sequence, and any error aborts the lock, so we don't allow
+        // interruptions.
+        const LCKK: u32 = 1 << 16;
+        let mask = mask as u32;
+
+        free(|_| {
+            regs.lckr.write(|w| unsafe { w.bits(LCKK | mask) });
+            regs.lckr.write(|w| unsafe { w.bits(mask) });
+            regs.lckr.write(|w| unsafe { w.bits(LCKK | mask) });
+            regs.lckr.read();
+        });
+
+        self.is_locked()
+    }
+
+    /// Check if this port's lock key is active, ie if `lock` has succeeded since the last reset.
+    /// Reads the `LCKR` register, `LCKK` bit.
+    pub fn is_locked(&self) -> bool {
+        unsafe { (*regs(*self)).lckr.read().bits() & (1 << 16) != 0 }
+    }
+
+    /// The port with letter `letter`, eg `'A'`, or `None` if this MCU doesn't have it.
+    pub fn from_letter(letter: char) -> Option<Self> {
+        Some(match letter {
+            'A' => Self::A,
+            'B' => Self::B,
+            #[cfg(not(feature = "wl"))]
+            'C' => Self::C,
+            #[cfg(not(any(feature = "f410", feature = "wl")))]
+            'D' => Self::D,
+            #[cfg(not(any(
+                feature = "f301",
+                feature = "f3x4",
+                feature = "f410",
+                feature = "g0",
+                feature = "wb",
+                feature = "wl"
+            )))]
+            'E' => Self::E,
+            #[cfg(not(any(
+                feature = "f401",
+                feature = "f410",
+                feature = "f411",
+                feature = "l4x1",
+                feature = "l4x2",
+                feature = "l412",
+                feature = "l4x3",
+                feature = "wb",
+                feature = "wl"
+            )))]
+            'F' => Self::F,
+            #[cfg(not(any(
+                feature = "f373",
+                feature = "f301",
+                feature = "f3x4",
+                feature = "f401",
+                feature = "f410",
+                feature = "f411",
+                feature = "l4",
+                feature = "g0",
+                feature = "g4",
+                feature = "wb",
+                feature = "wl"
+            )))]
+            'G' => Self::G,
+            #[cfg(not(any(
+                feature = "f373",
+                feature = "f301",
+                feature = "f3x4",
+                feature = "f410",
+                feature = "l4",
+                feature = "g0",
+                feature = "g4",
+                feature = "wb",
+                feature = "wl"
+            )))]
+            'H' => Self::H,
+            _ => return None,
+        })
+    }
+}
+
+/// Ports whose pins have been taken with `PortPins::take`; bit `n` is the port with `cr_val` `n`.
+static mut PORTS_TAKEN: u16 = 0;
+
+/// Exclusive ownership of a single pin, checked at compile time. Pin `N` of port `P`, eg
+/// `PinToken<'A', 5>` for PA5. Tokens can't be copied or cloned: Once a token is consumed, eg by
+/// `into_pin`, or by a driver constructor like `Spi::new_with_pins`, using the same pin elsewhere
+/// fails to compile with a "use of moved value" error, instead of silently reconfiguring the pin
+/// at runtime.
+///
+/// Example:
+/// ```ignore
+/// let pa = PortPins::<'A'>::take().unwrap();
+/// let sck = pa.p5.into_pin(PinMode::Alt(5));
+/// // Rejected by the compiler: `pa.p5` was moved above.
+/// let led = pa.p5.into_pin(PinMode::Output);
+/// ```
+///
+/// This is opt-in: `Pin::new` remains available, and isn't checked against tokens.
+pub struct PinToken<const P: char, const N: u8> {
+    _private: (),
+}
+
+impl<const P: char, const N: u8> PinToken<P, N> {
+    /// The port this pin is on.
+    pub fn port(&self) -> Port {
+        // `PortPins::take` only creates tokens for ports that exist.
+        Port::from_letter(P).unwrap()
+    }
+
+    /// The pin number: 0 - 15.
+    pub const fn pin(&self) -> u8 {
+        N
+    }
+
+    /// Configure the pin, consuming the token.
+    pub fn into_pin(self, mode: PinMode) -> Pin {
+        Pin::new(self.port(), N, mode)
+    }
+}
+
+/// Tokens for all 16 pins of port `P`. Move the individual tokens out of this struct as required.
+pub struct PortPins<const P: char> {
+    pub p0: PinToken<P, 0>,
+    pub p1: PinToken<P, 1>,
+    pub p2: PinToken<P, 2>,
+    pub p3: PinToken<P, 3>,
+    pub p4: PinToken<P, 4>,
+    pub p5: PinToken<P, 5>,
+    pub p6: PinToken<P, 6>,
+    pub p7: PinToken<P, 7>,
+    pub p8: PinToken<P, 8>,
+    pub p9: PinToken<P, 9>,
+    pub p10: PinToken<P, 10>,
+    pub p11: PinToken<P, 11>,
+    pub p12: PinToken<P, 12>,
+    pub p13: PinToken<P, 13>,
+    pub p14: PinToken<P, 14>,
+    pub p15: PinToken<P, 15>,
+}
+
+impl<const P: char> PortPins<P> {
+    /// Take the tokens for port `P`'s pins. Returns `None` if they've already been taken, or if
+    /// this MCU doesn't have the port. Since this can only succeed once per port, each pin has at
+    /// most one token.
+    pub fn take() -> Option<Self> {
+        let bit = 1 << Port::from_letter(P)?.cr_val();
+
+        let taken = free(|_| unsafe {
+            let taken = PORTS_TAKEN & bit != 0;
+            PORTS_TAKEN |= bit;
+            taken
+        });
+
+        if taken {
+            return None;
+        }
+
+        Some(Self {
+            p0: PinToken { _private: () },
+            p1: PinToken { _private: () },
+            p2: PinToken { _private: () },
+            p3: PinToken { _private: () },
+            p4: PinToken { _private: () },
+            p5: PinToken { _private: () },
+            p6: PinToken { _private: () },
+            p7: PinToken { _private: () },
+            p8: PinToken { _private: () },
+            p9: PinToken { _private: () },
+            p10: PinToken { _private: () },
+            p11: PinToken { _private: () },
+            p12: PinToken { _private: () },
+            p13: PinToken { _private: () },
+            p14: PinToken { _private: () },
+            p15: PinToken { _private: () },
+        })
+    }
+}
+
+/// A pin token that can be configured for an alternate function. Implemented by `PinToken`.
+/// Peripheral pin traits, eg `usart::TxPin`, extend this with the AF number of each pin that has
+/// the peripheral's signal, from the "Alternate function" tables in the datasheets, so constructors
+/// like `Usart::new_with_pins` can set the AF, and reject pins without the signal at compile time.
+pub trait AltFnPin: Sized {
+    /// Configure the pin as alternate function `af`, consuming its token.
+    fn into_alt_fn(self, af: u8) -> Pin;
+}
+
+impl<const P: char, const N: u8> AltFnPin for PinToken<P, N> {
+    fn into_alt_fn(self, af: u8) -> Pin {
+        self.into_pin(PinMode::Alt(af))
+    }
+}
+
+/// Implement a peripheral pin trait for the tokens of the pins with its signal.
+macro_rules! af_pins {
+    ($trait:path, [$($(#[$meta:meta])* $port:literal $n:literal => $af:literal),+ $(,)?]) => {
+        $(
+            $(#[$meta])*
+            impl $trait for PinToken<$port, $n> {
+                const AF: u8 = $af;
+            }
+        )+
+    };
+}
+
+// Pins for USART1, SPI1, and I2C1. G0 uses different AF numbers from the other families for these.
+// Note that G4's I2C1 doesn't have PB6.
+cfg_if! {
+    if #[cfg(feature = "g0")] {
+        af_pins!(crate::usart::TxPin<pac::USART1>, ['A' 9 => 1, 'B' 6 => 0]);
+        af_pins!(crate::usart::RxPin<pac::USART1>, ['A' 10 => 1, 'B' 7 => 0]);
+
+        af_pins!(crate::spi::SckPin<pac::SPI1>, ['A' 5 => 0, 'B' 3 => 0]);
+        af_pins!(crate::spi::MisoPin<pac::SPI1>, ['A' 6 => 0, 'B' 4 => 0]);
+        af_pins!(crate::spi::MosiPin<pac::SPI1>, ['A' 7 => 0, 'B' 5 => 0]);
+
+        af_pins!(crate::i2c::SclPin<pac::I2C1>, ['B' 6 => 6, 'B' 8 => 6]);
+        af_pins!(crate::i2c::SdaPin<pac::I2C1>, ['B' 7 => 6, 'B' 9 => 6]);
+    } else {
+        af_pins!(crate::usart::TxPin<pac::USART1>, [
+            'A' 9 => 7,
+            #[cfg(not(feature = "f410"))]
+            'B' 6 => 7,
+        ]);
+        af_pins!(crate::usart::RxPin<pac::USART1>, [
+            'A' 10 => 7,
+            #[cfg(not(feature = "f410"))]
+            'B' 7 => 7,
+        ]);
+
+        af_pins!(crate::spi::SckPin<pac::SPI1>, ['A' 5 => 5, 'B' 3 => 5]);
+        af_pins!(crate::spi::MisoPin<pac::SPI1>, ['A' 6 => 5, 'B' 4 => 5]);
+        af_pins!(crate::spi::MosiPin<pac::SPI1>, ['A' 7 => 5, 'B' 5 => 5]);
+
+        #[cfg(not(feature = "f4"))]
+        af_pins!(crate::i2c::SclPin<pac::I2C1>, [
+            #[cfg(not(feature = "g4"))]
+            'B' 6 => 4,
+            'B' 8 => 4,
+        ]);
+        #[cfg(not(feature = "f4"))]
+        af_pins!(crate::i2c::SdaPin<pac::I2C1>, ['B' 7 => 4, 'B' 9 => 4]);
+    }
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -246,6 +555,8 @@ impl Port {
 pub enum Edge {
     Rising,
     Falling,
+    /// Trigger on both rising and falling edges.
+    Both,
 }
 
 // These macros are used to interate over pin number, for use with PAC fields.
@@ -319,7 +630,7 @@ macro_rules! set_state {
 
 // Reduce DRY for setting up interrupts.
 macro_rules! set_exti {
-    ($pin:expr, $trigger:expr, $val:expr, [$(($num:expr, $crnum:expr)),+]) => {
+    ($pin:expr, $rising:expr, $falling:expr, $val:expr, [$(($num:expr, $crnum:expr)),+]) => {
         let exti = unsafe { &(*pac::EXTI::ptr()) };
         let syscfg  = unsafe { &(*pac::SYSCFG::ptr()) };
 
@@ -342,8 +653,8 @@ macro_rules! set_exti {
 
                         cfg_if! {
                             if #[cfg(any(feature = "g4", feature = "wb", feature = "wl"))] {
-                                exti.rtsr1.modify(|_, w| w.[<rt $num>]().bit($trigger));
-                                exti.ftsr1.modify(|_, w| w.[<ft $num>]().bit(!$trigger));
+                                exti.rtsr1.modify(|_, w| w.[<rt $num>]().bit($rising));
+                                exti.ftsr1.modify(|_, w| w.[<ft $num>]().bit($falling));
                             // } else if #[cfg(any(feature = "wb", feature = "wl"))] {
                             //     // todo: Missing in PAC, so we read+write. https://github.com/stm32-rs/stm32-rs/issues/570
                             //     let val_r =  $exti.rtsr1.read().bits();
@@ -352,8 +663,8 @@ macro_rules! set_exti {
                             //     $exti.ftsr1.write(|w| unsafe { w.bits(val_f | (1 << $num)) });
                             //     // todo: Core 2 interrupts.
                             } else {
-                                exti.rtsr1.modify(|_, w| w.[<tr $num>]().bit($trigger));
-                                exti.ftsr1.modify(|_, w| w.[<tr $num>]().bit(!$trigger));
+                                exti.rtsr1.modify(|_, w| w.[<tr $num>]().bit($rising));
+                                exti.ftsr1.modify(|_, w| w.[<tr $num>]().bit($falling));
                             }
                         }
                         syscfg
@@ -370,7 +681,7 @@ macro_rules! set_exti {
 #[cfg(feature = "f4")]
 // Similar to `set_exti`, but with reg names sans `1`.
 macro_rules! set_exti_f4 {
-    ($pin:expr, $trigger:expr, $val:expr, [$(($num:expr, $crnum:expr)),+]) => {
+    ($pin:expr, $rising:expr, $falling:expr, $val:expr, [$(($num:expr, $crnum:expr)),+]) => {
         let exti = unsafe { &(*pac::EXTI::ptr()) };
         let syscfg  = unsafe { &(*pac::SYSCFG::ptr()) };
 
@@ -379,8 +690,8 @@ macro_rules! set_exti_f4 {
                 $(
                     $num => {
                         exti.imr.modify(|_, w| w.[<mr $num>]().unmasked());
-                        exti.rtsr.modify(|_, w| w.[<tr $num>]().bit($trigger));
-                        exti.ftsr.modify(|_, w| w.[<tr $num>]().bit(!$trigger));
+                        exti.rtsr.modify(|_, w| w.[<tr $num>]().bit($rising));
+                        exti.ftsr.modify(|_, w| w.[<tr $num>]().bit($falling));
                         syscfg
                             .[<exticr $crnum>]
                             .modify(|_, w| unsafe { w.[<exti $num>]().bits($val) });
@@ -395,7 +706,7 @@ macro_rules! set_exti_f4 {
 #[cfg(feature = "l5")]
 // For L5 See `set_exti!`. Different method naming pattern for exticr.
 macro_rules! set_exti_l5 {
-    ($pin:expr, $trigger:expr, $val:expr, [$(($num:expr, $crnum:expr, $num2:expr)),+]) => {
+    ($pin:expr, $rising:expr, $falling:expr, $val:expr, [$(($num:expr, $crnum:expr, $num2:expr)),+]) => {
         let exti = unsafe { &(*pac::EXTI::ptr()) };
 
         paste! {
@@ -403,8 +714,8 @@ macro_rules! set_exti_l5 {
                 $(
                     $num => {
                         exti.imr1.modify(|_, w| w.[<im $num>]().set_bit());  // unmask
-                        exti.rtsr1.modify(|_, w| w.[<rt $num>]().bit($trigger));  // Rising trigger
-                        exti.ftsr1.modify(|_, w| w.[<ft $num>]().bit(!$trigger));   // Falling trigger
+                        exti.rtsr1.modify(|_, w| w.[<rt $num>]().bit($rising));  // Rising trigger
+                        exti.ftsr1.modify(|_, w| w.[<ft $num>]().bit($falling));   // Falling trigger
                         exti
                             .[<exticr $crnum>]
                             .modify(|_, w| unsafe { w.[<exti $num2>]().bits($val) });
@@ -419,7 +730,7 @@ macro_rules! set_exti_l5 {
 #[cfg(feature = "g0")]
 // For G0. See `set_exti!`. Todo? Reduce DRY.
 macro_rules! set_exti_g0 {
-    ($pin:expr, $trigger:expr, $val:expr, [$(($num:expr, $crnum:expr, $num2:expr)),+]) => {
+    ($pin:expr, $rising:expr, $falling:expr, $val:expr, [$(($num:expr, $crnum:expr, $num2:expr)),+]) => {
         let exti = unsafe { &(*pac::EXTI::ptr()) };
 
         paste! {
@@ -427,9 +738,9 @@ macro_rules! set_exti_g0 {
                 $(
                     $num => {
                         exti.imr1.modify(|_, w| w.[<im $num>]().set_bit());  // unmask
-                        exti.rtsr1.modify(|_, w| w.[<tr $num>]().bit($trigger));  // Rising trigger
+                        exti.rtsr1.modify(|_, w| w.[<tr $num>]().bit($rising));  // Rising trigger
                         // This field name is probably a PAC error.
-                        exti.ftsr1.modify(|_, w| w.[<tr $num>]().bit(!$trigger));   // Falling trigger
+                        exti.ftsr1.modify(|_, w| w.[<tr $num>]().bit($falling));   // Falling trigger
                         exti
                             .[<exticr $crnum>]
                             .modify(|_, w| unsafe { w.[<exti $num2>]().bits($val) });
@@ -441,6 +752,223 @@ macro_rules! set_exti_g0 {
     }
 }
 
+#[cfg(not(any(feature = "f373", feature = "wl")))]
+// Clear a pending flag for GPIO lines 0-15, and the internal lines listed. Direct lines have no
+// pending flag; they're cleared at the peripheral. Returns an error from the enclosing function
+// for other lines.
+macro_rules! clear_exti_pending {
+    ($line:expr, $pr:ident.$pif:ident, [$($(#[$meta:meta])* $c:literal),*]) => {
+        let exti = unsafe { &(*pac::EXTI::ptr()) };
+
+        paste! {
+            match $line {
+                0 => exti.$pr.write(|w| w.[<$pif 0>]().set_bit()),
+                1 => exti.$pr.write(|w| w.[<$pif 1>]().set_bit()),
+                2 => exti.$pr.write(|w| w.[<$pif 2>]().set_bit()),
+                3 => exti.$pr.write(|w| w.[<$pif 3>]().set_bit()),
+                4 => exti.$pr.write(|w| w.[<$pif 4>]().set_bit()),
+                5 => exti.$pr.write(|w| w.[<$pif 5>]().set_bit()),
+                6 => exti.$pr.write(|w| w.[<$pif 6>]().set_bit()),
+                7 => exti.$pr.write(|w| w.[<$pif 7>]().set_bit()),
+                8 => exti.$pr.write(|w| w.[<$pif 8>]().set_bit()),
+                9 => exti.$pr.write(|w| w.[<$pif 9>]().set_bit()),
+                10 => exti.$pr.write(|w| w.[<$pif 10>]().set_bit()),
+                11 => exti.$pr.write(|w| w.[<$pif 11>]().set_bit()),
+                12 => exti.$pr.write(|w| w.[<$pif 12>]().set_bit()),
+                13 => exti.$pr.write(|w| w.[<$pif 13>]().set_bit()),
+                14 => exti.$pr.write(|w| w.[<$pif 14>]().set_bit()),
+                15 => exti.$pr.write(|w| w.[<$pif 15>]().set_bit()),
+                $(
+                    $(#[$meta])*
+                    $c => exti.$pr.write(|w| w.[<$pif $c>]().set_bit()),
+                )*
+                _ => return Err(ExtiLineError {}),
+            }
+        }
+    }
+}
+
+#[cfg(any(
+    feature = "f3",
+    feature = "f4",
+    feature = "l4",
+    feature = "g4",
+    feature = "h7"
+))]
+// For internal EXTI lines. See `set_exti!`. Configurable lines are unmasked, and their edges set;
+// direct lines are only unmasked.
+macro_rules! set_exti_line {
+    ($line:expr, $rising:expr, $falling:expr, $imr:ident.$im:ident, $rtsr:ident.$rt:ident,
+        $ftsr:ident.$ft:ident, configurable: [$($(#[$meta:meta])* $c:literal),+],
+        direct: [$($d:literal),*]) => {
+        let exti = unsafe { &(*pac::EXTI::ptr()) };
+
+        paste! {
+            match $line {
+                $(
+                    $(#[$meta])*
+                    $c => {
+                        exti.$imr.modify(|_, w| w.[<$im $c>]().set_bit());
+                        exti.$rtsr.modify(|_, w| w.[<$rt $c>]().bit($rising));
+                        exti.$ftsr.modify(|_, w| w.[<$ft $c>]().bit($falling));
+                    }
+                )+
+                $(
+                    $d => exti.$imr.modify(|_, w| w.[<$im $d>]().set_bit()),
+                )*
+                _ => unreachable!(),
+            }
+        }
+    }
+}
+
+#[cfg(any(
+    feature = "f3",
+    feature = "f4",
+    feature = "l4",
+    feature = "g4",
+    feature = "h7"
+))]
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(u8)]
+/// EXTI lines connected to internal peripherals, rather than GPIO pins. Values are the line
+/// numbers. Use these to wake from Stop mode on internal events. See the reference manual's "EXTI
+/// lines connections" table for your MCU. Comparator lines are only present on variants with
+/// the corresponding comparator.
+pub enum ExtiLine {
+    /// PVD output (PVD and AVD on H7)
+    Pvd = 16,
+    #[cfg(any(feature = "f3", feature = "f4", feature = "g4", feature = "h7"))]
+    RtcAlarm = 17,
+    #[cfg(feature = "l4")]
+    RtcAlarm = 18,
+    #[cfg(any(feature = "f3", feature = "f4", feature = "g4"))]
+    /// USB (OTG FS on F4) wakeup
+    UsbWakeup = 18,
+    #[cfg(feature = "l4")]
+    /// USB OTG FS wakeup
+    UsbWakeup = 17,
+    #[cfg(any(feature = "f3", feature = "l4", feature = "g4"))]
+    /// RTC tamper, timestamp, and LSE CSS
+    RtcTamper = 19,
+    #[cfg(feature = "f4")]
+    /// RTC tamper and timestamp
+    RtcTamper = 21,
+    #[cfg(feature = "h7")]
+    /// RTC tamper, timestamp, and LSE CSS
+    RtcTamper = 18,
+    #[cfg(any(feature = "f3", feature = "l4", feature = "g4"))]
+    RtcWakeup = 20,
+    #[cfg(feature = "f4")]
+    RtcWakeup = 22,
+    #[cfg(feature = "h7")]
+    RtcWakeup = 19,
+    #[cfg(any(feature = "f3", feature = "l4", feature = "g4"))]
+    Comp1 = 21,
+    #[cfg(any(feature = "f3", feature = "l4", feature = "g4"))]
+    Comp2 = 22,
+    #[cfg(feature = "h7")]
+    Comp1 = 20,
+    #[cfg(feature = "h7")]
+    Comp2 = 21,
+    #[cfg(any(feature = "f303", feature = "g4"))]
+    Comp3 = 29,
+    #[cfg(any(feature = "f303", feature = "g4"))]
+    Comp4 = 30,
+    #[cfg(any(feature = "f303", feature = "g4"))]
+    Comp5 = 31,
+}
+
+#[cfg(any(
+    feature = "f3",
+    feature = "f4",
+    feature = "l4",
+    feature = "g4",
+    feature = "h7"
+))]
+/// Configure an internal EXTI line to generate an interrupt on the selected edge(s), eg to wake
+/// from Stop mode on an RTC alarm, or a comparator output change. The source peripheral must
+/// also be configured to generate the event, and its interrupt unmasked in the NVIC. Sets the
+/// `IMR`, `RTSR`, and `FTSR` registers. Clear the line's pending flag in the interrupt handler
+/// with `clear_exti_interrupt(line as u8)`, unless it's a direct line.
+pub fn enable_exti_line(line: ExtiLine, edge: Edge) {
+    let (rise_trigger, fall_trigger) = match edge {
+        Edge::Rising => (true, false),
+        Edge::Falling => (false, true),
+        Edge::Both => (true, true),
+    };
+
+    // Direct lines, eg USB wakeup on L4 and G4, have no edge selection. On F4, the registers
+    // don't have the `1` suffix; G4 names the fields IMx, RTx, and FTx instead of MRx and TRx.
+    free(|_| {
+        cfg_if! {
+            if #[cfg(feature = "f4")] {
+                set_exti_line!(line as u8, rise_trigger, fall_trigger, imr.mr, rtsr.tr, ftsr.tr,
+                    configurable: [16, 17, 18, 21, 22], direct: []);
+            } else if #[cfg(feature = "f3")] {
+                set_exti_line!(line as u8, rise_trigger, fall_trigger, imr1.mr, rtsr1.tr, ftsr1.tr,
+                    configurable: [16, 17, 18, 19, 20, 21, 22, #[cfg(feature = "f303")] 29,
+                        #[cfg(feature = "f303")] 30, #[cfg(feature = "f303")] 31], direct: []);
+            } else if #[cfg(feature = "l4")] {
+                set_exti_line!(line as u8, rise_trigger, fall_trigger, imr1.mr, rtsr1.tr, ftsr1.tr,
+                    configurable: [16, 18, 19, 20, 21, 22], direct: [17]);
+            } else if #[cfg(feature = "g4")] {
+                set_exti_line!(line as u8, rise_trigger, fall_trigger, imr1.im, rtsr1.rt, ftsr1.ft,
+                    configurable: [16, 17, 19, 20, 21, 22, 29, 30, 31], direct: [18]);
+            } else if #[cfg(any(feature = "h747cm4", feature = "h747cm7"))] {
+                set_exti_line!(line as u8, rise_trigger, fall_trigger, c1imr1.mr, rtsr1.tr, ftsr1.tr,
+                    configurable: [16, 17, 18, 19, 20, 21], direct: []);
+            } else {
+                // H7
+                set_exti_line!(line as u8, rise_trigger, fall_trigger, cpuimr1.mr, rtsr1.tr, ftsr1.tr,
+                    configurable: [16, 17, 18, 19, 20, 21], direct: []);
+            }
+        }
+    });
+}
+
+#[cfg(not(any(feature = "f373", feature = "wl")))]
+#[derive(Clone, Copy, Debug)]
+/// Used when clearing an EXTI line that has no pending flag on this MCU, eg a direct line, or
+/// one that doesn't exist.
+pub struct ExtiLineError {}
+
+#[cfg(not(any(feature = "f373", feature = "wl")))]
+/// Clear an EXTI line's interrupt pending flag, eg in the interrupt handler for a GPIO pin
+/// configured with `Pin::enable_interrupt`. `line` is the same as the pin number, or for internal
+/// lines, the `ExtiLine` value. This doesn't require access to the `Pin`. Returns an error if the
+/// line has no pending flag.
+pub fn clear_exti_interrupt(line: u8) -> Result<(), ExtiLineError> {
+    // The pending bits are cleared by writing 1; writing 0 has no effect. L5 and G0 have separate
+    // rising and falling edge pending registers. Pending field names are PRx on F3, F4, L4, and
+    // H7, PIFx on G4 and WB, and RPIFx and FPIFx on L5 and G0.
+    cfg_if! {
+        if #[cfg(any(feature = "l5", feature = "g0"))] {
+            clear_exti_pending!(line, rpr1.rpif, []);
+            clear_exti_pending!(line, fpr1.fpif, []);
+        } else if #[cfg(feature = "f4")] {
+            clear_exti_pending!(line, pr.pr, [16, 17, 18, 21, 22]);
+        } else if #[cfg(feature = "f3")] {
+            clear_exti_pending!(line, pr1.pr, [16, 17, 18, 19, 20, 21, 22,
+                #[cfg(feature = "f303")] 29, #[cfg(feature = "f303")] 30,
+                #[cfg(feature = "f303")] 31]);
+        } else if #[cfg(feature = "l4")] {
+            clear_exti_pending!(line, pr1.pr, [16, 18, 19, 20, 21, 22]);
+        } else if #[cfg(feature = "g4")] {
+            clear_exti_pending!(line, pr1.pif, [16, 17, 19, 20, 21, 22, 29, 30, 31]);
+        } else if #[cfg(any(feature = "h747cm4", feature = "h747cm7"))] {
+            clear_exti_pending!(line, c1pr1.pr, [16, 17, 18, 19, 20, 21]);
+        } else if #[cfg(feature = "h7")] {
+            clear_exti_pending!(line, cpupr1.pr, [16, 17, 18, 19, 20, 21]);
+        } else {
+            // WB
+            clear_exti_pending!(line, pr1.pif, []);
+        }
+    }
+
+    Ok(())
+}
+
 /// Represents a single GPIO pin. Allows configuration, and reading/setting state.
 pub struct Pin {
     /// The GPIO Port letter. Eg A, B, C.
@@ -449,6 +977,42 @@ pub struct Pin {
     pub pin: u8,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// Selects which debug pins to release for general use. See `release_debug_pins()`.
+pub enum DebugPins {
+    /// The JTAG-only pins: PA15 (JTDI), PB3 (JTDO/SWO), and PB4 (NJTRST). SWD remains usable,
+    /// but SWO trace output doesn't. G0 has no JTAG, so this has no effect on it.
+    Jtag,
+    /// The SWD pins, PA13 (SWDIO) and PA14 (SWCLK), in addition to the JTAG ones. After this,
+    /// a debugger can only connect under reset.
+    All,
+}
+
+/// Reclaim debug pins for use as GPIO. They're configured as floating inputs; set them up
+/// afterwards using `Pin` as required. On the families this library supports, debug functions
+/// are alternate function 0 on these pins, selected at reset; there's no separate remap register
+/// (as on F1), so changing the pin mode is what disconnects them. Enables the port RCC clocks, if
+/// not already enabled.
+pub fn release_debug_pins(pins: DebugPins) {
+    #[cfg(not(feature = "g0"))]
+    let jtag_pins = [(Port::A, 15), (Port::B, 3), (Port::B, 4)];
+    #[cfg(feature = "g0")]
+    let jtag_pins: [(Port, u8); 0] = [];
+
+    let swd_pins = [(Port::A, 13), (Port::A, 14)];
+
+    let swd_pins: &[(Port, u8)] = match pins {
+        DebugPins::Jtag => &[],
+        DebugPins::All => &swd_pins,
+    };
+
+    for &(port, pin) in jtag_pins.iter().chain(swd_pins) {
+        // The reset state of these pins includes pull resistors; remove them.
+        let mut p = Pin::new(port, pin, PinMode::Input);
+        p.pull(Pull::Floating);
+    }
+}
+
 impl Pin {
     /// Internal function to get the appropriate GPIO block pointer.
     const fn regs(&self) -> *const pac::gpioa::RegisterBlock {
@@ -780,6 +1344,47 @@ impl Pin {
         }
     }
 
+    #[cfg(any(feature = "l4", feature = "g4", feature = "wb", feature = "wl"))]
+    /// Set the pin to analog mode, and associate it with the channel it's connected to on a given ADC.
+    /// Pass a reference to the result to `Adc::read` instead of a channel number. Panics if the pin
+    /// isn't an input to that ADC. Example: `let pa0 = Pin::new(Port::A, 0, PinMode::Input).into_analog(AdcDevice::One);`
+    pub fn into_analog(self, device: AdcDevice) -> AnalogPin {
+        // Look up the channel first, so the pin's mode isn't changed if this panics.
+        let mut result = AnalogPin::new(self, device);
+        result.pin.mode(PinMode::Analog);
+
+        result
+    }
+
+    /// Reconfigure the pin's mode, output type, and pull resistor together. Useful for
+    /// bit-banged protocols that switch a line between driving and reading it, eg one-wire. Changes
+    /// are made in a critical section, and ordered to avoid glitches: When switching to output or
+    /// alternate function mode, the output type, pull, and alternate function are set before the
+    /// pin starts driving. When switching to input or analog mode, the pin stops driving first.
+    /// Sets the `MODER`, `OTYPER`, `PUPDR`, and `AFRL` or `AFRH` registers.
+    ///
+    /// To avoid a glitch when switching to output mode, set the output level first, using
+    /// `set_high()` or `set_low()`; the output data register retains its value in any mode.
+    pub fn set_mode(&mut self, mode: PinMode, output_type: OutputType, pull: Pull) {
+        free(|_| match mode {
+            PinMode::Output | PinMode::Alt(_) => {
+                self.output_type(output_type);
+                self.pull(pull);
+                // `mode()` sets the alternate function after `MODER`; set it before instead, so
+                // the pin doesn't briefly drive a different peripheral's signal.
+                if let PinMode::Alt(alt) = mode {
+                    self.alt_fn(alt);
+                }
+                self.mode(mode);
+            }
+            PinMode::Input | PinMode::Analog => {
+                self.mode(mode);
+                self.output_type(output_type);
+                self.pull(pull);
+            }
+        });
+    }
+
     /// Set output type. Sets the `OTYPER` register.
     pub fn output_type(&mut self, value: OutputType) {
         set_field!(
@@ -806,6 +1411,18 @@ impl Pin {
         );
     }
 
+    /// Set output speed, output type, and pull resistor together. Sets the `OSPEEDR`, `OTYPER`,
+    /// and `PUPDR` registers. Every family supports all type and pull combinations, including
+    /// open-drain with a pull-down; speeds a family doesn't have (eg `Fast` on F3) aren't
+    /// available in `OutputSpeed`.
+    pub fn output_cfg(&mut self, cfg: &OutputCfg) {
+        free(|_| {
+            self.output_speed(cfg.speed);
+            self.output_type(cfg.output_type);
+            self.pull(cfg.pull);
+        });
+    }
+
     /// Set internal pull resistor: Pull up, pull down, or floating. Sets the `PUPDR` register.
     pub fn pull(&mut self, value: Pull) {
         set_field!(
@@ -887,39 +1504,48 @@ impl Pin {
     }
 
     #[cfg(not(any(feature = "f373", feature = "wl")))]
-    /// Configure this pin as an interrupt source. Set the edge as Rising or Falling.
+    /// Configure this pin as an interrupt source. Set the edge as Rising, Falling, or Both. This
+    /// routes the pin to its EXTI line (using SYSCFG, or EXTI on L5 and G0), sets the edge
+    /// triggers, and unmasks the line. You still need to unmask the line's interrupt in the NVIC,
+    /// eg `EXTI0` for pin 0, or `EXTI9_5` for pins 5 - 9. Clear it in the handler with
+    /// `clear_interrupt`, or `clear_exti_interrupt`.
     pub fn enable_interrupt(&mut self, edge: Edge) {
-        let rise_trigger = match edge {
-            Edge::Rising => {
-                // configure EXTI line to trigger on rising edge, disable trigger on falling edge.
-                true
-            }
-            Edge::Falling => {
-                // configure EXTI line to trigger on falling edge, disable trigger on rising edge.
-                false
-            }
+        // Configure the EXTI line to trigger on the selected edge(s), and disable the trigger
+        // on the other.
+        let (rise_trigger, fall_trigger) = match edge {
+            Edge::Rising => (true, false),
+            Edge::Falling => (false, true),
+            Edge::Both => (true, true),
         };
 
+        // The SYSCFG clock must be enabled to write to the EXTICR registers. (On H7, this is
+        // handled during clock setup)
+        #[cfg(any(feature = "f3", feature = "f4", feature = "l4", feature = "g4"))]
+        free(|_| {
+            let rcc = unsafe { &(*RCC::ptr()) };
+            rcc.apb2enr.modify(|_, w| w.syscfgen().set_bit());
+        });
+
         cfg_if! {
             if #[cfg(feature = "g0")] {
-                set_exti_g0!(self.pin, rise_trigger, self.port.cr_val(), [(0, 1, 0_7), (1, 1, 0_7), (2, 1, 0_7),
+                set_exti_g0!(self.pin, rise_trigger, fall_trigger, self.port.cr_val(), [(0, 1, 0_7), (1, 1, 0_7), (2, 1, 0_7),
                     (3, 1, 0_7), (4, 2, 0_7), (5, 2, 0_7), (6, 2, 0_7), (7, 2, 0_7), (8, 3, 8_15),
                     (9, 3, 8_15), (10, 3, 8_15), (11, 3, 8_15), (12, 4, 8_15),
                     (13, 4, 8_15), (14, 4, 8_15), (15, 4, 8_15)]
                 );
             } else if #[cfg(feature = "l5")] {
-                set_exti_l5!(self.pin, rise_trigger, self.port.cr_val(), [(0, 1, 0_7), (1, 1, 0_7), (2, 1, 0_7),
+                set_exti_l5!(self.pin, rise_trigger, fall_trigger, self.port.cr_val(), [(0, 1, 0_7), (1, 1, 0_7), (2, 1, 0_7),
                     (3, 1, 0_7), (4, 2, 0_7), (5, 2, 0_7), (6, 2, 0_7), (7, 2, 0_7), (8, 3, 8_15),
                     (9, 3, 8_15), (10, 3, 8_15), (11, 3, 8_15), (12, 4, 8_15),
                     (13, 4, 8_15), (14, 4, 8_15), (15, 4, 8_15)]
                 );
             } else if #[cfg(feature = "f4")] {
-                set_exti_f4!(self.pin, rise_trigger, self.port.cr_val(), [(0, 1), (1, 1), (2, 1),
+                set_exti_f4!(self.pin, rise_trigger, fall_trigger, self.port.cr_val(), [(0, 1), (1, 1), (2, 1),
                         (3, 1), (4, 2), (5, 2), (6, 2), (7, 2), (8, 3), (9, 3), (10, 3), (11, 3), (12, 4),
                         (13, 4), (14, 4), (15, 4)]
                 );
             } else {
-                set_exti!(self.pin, rise_trigger, self.port.cr_val(), [(0, 1), (1, 1), (2, 1),
+                set_exti!(self.pin, rise_trigger, fall_trigger, self.port.cr_val(), [(0, 1), (1, 1), (2, 1),
                     (3, 1), (4, 2), (5, 2), (6, 2), (7, 2), (8, 3), (9, 3), (10, 3), (11, 3), (12, 4),
                     (13, 4), (14, 4), (15, 4)]
                 );
@@ -927,6 +1553,14 @@ impl Pin {
         }
     }
 
+    #[cfg(not(any(feature = "f373", feature = "wl")))]
+    /// Clear this pin's EXTI interrupt pending flag. Run this in the interrupt handler, to prevent
+    /// it from firing repeatedly.
+    pub fn clear_interrupt(&mut self) {
+        // Lines 0 - 15 all have pending flags.
+        clear_exti_interrupt(self.pin).ok();
+    }
+
     /// Check if the pin's input voltage is high. Reads from the `IDR` register.
     pub fn is_high(&self) -> bool {
         get_input_data!(
@@ -950,6 +1584,42 @@ impl Pin {
     pub fn set_low(&mut self) {
         self.set_state(PinState::Low);
     }
+
+    /// Set the pin's output voltage to high, using a single `BSRR` write. Unlike `set_high`, this
+    /// doesn't match on the pin number, so compiles to a few instructions; use it in tight loops,
+    /// eg for bit-banging. Atomic.
+    #[inline(always)]
+    pub fn set_high_fast(&mut self) {
+        unsafe { (*self.regs()).bsrr.write(|w| w.bits(1 << self.pin)) };
+    }
+
+    /// Set the pin's output voltage to low, using a single `BSRR` write. See `set_high_fast`.
+    /// Atomic.
+    #[inline(always)]
+    pub fn set_low_fast(&mut self) {
+        unsafe { (*self.regs()).bsrr.write(|w| w.bits(1 << (self.pin + 16))) };
+    }
+
+    /// Toggle the pin's output voltage, using an `ODR` read, and a single `BSRR` write. See
+    /// `set_high_fast`. Not atomic: If an interrupt changes this pin between the read and the
+    /// write, its change is overwritten. Other pins on the port are unaffected.
+    #[inline(always)]
+    pub fn toggle_fast(&mut self) {
+        let regs = unsafe { &(*self.regs()) };
+        let mask = 1 << self.pin;
+        let odr = regs.odr.read().bits();
+
+        // Reset the pin if it's set, and set it if it's reset.
+        regs.bsrr
+            .write(|w| unsafe { w.bits(((odr & mask) << 16) | (!odr & mask)) });
+    }
+
+    /// Lock this pin's configuration until the next MCU reset. See `Port::lock` for details. Note
+    /// that once a port's lock is active, other pins on it can't be locked; to lock multiple pins
+    /// on the same port, use `Port::lock` with a mask instead.
+    pub fn lock(&mut self) -> bool {
+        self.port.lock(1 << self.pin)
+    }
 }
 //
 #[cfg(feature = "embedded-hal")]
@@ -1102,13 +1772,89 @@ const fn regs(port: Port) -> *const pac::gpioa::RegisterBlock {
     }
 }
 
-#[cfg(not(any(
-    feature = "g0",
-    feature = "f4",
-    feature = "l5",
-    feature = "f3",
-    feature = "l4"
-)))]
+/// Set all pins on all ports to analog mode, with no pull resistors. This is the lowest-power
+/// state for unused pins. The SWD pins (PA13 and PA14), and SWO (PB3) are left unchanged, so a
+/// connected debugger isn't disrupted. Enables each port's RCC clock, if not already enabled.
+pub fn set_all_analog() {
+    let ports = [
+        Port::A,
+        Port::B,
+        #[cfg(not(feature = "wl"))]
+        Port::C,
+        #[cfg(not(any(feature = "f410", feature = "wl")))]
+        Port::D,
+        #[cfg(not(any(
+            feature = "f301",
+            feature = "f3x4",
+            feature = "f410",
+            feature = "g0",
+            feature = "wb",
+            feature = "wl"
+        )))]
+        Port::E,
+        #[cfg(not(any(
+            feature = "f401",
+            feature = "f410",
+            feature = "f411",
+            feature = "l4x1",
+            feature = "l4x2",
+            feature = "l412",
+            feature = "l4x3",
+            feature = "wb",
+            feature = "wl"
+        )))]
+        Port::F,
+        #[cfg(not(any(
+            feature = "f373",
+            feature = "f301",
+            feature = "f3x4",
+            feature = "f401",
+            feature = "f410",
+            feature = "f411",
+            feature = "l4",
+            feature = "g0",
+            feature = "g4",
+            feature = "wb",
+            feature = "wl"
+        )))]
+        Port::G,
+        #[cfg(not(any(
+            feature = "f373",
+            feature = "f301",
+            feature = "f3x4",
+            feature = "f410",
+            feature = "l4",
+            feature = "g0",
+            feature = "g4",
+            feature = "wb",
+            feature = "wl"
+        )))]
+        Port::H,
+    ];
+
+    for port in ports {
+        // Enable the port's clock; pin 0 is configured below, with the others.
+        Pin::new(port, 0, PinMode::Analog);
+
+        // 2 bits per pin, in both MODER and PUPDR.
+        let keep_mask: u32 = match port {
+            Port::A => (0b11 << (13 * 2)) | (0b11 << (14 * 2)),
+            Port::B => 0b11 << (3 * 2),
+            _ => 0,
+        };
+
+        let regs = unsafe { &(*regs(port)) };
+        let moder = regs.moder.read().bits();
+        let pupdr = regs.pupdr.read().bits();
+
+        // Analog mode is 0b11; no pull is 0b00.
+        regs.moder
+            .write(|w| unsafe { w.bits((moder & keep_mask) | !keep_mask) });
+        regs.pupdr.write(|w| unsafe { w.bits(pupdr & keep_mask) });
+    }
+}
+
+#[cfg(not(any(feature = "g0", feature = "f4", feature = "f3", feature = "l4")))]
 /// Write a series of words to the BSRR (atomic output) register. Note that these are direct writes
 /// to the full, 2-sided register - not a series of low/high values.
 pub unsafe fn write_dma<D>(
@@ -19,6 +19,60 @@ pub struct Hsem {
     regs: HSEM,
 }
 
+/// The COREID value the HSEM uses for CPU1 (the M4 core). Set in HSEM_Rx, COREID.
+const COREID_CPU1: u8 = 4;
+
+// Helper, since we need to access one of 32 similarly-named registers: Binds `$reg` to the
+// HSEM_Rx (`r`), or HSEM_RLRx (`rlr`) register for a semaphore, and evaluates `$body`.
+macro_rules! with_register_sem {
+    ($semaphore_num:expr, $regs:expr, $prefix:ident, $reg:ident => $body:expr) => {
+        with_register_sem!($semaphore_num, $regs, $prefix, $reg => $body, [0, 1, 2, 3, 4, 5, 6, 7,
+            8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29,
+            30, 31])
+    };
+    ($semaphore_num:expr, $regs:expr, $prefix:ident, $reg:ident => $body:expr,
+        [$($n:literal),+]) => {
+        paste! {
+            match $semaphore_num {
+                $(
+                    $n => {
+                        let $reg = &$regs.[<$prefix $n>];
+                        $body
+                    }
+                )+
+                _ => panic!("Semaphore number must be 0 - 31."),
+            }
+        }
+    };
+}
+
+/// Attempt to lock a semaphore from CPU1 using the 1-step procedure, with PROCID 0. Returns `true`
+/// if the lock succeeded, or if CPU1 already holds it. This doesn't require an `Hsem` struct, so
+/// other drivers (eg flash) can use it to coordinate with CPU2. The HSEM clock must be enabled.
+pub(crate) fn try_lock(semaphore_num: u8) -> bool {
+    let regs = unsafe { &(*HSEM::ptr()) };
+
+    // RM: "If read COREID matches and PROCID = 0, then lock is put in place."
+    with_register_sem!(semaphore_num, regs, rlr, rlr => {
+        let val = rlr.read();
+        val.lock().bit_is_set() && val.coreid().bits() == COREID_CPU1 && val.procid().bits() == 0
+    })
+}
+
+/// Release a semaphore locked by CPU1 with PROCID 0, eg using `try_lock`. The write is ignored if
+/// CPU1 doesn't hold the semaphore.
+pub(crate) fn release(semaphore_num: u8) {
+    let regs = unsafe { &(*HSEM::ptr()) };
+
+    with_register_sem!(semaphore_num, regs, r, r => {
+        r.write(|w| unsafe {
+            w.procid().bits(0);
+            w.coreid().bits(COREID_CPU1);
+            w.lock().clear_bit()
+        })
+    })
+}
+
 // Helper, since we need to access one of to 31 similarly-named registers.
 macro_rules! set_register_sem {
     ($semaphore_num:expr, $regs:expr, $core_id:expr, $proc_id:expr) => {
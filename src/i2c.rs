@@ -10,13 +10,17 @@ use cortex_m::interrupt::free;
 #[cfg(feature = "embedded-hal")]
 use embedded_hal::blocking::i2c::{Read, Write, WriteRead};
 
+#[cfg(feature = "embedded-hal-1")]
+use embedded_hal_1::i2c::{ErrorKind, ErrorType, NoAcknowledgeSource, Operation};
+
 use crate::{
     clocks::Clocks,
+    gpio::{AltFnPin, OutputType, Pin, PinMode},
     pac::{self, RCC},
     util::RccPeriph,
 };
 
-#[cfg(any(feature = "f3", feature = "l4"))]
+#[cfg(not(feature = "f4"))]
 use crate::util::DmaPeriph;
 
 #[cfg(any(feature = "g0"))]
@@ -24,6 +28,7 @@ use crate::pac::dma as dma_p;
 #[cfg(any(
     feature = "f3",
     feature = "l4",
+    feature = "l5",
     feature = "g4",
     feature = "h7",
     feature = "wb",
@@ -31,50 +36,56 @@ use crate::pac::dma as dma_p;
 ))]
 use crate::pac::dma1 as dma_p;
 
-#[cfg(not(feature = "l5"))]
 use crate::dma::{self, ChannelCfg, Dma, DmaChannel};
 
 #[cfg(any(feature = "f3", feature = "l4"))]
 use crate::dma::DmaInput;
 
 // todo: Get rid of this macro.
+/// Wait for an ISR flag, returning early if an error is flagged, or on timeout.
 macro_rules! busy_wait {
-    ($regs:expr, $flag:ident) => {
+    ($i2c:expr, $flag:ident) => {{
+        let mut polls = 0;
         loop {
-            let isr = $regs.isr.read();
+            let isr = $i2c.regs.isr.read();
 
             if isr.$flag().bit_is_set() {
                 break;
             } else if isr.berr().bit_is_set() {
-                $regs.icr.write(|w| w.berrcf().set_bit());
+                $i2c.regs.icr.write(|w| w.berrcf().set_bit());
                 return Err(Error::Bus);
             } else if isr.arlo().bit_is_set() {
-                $regs.icr.write(|w| w.arlocf().set_bit());
+                $i2c.regs.icr.write(|w| w.arlocf().set_bit());
                 return Err(Error::Arbitration);
             } else if isr.nackf().bit_is_set() {
-                $regs.icr.write(|w| w.stopcf().set_bit().nackcf().set_bit());
+                $i2c.regs.icr.write(|w| w.stopcf().set_bit().nackcf().set_bit());
 
                 // If a pending TXIS flag is set, write dummy data to TXDR
-                if $regs.isr.read().txis().bit_is_set() {
-                    $regs.txdr.write(|w| unsafe { w.txdata().bits(0) });
+                if $i2c.regs.isr.read().txis().bit_is_set() {
+                    $i2c.regs.txdr.write(|w| unsafe { w.txdata().bits(0) });
                 }
 
                 // If TXDR is not flagged as empty, write 1 to flush it
-                if $regs.isr.read().txe().bit_is_clear() {
-                    $regs.isr.write(|w| w.txe().set_bit());
+                if $i2c.regs.isr.read().txe().bit_is_clear() {
+                    $i2c.regs.isr.write(|w| w.txe().set_bit());
                 }
 
                 return Err(Error::Nack);
             } else {
                 // try again
+                $i2c.check_timeout(&mut polls)?;
             }
         }
-    };
+    }};
 }
 
+/// The minimum number of CPU cycles per status register poll, used to convert the timeout
+/// from ms.
+const CYCLES_PER_POLL: u32 = 8;
+
 /// I2C error
 #[non_exhaustive]
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug)]
 pub enum Error {
     /// Bus error
     Bus,
@@ -82,12 +93,27 @@ pub enum Error {
     Arbitration,
     /// NACK
     Nack,
+    /// A status flag wasn't set within `I2cConfig::timeout_ms`, eg because a device is holding
+    /// SCL low, or the bus has no pull-up resistors. The peripheral is reset, releasing the lines.
+    Timeout,
     // Overrun, // slave mode only
     // Pec, // SMBUS mode only
-    // Timeout, // SMBUS mode only
     // Alert, // SMBUS mode only
 }
 
+/// Diagnostic returned by `I2c::self_test` when the test fails.
+#[derive(Clone, Copy, Debug)]
+pub enum SelfTestError {
+    /// The bus was busy before the test started. This may indicate a missing pull-up resistor,
+    /// a line shorted low, or a device holding SDA low.
+    BusBusy,
+    /// The device didn't acknowledge its address. It may be absent, unpowered, or at a
+    /// different address, or the pins may be misconfigured.
+    NoAck,
+    /// A bus error or arbitration loss occurred.
+    I2c(Error),
+}
+
 #[derive(Clone, Copy)]
 #[repr(u8)]
 /// Set master or slave mode. Sets the __ register, _ field.
@@ -103,8 +129,8 @@ pub enum I2cMode {
 }
 
 #[derive(Clone, Copy)]
-/// Set a preset I2C speed, based on RM tables: Examples of timings settings.
-/// Sets 5 fields of the TIMINGR register.
+/// Set the I2C speed. The TIMINGR register's 5 fields are calculated from this and the I2C
+/// clock by `I2cTiming::calculate`, to meet the I2C spec's timing requirements for the mode.
 pub enum I2cSpeed {
     /// Standard-mode: 10kHz.
     Standard10K,
@@ -125,17 +151,185 @@ pub enum AddressBits {
 }
 
 #[derive(Clone, Copy, PartialEq)]
-/// Set the number of address bits to 7 or 10. Sets the CR1 register, ANFOFF and DNF fields.
+/// Select the noise filters applied to SDA and SCL. Sets the CR1 register, ANFOFF and DNF fields.
+/// The digital filter's value is the length of spikes it suppresses, in I2CCLK periods, between
+/// 1 and 15. Note that the digital filter adds to the SDA and SCL delays; see the RM's timing
+/// section if using it at high speeds.
 pub enum NoiseFilter {
-    /// Analog noise filter enabled.
+    /// Analog noise filter enabled. This suppresses spikes up to 50ns.
     Analog,
     /// Digital filter enabled and filtering capability ( filters spikes with a length of)
     /// up to (value) t_I2CCLK
     Digital(u8),
+    /// Both the analog filter, and the digital filter, with the value as above.
+    AnalogAndDigital(u8),
     /// Analog and digital filters disabled.
     Disabled,
 }
 
+impl NoiseFilter {
+    /// Returns the ANFOFF bit, and DNF bits.
+    fn bits(&self) -> (bool, u8) {
+        match self {
+            Self::Analog => (false, 0),
+            Self::Digital(filtering_len) => {
+                assert!(*filtering_len <= 0b1111);
+                (true, *filtering_len)
+            }
+            Self::AnalogAndDigital(filtering_len) => {
+                assert!(*filtering_len <= 0b1111);
+                (false, *filtering_len)
+            }
+            Self::Disabled => (true, 0),
+        }
+    }
+}
+
+/// I2C spec timing limits for a speed mode, in ns. From the I2C specification (UM10204), table 10,
+/// and L44 RM, table 234: "I2C-SMBUS specification data setup and hold times".
+struct SpecTiming {
+    freq: u32,
+    low_min: u32,
+    high_min: u32,
+    hd_dat_max: u32,
+    su_dat_min: u32,
+}
+
+impl I2cSpeed {
+    fn spec(&self) -> SpecTiming {
+        let (freq, low_min, high_min, hd_dat_max, su_dat_min) = match self {
+            Self::Standard10K => (10_000, 4_700, 4_000, 3_450, 250),
+            Self::Standard100K => (100_000, 4_700, 4_000, 3_450, 250),
+            Self::Fast400K => (400_000, 1_300, 600, 900, 100),
+            Self::FastPlus1M => (1_000_000, 500, 260, 450, 50),
+        };
+
+        SpecTiming {
+            freq,
+            low_min,
+            high_min,
+            hd_dat_max,
+            su_dat_min,
+        }
+    }
+}
+
+/// The analog filter's input delay range, in ns. (tAF, from the datasheet)
+const T_AF_MIN: u32 = 50;
+const T_AF_MAX: u32 = 260;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// Values for the TIMINGR register's fields. Calculated by `I2c::new`; use `calculate` directly to
+/// check which timings a clock configuration produces.
+pub struct I2cTiming {
+    pub presc: u8,
+    pub scldel: u8,
+    pub sdadel: u8,
+    pub sclh: u8,
+    pub scll: u8,
+}
+
+impl I2cTiming {
+    /// Calculate timings for `speed`, with an I2C kernel clock of `i2cclk` Hz, using the
+    /// procedure in L44 RM, section 37.4.9: "I2C master initialization", and "I2C timings".
+    /// Uses the smallest prescaler that works, for the best resolution. The noise filter and
+    /// rise and fall times delay the SCL and SDA edges, so they're taken into account. If the
+    /// I2C clock is too fast for the requested speed (eg 10kHz), uses the slowest SCL frequency
+    /// the prescaler and SCLL/SCLH fields allow. Returns `None` if no timing meets the spec, eg
+    /// if the I2C clock is too slow for 1MHz.
+    pub fn calculate(
+        i2cclk: u32,
+        speed: I2cSpeed,
+        filter: NoiseFilter,
+        rise_time_ns: u16,
+        fall_time_ns: u16,
+    ) -> Option<Self> {
+        let spec = speed.spec();
+        let (anfoff, dnf) = filter.bits();
+
+        // We work in picoseconds, for precision at high I2C clock speeds.
+        let ns = |v: u32| v as u64 * 1_000;
+        let t_clk = 1_000_000_000_000 / i2cclk as u64;
+        let t_r = ns(rise_time_ns as u32);
+        let t_f = ns(fall_time_ns as u32);
+        let t_dnf = dnf as u64 * t_clk;
+        let (t_af_min, t_af_max) = if anfoff {
+            (0, 0)
+        } else {
+            (ns(T_AF_MIN), ns(T_AF_MAX))
+        };
+
+        // RM: "tSCL = tSYNC1 + tSYNC2 + {[(SCLL+1) + (SCLH+1)] x (PRESC+1) x tI2CCLK}". Each
+        // sync delay is the edge time, the filter delays, and 2 to 3 I2CCLK periods.
+        let t_sync = t_f + t_r + 2 * (t_af_min + t_dnf) + 4 * t_clk;
+        let t_scl = 1_000_000_000_000 / spec.freq as u64;
+        if t_scl <= t_sync {
+            return None;
+        }
+
+        for presc in 0..16 {
+            let t_presc = (presc + 1) * t_clk;
+
+            // RM: "tSDADEL >= {tf + tHD;DAT(min) - tAF(min) - tDNF - 3 x tI2CCLK} / {(PRESC +1) x
+            // tI2CCLK}", and "tSDADEL <= {tHD;DAT(max) - tr - tAF(max) - tDNF - 4 x tI2CCLK} /
+            // {(PRESC +1) x tI2CCLK}". tHD;DAT(min) is 0. tSDADEL = SDADEL x tPRESC.
+            let sdadel_min =
+                (t_f.saturating_sub(t_af_min + t_dnf + 3 * t_clk) + t_presc - 1) / t_presc;
+            let sdadel_max =
+                match ns(spec.hd_dat_max).checked_sub(t_r + t_af_max + t_dnf + 4 * t_clk) {
+                    Some(t) => t / t_presc,
+                    None => continue,
+                };
+            if sdadel_min > 15 || sdadel_min > sdadel_max {
+                continue;
+            }
+
+            // RM: "tSCLDEL >= tr + tSU;DAT(min)". tSCLDEL = (SCLDEL+1) x tPRESC.
+            let scldel = ((t_r + ns(spec.su_dat_min) + t_presc - 1) / t_presc).max(1) - 1;
+            if scldel > 15 {
+                continue;
+            }
+
+            // Split the SCL period between low and high, meeting each minimum, and sharing the
+            // remainder equally.
+            let cycles = (t_scl - t_sync) / t_presc;
+            let low = (ns(spec.low_min) + t_presc - 1) / t_presc;
+            let high = (ns(spec.high_min) + t_presc - 1) / t_presc;
+            if low + high > cycles {
+                // A larger prescaler won't help.
+                return None;
+            }
+            let spare = cycles - low - high;
+            let mut low = low + (spare + 1) / 2;
+            let mut high = high + spare / 2;
+
+            if low > 256 || high > 256 {
+                if presc < 15 {
+                    continue;
+                }
+                // The I2C clock is too fast for this speed, even with the largest prescaler, eg
+                // for 10kHz. Use the slowest SCL the fields allow. It's faster than requested, but
+                // still meets the low and high period minimums.
+                if ns(spec.low_min) > 256 * t_presc || ns(spec.high_min) > 256 * t_presc {
+                    return None;
+                }
+                low = low.min(256);
+                high = high.min(256);
+            }
+
+            return Some(Self {
+                presc: presc as u8,
+                scldel: scldel as u8,
+                sdadel: sdadel_min as u8,
+                sclh: (high - 1) as u8,
+                scll: (low - 1) as u8,
+            });
+        }
+
+        None
+    }
+}
+
 /// Initial configuration data for the I2C peripheral.
 #[derive(Clone)]
 pub struct I2cConfig {
@@ -143,10 +337,15 @@ pub struct I2cConfig {
     pub mode: I2cMode,
     // /// Select between standard, fast, and fast-plus speeds. Default to standard.
     /// pub speed_mode: SpeedMode,
-    /// Select between one of 4 preset speeds. If you'd like to use custom
-    /// speed settings, use the PAC directly, with I2C disabled, after the
-    /// peripheral clocks are enabled by `new()`. Default to Standard mode, 100kHz.
+    /// Select between one of 4 speeds. If you'd like to use custom timing settings, use the PAC
+    /// directly, with I2C disabled, after the peripheral clocks are enabled by `new()`. Default to
+    /// Standard mode, 100kHz.
     pub speed: I2cSpeed,
+    /// The SCL and SDA rise time, in ns, as measured on the board: It depends on the pull-up
+    /// resistors and bus capacitance. Used to calculate timings. Defaults to 100ns.
+    pub rise_time_ns: u16,
+    /// The SCL and SDA fall time, in ns. Defaults to 10ns.
+    pub fall_time_ns: u16,
     /// Allows setting 7 or 10-bit addresses. Defaults to 7.
     pub address_bits: AddressBits,
     /// Select the analog noise filter, a digital filter, or no filter.
@@ -154,8 +353,12 @@ pub struct I2cConfig {
     /// Support for SMBUS, including hardware PEC, and alert pin. Defaults to false.
     pub smbus: bool,
     /// Optionally disable clock stretching. Defaults to false (stretching allowed)
-    /// Only relevant in slave mode.
+    /// Only relevant in slave mode; ignored in master mode.
     pub nostretch: bool,
+    /// The longest time blocking transfers wait for each status flag before returning
+    /// `Error::Timeout`, in ms. This is a minimum; the actual time may be longer. `None` waits
+    /// indefinitely. Defaults to 25ms, the SMBus clock low timeout.
+    pub timeout_ms: Option<u32>,
 }
 
 impl Default for I2cConfig {
@@ -163,18 +366,38 @@ impl Default for I2cConfig {
         Self {
             mode: I2cMode::Master,
             speed: I2cSpeed::Standard100K,
+            rise_time_ns: 100,
+            fall_time_ns: 10,
             address_bits: AddressBits::B7,
             noise_filter: NoiseFilter::Analog,
             smbus: false,
             nostretch: false,
+            timeout_ms: Some(25),
         }
     }
 }
 
+/// A pin that can be used as I2C `R`'s SCL signal. Implemented for the tokens of pins with the
+/// signal, along with its alternate function number. See `I2c::new_with_pins`.
+pub trait SclPin<R>: AltFnPin {
+    const AF: u8;
+}
+
+/// A pin that can be used as I2C `R`'s SDA signal. See `SclPin`.
+pub trait SdaPin<R>: AltFnPin {
+    const AF: u8;
+}
+
 /// Represents an Inter-Integrated Circuit (I2C) peripheral.
 pub struct I2c<R> {
     pub regs: R,
     pub cfg: I2cConfig,
+    /// Bytes remaining in a DMA transfer after the current chunk of up to 255. See `handle_reload`.
+    reload_remaining: usize,
+    /// The AUTOEND setting to apply to the last chunk of a DMA transfer.
+    reload_autoend: bool,
+    /// `I2cConfig::timeout_ms`, as a number of status register polls.
+    timeout_polls: Option<u32>,
 }
 
 impl<R> I2c<R>
@@ -184,6 +407,8 @@ where
 {
     /// Initialize a I2C peripheral, including configuration register writes, and enabling and resetting
     /// its RCC peripheral clock. `freq` is in Hz.
+    ///
+    /// Panics if the I2C clock is too slow for `cfg.speed`; see `I2cTiming::calculate`.
     pub fn new(regs: R, cfg: I2cConfig, clocks: &Clocks) -> Self {
         free(|_| {
             let rcc = unsafe { &(*RCC::ptr()) };
@@ -202,137 +427,21 @@ where
         // SCLDEL[3:0] and SDADEL[3:0] bits in the I2C_TIMINGR register.
         // ... Additionally, in master mode, the SCL clock high and low levels must be configured by
         // programming the PRESC[3:0], SCLH[7:0] and SCLL[7:0] bits in the I2C_TIMINGR register
-
-        // For these speed and frequency variables, we use the RM's conventions.
-        let t_i2cclk = clocks.apb1();
-
-        // assert!(t_i2cclk < (t_low - f_f) / 4);
-        // assert!(t_i2cclk < t_high);
-
-        // Set the prescaler using RM tables as a guide;
-        // L552 RM, Tables 324 - 326: Examples of timings settings.
-        // Note that the table only includes I2C clock multiples of 4Mhz (well, multiples of 8Mhz).
-        // In this case, we'll use the integer floor rounding to handle in-between
-        // values.
-
-        // We use this constant in several calculations.
-        let presc_const = match cfg.speed {
-            I2cSpeed::Standard10K => 4_000_000,
-            I2cSpeed::Standard100K => 4_000_000,
-            I2cSpeed::Fast400K => 8_000_000,
-            // Note: The 16Mhz example uses F+ / 16. The other 2 examples
-            // use 8e6.
-            I2cSpeed::FastPlus1M => 8_000_000,
-        };
-
-        // This is (offset by 1) which we set as the prescaler.
-        let mut presc_val = t_i2cclk / presc_const;
-
-        // The tables don't show faster I2C input clocks than 48Mhz, but it often will be.
-        // For example, an 80Mhz APB clock will peg prescaler at its maximum value.
-        // Let's just set it to this max. (Maybe we should use fast mode etc if this is so?)
-        if presc_val > 16 {
-            presc_val = 16;
-        }
-
-        // Hit the target freq by setting up t_scll (Period of SCL low)
-        // to be half the whole period. These constants
-        // are from the tables.
-        let freq = match cfg.speed {
-            I2cSpeed::Standard10K => 10_000,
-            I2cSpeed::Standard100K => 100_000,
-            I2cSpeed::Fast400K => 400_000,
-            I2cSpeed::FastPlus1M => 1_000_000,
-        };
-
-        // Set SCLL (SCL low time) to be half the duty period
-        // associated with the target frequency.
-        // todo: QC this is right if you peg presc_val at 16.
-        let scll_val;
-        if presc_val == 16 {
-            // IF we peg presc, we need to modify out calculation of scll (??)
-            scll_val = (t_i2cclk / presc_val) / (2 * freq);
-        } else {
-            scll_val = presc_const / (2 * freq);
-        }
-
-        // SCLH is smaller than SCLH. For standard mode it's close, although
-        // in the example tables, 20% different for 100Khz, and 2% different for
-        // 10K. THis may be due to delays
-        // involved. The ratio is different for Fast-mode and Fast-mode+.
-        // todo: Come back to this. How should we set this?
-        let sclh_val = match cfg.speed {
-            I2cSpeed::Standard10K => scll_val - 4,
-            I2cSpeed::Standard100K => scll_val - 4,
-            I2cSpeed::Fast400K => scll_val * 4 / 10,
-            I2cSpeed::FastPlus1M => scll_val / 2,
-        };
-
-        // Timing prescaler. This field is used to prescale I2CCLK in order to generate the clock period tPRESC used for
-        // data setup and hold counters (refer to I2C timings on page 1495) and for SCL high and low
-        // level counters (refer to I2C master initialization on page 1510).
-        // Sets TIMINGR reg, PRESC field.
-
-        let presc = presc_val - 1;
-
-        // SCL low period (master mode)
-        // This field is used to generate the SCL low period in master mode.
-        // tSCLL = (SCLL+1) x tPRESC
-        // Note: SCLL is also used to generate tBUF and tSU:STA timings.
-        // Sets TIMINGR reg, SCLL field.
-        let scll = scll_val - 1;
-
-        // SCL high period (master mode)
-        // This field is used to generate the SCL high period in master mode.
-        // tSCLH = (SCLH+1) x tPRESC
-        // Note: SCLH is also used to generate tSU:STO and tHD:STA timing
-        // Set the clock prescaler value. Sets TIMINGR reg, SCLH field.
-        let sclh = sclh_val - 1;
-
-        // todo: Can't find the sdadel and scldel pattern
-        // Data hold time
-        // This field is used to generate the delay tSDADEL between SCL falling edge and SDA edge. In
-        // master mode and in slave mode with NOSTRETCH = 0, the SCL line is stretched low during
-        // tSDADEL.
-        // tSDADEL= SDADEL x tPRESC
-        // Note: SDADEL is used to generate tHD:DAT timing
-        // Sets TIMINGR reg, SDADEL field.
-        let sdadel = match cfg.speed {
-            I2cSpeed::Standard10K => 0x2,
-            I2cSpeed::Standard100K => 0x2,
-            I2cSpeed::Fast400K => 0x3,
-            I2cSpeed::FastPlus1M => 0x0,
-        };
-
-        // Data setup time
-        // This field is used to generate a delay tSCLDEL between SDA edge and SCL rising edge. In
-        // master mode and in slave mode with NOSTRETCH = 0, the SCL line is stretched low during
-        // tSCLDEL.
-        // tSCLDEL = (SCLDEL+1) x tPRESC
-        // Note: tSCLDEL is used to generate tSU:DAT timing
-        // Sets TIMINGR reg, SCLDEL field.
-        let scldel = match cfg.speed {
-            I2cSpeed::Standard10K => 0x4,
-            I2cSpeed::Standard100K => 0x4,
-            I2cSpeed::Fast400K => 0x3,
-            I2cSpeed::FastPlus1M => 0x1,
-        };
-
-        // The fields for PRESC, SCLDEL, and SDADEL are 4-bits; don't overflow.
-        // The other TIMINGR fields we set are 8-bits, so won't overflow with u8.
-        assert!(presc <= 15);
-        assert!(scldel <= 15);
-        assert!(sdadel <= 15);
-
-        assert!(scll <= 255);
-        assert!(sclh <= 255);
+        let timing = I2cTiming::calculate(
+            clocks.apb1(),
+            cfg.speed,
+            cfg.noise_filter,
+            cfg.rise_time_ns,
+            cfg.fall_time_ns,
+        )
+        .expect("No I2C timing meets the spec for this speed with this I2C clock.");
 
         regs.timingr.write(|w| unsafe {
-            w.presc().bits(presc as u8);
-            w.scldel().bits(scldel as u8);
-            w.sdadel().bits(sdadel as u8);
-            w.sclh().bits(sclh as u8);
-            w.scll().bits(scll as u8)
+            w.presc().bits(timing.presc);
+            w.scldel().bits(timing.scldel);
+            w.sdadel().bits(timing.sdadel);
+            w.sclh().bits(timing.sclh);
+            w.scll().bits(timing.scll)
         });
 
         // Before enabling the I2C peripheral by setting the PE bit in I2C_CR1 register, the user must
@@ -344,14 +453,7 @@ where
         // When the digital filter is enabled, the level of the SCL or the SDA line is internally changed
         // only if it remains stable for more than DNF x I2CCLK periods. This allows spikes with a
         // programmable length of 1 to 15 I2CCLK periods to be suppressed.
-        let (anf_bit, dnf_bits) = match cfg.noise_filter {
-            NoiseFilter::Analog => (false, 0),
-            NoiseFilter::Digital(filtering_len) => {
-                assert!(filtering_len <= 0b1111);
-                (true, filtering_len)
-            }
-            NoiseFilter::Disabled => (true, 0),
-        };
+        let (anf_bit, dnf_bits) = cfg.noise_filter.bits();
 
         regs.cr1.modify(|_, w| unsafe {
             w.anfoff().bit(anf_bit);
@@ -362,18 +464,80 @@ where
             regs.cr1.modify(|_, w| w.nostretch().bit(cfg.nostretch));
         }
 
-        let mut result = Self { regs, cfg };
+        let timeout_polls = cfg
+            .timeout_ms
+            .map(|ms| (clocks.sysclk() / 1_000 / CYCLES_PER_POLL).saturating_mul(ms));
+
+        let mut result = Self {
+            regs,
+            cfg,
+            reload_remaining: 0,
+            reload_autoend: false,
+            timeout_polls,
+        };
 
         if result.cfg.smbus {
             result.enable_smbus();
         }
 
-        // Enable the peripheral
-        result.regs.cr1.write(|w| w.pe().set_bit());
+        // Enable the peripheral. Use `modify`, to preserve the filter, clock stretching, and
+        // SMBus settings above.
+        result.regs.cr1.modify(|_, w| w.pe().set_bit());
 
         result
     }
 
+    /// Initialize an I2C peripheral as with `new`, and configure its SCL and SDA pins with the
+    /// correct alternate function, as open-drain outputs. Pins without this I2C's signal fail to
+    /// compile. Their tokens are consumed, so the pins can't be taken by other drivers.
+    pub fn new_with_pins<SCL: SclPin<R>, SDA: SdaPin<R>>(
+        regs: R,
+        scl: SCL,
+        sda: SDA,
+        cfg: I2cConfig,
+        clocks: &Clocks,
+    ) -> Self {
+        let mut scl = scl.into_alt_fn(SCL::AF);
+        scl.output_type(OutputType::OpenDrain);
+        let mut sda = sda.into_alt_fn(SDA::AF);
+        sda.output_type(OutputType::OpenDrain);
+
+        Self::new(regs, cfg, clocks)
+    }
+
+    /// Change the noise filter settings. The filters can only be configured with the peripheral
+    /// disabled, so this disables it, then re-enables it; don't call during a transfer.
+    pub fn set_noise_filter(&mut self, filter: NoiseFilter) {
+        let (anf_bit, dnf_bits) = filter.bits();
+
+        self.regs.cr1.modify(|_, w| w.pe().clear_bit());
+        self.regs.cr1.modify(|_, w| unsafe {
+            w.anfoff().bit(anf_bit);
+            w.dnf().bits(dnf_bits)
+        });
+        self.regs.cr1.modify(|_, w| w.pe().set_bit());
+
+        self.cfg.noise_filter = filter;
+    }
+
+    /// Enable or disable clock stretching, for communicating with masters that don't support it.
+    /// (CR1 register, NOSTRETCH field) Only relevant in slave mode; in master mode, the RM requires
+    /// stretching to remain enabled, so disabling it panics. Disables the peripheral, then
+    /// re-enables it.
+    pub fn set_nostretch(&mut self, nostretch: bool) {
+        if nostretch {
+            if let I2cMode::Master = self.cfg.mode {
+                panic!("Clock stretching can only be disabled in slave mode.");
+            }
+        }
+
+        self.regs.cr1.modify(|_, w| w.pe().clear_bit());
+        self.regs.cr1.modify(|_, w| w.nostretch().bit(nostretch));
+        self.regs.cr1.modify(|_, w| w.pe().set_bit());
+
+        self.cfg.nostretch = nostretch;
+    }
+
     /// Enable SMBus support. See L44 RM, section 37.4.11: SMBus initialization
     pub fn enable_smbus(&mut self) {
         // todo: Roll this into an init setting or I2cConfig struct etc.
@@ -410,16 +574,16 @@ where
         // Wait for any previous address sequence to end
         // automatically. This could be up to 50% of a bus
         // cycle (ie. up to 0.5/freq)
-        while self.regs.cr2.read().start().bit_is_set() {}
+        self.wait_for_start()?;
 
         // Set START and prepare to receive bytes into
         // `buffer`. The START bit can be set even if the bus
         // is BUSY or I2C is in slave mode.
-        self.set_cr2_read(addr, bytes.len() as u8);
+        self.set_cr2_read(addr, bytes.len() as u8, true, false);
 
         for byte in bytes {
             // Wait until we have received something
-            busy_wait!(self.regs, rxne);
+            busy_wait!(self, rxne);
 
             *byte = self.regs.rxdr.read().rxdata().bits();
         }
@@ -432,15 +596,15 @@ where
         // Wait for any previous address sequence to end
         // automatically. This could be up to 50% of a bus
         // cycle (ie. up to 0.5/freq)
-        while self.regs.cr2.read().start().bit_is_set() {}
+        self.wait_for_start()?;
 
-        self.set_cr2_write(addr, bytes.len() as u8, true);
+        self.set_cr2_write(addr, bytes.len() as u8, true, false);
 
         for byte in bytes {
             // Wait until we are allowed to send data
             // (START has been ACKed or last byte when
             // through)
-            busy_wait!(self.regs, txis); // TXDR register is empty
+            busy_wait!(self, txis); // TXDR register is empty
 
             // Put byte on the wire
             self.regs.txdr.write(|w| unsafe { w.txdata().bits(*byte) });
@@ -449,44 +613,210 @@ where
         Ok(())
     }
 
-    /// Write and read an array of words. Can return an error due to Bus, Arbitration, or NACK.
+    /// Write, then read, as a single transaction, eg to read a sensor's registers: `bytes` is
+    /// typically the register address. The read begins with a repeated START instead of a STOP,
+    /// so the bus isn't released between the two; another master can't take it, and devices that
+    /// reset their register pointer on STOP return the right data. A STOP ends the transaction.
+    /// Can return an error due to Bus, Arbitration, or NACK. `bytes` and `buffer` can each be up to
+    /// 255 bytes. See L44 RM, section 37.4.9: "Master receiver".
     pub fn write_read(&mut self, addr: u8, bytes: &[u8], buffer: &mut [u8]) -> Result<(), Error> {
+        assert!(
+            bytes.len() <= 255 && buffer.len() <= 255,
+            "I2C writes and reads are limited to 255 bytes."
+        );
+
         // Wait for any previous address sequence to end
         // automatically. This could be up to 50% of a bus
         // cycle (ie. up to 0.5/freq)
-        while self.regs.cr2.read().start().bit_is_set() {}
+        self.wait_for_start()?;
 
-        self.set_cr2_write(addr, bytes.len() as u8, false);
+        // Software end mode (AUTOEND = 0): TC is set after the write, instead of a STOP being
+        // generated, and the SCL line is stretched low until we set START or STOP.
+        self.set_cr2_write(addr, bytes.len() as u8, false, false);
 
         for byte in bytes {
             // Wait until we are allowed to send data
             // (START has been ACKed or last byte went through)
 
-            busy_wait!(self.regs, txis); // TXDR register is empty
+            busy_wait!(self, txis); // TXDR register is empty
 
             // Put byte on the wire
             self.regs.txdr.write(|w| unsafe { w.txdata().bits(*byte) });
         }
 
         // Wait until the write finishes before beginning to read.
-        busy_wait!(self.regs, tc); // transfer is complete
+        busy_wait!(self, tc); // transfer is complete
+
+        if buffer.is_empty() {
+            // Nothing to read; end the transaction.
+            self.regs.cr2.modify(|_, w| w.stop().set_bit());
+        } else {
+            // RM: "If the I2C is already in master mode with AUTOEND = 0, setting this bit
+            // generates a Repeated Start condition". The read uses automatic end mode, so a
+            // STOP follows the last byte.
+            self.set_cr2_read(addr, buffer.len() as u8, true, false);
 
-        // reSTART and prepare to receive bytes into `buffer`
+            for byte in buffer {
+                // Wait until we have received something
+                busy_wait!(self, rxne);
 
-        self.set_cr2_read(addr, buffer.len() as u8);
+                *byte = self.regs.rxdr.read().rxdata().bits();
+            }
+        }
 
-        for byte in buffer {
-            // Wait until we have received something
-            busy_wait!(self.regs, rxne);
+        // Wait for the STOP, so the transaction is over when we return.
+        busy_wait!(self, stopf);
+        self.regs.icr.write(|w| w.stopcf().set_bit());
 
-            *byte = self.regs.rxdr.read().rxdata().bits();
+        Ok(())
+    }
+
+    /// Wait for the START bit to clear, ie for any previous address sequence to end.
+    fn wait_for_start(&mut self) -> Result<(), Error> {
+        let mut polls = 0;
+        while self.regs.cr2.read().start().bit_is_set() {
+            self.check_timeout(&mut polls)?;
         }
+        Ok(())
+    }
 
+    /// Count a status register poll. On timeout, reset the peripheral, releasing SCL and SDA, and
+    /// return `Error::Timeout`.
+    fn check_timeout(&mut self, polls: &mut u32) -> Result<(), Error> {
+        if let Some(max) = self.timeout_polls {
+            if *polls >= max {
+                // RM: "PE must be kept low during at least 3 APB clock cycles in order to
+                // perform the software reset. This is ensured by writing the following software
+                // sequence: - Write PE=0 - Check PE=0 - Write PE=1."
+                self.regs.cr1.modify(|_, w| w.pe().clear_bit());
+                while self.regs.cr1.read().pe().bit_is_set() {}
+                self.regs.cr1.modify(|_, w| w.pe().set_bit());
+
+                return Err(Error::Timeout);
+            }
+        }
+        *polls += 1;
         Ok(())
     }
 
+    /// Recover a stuck bus, eg after the MCU was reset mid-transaction, leaving a target holding
+    /// SDA low while it waits to send the rest of a byte. Temporarily takes the SCL and SDA pins as
+    /// open-drain GPIO outputs, clocks SCL up to 9 times at about 100kHz until the target releases
+    /// SDA, issues a STOP, then returns the pins to alternate function `alt_fn`, and re-initializes
+    /// the peripheral. The pins' pull resistor settings are left unchanged. Must be in master mode.
+    ///
+    /// Returns `Error::Bus` if either line is still low afterwards, eg due to a short, or a missing
+    /// pull-up resistor. See the I2C specification (UM10204), section 3.1.16: "Bus clear".
+    pub fn recover_bus(
+        &mut self,
+        scl: &mut Pin,
+        sda: &mut Pin,
+        alt_fn: u8,
+        clocks: &Clocks,
+    ) -> Result<(), Error> {
+        // Half an SCL period at 100kHz, in CPU cycles. The CPU clock is at most SYSCLK, so this
+        // is at least 5us.
+        let half_period = clocks.sysclk() / 200_000 + 1;
+        let delay = || cortex_m::asm::delay(half_period);
+
+        // RM: "PE must be kept low during at least 3 APB clock cycles in order to perform the
+        // software reset." This releases the lines, and resets the peripheral's state machines.
+        self.regs.cr1.modify(|_, w| w.pe().clear_bit());
+
+        // Set the outputs high (released) before switching modes, so we don't drive the lines.
+        for pin in [&mut *scl, &mut *sda] {
+            pin.set_high();
+            pin.output_type(OutputType::OpenDrain);
+            pin.mode(PinMode::Output);
+        }
+        delay();
+
+        for _ in 0..9 {
+            if sda.is_high() {
+                break;
+            }
+            scl.set_low();
+            delay();
+            scl.set_high();
+            // Allow the target to stretch the clock, up to about 5ms.
+            for _ in 0..1_000 {
+                if scl.is_high() {
+                    break;
+                }
+                delay();
+            }
+            delay();
+        }
+
+        // STOP: SDA rises while SCL is high.
+        scl.set_low();
+        delay();
+        sda.set_low();
+        delay();
+        scl.set_high();
+        delay();
+        sda.set_high();
+        delay();
+
+        let released = scl.is_high() && sda.is_high();
+
+        scl.mode(PinMode::Alt(alt_fn));
+        sda.mode(PinMode::Alt(alt_fn));
+
+        // The configuration registers are unaffected by the software reset.
+        self.regs.cr1.modify(|_, w| w.pe().set_bit());
+
+        if released {
+            Ok(())
+        } else {
+            Err(Error::Bus)
+        }
+    }
+
+    /// Run a bus self-test, eg from production test firmware. Checks that the bus is idle, then
+    /// addresses the device at `addr` with a zero-length write, and checks that it acknowledges.
+    /// Must be in master mode. Flag waits are bounded by `I2cConfig::timeout_ms`.
+    pub fn self_test(&mut self, addr: u8) -> Result<(), SelfTestError> {
+        if self.regs.isr.read().busy().bit_is_set() {
+            return Err(SelfTestError::BusBusy);
+        }
+
+        self.wait_for_start().map_err(SelfTestError::I2c)?;
+
+        // With NBYTES = 0 and AUTOEND set, a STOP is generated right after the address phase.
+        self.set_cr2_write(addr, 0, true, false);
+
+        let mut polls = 0;
+        loop {
+            let isr = self.regs.isr.read();
+
+            if isr.berr().bit_is_set() {
+                self.regs.icr.write(|w| w.berrcf().set_bit());
+                return Err(SelfTestError::I2c(Error::Bus));
+            } else if isr.arlo().bit_is_set() {
+                self.regs.icr.write(|w| w.arlocf().set_bit());
+                return Err(SelfTestError::I2c(Error::Arbitration));
+            } else if isr.nackf().bit_is_set() {
+                // In automatic end mode, a STOP follows the NACK.
+                while self.regs.isr.read().stopf().bit_is_clear() {
+                    self.check_timeout(&mut polls).map_err(SelfTestError::I2c)?;
+                }
+                self.regs
+                    .icr
+                    .write(|w| w.nackcf().set_bit().stopcf().set_bit());
+                return Err(SelfTestError::NoAck);
+            } else if isr.stopf().bit_is_set() {
+                self.regs.icr.write(|w| w.stopcf().set_bit());
+                return Ok(());
+            }
+
+            self.check_timeout(&mut polls).map_err(SelfTestError::I2c)?;
+        }
+    }
+
     /// Helper function to prevent repetition between `write`, `write_read`, and `write_dma`.
-    fn set_cr2_write(&mut self, addr: u8, len: u8, autoend: bool) {
+    /// `reload` is for transfers of over 255 bytes; see `handle_reload`.
+    fn set_cr2_write(&mut self, addr: u8, len: u8, autoend: bool, reload: bool) {
         // L44 RM: "Master communication initialization (address phase)
         // In order to initiate the communication, the user must program the following parameters for
         // the addressed slave in the I2C_CR2 register:
@@ -503,6 +833,7 @@ where
                                         // The number of bytes to be transferred: NBYTES[7:0]. If the number of bytes is equal to
                                         // or greater than 255 bytes, NBYTES[7:0] must initially be filled with 0xFF.
                 w.nbytes().bits(len);
+                w.reload().bit(reload);
                 w.autoend().bit(autoend); // software end mode
                                           // The user must then set the START bit in I2C_CR2 register. Changing all the above bits is
                                           // not allowed when START bit is set.
@@ -525,14 +856,16 @@ where
     }
 
     /// Helper function to prevent repetition between `read`, `write_read`, and `read_dma`.
-    fn set_cr2_read(&mut self, addr: u8, len: u8) {
+    /// `reload` is for transfers of over 255 bytes; see `handle_reload`.
+    fn set_cr2_read(&mut self, addr: u8, len: u8, autoend: bool, reload: bool) {
         self.regs.cr2.write(|w| {
             unsafe {
                 w.add10().bit(self.cfg.address_bits as u8 != 0);
                 w.sadd().bits(u16(addr << 1));
                 w.rd_wrn().set_bit(); // read
                 w.nbytes().bits(len);
-                w.autoend().set_bit(); // automatic end mode
+                w.reload().bit(reload);
+                w.autoend().bit(autoend); // automatic end mode, unless part of a transaction
                                        // When the SMBus master wants to receive the PEC followed by a STOP at the end of the
                                        // transfer, automatic end mode can be selected (AUTOEND=1). The PECBYTE bit must be
                                        // set and the slave address must be programmed, before setting the START bit. In this case,
@@ -545,11 +878,20 @@ where
         });
     }
 
-    #[cfg(not(any(feature = "g0", feature = "f4", feature = "l5")))]
+    #[cfg(not(any(feature = "g0", feature = "f4")))]
     /// Read data, using DMA. See L44 RM, 37.4.16: "Transmission using DMA"
-    /// Note that the `channel` argument is only used on F3 and L4.
+    /// Note that the `channel` argument has no effect on F3 and L4.
+    /// On WB and WL, the DMAMUX is configured to route this peripheral's request to it.
     /// For a single write, set `autoend` to `true`. For a write_read and other use cases,
     /// set it to `false`.
+    ///
+    /// Transfers of over 255 bytes are sent in chunks, since the NBYTES field is 8 bits. This
+    /// enables the transfer complete reload interrupt; call `handle_reload` from this I2C's event
+    /// interrupt handler to start each chunk. This is required: Without it, the transfer stops
+    /// after the first 255 bytes, with SCL held low.
+    ///
+    /// Returns `Error::Timeout` if a previous address sequence doesn't end within
+    /// `I2cConfig::timeout_ms`.
     pub unsafe fn write_dma<D>(
         &mut self,
         addr: u8,
@@ -558,10 +900,12 @@ where
         channel: DmaChannel,
         channel_cfg: ChannelCfg,
         dma: &mut Dma<D>,
-    ) where
+    ) -> Result<(), Error>
+    where
         D: Deref<Target = dma_p::RegisterBlock>,
+        R: DmaPeriph,
     {
-        while self.regs.cr2.read().start().bit_is_set() {}
+        self.wait_for_start()?;
 
         let (ptr, len) = (buf.as_ptr(), buf.len());
 
@@ -569,6 +913,8 @@ where
         let channel = R::write_chan();
         #[cfg(feature = "l4")]
         R::write_sel(dma);
+        #[cfg(any(feature = "wb", feature = "wl"))]
+        dma::mux_dma(dma, channel, R::write_input());
 
         // DMA (Direct Memory Access) can be enabled for transmission by setting the TXDMAEN bit
         // in the I2C_CR1 register. Data is loaded from an SRAM area configured using the DMA
@@ -584,7 +930,8 @@ where
         // initialized before setting the START bit. The end of transfer is managed with the
         // NBYTES counter. Refer to Master transmitter on page 1151.
         // (The steps above are handled in the write this function performs.)
-        self.set_cr2_write(addr, len as u8, autoend);
+        let first = self.start_reload(len, autoend);
+        self.set_cr2_write(addr, first, autoend, self.reload_remaining > 0);
 
         // • In slave mode:
         // – With NOSTRETCH=0, when all data are transferred using DMA, the DMA must be
@@ -613,11 +960,18 @@ where
             dma::DataSize::S8,
             channel_cfg,
         );
+
+        Ok(())
     }
 
-    #[cfg(not(any(feature = "g0", feature = "f4", feature = "l5")))]
+    #[cfg(not(any(feature = "g0", feature = "f4")))]
     /// Read data, using DMA. See L44 RM, 37.4.16: "Reception using DMA"
-    /// Note that the `channel` argument is only used on F3 and L4.
+    /// Note that the `channel` argument has no effect on F3 and L4.
+    /// On WB and WL, the DMAMUX is configured to route this peripheral's request to it.
+    ///
+    /// As with `write_dma`, transfers of over 255 bytes require calling `handle_reload` from
+    /// this I2C's event interrupt handler; without it, the transfer stops after the first 255
+    /// bytes.
     pub unsafe fn read_dma<D>(
         &mut self,
         addr: u8,
@@ -627,6 +981,7 @@ where
         dma: &mut Dma<D>,
     ) where
         D: Deref<Target = dma_p::RegisterBlock>,
+        R: DmaPeriph,
     {
         // while self.regs.cr2.read().start().bit_is_set() {}
         // todo: Think about how you want to do write reads. Ie there's no stopping there.
@@ -637,6 +992,8 @@ where
         let channel = R::read_chan();
         #[cfg(feature = "l4")]
         R::read_sel(dma);
+        #[cfg(any(feature = "wb", feature = "wl"))]
+        dma::mux_dma(dma, channel, R::read_input());
 
         // DMA (Direct Memory Access) can be enabled for reception by setting the RXDMAEN bit in
         // the I2C_CR1 register. Data is loaded from the I2C_RXDR register to an SRAM area
@@ -650,7 +1007,8 @@ where
         // START bit are programmed by software. When all data are transferred using DMA, the
         // DMA must be initialized before setting the START bit. The end of transfer is managed
         // with the NBYTES counter.
-        self.set_cr2_read(addr, len as u8);
+        let first = self.start_reload(len, true);
+        self.set_cr2_read(addr, first, true, self.reload_remaining > 0);
 
         // • In slave mode with NOSTRETCH=0, when all data are transferred using DMA, the
         // DMA must be initialized before the address match event, or in the ADDR interrupt
@@ -676,6 +1034,47 @@ where
             channel_cfg,
         );
     }
+
+    /// Set up chunking for a DMA transfer of `len` bytes, and return the size of the first chunk.
+    fn start_reload(&mut self, len: usize, autoend: bool) -> u8 {
+        let first = len.min(255);
+        self.reload_remaining = len - first;
+        self.reload_autoend = autoend;
+
+        if self.reload_remaining > 0 {
+            self.regs.cr1.modify(|_, w| w.tcie().set_bit());
+        }
+
+        first as u8
+    }
+
+    /// For DMA transfers of over 255 bytes: Call this from the I2C's event interrupt handler. If
+    /// a chunk is complete (TCR), this starts the next one, and returns `true`. RM: "When RELOAD=1
+    /// and NBYTES data have been transferred, the TCR flag is set [...] SCL is stretched low until
+    /// NBYTES[7:0] is written to a non-zero value." The DMA channel transfers the whole buffer,
+    /// so only NBYTES needs updating. Disables the interrupt after the last chunk starts.
+    pub fn handle_reload(&mut self) -> bool {
+        if self.regs.isr.read().tcr().bit_is_clear() {
+            return false;
+        }
+
+        let next = self.reload_remaining.min(255);
+        self.reload_remaining -= next;
+        let last = self.reload_remaining == 0;
+
+        // Writing NBYTES clears TCR.
+        self.regs.cr2.modify(|_, w| unsafe {
+            w.nbytes().bits(next as u8);
+            w.reload().bit(!last);
+            w.autoend().bit(last && self.reload_autoend)
+        });
+
+        if last {
+            self.regs.cr1.modify(|_, w| w.tcie().clear_bit());
+        }
+
+        true
+    }
 }
 
 #[cfg(feature = "embedded-hal")]
@@ -716,3 +1115,201 @@ where
         I2c::write_read(self, addr, bytes, buffer)
     }
 }
+
+#[cfg(feature = "embedded-hal-1")]
+impl embedded_hal_1::i2c::Error for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Self::Bus => ErrorKind::Bus,
+            Self::Arbitration => ErrorKind::ArbitrationLoss,
+            Self::Nack => ErrorKind::NoAcknowledge(NoAcknowledgeSource::Unknown),
+            Self::Timeout => ErrorKind::Other,
+        }
+    }
+}
+
+#[cfg(feature = "embedded-hal-1")]
+impl<R> ErrorType for I2c<R>
+where
+    R: Deref<Target = pac::i2c1::RegisterBlock> + RccPeriph,
+{
+    type Error = Error;
+}
+
+#[cfg(feature = "embedded-hal-1")]
+impl<R> embedded_hal_1::i2c::I2c for I2c<R>
+where
+    R: Deref<Target = pac::i2c1::RegisterBlock> + RccPeriph,
+{
+    /// Runs the operations as one transaction. Adjacent operations of the same type are
+    /// combined, with no START between them; operations of different types are separated by a
+    /// repeated START. A STOP ends the transaction. Groups of over 255 bytes are transferred in
+    /// chunks, using NBYTES reload.
+    fn transaction(&mut self, addr: u8, operations: &mut [Operation<'_>]) -> Result<(), Error> {
+        if operations.is_empty() {
+            return Ok(());
+        }
+
+        self.wait_for_start()?;
+
+        let mut i = 0;
+        while i < operations.len() {
+            let read = matches!(operations[i], Operation::Read(_));
+            let mut end = i;
+            while end < operations.len() && matches!(operations[end], Operation::Read(_)) == read
+            {
+                end += 1;
+            }
+            let group = &mut operations[i..end];
+
+            let mut remaining: usize = group
+                .iter()
+                .map(|op| match op {
+                    Operation::Read(buf) => buf.len(),
+                    Operation::Write(buf) => buf.len(),
+                })
+                .sum();
+
+            // Software end mode: TC is set after each group, and SCL is stretched until we
+            // set START (a repeated START), or STOP.
+            let mut in_chunk = remaining.min(255);
+            remaining -= in_chunk;
+            if read {
+                self.set_cr2_read(addr, in_chunk as u8, false, remaining > 0);
+            } else {
+                self.set_cr2_write(addr, in_chunk as u8, false, remaining > 0);
+            }
+
+            for op in group.iter_mut() {
+                let len = match op {
+                    Operation::Read(buf) => buf.len(),
+                    Operation::Write(buf) => buf.len(),
+                };
+
+                for j in 0..len {
+                    if in_chunk == 0 {
+                        // Start the next chunk. Writing NBYTES clears TCR.
+                        busy_wait!(self, tcr);
+                        in_chunk = remaining.min(255);
+                        remaining -= in_chunk;
+                        self.regs.cr2.modify(|_, w| unsafe {
+                            w.nbytes().bits(in_chunk as u8);
+                            w.reload().bit(remaining > 0)
+                        });
+                    }
+
+                    match op {
+                        Operation::Read(buf) => {
+                            busy_wait!(self, rxne);
+                            buf[j] = self.regs.rxdr.read().rxdata().bits();
+                        }
+                        Operation::Write(buf) => {
+                            busy_wait!(self, txis);
+                            self.regs.txdr.write(|w| unsafe { w.txdata().bits(buf[j]) });
+                        }
+                    }
+                    in_chunk -= 1;
+                }
+            }
+
+            busy_wait!(self, tc);
+            i = end;
+        }
+
+        self.regs.cr2.modify(|_, w| w.stop().set_bit());
+        busy_wait!(self, stopf);
+        self.regs.icr.write(|w| w.stopcf().set_bit());
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Check that `timing` meets the spec's SCL low and high minimums, and doesn't exceed the
+    /// spec's frequency.
+    fn check_spec(i2cclk: u32, speed: I2cSpeed, timing: I2cTiming) {
+        let spec = speed.spec();
+        let t_presc = (timing.presc as u64 + 1) * (1_000_000_000_000 / i2cclk as u64);
+        let t_low = (timing.scll as u64 + 1) * t_presc;
+        let t_high = (timing.sclh as u64 + 1) * t_presc;
+
+        assert!(t_low >= spec.low_min as u64 * 1_000);
+        assert!(t_high >= spec.high_min as u64 * 1_000);
+        assert!(t_low + t_high <= 1_000_000_000_000 / spec.freq as u64);
+    }
+
+    #[test]
+    fn timing_standard_100k_16mhz() {
+        let timing = I2cTiming::calculate(
+            16_000_000,
+            I2cSpeed::Standard100K,
+            NoiseFilter::Analog,
+            100,
+            10,
+        )
+        .unwrap();
+
+        assert_eq!(
+            timing,
+            I2cTiming {
+                presc: 0,
+                scldel: 5,
+                sdadel: 0,
+                sclh: 69,
+                scll: 81,
+            }
+        );
+        check_spec(16_000_000, I2cSpeed::Standard100K, timing);
+    }
+
+    #[test]
+    fn timing_meets_spec() {
+        for i2cclk in [16_000_000, 48_000_000, 80_000_000, 170_000_000] {
+            for speed in [I2cSpeed::Standard100K, I2cSpeed::Fast400K] {
+                let timing =
+                    I2cTiming::calculate(i2cclk, speed, NoiseFilter::Analog, 100, 10).unwrap();
+                check_spec(i2cclk, speed, timing);
+            }
+        }
+
+        // Fast-mode plus leaves no room for the analog filter's delay, or slow edges.
+        for i2cclk in [48_000_000, 80_000_000, 170_000_000] {
+            let speed = I2cSpeed::FastPlus1M;
+            let timing =
+                I2cTiming::calculate(i2cclk, speed, NoiseFilter::Disabled, 20, 10).unwrap();
+            check_spec(i2cclk, speed, timing);
+        }
+    }
+
+    #[test]
+    fn timing_clamps_slow_speed() {
+        // 10kHz needs more than 256 prescaled cycles per half period at 170Mhz.
+        let timing = I2cTiming::calculate(
+            170_000_000,
+            I2cSpeed::Standard10K,
+            NoiseFilter::Analog,
+            100,
+            10,
+        )
+        .unwrap();
+
+        assert_eq!((timing.presc, timing.scll, timing.sclh), (15, 255, 255));
+    }
+
+    #[test]
+    fn timing_clock_too_slow() {
+        assert_eq!(
+            I2cTiming::calculate(
+                4_000_000,
+                I2cSpeed::FastPlus1M,
+                NoiseFilter::Analog,
+                100,
+                10
+            ),
+            None
+        );
+    }
+}
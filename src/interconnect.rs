@@ -0,0 +1,202 @@
+//! Support for the G4's internal interconnect matrix: Routing timer, comparator, EXTI, and HRTIM
+//! events to ADC and DAC triggers, and comparator outputs to advanced timer break inputs. See G4 RM,
+//! the Interconnect matrix section, and the EXTSEL and TSEL tables in the ADC and DAC chapters.
+//!
+//! Routes are validated against these tables: Lookups return `Err(RouteError {})` if a source
+//! isn't wired to the requested destination. Note that some sources (eg TIM20, HRTIM, COMP5-7)
+//! aren't present on all G4 variants; their selection values are still reserved for them.
+//!
+//! Example: Trigger ADC1 conversions from TIM1 TRGO, and DAC1 channel 1 from TIM6 TRGO:
+//! ```
+//! adc1.set_trigger(TriggerSource::Tim1Trgo, TriggerEdge::Rising).unwrap();
+//! dac1.set_trigger_source(DacChannel::C1, TriggerSource::Tim6Trgo).unwrap();
+//! interconnect::route_comp_to_break(&timer1.regs, Comparator::C1, BreakInput::Bk, true, false).unwrap();
+//! ```
+
+use crate::{adc::AdcDevice, pac::tim1};
+
+pub use crate::{adc::TriggerEdge, timer::BreakInput, util::RouteError};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// An internal event that can trigger ADC conversions, or DAC conversions.
+pub enum TriggerSource {
+    Tim1Trgo,
+    Tim1Trgo2,
+    Tim1Cc1,
+    Tim1Cc2,
+    Tim1Cc3,
+    Tim2Trgo,
+    Tim2Cc1,
+    Tim2Cc2,
+    Tim2Cc3,
+    Tim3Trgo,
+    Tim3Cc1,
+    Tim3Cc4,
+    Tim4Trgo,
+    Tim4Cc1,
+    Tim4Cc4,
+    Tim6Trgo,
+    Tim7Trgo,
+    Tim8Trgo,
+    Tim8Trgo2,
+    Tim8Cc1,
+    Tim15Trgo,
+    Tim20Trgo,
+    Tim20Trgo2,
+    Tim20Cc1,
+    Tim20Cc2,
+    Tim20Cc3,
+    Exti2,
+    Exti9,
+    Exti11,
+    LptimOut,
+    /// HRTIM ADC trigger 1 - 10 (`HRTIM_ADCTRGx`). Used by ADCs.
+    HrtimAdcTrg(u8),
+    /// HRTIM DAC reset trigger 1 - 6 (`HRTIM_RST_TRGx`). Used by DACs.
+    HrtimDacResetTrg(u8),
+    /// The HRTIM DAC step trigger (`HRTIM_STEP_TRGx`) associated with the DAC in use. Used by DACs.
+    HrtimDacStepTrg,
+    /// Software trigger, using the SWTRIG register. Used by DACs.
+    Software,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(u8)]
+/// A comparator peripheral, as a source for timer break inputs.
+pub enum Comparator {
+    C1 = 1,
+    C2 = 2,
+    C3 = 3,
+    C4 = 4,
+    C5 = 5,
+    C6 = 6,
+    C7 = 7,
+}
+
+/// Find the EXTSEL value for an ADC's regular conversion trigger. See G4 RM, ADC chapter:
+/// "ADC1/2 - External triggers for regular channels", and the ADC3/4/5 equivalent.
+pub fn adc_extsel(device: AdcDevice, source: TriggerSource) -> Result<u8, RouteError> {
+    use TriggerSource::*;
+
+    // These sources share the same value on all ADCs.
+    let common = match source {
+        Tim8Trgo => Some(7),
+        Tim8Trgo2 => Some(8),
+        Tim1Trgo => Some(9),
+        Tim1Trgo2 => Some(10),
+        Tim2Trgo => Some(11),
+        Tim4Trgo => Some(12),
+        Tim6Trgo => Some(13),
+        Tim15Trgo => Some(14),
+        Tim20Trgo => Some(16),
+        Tim20Trgo2 => Some(17),
+        Tim20Cc1 => Some(18),
+        HrtimAdcTrg(n @ 5..=10) => Some(n + 18),
+        LptimOut => Some(29),
+        Tim7Trgo => Some(30),
+        _ => None,
+    };
+
+    if let Some(v) = common {
+        return Ok(v);
+    }
+
+    match device {
+        AdcDevice::One | AdcDevice::Two => match source {
+            Tim1Cc1 => Ok(0),
+            Tim1Cc2 => Ok(1),
+            Tim1Cc3 => Ok(2),
+            Tim2Cc2 => Ok(3),
+            Tim3Trgo => Ok(4),
+            Tim4Cc4 => Ok(5),
+            Exti11 => Ok(6),
+            Tim3Cc4 => Ok(15),
+            Tim20Cc2 => Ok(19),
+            Tim20Cc3 => Ok(20),
+            HrtimAdcTrg(1) => Ok(21),
+            HrtimAdcTrg(3) => Ok(22),
+            _ => Err(RouteError {}),
+        },
+        _ => match source {
+            Tim3Cc1 => Ok(0),
+            Tim2Cc3 => Ok(1),
+            Tim1Cc3 => Ok(2),
+            Tim8Cc1 => Ok(3),
+            Tim3Trgo => Ok(4),
+            Exti2 => Ok(5),
+            Tim4Cc1 => Ok(6),
+            Tim2Cc1 => Ok(15),
+            HrtimAdcTrg(2) => Ok(19),
+            HrtimAdcTrg(4) => Ok(20),
+            HrtimAdcTrg(1) => Ok(21),
+            HrtimAdcTrg(3) => Ok(22),
+            _ => Err(RouteError {}),
+        },
+    }
+}
+
+/// Find the TSEL value for a DAC channel trigger. See G4 RM, DAC chapter: DAC trigger selection.
+/// The same table applies to DAC1 - 4; `HrtimDacStepTrg` selects `HRTIM_STEP_TRG1` - `4`, depending
+/// on the DAC.
+pub fn dac_tsel(source: TriggerSource) -> Result<u8, RouteError> {
+    use TriggerSource::*;
+
+    match source {
+        Software => Ok(0),
+        Tim8Trgo => Ok(1),
+        Tim7Trgo => Ok(2),
+        Tim15Trgo => Ok(3),
+        Tim2Trgo => Ok(4),
+        Tim4Trgo => Ok(5),
+        Exti9 => Ok(6),
+        Tim6Trgo => Ok(7),
+        Tim3Trgo => Ok(8),
+        HrtimDacResetTrg(n @ 1..=6) => Ok(n + 8),
+        HrtimDacStepTrg => Ok(15),
+        _ => Err(RouteError {}),
+    }
+}
+
+/// Connect a comparator's output to an advanced timer's (TIM1, TIM8, or TIM20) break input, or
+/// disconnect it. If `inverted` is set, the comparator output is inverted before reaching the break
+/// circuitry; polarity selection is only available for COMP1 - 4. Sets the TIMx_AF1 (BRK) or
+/// TIMx_AF2 (BRK2) registers' BKCMPxE and BKCMPxP fields.
+///
+/// Note that the break function itself must still be enabled in the BDTR register, eg with
+/// `Timer::set_break_input`.
+pub fn route_comp_to_break(
+    regs: &tim1::RegisterBlock,
+    comp: Comparator,
+    input: BreakInput,
+    enabled: bool,
+    inverted: bool,
+) -> Result<(), RouteError> {
+    if inverted && comp as u8 > 4 {
+        return Err(RouteError {});
+    }
+
+    // `TIMx_AF1` and `TIMx_AF2` share a layout, but their fields are named for BRK and BRK2
+    // respectively.
+    match input {
+        BreakInput::Bk => regs.af1.modify(|_, w| match comp {
+            Comparator::C1 => w.bkcmp1e().bit(enabled).bkcmp1p().bit(inverted),
+            Comparator::C2 => w.bkcmp2e().bit(enabled).bkcmp2p().bit(inverted),
+            Comparator::C3 => w.bkcmp3e().bit(enabled).bkcmp3p().bit(inverted),
+            Comparator::C4 => w.bkcmp4e().bit(enabled).bkcmp4p().bit(inverted),
+            Comparator::C5 => w.bkcmp5e().bit(enabled),
+            Comparator::C6 => w.bkcmp6e().bit(enabled),
+            Comparator::C7 => w.bkcmp7e().bit(enabled),
+        }),
+        BreakInput::Bk2 => regs.af2.modify(|_, w| match comp {
+            Comparator::C1 => w.bk2cmp1e().bit(enabled).bk2cmp1p().bit(inverted),
+            Comparator::C2 => w.bk2cmp2e().bit(enabled).bk2cmp2p().bit(inverted),
+            Comparator::C3 => w.bk2cmp3e().bit(enabled).bk2cmp3p().bit(inverted),
+            Comparator::C4 => w.bk2cmp4e().bit(enabled).bk2cmp4p().bit(inverted),
+            Comparator::C5 => w.bk2cmp5e().bit(enabled),
+            Comparator::C6 => w.bk2cmp6e().bit(enabled),
+            Comparator::C7 => w.bk2cmp7e().bit(enabled),
+        }),
+    }
+
+    Ok(())
+}
@@ -39,7 +39,8 @@
 //! stm32-hal2 = { version = "^1.4.0", features = ["l4x3", "l4rt"]}
 //! ```
 //!
-//! If you need `embedded-hal` traits, include the `embedded-hal` feature.
+//! If you need `embedded-hal` traits, include the `embedded-hal` feature. For embedded-hal 1.0 traits,
+//! include the `embedded-hal-1` feature.
 //!
 //! You can review [this section of Cargo.toml](https://github.com/David-OConnor/stm32-hal/blob/main/Cargo.toml#L61)
 //! to see which MCU and runtime features are available.
@@ -110,7 +111,8 @@
 // Using `unsafe` for all is cleaner than feature-gating, due to how many fields this affects. We've allowed
 // these warnings; ie hidden during build.
 
-#![no_std]
+// Host-side unit tests use `std`; see the Readme's "Tests" section.
+#![cfg_attr(not(test), no_std)]
 // Some reg modifications are marked `unsafe` in some PAC crates, but not others.
 // Disable these warnings.
 #![allow(unused_unsafe)]
@@ -179,6 +181,19 @@
 )))]
 compile_error!("This crate requires an MCU-specifying feature to be enabled. eg `l552`.");
 
+#[cfg(all(
+    any(feature = "h7_smps", feature = "h7_smps_ldo"),
+    not(any(feature = "h747cm4", feature = "h747cm7", feature = "h7b3"))
+))]
+compile_error!("The `h7_smps` and `h7_smps_ldo` features require an H7 variant with an SMPS.");
+
+#[cfg(any(
+    all(feature = "h7_smps", feature = "h7_smps_ldo"),
+    all(feature = "h7_smps", feature = "h7_bypass"),
+    all(feature = "h7_smps_ldo", feature = "h7_bypass"),
+))]
+compile_error!("Only one H7 supply topology feature may be enabled.");
+
 // Re-export of the [svd2rust](https://crates.io/crates/svd2rust) auto-generated API for
 // stm32 peripherals.
 
@@ -358,6 +373,9 @@ pub use stm32wl::stm32wle5 as pac;
 #[cfg(not(any(feature = "f301", feature = "f302")))]
 pub mod adc;
 
+#[cfg(feature = "async_wfe")]
+pub mod async_wfe;
+
 // bxCAN families: F3, F4, L4,
 // fdCAN families: L5, U5, G4, H7
 // H7 suppords fd and can_ccu. (What's that?)
@@ -369,6 +387,9 @@ pub mod adc;
 pub mod can;
 
 pub mod clocks;
+
+#[cfg(feature = "l4")]
+pub mod comp;
 // todo: You could get CRC working on most of these with some effort.
 #[cfg(not(any(
     feature = "f4",
@@ -407,9 +428,7 @@ pub mod dfsdm;
 
 // todo: G0 missing many DMA registers like CCR?
 // todo: F4 needs some mods. So, only working on L4 and G4.
-// todo: L5 has a PAC bug on CCR registers past 1.
-// https://github.com/stm32-rs/stm32-rs/issues/551
-#[cfg(not(any(feature = "f4", feature = "l5")))]
+#[cfg(not(feature = "f4"))]
 pub mod dma;
 
 // #[cfg(not(any(feature = "h747cm4", feature = "h747cm7")))]
@@ -421,6 +440,14 @@ pub mod flash;
 // todo: Also G4.
 // pub mod fmac;
 
+#[cfg(any(feature = "g4", feature = "f303", feature = "f3x4"))]
+pub mod foc;
+
+#[cfg(all(feature = "xmodem", any(feature = "f3", feature = "l4")))]
+pub mod xmodem;
+
+pub mod framing;
+
 pub mod gpio;
 
 // #[cfg(feature = "wb")]
@@ -438,12 +465,17 @@ pub mod i2c_f4;
 #[cfg(feature = "f4")]
 pub use i2c_f4 as i2c;
 
+#[cfg(feature = "g4")]
+pub mod interconnect;
+
 #[cfg(feature = "wb")]
 pub mod ipcc;
 
 pub mod low_power;
 
-#[cfg(any(feature = "h747cm4", feature = "h747cm7"))]
+#[cfg(feature = "l4")]
+pub mod opamp;
+
 pub mod power;
 
 // F3, F4, L5, G0, and WL don't have Quad SPI.
@@ -463,6 +495,10 @@ feature = "wl",
 )))]
 pub mod qspi;
 
+pub mod ram;
+
+pub mod reset;
+
 // Note: Some F4 variants support RNG, but we haven't figured out the details yet. Send a PR if interested.
 #[cfg(not(any(
     feature = "f3",
@@ -486,8 +522,20 @@ pub mod rtc;
 )))]
 pub mod sai;
 
+// G0 has neither SWO, nor (on the variants we support) a DAC.
+#[cfg(not(feature = "g0"))]
+pub mod scope;
+
 pub mod spi;
 
+#[cfg(feature = "wl")]
+pub mod subghz;
+
+pub mod supervisor;
+
+#[cfg(feature = "testsuite")]
+pub mod testsuite;
+
 pub mod timer;
 pub mod usart;
 
@@ -576,31 +624,12 @@ use cortex_m::interrupt::free;
 /// use by the DMA clock.
 /// For why we enable the DMA clock, see STM32F446 errata, section 2.1.1.
 pub fn debug_workaround() {
-    free(|_| {
-        let dbgmcu = unsafe { &(*pac::DBGMCU::ptr()) };
-
-        cfg_if::cfg_if! {
-            if #[cfg(all(feature = "h7", not(any(feature = "h747cm4", feature = "h747cm7"))))] {
-                dbgmcu.cr.modify(|_, w| w.dbgsleep_d1().set_bit());
-                dbgmcu.cr.modify(|_, w| w.dbgstop_d1().set_bit());
-                dbgmcu.cr.modify(|_, w| w.dbgstby_d1().set_bit());
-            } else if #[cfg(feature = "h7")] {
-                dbgmcu.cr.modify(|_, w| w.dbgslpd1().set_bit());
-                dbgmcu.cr.modify(|_, w| w.dbgstpd1().set_bit());
-                dbgmcu.cr.modify(|_, w| w.dbgstbd1().set_bit());
-            } else {
-                #[cfg(not(feature = "l5"))]
-                dbgmcu.cr.modify(|_, w| w.dbg_sleep().set_bit());
-                dbgmcu.cr.modify(|_, w| w.dbg_stop().set_bit());
-                dbgmcu.cr.modify(|_, w| w.dbg_standby().set_bit());
-            }
-        }
-    });
+    free(|_| low_power::debug_in_low_power(true, true, true));
 
     free(|_| {
         let rcc = unsafe { &(*pac::RCC::ptr()) };
 
-        // todo Some MCUs may need the dbgmcu lines, but not DMA enabled.
+        // If only the DBGMCU bits are needed, use `low_power::debug_in_low_power` instead.
         // todo: Remove this part on MCUs not affected. F4 and L4 are confirmed affected.
 
         #[cfg(feature = "f3")]
@@ -616,4 +645,6 @@ pub mod prelude {
     pub use access_global;
     pub use make_globals;
     pub use make_simple_globals;
+    #[cfg(not(feature = "f4"))]
+    pub use dma_buffer;
 }
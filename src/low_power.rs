@@ -3,7 +3,13 @@
 
 use crate::{
     clocks::Clocks,
-    pac::{PWR, RCC},
+    pac::{self, PWR, RCC},
+};
+
+#[cfg(any(feature = "l4", feature = "l5", feature = "g0", feature = "g4"))]
+use crate::{
+    gpio::{self, Port},
+    reset,
 };
 
 #[cfg(any(feature = "l4", feature = "l5"))]
@@ -11,6 +17,12 @@ use crate::clocks::MsiRange;
 
 use cortex_m::{asm::wfi, peripheral::SCB, Peripherals};
 
+#[cfg(any(feature = "l4", feature = "l5", feature = "g0", feature = "g4"))]
+use cortex_m::{asm::delay, interrupt::free, peripheral::NVIC};
+
+#[cfg(any(feature = "l4", feature = "l5", feature = "g0", feature = "g4"))]
+use paste::paste;
+
 use cfg_if::cfg_if;
 
 // See L4 Reference Manual section 5.3.6. The values correspond to the PWR_CR1 LPMS bits.
@@ -80,6 +92,76 @@ pub fn sleep_on_exit() {
     wfi();
 }
 
+/// Set or clear the SLEEPONEXIT bit in the System Control Register, without entering sleep.
+/// When set, the MCU enters Sleep (or Stop etc, if SLEEPDEEP is set) as soon as it returns from
+/// the lowest priority ISR, instead of returning to the main thread. Useful for interrupt-driven
+/// firmware, eg RTIC, where all work is done in ISRs.
+pub fn set_sleep_on_exit(enabled: bool) {
+    let mut scb = unsafe { Peripherals::steal().SCB };
+
+    if enabled {
+        scb.set_sleeponexit();
+    } else {
+        scb.clear_sleeponexit();
+    }
+}
+
+/// Set or clear the SEVONPEND bit in the System Control Register. When set, an interrupt
+/// becoming pending generates an event that wakes the MCU from `WFE`, even if that interrupt is
+/// disabled in the NVIC.
+pub fn set_sev_on_pend(enabled: bool) {
+    let scb = unsafe { Peripherals::steal().SCB };
+    // SCB_SCR, SEVONPEND bit.
+    let bit = 1 << 4;
+
+    unsafe {
+        if enabled {
+            scb.scr.modify(|v| v | bit);
+        } else {
+            scb.scr.modify(|v| v & !bit);
+        }
+    }
+}
+
+/// Keep the debugger connected while in low-power modes, by keeping the debug clocks running.
+/// Sets the DBGMCU_CR register's DBG_SLEEP, DBG_STOP, and DBG_STANDBY bits, according to the
+/// arguments. (D1 domain bits on H7) Without this, the debugger disconnects when the MCU enters
+/// these modes. This increases power consumption in low-power modes, so only use it while
+/// debugging. L5 and G0 don't have the DBG_SLEEP bit; `sleep` is ignored on these.
+///
+/// Unlike `debug_workaround`, this doesn't enable the DMA clock.
+pub fn debug_in_low_power(sleep: bool, stop: bool, standby: bool) {
+    let dbgmcu = unsafe { &(*pac::DBGMCU::ptr()) };
+
+    cfg_if! {
+        if #[cfg(any(feature = "h747cm4", feature = "h747cm7"))] {
+            dbgmcu.cr.modify(|_, w| {
+                w.dbgslpd1().bit(sleep);
+                w.dbgstpd1().bit(stop);
+                w.dbgstbd1().bit(standby)
+            });
+        } else if #[cfg(feature = "h7")] {
+            dbgmcu.cr.modify(|_, w| {
+                w.dbgsleep_d1().bit(sleep);
+                w.dbgstop_d1().bit(stop);
+                w.dbgstby_d1().bit(standby)
+            });
+        } else if #[cfg(any(feature = "l5", feature = "g0"))] {
+            let _ = sleep;
+            dbgmcu.cr.modify(|_, w| {
+                w.dbg_stop().bit(stop);
+                w.dbg_standby().bit(standby)
+            });
+        } else {
+            dbgmcu.cr.modify(|_, w| {
+                w.dbg_sleep().bit(sleep);
+                w.dbg_stop().bit(stop);
+                w.dbg_standby().bit(standby)
+            });
+        }
+    }
+}
+
 cfg_if! {
     if #[cfg(any(feature = "f3", feature = "f4"))] {
         /// Enter `Stop` mode: the middle of the 3 low-power states avail on the
@@ -337,6 +419,189 @@ cfg_if! {
     }
 }
 
+#[cfg(any(feature = "l4", feature = "l5", feature = "g0", feature = "g4"))]
+#[derive(Clone, Copy)]
+/// A GPIO pin that can wake the MCU from `sleep_until`. Configure it as an input, and enable its
+/// interrupt with `Pin::enable_interrupt`, first.
+pub struct WakePin {
+    pub port: Port,
+    pub pin: u8,
+    /// The pin's level while it's active, eg low for a button that pulls to ground. A wake is
+    /// only reported if the pin is still at this level after the debounce time.
+    pub active_high: bool,
+}
+
+#[cfg(any(feature = "l4", feature = "l5", feature = "g0", feature = "g4"))]
+/// The sources that can end `sleep_until`. Their interrupts must be configured and enabled in
+/// the NVIC, as usual; this only determines which one caused the wake.
+pub struct WakeSources<'a> {
+    pub pins: &'a [WakePin],
+    /// RTC alarm A. (RTC_ISR or RTC_SR, ALRAF)
+    pub rtc_alarm: bool,
+    /// The RTC wakeup timer, eg configured with `Rtc::set_wakeup`. (RTC_ISR or RTC_SR, WUTF)
+    pub rtc_wakeup: bool,
+    /// The interrupt of a USART configured to wake the MCU from Stop mode.
+    pub usart: Option<pac::Interrupt>,
+    /// Refresh the independent watchdog on RTC wakeup timer events, and report these as
+    /// `WakeReason::Watchdog`, instead of `RtcWakeup`. The IWDG keeps running in Stop mode, so
+    /// set the wakeup timer's period shorter than the watchdog's timeout.
+    pub watchdog: bool,
+    /// How long to wait, in CPU cycles, before confirming a pin's level after it wakes the MCU.
+    pub debounce_cycles: u32,
+}
+
+#[cfg(any(feature = "l4", feature = "l5", feature = "g0", feature = "g4"))]
+impl Default for WakeSources<'_> {
+    fn default() -> Self {
+        Self {
+            pins: &[],
+            rtc_alarm: false,
+            rtc_wakeup: false,
+            usart: None,
+            watchdog: false,
+            debounce_cycles: 0,
+        }
+    }
+}
+
+#[cfg(any(feature = "l4", feature = "l5", feature = "g0", feature = "g4"))]
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// The reason `sleep_until` returned.
+pub enum WakeReason {
+    /// A wake pin, by pin number.
+    Pin(u8),
+    RtcAlarm,
+    RtcWakeup,
+    Usart,
+    /// The RTC wakeup timer fired, and the IWDG was refreshed.
+    Watchdog,
+    /// An interrupt not listed in the wake sources.
+    Other,
+}
+
+#[cfg(any(feature = "l4", feature = "l5", feature = "g0", feature = "g4"))]
+/// Enter Stop mode until one of `sources` wakes the MCU, then restore the clocks, and return
+/// the reason. Pin wakes that don't hold their active level through the debounce time are
+/// treated as bounces, and the MCU goes back to sleep.
+///
+/// The wake is handled with interrupts masked, so flags are read before any ISR clears them.
+/// ISRs for the wake source run as usual once this returns; clear the flags in them, as usual.
+///
+/// A watchdog timeout can't wake the MCU: It resets it. Use `reset::reset_cause` at startup
+/// to detect this.
+///
+/// Example:
+/// ```
+/// let pins = [WakePin { port: Port::C, pin: 13, active_high: false }];
+/// let sources = WakeSources { pins: &pins, rtc_alarm: true, debounce_cycles: 10_000, ..Default::default() };
+///
+/// loop {
+///     match low_power::sleep_until(StopMode::One, &sources, &clock_cfg) {
+///         WakeReason::Pin(13) => handle_button(),
+///         WakeReason::RtcAlarm => take_reading(),
+///         _ => (),
+///     }
+/// }
+/// ```
+pub fn sleep_until(mode: StopMode, sources: &WakeSources, clocks: &Clocks) -> WakeReason {
+    loop {
+        // Each wake is handled in its own critical section, so the ISRs of any bounced pins
+        // can run, and clear their flags, before we sleep again.
+        let reason = free(|_| {
+            stop(mode);
+            clocks.reselect_input();
+
+            let exti_pending = exti_pending();
+
+            for pin in sources.pins {
+                if exti_pending & (1 << pin.pin) != 0 {
+                    delay(sources.debounce_cycles);
+
+                    if gpio::is_high(pin.port, pin.pin) == pin.active_high {
+                        return Some(WakeReason::Pin(pin.pin));
+                    }
+                    return None;
+                }
+            }
+
+            let (alarm, wakeup) = rtc_flags();
+
+            if sources.rtc_alarm && alarm {
+                return Some(WakeReason::RtcAlarm);
+            }
+
+            if wakeup {
+                if sources.watchdog {
+                    reset::refresh_iwdg();
+                    return Some(WakeReason::Watchdog);
+                }
+                if sources.rtc_wakeup {
+                    return Some(WakeReason::RtcWakeup);
+                }
+            }
+
+            if let Some(irq) = sources.usart {
+                if NVIC::is_pending(irq) {
+                    return Some(WakeReason::Usart);
+                }
+            }
+
+            Some(WakeReason::Other)
+        });
+
+        if let Some(r) = reason {
+            return r;
+        }
+    }
+}
+
+#[cfg(any(feature = "l4", feature = "l5", feature = "g0", feature = "g4"))]
+// Collect a pending register's flags for lines 0 - 15 into a bitmask.
+macro_rules! pending_mask {
+    ($r:expr, $pif:ident) => {
+        pending_mask!($r, $pif, [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15])
+    };
+    ($r:expr, $pif:ident, [$($n:literal),+]) => {{
+        let r = $r;
+        paste! { 0 $(| ((r.[<$pif $n>]().bit_is_set() as u32) << $n))+ }
+    }};
+}
+
+#[cfg(any(feature = "l4", feature = "l5", feature = "g0", feature = "g4"))]
+/// Read the GPIO EXTI lines' pending flags, for lines 0 - 15.
+fn exti_pending() -> u32 {
+    let exti = unsafe { &(*pac::EXTI::ptr()) };
+
+    // Pending field names are PRx on L4, PIFx on G4, and RPIFx and FPIFx on L5 and G0.
+    cfg_if! {
+        if #[cfg(any(feature = "l5", feature = "g0"))] {
+            // These have separate rising and falling edge pending registers.
+            pending_mask!(exti.rpr1.read(), rpif) | pending_mask!(exti.fpr1.read(), fpif)
+        } else if #[cfg(feature = "g4")] {
+            pending_mask!(exti.pr1.read(), pif)
+        } else {
+            pending_mask!(exti.pr1.read(), pr)
+        }
+    }
+}
+
+#[cfg(any(feature = "l4", feature = "l5", feature = "g0", feature = "g4"))]
+/// Read the RTC's alarm A and wakeup timer flags.
+fn rtc_flags() -> (bool, bool) {
+    let rtc = unsafe { &(*pac::RTC::ptr()) };
+
+    // ALRAF and WUTF are in SR on newer RTCs, and ISR on older ones.
+    cfg_if! {
+        if #[cfg(any(feature = "l412", feature = "l5", feature = "g0", feature = "g4"))] {
+            let sr = rtc.sr.read();
+            (sr.alraf().bit_is_set(), sr.wutf().bit_is_set())
+        } else {
+            let isr = rtc.isr.read();
+            (isr.alraf().bit_is_set(), isr.wutf().bit_is_set())
+        }
+    }
+}
+
 /// This function is used by both `sleep_now` (non-H7), and `csleep` (H7), so that the names
 /// can correctly reflect functionality.
 fn sleep() {
@@ -1,20 +1,361 @@
-//! Support for operational amplifiers.
-//! WIP / non-functional
+//! Support for operational amplifiers (OPAMP), including automated offset calibration.
+//! Currently supports L4.
 
-use core::ops::Deref;
+use cortex_m::{delay::Delay, interrupt::free};
 
-use crate::pac::opamp;
+use crate::{
+    pac::{OPAMP, RCC},
+    rcc_en_reset,
+};
 
-/// Represents an operational amplifier peripheral.
-pub struct Opamp<R> {
-    pub regs: R,
+#[derive(Clone, Copy, PartialEq)]
+/// Select which op amp to use. OPAMP1 and OPAMP2 share a register block. OPAMP2 is only
+/// present on L47x, L48x, L49x, and L4Ax. It's not available with the `l4x1` feature, since
+/// that also covers L431 and L451, which only have OPAMP1.
+pub enum OpampDevice {
+    One,
+    #[cfg(any(feature = "l4x5", feature = "l4x6"))]
+    Two,
 }
 
-impl<R> Opamp<R>
-    where
-        R: Deref<Target = opamp::RegisterBlock>,
-{
-    pub fn new() -> Self {
+#[derive(Clone, Copy, PartialEq)]
+#[repr(u8)]
+/// Op amp mode. Sets the OPAMPx_CSR register, OPAMODE field.
+pub enum OpampMode {
+    /// Internal PGA disabled; external components set the gain.
+    Standalone = 0b00,
+    /// Internal PGA enabled; the gain is set by `PgaGain`.
+    Pga = 0b10,
+    /// Internal follower.
+    Follower = 0b11,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+#[repr(u8)]
+/// Gain of the internal programmable gain amplifier. Sets the OPAMPx_CSR register, PGA_GAIN field.
+pub enum PgaGain {
+    G2 = 0b00,
+    G4 = 0b01,
+    G8 = 0b10,
+    G16 = 0b11,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+#[repr(u8)]
+/// Inverting input selection. Sets the OPAMPx_CSR register, VM_SEL field.
+pub enum InvertingInput {
+    /// GPIO connected to VINM (valid in standalone mode).
+    Pin = 0b00,
+    /// Dedicated low leakage input, connected to VINM (valid in standalone mode).
+    LowLeakage = 0b01,
+    /// Not externally connected; use in PGA or follower mode.
+    NotConnected = 0b10,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+#[repr(u8)]
+/// Non-inverting input selection. Sets the OPAMPx_CSR register, VP_SEL field.
+pub enum NonInvertingInput {
+    /// GPIO connected to VINP.
+    Pin = 0,
+    /// DAC output connected to VINP. (DAC channel 1 for OPAMP1, channel 2 for OPAMP2).
+    Dac = 1,
+}
+
+/// Configuration for both op amps. Applied in `Opamp::new`.
+pub struct OpampConfig {
+    pub mode: OpampMode,
+    pub pga_gain: PgaGain,
+    pub inverting_input: InvertingInput,
+    pub non_inverting_input: NonInvertingInput,
+    /// Low-power mode. Note that this uses a separate set of trim values: Calibrate in the
+    /// power mode you'll use.
+    pub low_power: bool,
+    /// Set this if VDDA is above 2.4V. Sets OPAMP1_CSR, OPA_RANGE, which applies to both op amps.
+    pub vdda_high: bool,
+}
+
+impl Default for OpampConfig {
+    fn default() -> Self {
+        Self {
+            mode: OpampMode::Follower,
+            pga_gain: PgaGain::G2,
+            inverting_input: InvertingInput::NotConnected,
+            non_inverting_input: NonInvertingInput::Pin,
+            low_power: false,
+            vdda_high: true,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+/// Offset trim values, as found by calibration. These can be stored, eg in flash, and applied
+/// later using `CalibratedOpamp::from_trims`, to skip calibration at startup.
+pub struct OpampTrim {
+    /// NMOS differential pair trim. 0 - 31. Sets the OPAMPx_OTR or OPAMPx_LPOTR register,
+    /// TRIMOFFSETN field.
+    pub nmos: u8,
+    /// PMOS differential pair trim. 0 - 31. Sets the OPAMPx_OTR or OPAMPx_LPOTR register,
+    /// TRIMOFFSETP field.
+    pub pmos: u8,
+}
+
+/// Represents the operational amplifier peripheral; this includes OPAMP1 and OPAMP2.
+pub struct Opamp {
+    pub regs: OPAMP,
+    pub cfg: OpampConfig,
+}
+
+impl Opamp {
+    /// Initialize the OPAMP peripheral, including enabling and resetting its RCC peripheral
+    /// clock, and configuring each op amp. They're left disabled.
+    pub fn new(regs: OPAMP, cfg: OpampConfig) -> Self {
+        free(|_| {
+            let rcc = unsafe { &(*RCC::ptr()) };
+            rcc_en_reset!(apb1, opamp, rcc);
+        });
+
+        regs.opamp1_csr.modify(|_, w| unsafe {
+            w.opalpm().bit(cfg.low_power);
+            w.opamode().bits(cfg.mode as u8);
+            w.pga_gain().bits(cfg.pga_gain as u8);
+            w.vm_sel().bits(cfg.inverting_input as u8);
+            w.vp_sel().bit(cfg.non_inverting_input as u8 != 0);
+            w.opa_range().bit(cfg.vdda_high)
+        });
+
+        #[cfg(any(feature = "l4x5", feature = "l4x6"))]
+        regs.opamp2_csr.modify(|_, w| unsafe {
+            w.opalpm().bit(cfg.low_power);
+            w.opamode().bits(cfg.mode as u8);
+            w.pga_gain().bits(cfg.pga_gain as u8);
+            w.vm_sel().bits(cfg.inverting_input as u8);
+            w.vp_sel().bit(cfg.non_inverting_input as u8 != 0)
+        });
+
+        Self { regs, cfg }
+    }
+
+    /// Enable an op amp.
+    pub fn enable(&mut self, device: OpampDevice) {
+        match device {
+            OpampDevice::One => self.regs.opamp1_csr.modify(|_, w| w.opaen().set_bit()),
+            #[cfg(any(feature = "l4x5", feature = "l4x6"))]
+            OpampDevice::Two => self.regs.opamp2_csr.modify(|_, w| w.opaen().set_bit()),
+        }
+    }
+
+    /// Disable an op amp.
+    pub fn disable(&mut self, device: OpampDevice) {
+        match device {
+            OpampDevice::One => self.regs.opamp1_csr.modify(|_, w| w.opaen().clear_bit()),
+            #[cfg(any(feature = "l4x5", feature = "l4x6"))]
+            OpampDevice::Two => self.regs.opamp2_csr.modify(|_, w| w.opaen().clear_bit()),
+        }
+    }
+
+    /// Apply offset trim values for the configured power mode, and switch from factory trim
+    /// values to these, by setting USERTRIM.
+    pub fn set_trim(&mut self, device: OpampDevice, trim: OpampTrim) {
+        self.write_trim(device, false, trim.nmos);
+        self.write_trim(device, true, trim.pmos);
+
+        match device {
+            OpampDevice::One => self.regs.opamp1_csr.modify(|_, w| w.usertrim().set_bit()),
+            #[cfg(any(feature = "l4x5", feature = "l4x6"))]
+            OpampDevice::Two => self.regs.opamp2_csr.modify(|_, w| w.usertrim().set_bit()),
+        }
+    }
 
+    /// Run the offset calibration procedure on each op amp, in the configured power mode, and
+    /// apply the results. The op amps are disabled afterwards. This takes about 20ms.
+    pub fn calibrate(mut self, delay: &mut Delay) -> CalibratedOpamp {
+        let trim1 = self.calibrate_device(OpampDevice::One, delay);
+        #[cfg(any(feature = "l4x5", feature = "l4x6"))]
+        let trim2 = self.calibrate_device(OpampDevice::Two, delay);
+
+        CalibratedOpamp {
+            opamp: self,
+            trim1,
+            #[cfg(any(feature = "l4x5", feature = "l4x6"))]
+            trim2,
+        }
+    }
+
+    /// Calibrate a single op amp. L4 RM, OPAMP section: Calibration:
+    /// "1. Set the OPAEN bit in OPAMPx_CSR to 1 to enable the operational amplifier.
+    /// 2. Set the USERTRIM bit in the OPAMPx_CSR register to 1.
+    /// 3. Choose a calibration mode. The steps 3 to 5 must be repeated 2 times (NMOS and PMOS).
+    /// 4. Increment TRIMOFFSETN[4:0] in OPAMPx_OTR starting from 00000b until CALOUT changes
+    /// to 1 in OPAMPx_CSR."
+    /// Instead of incrementing linearly, we do a binary search over the trim codes, as ST's HAL
+    /// does.
+    fn calibrate_device(&mut self, device: OpampDevice, delay: &mut Delay) -> OpampTrim {
+        match device {
+            OpampDevice::One => self.regs.opamp1_csr.modify(|_, w| {
+                w.opaen().set_bit();
+                w.usertrim().set_bit();
+                w.calon().set_bit()
+            }),
+            #[cfg(any(feature = "l4x5", feature = "l4x6"))]
+            OpampDevice::Two => self.regs.opamp2_csr.modify(|_, w| {
+                w.opaen().set_bit();
+                w.usertrim().set_bit();
+                w.calon().set_bit()
+            }),
+        }
+
+        // CALSEL = 0: NMOS calibration; 0.2V applied on the inputs. Adjusts TRIMOFFSETN.
+        // CALSEL = 1: PMOS calibration; VDDA - 0.2V applied on the inputs. Adjusts TRIMOFFSETP.
+        let nmos = self.search_trim(device, false, delay);
+        let pmos = self.search_trim(device, true, delay);
+
+        match device {
+            OpampDevice::One => self.regs.opamp1_csr.modify(|_, w| {
+                w.calon().clear_bit();
+                w.opaen().clear_bit()
+            }),
+            #[cfg(any(feature = "l4x5", feature = "l4x6"))]
+            OpampDevice::Two => self.regs.opamp2_csr.modify(|_, w| {
+                w.calon().clear_bit();
+                w.opaen().clear_bit()
+            }),
+        }
+
+        OpampTrim { nmos, pmos }
+    }
+
+    /// Find the trim code where CALOUT switches from 0 to 1, for one differential pair.
+    fn search_trim(&mut self, device: OpampDevice, pmos: bool, delay: &mut Delay) -> u8 {
+        match device {
+            OpampDevice::One => self.regs.opamp1_csr.modify(|_, w| w.calsel().bit(pmos)),
+            #[cfg(any(feature = "l4x5", feature = "l4x6"))]
+            OpampDevice::Two => self.regs.opamp2_csr.modify(|_, w| w.calsel().bit(pmos)),
+        }
+
+        let mut trim = 16;
+        let mut delta = 8;
+
+        while delta != 0 {
+            self.write_trim(device, pmos, trim);
+            // The RM specifies a maximum of 1ms for CALOUT to settle after changing the trim.
+            delay.delay_ms(1);
+
+            if self.calout(device) {
+                trim -= delta;
+            } else {
+                trim += delta;
+            }
+            delta >>= 1;
+        }
+
+        // The search ends on 1 - 31, at the switching point, or one code below it. Since it
+        // can't reach 0, if CALOUT is set, check the code below too.
+        self.write_trim(device, pmos, trim);
+        delay.delay_ms(1);
+        if self.calout(device) {
+            self.write_trim(device, pmos, trim - 1);
+            delay.delay_ms(1);
+            if self.calout(device) {
+                trim -= 1;
+            } else {
+                self.write_trim(device, pmos, trim);
+            }
+        } else if trim < 31 {
+            trim += 1;
+            self.write_trim(device, pmos, trim);
+        }
+
+        trim
+    }
+
+    /// Read the calibration output. Reads the OPAMPx_CSR register, CALOUT field.
+    fn calout(&self, device: OpampDevice) -> bool {
+        match device {
+            OpampDevice::One => self.regs.opamp1_csr.read().calout().bit_is_set(),
+            #[cfg(any(feature = "l4x5", feature = "l4x6"))]
+            OpampDevice::Two => self.regs.opamp2_csr.read().calout().bit_is_set(),
+        }
+    }
+
+    /// Write a trim code for the NMOS or PMOS differential pair, in the configured power mode.
+    fn write_trim(&mut self, device: OpampDevice, pmos: bool, val: u8) {
+        match (device, self.cfg.low_power) {
+            (OpampDevice::One, false) => self.regs.opamp1_otr.modify(|_, w| unsafe {
+                if pmos {
+                    w.trimoffsetp().bits(val)
+                } else {
+                    w.trimoffsetn().bits(val)
+                }
+            }),
+            (OpampDevice::One, true) => self.regs.opamp1_lpotr.modify(|_, w| unsafe {
+                if pmos {
+                    w.trimlpoffsetp().bits(val)
+                } else {
+                    w.trimlpoffsetn().bits(val)
+                }
+            }),
+            #[cfg(any(feature = "l4x5", feature = "l4x6"))]
+            (OpampDevice::Two, false) => self.regs.opamp2_otr.modify(|_, w| unsafe {
+                if pmos {
+                    w.trimoffsetp().bits(val)
+                } else {
+                    w.trimoffsetn().bits(val)
+                }
+            }),
+            #[cfg(any(feature = "l4x5", feature = "l4x6"))]
+            (OpampDevice::Two, true) => self.regs.opamp2_lpotr.modify(|_, w| unsafe {
+                if pmos {
+                    w.trimlpoffsetp().bits(val)
+                } else {
+                    w.trimlpoffsetn().bits(val)
+                }
+            }),
+        }
+    }
+}
+
+/// An `Opamp` whose offset trims have been set, either by running `Opamp::calibrate`, or
+/// from stored values using `CalibratedOpamp::from_trims`.
+pub struct CalibratedOpamp {
+    pub opamp: Opamp,
+    /// Trim values for OPAMP1.
+    pub trim1: OpampTrim,
+    #[cfg(any(feature = "l4x5", feature = "l4x6"))]
+    /// Trim values for OPAMP2.
+    pub trim2: OpampTrim,
+}
+
+impl CalibratedOpamp {
+    /// Apply previously-stored trim values, instead of running calibration.
+    pub fn from_trims(
+        mut opamp: Opamp,
+        trim1: OpampTrim,
+        #[cfg(any(feature = "l4x5", feature = "l4x6"))] trim2: OpampTrim,
+    ) -> Self {
+        opamp.set_trim(OpampDevice::One, trim1);
+        #[cfg(any(feature = "l4x5", feature = "l4x6"))]
+        opamp.set_trim(OpampDevice::Two, trim2);
+
+        Self {
+            opamp,
+            trim1,
+            #[cfg(any(feature = "l4x5", feature = "l4x6"))]
+            trim2,
+        }
+    }
+
+    /// Get the trim values found for an op amp.
+    pub fn trim(&self, device: OpampDevice) -> OpampTrim {
+        match device {
+            OpampDevice::One => self.trim1,
+            #[cfg(any(feature = "l4x5", feature = "l4x6"))]
+            OpampDevice::Two => self.trim2,
+        }
     }
-}
\ No newline at end of file
+
+    /// Release the underlying `Opamp`. Its trim values remain applied.
+    pub fn free(self) -> Opamp {
+        self.opamp
+    }
+}
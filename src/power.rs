@@ -1,10 +1,135 @@
-//! Manage STM32H7 supply configuration.
+//! Power control (PWR) functionality. Includes backup domain access control, STM32H7 supply
+//! configuration, I/O analog switch supply selection, and VDDIO2 validation.
 
-use crate::pac::PWR;
+use cortex_m::interrupt::free;
 
+use crate::pac::{self, PWR, RCC};
+
+#[cfg(any(feature = "l4", feature = "l5", feature = "g4", feature = "h7"))]
+use crate::pac::SYSCFG;
+
+use cfg_if::cfg_if;
+
+/// Enable write access to the backup domain by setting the DBP bit in PWR_CR1 (PWR_CR on F3
+/// and F4). The backup domain includes the RTC registers, the RTC backup registers, and the RCC
+/// BDCR register, which configures the LSE and RTC clock. It's write-protected after reset.
+/// Access remains enabled until `lock_backup_domain()` is called; prefer
+/// `enable_backup_domain_access()` where you only need access temporarily.
+pub fn unlock_backup_domain() {
+    free(|_| {
+        let pwr = unsafe { &(*PWR::ptr()) };
+
+        // See L4 RM, `Backup domain access` section.
+        cfg_if! {
+            if #[cfg(any(feature = "f3", feature = "f4"))] {
+                let rcc = unsafe { &(*RCC::ptr()) };
+                rcc.apb1enr.modify(|_, w| w.pwren().set_bit());
+                pwr.cr.read(); // read to allow the pwr clock to enable
+                pwr.cr.modify(|_, w| w.dbp().set_bit());
+                while pwr.cr.read().dbp().bit_is_clear() {}
+            } else if #[cfg(any(feature = "l4", feature = "l5", feature = "g0", feature = "g4"))] {
+                let rcc = unsafe { &(*RCC::ptr()) };
+                // 1. Enable the power interface clock by setting the PWREN bits in the Section 6.4.18:
+                // APB1 peripheral clock enable register 1 (RCC_APB1ENR1)
+                #[cfg(feature = "g0")]
+                rcc.apbenr1.modify(|_, w| w.pwren().set_bit());
+                #[cfg(not(feature = "g0"))]
+                rcc.apb1enr1.modify(|_, w| w.pwren().set_bit());
+
+                pwr.cr1.read(); // Read to allow the pwr clock to enable
+                // 2. Set the DBP bit in the Power control register 1 (PWR_CR1) to enable access to the
+                // backup domain
+                pwr.cr1.modify(|_, w| w.dbp().set_bit());
+                while pwr.cr1.read().dbp().bit_is_clear() {}
+            } else { // H7, WB, and WL don't have a PWREN bit.
+                pwr.cr1.read();
+                pwr.cr1.modify(|_, w| w.dbp().set_bit());
+                while pwr.cr1.read().dbp().bit_is_clear() {}
+            }
+        }
+    });
+}
+
+/// Disable write access to the backup domain by clearing the DBP bit in PWR_CR1 (PWR_CR on F3
+/// and F4). This protects the RTC and its backup registers from unintended writes.
+pub fn lock_backup_domain() {
+    free(|_| {
+        let pwr = unsafe { &(*PWR::ptr()) };
+
+        cfg_if! {
+            if #[cfg(any(feature = "f3", feature = "f4"))] {
+                pwr.cr.modify(|_, w| w.dbp().clear_bit());
+            } else {
+                pwr.cr1.modify(|_, w| w.dbp().clear_bit());
+            }
+        }
+    });
+}
+
+/// Returns true if write access to the backup domain is currently enabled. (DBP bit set)
+pub fn backup_domain_unlocked() -> bool {
+    let pwr = unsafe { &(*PWR::ptr()) };
+
+    cfg_if! {
+        if #[cfg(any(feature = "f3", feature = "f4"))] {
+            pwr.cr.read().dbp().bit_is_set()
+        } else {
+            pwr.cr1.read().dbp().bit_is_set()
+        }
+    }
+}
+
+/// Enable write access to the backup domain, returning a guard that re-locks it when dropped.
+/// If access was already enabled when this is called (eg by the RTC, which keeps the domain
+/// unlocked), dropping the guard leaves it enabled.
+///
+/// Example:
+/// ```rust
+/// {
+///     let bd = power::enable_backup_domain_access();
+///     bd.reset();
+/// } // Backup domain is locked again here.
+/// ```
+pub fn enable_backup_domain_access() -> BackupDomainAccess {
+    let was_unlocked = backup_domain_unlocked();
+    unlock_backup_domain();
+
+    BackupDomainAccess { was_unlocked }
+}
+
+/// Guard representing write access to the backup domain. Create with
+/// `enable_backup_domain_access()`. Write access is disabled when this is dropped, unless it
+/// was already enabled when the guard was created.
+pub struct BackupDomainAccess {
+    was_unlocked: bool,
+}
+
+impl BackupDomainAccess {
+    /// Reset the backup domain by setting, then clearing the BDRST bit in RCC_BDCR. This resets
+    /// the RTC (including its clock source selection and calendar), the RTC backup registers, and
+    /// the LSE configuration. Note that this stops the LSE if it's running.
+    pub fn reset(&self) {
+        free(|_| {
+            let rcc = unsafe { &(*RCC::ptr()) };
+
+            rcc.bdcr.modify(|_, w| w.bdrst().set_bit());
+            rcc.bdcr.modify(|_, w| w.bdrst().clear_bit());
+        });
+    }
+}
+
+impl Drop for BackupDomainAccess {
+    fn drop(&mut self) {
+        if !self.was_unlocked {
+            lock_backup_domain();
+        }
+    }
+}
+
+#[cfg(any(feature = "h747cm4", feature = "h747cm7", feature = "h7b3"))]
 #[derive(Clone, Copy)]
 #[repr(u8)]
-/// SMPS step-down converter voltage output level selection.
+/// SMPS step-down converter voltage output level selection. (SMPSLEVEL on H7B3)
 /// This bit is used when both the LDO and SMPS step-down converter are enabled with SDEN and
 /// LDOEN enabled or when SDEXTHP is enabled. In this case SDLEVEL has to be written with a
 /// value different than 00 at system startup.
@@ -15,6 +140,7 @@ pub enum VoltageLevel {
     V2_5 = 0b10,
 }
 
+#[cfg(any(feature = "h747cm4", feature = "h747cm7"))]
 #[derive(Clone, Copy)]
 /// See RM0399, Table 32. Supply configuration control, for available configurations.
 /// Sets the PWR_CR3 register, LDOEN, SDEN, SDEXTHP, SDLEVEL, and BYPASS fields.
@@ -35,9 +161,10 @@ pub enum SupplyConfig {
     SmpsStepdownDisabledBypass,
 }
 
+#[cfg(any(feature = "h747cm4", feature = "h747cm7"))]
 impl SupplyConfig {
     /// Apply a given supply config. `voltage_level` only affects certain variants.
-    pub fn setup(&self, pwr: &mut PWR, voltage_level: VoltageLevel) {
+    pub fn setup(&self, pwr: &pac::pwr::RegisterBlock, voltage_level: VoltageLevel) {
         match self {
             Self::Default => pwr.cr3.modify(|_, w| unsafe {
                 w.sdlevel().bits(voltage_level as u8);
@@ -86,3 +213,119 @@ impl SupplyConfig {
         }
     }
 }
+
+#[cfg(any(feature = "l4", feature = "l5", feature = "g4", feature = "h7"))]
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// Supply for the I/O analog switches, which connect GPIO pins to the ADC, comparators, and
+/// op amps. Their performance degrades when VDDA is low; the booster, or VDD supply, prevents
+/// this. Sets the SYSCFG_CFGR1 register, BOOSTEN and ANASWVDD fields (SYSCFG_PMCR register, BOOSTE
+/// and BOOSTVDDSEL fields on H7).
+pub enum AnalogSwitchSupply {
+    /// Supplied from VDDA, without the booster. This is the reset configuration, and is suitable
+    /// when VDDA is above 2.4V.
+    Vdda,
+    /// Supplied from the voltage booster, which runs from VDDA. Use when VDDA is below 2.4V.
+    /// Note that the booster increases current consumption.
+    Booster,
+    #[cfg(not(feature = "l4"))]
+    /// L5 and G4: Supplied from VDD. Use when VDDA is low, and VDD is above 2.4V. H7: Supplied
+    /// from the voltage booster, which runs from VDD.
+    Vdd,
+}
+
+#[cfg(any(feature = "l4", feature = "l5", feature = "g4", feature = "h7"))]
+/// Select the supply for the I/O analog switches. Configure this before enabling analog
+/// peripherals that use GPIO pins. Enables the SYSCFG clock, if not already enabled.
+pub fn set_analog_switch_supply(supply: AnalogSwitchSupply) {
+    free(|_| {
+        let rcc = unsafe { &(*RCC::ptr()) };
+        let syscfg = unsafe { &(*SYSCFG::ptr()) };
+
+        #[cfg(feature = "h7")]
+        rcc.apb4enr.modify(|_, w| w.syscfgen().set_bit());
+        #[cfg(not(feature = "h7"))]
+        rcc.apb2enr.modify(|_, w| w.syscfgen().set_bit());
+
+        let (boost, vdd) = match supply {
+            AnalogSwitchSupply::Vdda => (false, false),
+            AnalogSwitchSupply::Booster => (true, false),
+            #[cfg(not(feature = "l4"))]
+            AnalogSwitchSupply::Vdd => (cfg!(feature = "h7"), true),
+        };
+
+        cfg_if! {
+            if #[cfg(feature = "h7b3")] {
+                // We use raw bits, since H7B3's PAC is missing these fields.
+                // BOOSTE is bit 8, and BOOSTVDDSEL is bit 9.
+                syscfg.pmcr.modify(|r, w| unsafe {
+                    w.bits((r.bits() & !(0b11 << 8)) | ((boost as u32) << 8) | ((vdd as u32) << 9))
+                });
+            } else if #[cfg(feature = "h7")] {
+                syscfg.pmcr.modify(|_, w| {
+                    w.booste().bit(boost);
+                    w.boostvddsel().bit(vdd)
+                });
+            } else if #[cfg(feature = "l4")] {
+                let _ = vdd;
+                syscfg.cfgr1.modify(|_, w| w.boosten().bit(boost));
+            } else {
+                syscfg.cfgr1.modify(|_, w| {
+                    w.boosten().bit(boost);
+                    w.anaswvdd().bit(vdd)
+                });
+            }
+        }
+    });
+}
+
+#[cfg(feature = "h7")]
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(u8)]
+/// H7 pins with a dual pad: Pxy, and Pxy_C, which connects directly to the ADC. Values are the
+/// bit positions in SYSCFG_PMCR, used on H7B3, whose PAC is missing the PxySO fields.
+pub enum DualPadPin {
+    Pa0 = 24,
+    Pa1 = 25,
+    Pc2 = 26,
+    Pc3 = 27,
+}
+
+#[cfg(feature = "h7")]
+/// Open or close the analog switch between a dual pad pin, and its `_C` pad. When closed, the
+/// two pads are connected. When open (the reset state), they're isolated, eg to use Pxy as digital
+/// I/O while Pxy_C is used by the ADC. Sets the SYSCFG_PMCR register, PxySO fields.
+pub fn set_dual_pad_switch(pin: DualPadPin, closed: bool) {
+    free(|_| {
+        let rcc = unsafe { &(*RCC::ptr()) };
+        let syscfg = unsafe { &(*SYSCFG::ptr()) };
+
+        rcc.apb4enr.modify(|_, w| w.syscfgen().set_bit());
+
+        // PxySO = 1 means the switch is open.
+        #[cfg(feature = "h7b3")]
+        syscfg.pmcr.modify(|r, w| unsafe {
+            w.bits((r.bits() & !(1 << pin as u8)) | ((!closed as u32) << pin as u8))
+        });
+        #[cfg(not(feature = "h7b3"))]
+        syscfg.pmcr.modify(|_, w| match pin {
+            DualPadPin::Pa0 => w.pa0so().bit(!closed),
+            DualPadPin::Pa1 => w.pa1so().bit(!closed),
+            DualPadPin::Pc2 => w.pc2so().bit(!closed),
+            DualPadPin::Pc3 => w.pc3so().bit(!closed),
+        });
+    });
+}
+
+#[cfg(any(feature = "l4", feature = "l5"))]
+/// Indicate that VDDIO2 (the supply for port G's PG2 - PG15) is valid, or not. Until this is set,
+/// these pins are electrically isolated, even if VDDIO2 is present. Set it only after VDDIO2 is
+/// powered, eg confirmed using the PVM. Sets the PWR_CR2 register, IOSV field.
+pub fn set_vddio2_valid(valid: bool) {
+    free(|_| {
+        let rcc = unsafe { &(*RCC::ptr()) };
+        let pwr = unsafe { &(*PWR::ptr()) };
+
+        rcc.apb1enr1.modify(|_, w| w.pwren().set_bit());
+        pwr.cr2.modify(|_, w| w.iosv().bit(valid));
+    });
+}
@@ -1,5 +1,22 @@
 //! Quad Serial Peripheral Interface (SPI) bus: A specialized interface used for
 //! high-speed communications with external flash memory.
+//!
+//! Execute-in-place (XIP) boot sequence, for running code or reading constants from external
+//! flash, in memory-mapped mode:
+//! 1. Run from internal flash, and set up the system clocks with `Clocks::setup`. `Qspi::new`
+//!    derives its prescaler from the clocks, so they must be final first.
+//! 2. Configure the QSPI pins for their alternate function, with very high output speed.
+//! 3. Construct the `Qspi`. Using indirect mode, put the flash in the mode your read instruction
+//!    requires, eg set its Quad Enable bit, if required.
+//! 4. Call `enable_xip`, with the dummy cycles the flash's datasheet specifies for that
+//!    instruction at the QSPI clock frequency. External flash is then readable at `0x9000_0000`.
+//! 5. Optionally, call `relocate_vector_table` to handle interrupts using a vector table in
+//!    external flash.
+//!
+//! Code that places functions in external flash does so with a linker section mapped to
+//! `0x9000_0000` in `memory.x`. The code performing steps 1-4, and the reset handler, must stay
+//! in internal flash. On H7, also configure an MPU region for the QSPI range: Without one,
+//! speculative accesses to unmapped parts of it can stall the bus.
 
 use crate::{
     clocks::Clocks,
@@ -10,7 +27,22 @@ use core::ptr;
 
 use cortex_m::interrupt::free;
 
-// todo: Status-polling mode.
+#[cfg(not(feature = "h7"))]
+use core::ops::Deref;
+
+#[cfg(not(feature = "h7"))]
+use crate::dma::{self, ChannelCfg, Dma, DmaChannel};
+
+#[cfg(feature = "wb")]
+use crate::dma::DmaInput;
+
+#[cfg(feature = "l4")]
+use crate::pac;
+
+#[cfg(feature = "g0")]
+use crate::pac::dma as dma_p;
+#[cfg(any(feature = "l4", feature = "g4", feature = "wb"))]
+use crate::pac::dma1 as dma_p;
 
 // todo: Is this avail in PAC? Feature-gate if diff on diff platforms?
 const MEM_MAPPED_BASE_ADDR: usize = 0x9000_0000;
@@ -71,11 +103,61 @@ pub enum SamplingEdge {
     Falling = 1,
 }
 
+#[derive(Copy, Clone, PartialEq)]
+#[repr(u8)]
+/// How the masked status bytes are compared to the match value in automatic status-polling mode.
+/// Sets the CR register, PMM field.
+pub enum PollingMatchMode {
+    /// All unmasked bits must match.
+    And = 0,
+    /// Any unmasked bit matching is sufficient.
+    Or = 1,
+}
+
+/// Configuration for automatic status-polling mode. The QSPI periodically reads a status register
+/// from the memory, and sets the status match flag when the masked value matches `match_val`.
+/// The default is set up to wait for the Write In Progress (WIP) bit, bit 0 of the status
+/// register read with instruction `0x05`, to clear; this is what most serial flash memories use.
+#[derive(Copy, Clone)]
+pub struct StatusPollingCfg {
+    /// The instruction used to read the status register.
+    pub instruction: u8,
+    /// Number of status bytes read each poll; 1 to 4.
+    pub num_bytes: u8,
+    /// Bits of the status bytes to compare. Sets the PSMKR register.
+    pub mask: u32,
+    /// The value the masked status bits are compared to. Sets the PSMAR register.
+    pub match_val: u32,
+    pub match_mode: PollingMatchMode,
+    /// Number of CLK cycles between two reads. Sets the PIR register.
+    pub interval: u16,
+    /// Number of status register polls `poll_status` waits for a match before aborting the
+    /// command, and returning `QspiError::Timeout`. `None` waits indefinitely. Defaults to `None`,
+    /// since erase times vary widely between memories.
+    pub timeout_polls: Option<u32>,
+}
+
+impl Default for StatusPollingCfg {
+    fn default() -> Self {
+        Self {
+            instruction: 0x05,
+            num_bytes: 1,
+            mask: 0x01,
+            match_val: 0,
+            match_mode: PollingMatchMode::And,
+            interval: 0x10,
+            timeout_polls: None,
+        }
+    }
+}
+
 /// Indicates an error with the QSPI peripheral.
 #[derive(Copy, Clone, PartialEq)]
 pub enum QspiError {
     Busy,
     Underflow,
+    /// The status register didn't match within `StatusPollingCfg::timeout_polls`.
+    Timeout,
 }
 
 // todo: Use bank on suitable MCUs? Which? F7 / H7?
@@ -109,6 +191,35 @@ impl Default for QspiConfig {
     }
 }
 
+/// Memory-mapped mode configuration, for execute-in-place; see `Qspi::enable_xip`.
+#[derive(Copy, Clone)]
+pub struct XipConfig {
+    /// The flash's read instruction, eg 0xEB for Fast Read Quad I/O. It must match the
+    /// protocol mode set in `QspiConfig`.
+    pub read_instruction: u8,
+    /// Dummy cycles between the address and data phases, from the flash's datasheet. These
+    /// depend on the instruction, and the QSPI clock frequency. Must be 31 or lower.
+    pub dummy_cycles: u8,
+    /// Send the instruction only with the first read, for flash in continuous read (XIP) mode.
+    /// The flash must be configured for this mode separately. (QUADSPI_CCR, SIOO)
+    pub send_instruction_once: bool,
+    /// The number of QSPI clock cycles to keep prefetching after an access, before releasing
+    /// nCS. `None` prefetches indefinitely, which gives the fastest sequential execution, at the
+    /// cost of power. (QUADSPI_CR, TCEN, and QUADSPI_LPTR)
+    pub prefetch_timeout: Option<u16>,
+}
+
+impl Default for XipConfig {
+    fn default() -> Self {
+        Self {
+            read_instruction: 0xEB,
+            dummy_cycles: 6,
+            send_instruction_once: false,
+            prefetch_timeout: None,
+        }
+    }
+}
+
 /// Interrupt events
 #[derive(Copy, Clone, PartialEq)]
 pub enum QspiInterrupt {
@@ -129,7 +240,7 @@ impl Qspi {
     pub fn new(regs: QUADSPI, cfg: QspiConfig, clocks: &Clocks) -> Self {
         assert!(
             cfg.dummy_cycles < 32,
-            "Dumy cycles must be between 0 and 31."
+            "Dummy cycles must be between 0 and 31."
         );
 
         free(|_| {
@@ -367,7 +478,198 @@ impl Qspi {
         Ok(())
     }
 
-    // todo: write_indirect_dma fn.
+    #[cfg(not(feature = "h7"))]
+    /// Perform a memory write in indirect mode, with the data fed from a DMA channel. This
+    /// removes the FIFO size limit of `write_indirect`, and frees the CPU while the data is
+    /// transferred. Enable the QSPI's `TransferComplete` interrupt, or poll `is_busy`, to determine
+    /// when the write is done, then call `stop_dma`. Note that on L4, QUADSPI requests are on DMA1
+    /// channel 5, or DMA2 channel 7. On WB, the DMAMUX is configured to route the request to
+    /// `channel`; on G4, route it to the `Quadspi` input using `dma::mux`. H7 uses the MDMA for
+    /// QUADSPI, which isn't supported here.
+    pub unsafe fn write_indirect_dma<D>(
+        &mut self,
+        addr: u32,
+        buf: &[u8],
+        channel: DmaChannel,
+        channel_cfg: ChannelCfg,
+        dma: &mut Dma<D>,
+    ) where
+        D: Deref<Target = dma_p::RegisterBlock>,
+    {
+        let (ptr, len) = (buf.as_ptr(), buf.len());
+
+        assert!(
+            len >= 1 && len <= u16::MAX as usize,
+            "DMA transfers must be 1 - 65,535 bytes long."
+        );
+
+        self.clear_interrupt(QspiInterrupt::TransferComplete);
+        while self.is_busy() {}
+
+        // Steps are equivalent to those listed in `write_indirect`, with the exception that
+        // QUADSPI_AR is specified before setting DMAEN; see step 4.
+        self.regs.dlr.write(|w| w.dl().bits(len as u32 - 1));
+        self.regs
+            .ccr
+            .modify(|_, w| w.fmode().bits(FunctionalMode::IndirectWrite as u8));
+        self.regs.ar.modify(|_, w| w.address().bits(addr));
+
+        // L4 RM, Tables 41 and 42: QUADSPI is DMA1 C5S = 0b0101, and DMA2 C7S = 0b0011.
+        #[cfg(feature = "l4")]
+        {
+            let is_dma2 = &*dma.regs as *const _ as u32 == pac::DMA2::ptr() as u32;
+            match (is_dma2, channel) {
+                (false, DmaChannel::C5) => dma.regs.cselr.modify(|_, w| w.c5s().bits(0b0101)),
+                (true, DmaChannel::C7) => dma.regs.cselr.modify(|_, w| w.c7s().bits(0b0011)),
+                _ => panic!("QUADSPI DMA requests are only on DMA1 channel 5, and DMA2 channel 7."),
+            }
+        }
+        #[cfg(feature = "wb")]
+        dma::mux_dma(dma, channel, DmaInput::Quadspi);
+
+        dma.cfg_channel(
+            channel,
+            &self.regs.dr as *const _ as u32,
+            ptr as u32,
+            len as u16,
+            dma::Direction::ReadFromMem,
+            dma::DataSize::S8,
+            dma::DataSize::S8,
+            channel_cfg,
+        );
+
+        // RM: In indirect mode, when the DMA is enabled, a DMA request is generated each time
+        // the FIFO level is at or below the threshold.
+        self.regs.cr.modify(|_, w| w.dmaen().set_bit());
+    }
+
+    #[cfg(not(feature = "h7"))]
+    /// Stop DMA requests from the QSPI, eg once a transfer started with `write_indirect_dma`
+    /// is complete.
+    pub fn stop_dma(&mut self) {
+        self.regs.cr.modify(|_, w| w.dmaen().clear_bit());
+    }
+
+    /// Start automatic status-polling mode. The QSPI reads the status register repeatedly,
+    /// without CPU involvement, and stops once it matches. Enable the `StatusMatch` interrupt to
+    /// be notified of the match, then call `stop_status_polling`. See `poll_status` for a blocking
+    /// version. L4 RM, section 17.3.6: QUADSPI status flag polling mode.
+    pub fn start_status_polling(&mut self, cfg: &StatusPollingCfg) {
+        assert!(
+            cfg.num_bytes >= 1 && cfg.num_bytes <= 4,
+            "Status polling must read between 1 and 4 bytes."
+        );
+
+        self.clear_interrupt(QspiInterrupt::StatusMatch);
+        while self.is_busy() {}
+
+        // RM: In automatic polling mode, the QUADSPI periodically starts a command to read a
+        // defined number of status bytes (up to 4). The received bytes can be masked to isolate
+        // some status bits and an interrupt can be generated when the selected bits have a
+        // defined value.
+        self.regs
+            .psmkr
+            .write(|w| unsafe { w.mask().bits(cfg.mask) });
+        self.regs
+            .psmar
+            .write(|w| unsafe { w.match_().bits(cfg.match_val) });
+        self.regs
+            .pir
+            .write(|w| unsafe { w.interval().bits(cfg.interval) });
+        self.regs
+            .dlr
+            .write(|w| unsafe { w.dl().bits(cfg.num_bytes as u32 - 1) });
+
+        // If the automatic polling mode stop (APMS) bit is set, operation stops and BUSY goes to 0
+        // as soon as a match is detected.
+        self.regs.cr.modify(|_, w| {
+            w.pmm().bit(cfg.match_mode as u8 != 0);
+            w.apms().set_bit()
+        });
+
+        // Status reads have no address phase. Since there's no address, writing the instruction
+        // starts the command.
+        self.regs.ccr.modify(|_, w| unsafe {
+            w.admode().bits(0);
+            w.fmode().bits(FunctionalMode::StatusPolling as u8);
+            w.instruction().bits(cfg.instruction)
+        });
+    }
+
+    /// Clean up after automatic status-polling mode: Clear the status match flag, and restore
+    /// the address mode used by other functional modes.
+    pub fn stop_status_polling(&mut self) {
+        self.clear_interrupt(QspiInterrupt::StatusMatch);
+        while self.is_busy() {}
+
+        self.regs
+            .ccr
+            .modify(|_, w| unsafe { w.admode().bits(self.cfg.protocol_mode as u8) });
+    }
+
+    /// Blocks until the memory's status register matches, using automatic status-polling mode.
+    /// With `StatusPollingCfg::default()`, this waits for a flash program or erase operation to
+    /// complete. If `cfg.timeout_polls` is set and there's no match in time, the command is
+    /// aborted, and `QspiError::Timeout` returned.
+    pub fn poll_status(&mut self, cfg: &StatusPollingCfg) -> Result<(), QspiError> {
+        self.start_status_polling(cfg);
+
+        let mut polls = 0;
+        while self.regs.sr.read().smf().bit_is_clear() {
+            if let Some(max) = cfg.timeout_polls {
+                if polls >= max {
+                    // RM: "ABORT is automatically reset when the abort is complete."
+                    self.regs.cr.modify(|_, w| w.abort().set_bit());
+                    while self.regs.cr.read().abort().bit_is_set() {}
+                    self.stop_status_polling();
+                    return Err(QspiError::Timeout);
+                }
+            }
+            polls += 1;
+        }
+
+        self.stop_status_polling();
+        Ok(())
+    }
+
+    /// Enter memory-mapped mode, for execute-in-place. After this, the external flash can be
+    /// read, and code run from it, at `0x9000_0000`. It can't be written to, and indirect mode
+    /// transactions can't be used until memory-mapped mode is exited, eg by resetting the
+    /// peripheral. See the module documentation for the boot sequence.
+    pub fn enable_xip(&mut self, cfg: &XipConfig) {
+        assert!(
+            cfg.dummy_cycles < 32,
+            "Dummy cycles must be between 0 and 31."
+        );
+
+        // FMODE, and other CCR fields, can only be set when BUSY = 0.
+        while self.is_busy() {}
+
+        // RM: In memory-mapped mode, the QUADSPI keeps prefetching data after an access, until
+        // the timeout elapses, if TCEN = 1, or until the next non-sequential access.
+        match cfg.prefetch_timeout {
+            Some(timeout) => {
+                self.regs.lptr.write(|w| unsafe { w.timeout().bits(timeout) });
+                self.regs.cr.modify(|_, w| w.tcen().set_bit());
+            }
+            None => self.regs.cr.modify(|_, w| w.tcen().clear_bit()),
+        }
+
+        // RM: "In memory-mapped mode, the instruction, and frame format, are set in QUADSPI_CCR.
+        // The address phase is generated automatically from the accessed address."
+        self.regs.ccr.modify(|_, w| unsafe {
+            w.instruction().bits(cfg.read_instruction);
+            w.dcyc().bits(cfg.dummy_cycles);
+            w.sioo().bit(cfg.send_instruction_once);
+            w.fmode().bits(FunctionalMode::MemoryMapped as u8)
+        });
+
+        self.cfg.dummy_cycles = cfg.dummy_cycles;
+
+        // Make sure no instruction is fetched from the region before the mode change completes.
+        cortex_m::asm::dsb();
+        cortex_m::asm::isb();
+    }
 
     /// Read one word from memory in memory-mapped mode
     pub fn read_1_mem_mapped(&mut self, offset: isize) -> u32 {
@@ -384,3 +686,21 @@ impl Qspi {
         unsafe { core::ptr::read(addr.offset(offset)) }
     }
 }
+
+/// Use a vector table in memory-mapped external flash, at `offset` bytes from its start. The
+/// table must be aligned to its size, rounded up to a power of two; eg 0x200 for a table of
+/// 100 entries. Call this after `Qspi::enable_xip`.
+///
+/// Safety: The table at this address must be valid, and stay readable; if memory-mapped mode is
+/// exited, any exception or interrupt will fault.
+pub unsafe fn relocate_vector_table(offset: u32) {
+    let addr = MEM_MAPPED_BASE_ADDR as u32 + offset;
+    // VTOR's lowest 7 bits are reserved.
+    assert!(addr % 0x80 == 0, "The vector table must be aligned to its size.");
+
+    free(|_| {
+        (*cortex_m::peripheral::SCB::ptr()).vtor.write(addr);
+        cortex_m::asm::dsb();
+        cortex_m::asm::isb();
+    });
+}
@@ -0,0 +1,159 @@
+//! Utilities for executing from RAM: Relocating the vector table (SCB VTOR) to RAM, copying code
+//! into RAM sections, and marking functions to run from RAM. Running from RAM avoids flash wait
+//! states, which makes ISR timing deterministic, and lets code keep running while the flash
+//! is being erased or programmed.
+//!
+//! Note that some RAM regions can't be used to execute code. For example, CCM SRAM on F3, and
+//! DTCM on H7. Make sure the `.data` section (or the section you copy code into) is placed in
+//! executable RAM in your `memory.x`.
+
+use core::ptr;
+
+use cortex_m::{
+    asm::{dsb, isb},
+    interrupt::InterruptNumber,
+    peripheral::SCB,
+};
+
+use crate::pac::Interrupt;
+
+/// The number of Cortex-M system exception entries at the start of the vector table. This
+/// includes the initial stack pointer, and reset vector.
+pub const NUM_EXCEPTIONS: usize = 16;
+
+/// A vector table located in RAM. `N` is the total number of entries: 16 system exceptions,
+/// plus the number of interrupts for your MCU, as listed in the RM's vector table. For example,
+/// `RamVectorTable<{ 16 + 82 }>`. Alignment is set to satisfy VTOR's requirement (a power of two
+/// at least as large as the table) for all supported MCUs.
+///
+/// Example:
+/// ```
+/// static mut VECTORS: RamVectorTable<{ 16 + 82 }> = RamVectorTable::new();
+///
+/// unsafe { VECTORS.relocate(&mut cp.SCB) };
+/// unsafe { VECTORS.set_handler(pac::Interrupt::TIM2, tim2_handler) };
+/// ```
+#[repr(C, align(1024))]
+pub struct RamVectorTable<const N: usize> {
+    entries: [u32; N],
+}
+
+impl<const N: usize> RamVectorTable<N> {
+    /// Create a blank vector table. It's populated from the active table in `relocate`.
+    pub const fn new() -> Self {
+        Self { entries: [0; N] }
+    }
+
+    /// Copy the currently active vector table (eg the one in flash) into this one, and point VTOR
+    /// to it. Interrupts are disabled during the switch.
+    ///
+    /// Safety: This table must not be moved or dropped while in use; ie it should be a `static`.
+    pub unsafe fn relocate(&'static mut self, scb: &mut SCB) {
+        cortex_m::interrupt::free(|_| {
+            let active = scb.vtor.read() as *const u32;
+            for (i, entry) in self.entries.iter_mut().enumerate() {
+                *entry = ptr::read_volatile(active.add(i));
+            }
+
+            scb.vtor.write(self.entries.as_ptr() as u32);
+            dsb();
+            isb();
+        });
+    }
+
+    /// Set the handler for an interrupt. This can be called before or after `relocate`. If after,
+    /// the handler is used the next time the interrupt fires.
+    ///
+    /// Safety: The handler replaces any `#[interrupt]` handler defined for this interrupt, and must
+    /// be safe to run in interrupt context.
+    pub unsafe fn set_handler(&mut self, interrupt: Interrupt, handler: unsafe extern "C" fn()) {
+        let i = NUM_EXCEPTIONS + interrupt.number() as usize;
+        ptr::write_volatile(&mut self.entries[i], handler as usize as u32);
+        dsb();
+    }
+
+    /// Set the handler for a system exception, by its vector table position. Eg 15 for SysTick.
+    ///
+    /// Safety: See `set_handler`.
+    pub unsafe fn set_exception_handler(
+        &mut self,
+        position: usize,
+        handler: unsafe extern "C" fn(),
+    ) {
+        if position < 2 || position >= NUM_EXCEPTIONS {
+            panic!("Exception position must be between 2 and 15.");
+        }
+        ptr::write_volatile(&mut self.entries[position], handler as usize as u32);
+        dsb();
+    }
+
+    /// Get the address of this table, eg to verify VTOR is pointing to it.
+    pub fn address(&self) -> u32 {
+        self.entries.as_ptr() as u32
+    }
+}
+
+/// Point VTOR back to an existing vector table, such as the original one in flash.
+///
+/// Safety: `address` must point to a valid vector table, aligned as VTOR requires.
+pub unsafe fn set_vector_table(scb: &mut SCB, address: u32) {
+    cortex_m::interrupt::free(|_| {
+        scb.vtor.write(address);
+        dsb();
+        isb();
+    });
+}
+
+/// Copy a section from its load address (in flash) to its run address (in RAM), eg for a custom
+/// `.ramfunc` section defined in your linker script. The arguments are generally linker symbols:
+/// the section's load address, and its start and end addresses in RAM.
+///
+/// This isn't required for functions marked with `ram_fn!`, since they're placed in `.data`, which
+/// `cortex-m-rt` copies to RAM before `main`.
+///
+/// Example, with `__sramfunc`, `__eramfunc`, and `__siramfunc` defined in `memory.x`:
+/// ```
+/// extern "C" {
+///     static mut __sramfunc: u32;
+///     static mut __eramfunc: u32;
+///     static __siramfunc: u32;
+/// }
+///
+/// unsafe { copy_section(&__siramfunc, &mut __sramfunc, &mut __eramfunc) };
+/// ```
+///
+/// On H7, if the data cache is enabled, clean it (eg with `SCB::clean_dcache`) after copying,
+/// so the instruction fetch sees the new code.
+///
+/// Safety: The addresses must be word-aligned, and the RAM region must not be in use.
+pub unsafe fn copy_section(load: *const u32, start: *mut u32, end: *mut u32) {
+    let len = (end as usize - start as usize) / 4;
+    for i in 0..len {
+        ptr::write_volatile(start.add(i), ptr::read_volatile(load.add(i)));
+    }
+    // Make sure the copied code is visible to instruction fetches before it's called.
+    dsb();
+    isb();
+}
+
+/// Mark a function to execute from RAM. It's placed in a `.data` subsection, which
+/// `cortex-m-rt` copies from flash to RAM at startup. Note that functions this calls run from
+/// flash unless they're inlined, or also marked with this macro.
+///
+/// Example:
+/// ```
+/// ram_fn! {
+///     fn control_loop(input: f32) -> f32 {
+///         input * 2.
+///     }
+/// }
+/// ```
+#[macro_export]
+macro_rules! ram_fn {
+    ($(#[$attr:meta])* $vis:vis fn $name:ident($($arg:tt)*) $(-> $ret:ty)? $body:block) => {
+        $(#[$attr])*
+        #[inline(never)]
+        #[link_section = ".data.ramfunc"]
+        $vis fn $name($($arg)*) $(-> $ret)? $body
+    };
+}
@@ -0,0 +1,281 @@
+//! Query the cause of the most recent reset, using the reset flags in RCC_CSR (RCC_RSR on H7).
+//! Useful for logging why the firmware rebooted, eg after a watchdog timeout.
+//!
+//! The flags are cumulative; they're only cleared by a power-on reset, or by software. Call
+//! `clear_reset_flags` after reading them, so the next reset's cause isn't mixed with this one's.
+//!
+//! This module also includes `safe_init`, which returns peripherals to a known state after a
+//! bootloader handoff or software reset.
+
+use cortex_m::{
+    interrupt::free,
+    peripheral::{NVIC, SYST},
+};
+
+use crate::{gpio, pac::RCC};
+
+use cfg_if::cfg_if;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// The cause of the most recent reset.
+pub enum ResetCause {
+    /// Reset from entering Standby or Stop mode while the nRST_STDBY or nRST_STOP option bit is
+    /// cleared.
+    LowPower,
+    /// The window watchdog (WWDG) timed out, or was refreshed outside its window.
+    WindowWatchdog,
+    /// The independent watchdog (IWDG) timed out.
+    IndependentWatchdog,
+    /// Software reset, eg from `SCB::sys_reset`.
+    Software,
+    #[cfg(feature = "l4")]
+    /// Firewall access violation.
+    Firewall,
+    #[cfg(not(any(feature = "f4", feature = "h7")))]
+    /// Option bytes were reloaded, eg by setting OBL_LAUNCH in FLASH_CR.
+    OptionByteLoad,
+    #[cfg(any(feature = "f3", feature = "f4", feature = "g0", feature = "h7"))]
+    /// Power-on or power-down reset.
+    PowerOn,
+    #[cfg(not(any(feature = "f3", feature = "g0")))]
+    /// Brownout reset. On families without a separate POR flag (eg L4, G4), this includes
+    /// power-on resets.
+    Brownout,
+    #[cfg(all(feature = "h7", not(feature = "h7b3")))]
+    /// D1 domain power switch reset, eg on exiting D1 DStandby mode.
+    D1Domain,
+    #[cfg(all(feature = "h7", not(feature = "h7b3")))]
+    /// D2 domain power switch reset, eg on exiting D2 DStandby mode.
+    D2Domain,
+    /// The NRST pin was pulled low, eg from a reset button or debugger.
+    Pin,
+    /// None of the reset flags are set; eg they were cleared since the last reset.
+    Unknown,
+}
+
+/// Read the reset flags, and return the cause of the most recent reset. Several flags are often
+/// set at once; eg the pin flag is set by all reset sources, since they drive NRST low. This
+/// returns the most specific cause.
+pub fn reset_cause() -> ResetCause {
+    let rcc = unsafe { &(*RCC::ptr()) };
+
+    cfg_if! {
+        if #[cfg(feature = "h7")] {
+            let flags = rcc.rsr.read();
+
+            if flags.lpwrrstf().bit_is_set() {
+                return ResetCause::LowPower;
+            }
+
+            cfg_if! {
+                if #[cfg(feature = "h7b3")] {
+                    if flags.wwdgrstf().bit_is_set() {
+                        return ResetCause::WindowWatchdog;
+                    }
+                    if flags.iwdgrstf().bit_is_set() {
+                        return ResetCause::IndependentWatchdog;
+                    }
+                } else {
+                    if flags.wwdg1rstf().bit_is_set() {
+                        return ResetCause::WindowWatchdog;
+                    }
+                    if flags.iwdg1rstf().bit_is_set() {
+                        return ResetCause::IndependentWatchdog;
+                    }
+                }
+            }
+
+            if flags.sftrstf().bit_is_set() {
+                return ResetCause::Software;
+            }
+            // A power-on reset sets the POR, BOR, and pin flags.
+            if flags.porrstf().bit_is_set() {
+                return ResetCause::PowerOn;
+            }
+            if flags.borrstf().bit_is_set() {
+                return ResetCause::Brownout;
+            }
+
+            #[cfg(not(feature = "h7b3"))]
+            if flags.d1rstf().bit_is_set() {
+                return ResetCause::D1Domain;
+            }
+            #[cfg(not(feature = "h7b3"))]
+            if flags.d2rstf().bit_is_set() {
+                return ResetCause::D2Domain;
+            }
+
+            if flags.pinrstf().bit_is_set() {
+                return ResetCause::Pin;
+            }
+        } else {
+            let flags = rcc.csr.read();
+
+            cfg_if! {
+                if #[cfg(any(feature = "l4", feature = "l5", feature = "g4"))] {
+                    let low_power = flags.lpwrstf().bit_is_set();
+                } else {
+                    let low_power = flags.lpwrrstf().bit_is_set();
+                }
+            }
+            if low_power {
+                return ResetCause::LowPower;
+            }
+
+            if flags.wwdgrstf().bit_is_set() {
+                return ResetCause::WindowWatchdog;
+            }
+
+            cfg_if! {
+                if #[cfg(feature = "f4")] {
+                    let iwdg = flags.wdgrstf().bit_is_set();
+                } else if #[cfg(feature = "l5")] {
+                    let iwdg = flags.iwwdgrstf().bit_is_set();
+                } else {
+                    let iwdg = flags.iwdgrstf().bit_is_set();
+                }
+            }
+            if iwdg {
+                return ResetCause::IndependentWatchdog;
+            }
+
+            if flags.sftrstf().bit_is_set() {
+                return ResetCause::Software;
+            }
+
+            #[cfg(feature = "l4")]
+            if flags.firewallrstf().bit_is_set() {
+                return ResetCause::Firewall;
+            }
+
+            #[cfg(not(feature = "f4"))]
+            if flags.oblrstf().bit_is_set() {
+                return ResetCause::OptionByteLoad;
+            }
+
+            #[cfg(any(feature = "f3", feature = "f4"))]
+            if flags.porrstf().bit_is_set() {
+                return ResetCause::PowerOn;
+            }
+            #[cfg(feature = "g0")]
+            if flags.pwrrstf().bit_is_set() {
+                return ResetCause::PowerOn;
+            }
+
+            #[cfg(not(any(feature = "f3", feature = "g0")))]
+            if flags.borrstf().bit_is_set() {
+                return ResetCause::Brownout;
+            }
+
+            cfg_if! {
+                if #[cfg(feature = "f4")] {
+                    let pin = flags.padrstf().bit_is_set();
+                } else {
+                    let pin = flags.pinrstf().bit_is_set();
+                }
+            }
+            if pin {
+                return ResetCause::Pin;
+            }
+        }
+    }
+
+    ResetCause::Unknown
+}
+
+/// Clear all reset flags, by setting the RMVF bit.
+pub fn clear_reset_flags() {
+    let rcc = unsafe { &(*RCC::ptr()) };
+
+    #[cfg(feature = "h7")]
+    rcc.rsr.modify(|_, w| w.rmvf().set_bit());
+    #[cfg(not(feature = "h7"))]
+    rcc.csr.modify(|_, w| w.rmvf().set_bit());
+}
+
+/// Put the MCU in a deterministic, low-power state, similar to after a power-on reset. Run this
+/// at the start of `main`, before setting up clocks, if the firmware may be started by a
+/// bootloader, or after a software reset, where peripherals aren't in their reset state.
+///
+/// This:
+/// - Refreshes the independent watchdog, in case it was started by the bootloader. (Once started,
+/// it can't be stopped.)
+/// - Disables SysTick, and disables and clears all interrupts in the NVIC.
+/// - Sets all GPIO pins to analog mode, except the debug pins. See `gpio::set_all_analog`.
+/// - Returns all peripheral clock enable registers to their reset values. Memory and flash
+/// interface clocks are left enabled, as they are after reset. On H7, the AHB2 clocks (which
+/// include D2 domain SRAM) are left unchanged, in case they hold program data.
+///
+/// It doesn't change the system clock; `Clocks::setup` handles that.
+pub fn safe_init() {
+    refresh_iwdg();
+
+    free(|_| unsafe {
+        let syst = &(*SYST::PTR);
+        syst.csr.write(0);
+
+        let nvic = &(*NVIC::PTR);
+        // Cortex-M0+ (G0) supports up to 32 interrupts; the others, up to 240.
+        #[cfg(feature = "g0")]
+        let num_regs = 1;
+        #[cfg(not(feature = "g0"))]
+        let num_regs = 8;
+
+        for i in 0..num_regs {
+            nvic.icer[i].write(0xffff_ffff);
+            nvic.icpr[i].write(0xffff_ffff);
+        }
+    });
+
+    gpio::set_all_analog();
+
+    free(|_| {
+        let rcc = unsafe { &(*RCC::ptr()) };
+
+        cfg_if! {
+            if #[cfg(feature = "f3")] {
+                rcc.ahbenr.reset();
+                rcc.apb1enr.reset();
+                rcc.apb2enr.reset();
+            } else if #[cfg(feature = "f4")] {
+                // todo: AHB2 and AHB3, on variants that have them.
+                rcc.ahb1enr.reset();
+                rcc.apb1enr.reset();
+                rcc.apb2enr.reset();
+            } else if #[cfg(feature = "g0")] {
+                rcc.iopenr.reset();
+                rcc.ahbenr.reset();
+                rcc.apbenr1.reset();
+                rcc.apbenr2.reset();
+            } else if #[cfg(feature = "h7")] {
+                rcc.ahb1enr.reset();
+                rcc.ahb3enr.reset();
+                rcc.ahb4enr.reset();
+                rcc.apb1lenr.reset();
+                rcc.apb1henr.reset();
+                rcc.apb2enr.reset();
+                rcc.apb3enr.reset();
+                rcc.apb4enr.reset();
+            } else {
+                rcc.ahb1enr.reset();
+                rcc.ahb2enr.reset();
+                rcc.ahb3enr.reset();
+                rcc.apb1enr1.reset();
+                rcc.apb1enr2.reset();
+                rcc.apb2enr.reset();
+            }
+        }
+    });
+
+    refresh_iwdg();
+}
+
+/// Reload the independent watchdog's counter. This has no effect if the watchdog isn't running.
+pub(crate) fn refresh_iwdg() {
+    #[cfg(any(feature = "h747cm4", feature = "h747cm7"))]
+    let iwdg = unsafe { &(*crate::pac::IWDG1::ptr()) };
+    #[cfg(not(any(feature = "h747cm4", feature = "h747cm7")))]
+    let iwdg = unsafe { &(*crate::pac::IWDG::ptr()) };
+
+    iwdg.kr.write(|w| unsafe { w.bits(0xAAAA) });
+}
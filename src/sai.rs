@@ -4,9 +4,12 @@
 use core::ops::Deref;
 
 use cortex_m::interrupt::free;
+use num_traits::Float; // Float abs.
 
 use crate::{clocks::Clocks, pac::RCC, util::RccPeriph};
 
+use cfg_if::cfg_if;
+
 #[cfg(not(feature = "h7"))]
 use crate::pac::sai1 as sai;
 #[cfg(feature = "h7")]
@@ -20,13 +23,14 @@ use crate::pac::dma as dma_p;
 #[cfg(any(
     feature = "f3",
     feature = "l4",
+    feature = "l5",
     feature = "g4",
     feature = "h7",
     feature = "wb"
 ))]
 use crate::pac::dma1 as dma_p;
 
-#[cfg(not(any(feature = "f4", feature = "l5")))]
+#[cfg(not(feature = "f4"))]
 use crate::dma::{self, ChannelCfg, Dma, DmaChannel};
 
 #[cfg(any(feature = "f3", feature = "l4"))]
@@ -400,7 +404,8 @@ pub struct SaiConfig {
     pub num_pdm_mics: NumPdmMics,
     /// Which PDM CK line to enable. Must be 1-4. Defaults to 1. (CK1 in User manuals)
     pub pdm_clock_used: u8,
-    /// Master clock divider. Divides the kernel clock input. Defaults to 0, for no division.
+    /// Master clock divider. Divides the kernel clock input. Defaults to 0, for no division. Up
+    /// to 15 on L4, and 63 on other families.
     pub mckdiv: u8,
 }
 
@@ -576,6 +581,84 @@ impl SaiConfig {
     }
 }
 
+/// The sample rate produced by a given SAI kernel clock and config, and its error relative to a
+/// target rate. See `SaiConfig::sample_rate` and `SaiConfig::best_mckdiv`.
+#[derive(Clone, Copy, Debug)]
+pub struct SampleRate {
+    /// The master clock divider that produces this rate.
+    pub mckdiv: u8,
+    /// The actual frame synchronization (sample) rate, in Hz.
+    pub actual: f32,
+    /// Error relative to the target rate, in parts-per-million.
+    pub error_ppm: f32,
+}
+
+/// The largest MCKDIV value: It's a 4-bit field on L4, and 6 bits on other families.
+#[cfg(feature = "l4")]
+const MCKDIV_MAX: u8 = 0b1111;
+#[cfg(not(feature = "l4"))]
+const MCKDIV_MAX: u8 = 0b11_1111;
+
+impl SaiConfig {
+    /// Calculate the frame synchronization (sample) rate, in Hz, for a given SAI kernel clock
+    /// speed and master clock divider. Use `Clocks::sai1_speed()` etc for `sai_ker_ck`.
+    /// H743 RM, section 51.4: SAI clock generator:
+    /// With the master clock (NOMCK = 0): F_FS = F_sai_ker_ck / (MCKDIV x (OSR + 1) x 256).
+    /// Without (NOMCK = 1): F_FS = F_sai_ker_ck / ((FRL + 1) x MCKDIV).
+    /// An MCKDIV of 0 is treated as 1. On L4, the divider is 2 x MCKDIV, and there's no OSR.
+    pub fn sample_rate(&self, sai_ker_ck: u32, mckdiv: u8) -> f32 {
+        let mckdiv = if mckdiv == 0 { 1 } else { mckdiv as u32 };
+
+        match self.master_clock {
+            MasterClock::Used => {
+                cfg_if! {
+                    if #[cfg(feature = "l4")] {
+                        let div = if mckdiv == 1 { 1 } else { 2 * mckdiv };
+                        sai_ker_ck as f32 / (div * 256) as f32
+                    } else {
+                        let osr = self.oversampling_ratio as u32 + 1;
+                        sai_ker_ck as f32 / (mckdiv * osr * 256) as f32
+                    }
+                }
+            }
+            MasterClock::NotUsed => sai_ker_ck as f32 / (self.frame_length as u32 * mckdiv) as f32,
+        }
+    }
+
+    /// Find the master clock divider that gets closest to a target sample rate, eg 44_100 or
+    /// 48_000, for a given SAI kernel clock speed. Returns the divider, the rate it produces,
+    /// and the error. If the error is too high, adjust the PLL feeding the SAI, eg using its
+    /// fractional divider on H7. Set `mckdiv` in the config to the result.
+    pub fn best_mckdiv(&self, sai_ker_ck: u32, target: u32) -> SampleRate {
+        let mut best = SampleRate {
+            mckdiv: 0,
+            actual: self.sample_rate(sai_ker_ck, 0),
+            error_ppm: 0.,
+        };
+        best.error_ppm = error_ppm(best.actual, target);
+
+        for mckdiv in 1..=MCKDIV_MAX {
+            let actual = self.sample_rate(sai_ker_ck, mckdiv);
+            let error = error_ppm(actual, target);
+
+            if error.abs() < best.error_ppm.abs() {
+                best = SampleRate {
+                    mckdiv,
+                    actual,
+                    error_ppm: error,
+                };
+            }
+        }
+
+        best
+    }
+}
+
+/// Error of a rate relative to a target, in parts-per-million.
+fn error_ppm(actual: f32, target: u32) -> f32 {
+    (actual - target as f32) / target as f32 * 1_000_000.
+}
+
 /// Represents the Serial Audio Interface (SAI) peripheral, used for digital audio
 /// input and output.
 pub struct Sai<R> {
@@ -608,9 +691,8 @@ where
         // F_SCK = F_sai_ker_ck / MCKDIV
         // F_FS = F_sai_ker_ck / ((FRL + 1) * MCKDIV)
 
-        // 6-bit fields.
-        assert!(config_a.mckdiv <= 0b111111);
-        assert!(config_b.mckdiv <= 0b111111);
+        assert!(config_a.mckdiv <= MCKDIV_MAX, "MCKDIV is out of range.");
+        assert!(config_b.mckdiv <= MCKDIV_MAX, "MCKDIV is out of range.");
 
         // For info on modes, reference H743 RM, section 51.4.3: "Configuring and
         // Enabling SAI modes".
@@ -978,7 +1060,7 @@ It can generate an interrupt if WCKCFGIE bit is set in SAI_xIM register");
     /// There is one DMA channel per audio subblock supporting basic DMA request/acknowledge
     /// protocol.
     /// Before configuring the SAI block, the SAI DMA channel must be disabled.
-    #[cfg(not(any(feature = "g0", feature = "f4", feature = "l5")))]
+    #[cfg(not(any(feature = "g0", feature = "f4")))]
     pub unsafe fn write_dma<D>(
         &mut self,
         buf: &[i32], // todo size?
@@ -1067,7 +1149,7 @@ It can generate an interrupt if WCKCFGIE bit is set in SAI_xIM register");
     /// DMA interface to read/write from/to the SAI_xDR register (to access the internal FIFO).
     /// There is one DMA channel per audio subblock supporting basic DMA request/acknowledge
     /// protocol.
-    #[cfg(not(any(feature = "g0", feature = "f4", feature = "l5")))]
+    #[cfg(not(any(feature = "g0", feature = "f4")))]
     pub unsafe fn read_dma<D>(
         &mut self,
         buf: &mut [i32], // todo size?
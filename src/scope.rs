@@ -0,0 +1,283 @@
+//! A diagnostic facility for observing internal variables, such as control loop states, on an
+//! oscilloscope. Streams one or two variables per loop iteration to the DAC outputs, or to SWO
+//! via ITM stimulus ports.
+//!
+//! For low jitter on the DAC outputs, set a DAC trigger from a timer (eg Tim6 or Tim7). Written
+//! values are then latched to the outputs at the timer's rate, independent of when in the loop
+//! they're written. For continuous streaming without CPU involvement, use `DacScope::start_dma`
+//! with a circular DMA channel, and `DacScope::push`.
+
+#[cfg(not(any(
+    feature = "f401",
+    feature = "f411",
+    feature = "f412",
+    feature = "wb",
+    feature = "g0"
+)))]
+use core::ops::Deref;
+
+#[cfg(not(any(
+    feature = "f401",
+    feature = "f411",
+    feature = "f412",
+    feature = "wb",
+    feature = "g0"
+)))]
+use crate::{
+    dac::{Dac, DacChannel},
+    util::RccPeriph,
+};
+
+#[cfg(not(any(feature = "h7", feature = "wl")))]
+use cortex_m::peripheral::{DCB, ITM, TPIU};
+
+#[cfg(not(any(feature = "h7", feature = "wl")))]
+use crate::pac::DBGMCU;
+
+use cfg_if::cfg_if;
+
+cfg_if! {
+    if #[cfg(any(all(feature = "f3", not(feature = "f302")), all(feature = "l4", not(feature = "l4x6")), feature = "g4", feature = "h7b3"))] {
+        use crate::pac::dac1 as dac_p;
+    } else if #[cfg(not(any(feature = "f401", feature = "f411", feature = "f412", feature = "wb")))] {
+        use crate::pac::dac as dac_p;
+    }
+}
+
+#[cfg(not(any(feature = "f4", feature = "wb", feature = "g0")))]
+use crate::{
+    dma::{ChannelCfg, Dma, DmaChannel},
+    util::{DacChannelDma, RouteError},
+};
+
+#[cfg(any(
+    feature = "f3",
+    feature = "l4",
+    feature = "l5",
+    feature = "g4",
+    feature = "h7",
+    feature = "wl"
+))]
+use crate::pac::dma1 as dma_p;
+
+#[derive(Clone, Copy)]
+/// Maps a variable's range to the scope output's full scale. Values outside the range are
+/// clamped.
+pub struct ScopeScale {
+    /// The value that maps to the minimum output, eg 0V on the DAC.
+    pub min: f32,
+    /// The value that maps to the maximum output, eg VREF on the DAC.
+    pub max: f32,
+}
+
+impl ScopeScale {
+    pub fn new(min: f32, max: f32) -> Self {
+        Self { min, max }
+    }
+
+    /// Convert a value to an output word, where `max_word` is the full-scale word.
+    pub fn to_word(&self, val: f32, max_word: u16) -> u16 {
+        let portion = (val - self.min) / (self.max - self.min);
+
+        if portion <= 0. {
+            0
+        } else if portion >= 1. {
+            max_word
+        } else {
+            (portion * max_word as f32) as u16
+        }
+    }
+}
+
+impl Default for ScopeScale {
+    fn default() -> Self {
+        Self { min: 0., max: 1. }
+    }
+}
+
+#[cfg(not(any(
+    feature = "f401",
+    feature = "f411",
+    feature = "f412",
+    feature = "wb",
+    feature = "g0"
+)))]
+/// Streams variables to the DAC outputs. Channel 1 uses `scale1`, and channel 2 uses `scale2`.
+pub struct DacScope<R> {
+    pub dac: Dac<R>,
+    pub scale1: ScopeScale,
+    #[cfg(not(feature = "wl"))]
+    pub scale2: ScopeScale,
+    buf_ptr: *mut u16,
+    buf_len: usize,
+    buf_i: usize,
+}
+
+#[cfg(not(any(
+    feature = "f401",
+    feature = "f411",
+    feature = "f412",
+    feature = "wb",
+    feature = "g0"
+)))]
+impl<R> DacScope<R>
+where
+    R: Deref<Target = dac_p::RegisterBlock> + RccPeriph,
+{
+    /// Create a scope from a DAC that's already been set up, including any triggers. This
+    /// enables the DAC channels.
+    pub fn new(
+        mut dac: Dac<R>,
+        scale1: ScopeScale,
+        #[cfg(not(feature = "wl"))] scale2: ScopeScale,
+    ) -> Self {
+        dac.enable(DacChannel::C1);
+        #[cfg(not(feature = "wl"))]
+        dac.enable(DacChannel::C2);
+
+        Self {
+            dac,
+            scale1,
+            #[cfg(not(feature = "wl"))]
+            scale2,
+            buf_ptr: core::ptr::null_mut(),
+            buf_len: 0,
+            buf_i: 0,
+        }
+    }
+
+    /// Write a variable to a DAC channel, scaled by that channel's `ScopeScale`.
+    pub fn write(&mut self, channel: DacChannel, val: f32) {
+        let scale = match channel {
+            DacChannel::C1 => &self.scale1,
+            #[cfg(not(feature = "wl"))]
+            DacChannel::C2 => &self.scale2,
+        };
+
+        let word = scale.to_word(val, self.dac.max_word());
+        self.dac.write(channel, word);
+    }
+
+    #[cfg(not(feature = "wl"))]
+    /// Write a variable to each DAC channel. Run this once per loop iteration.
+    pub fn write_both(&mut self, val1: f32, val2: f32) {
+        self.write(DacChannel::C1, val1);
+        self.write(DacChannel::C2, val2);
+    }
+
+    #[cfg(not(any(feature = "f4", feature = "wb")))]
+    /// Start streaming from `buf` to a DAC channel using DMA. Each DAC trigger outputs the next
+    /// word in the buffer, so `channel_cfg` should be set to circular mode. Use `push` to
+    /// write values into the buffer, eg once per loop iteration, at the same rate as the trigger.
+    /// Returns an error if the DAC doesn't have `dac_channel`.
+    pub unsafe fn start_dma<D>(
+        &mut self,
+        buf: &'static mut [u16],
+        dac_channel: DacChannel,
+        dma_channel: DmaChannel,
+        channel_cfg: ChannelCfg,
+        dma: &mut Dma<D>,
+    ) -> Result<(), RouteError>
+    where
+        D: Deref<Target = dma_p::RegisterBlock>,
+        R: DacChannelDma,
+    {
+        self.dac
+            .write_dma(buf, dac_channel, dma_channel, channel_cfg, dma)?;
+
+        self.buf_ptr = buf.as_mut_ptr();
+        self.buf_len = buf.len();
+        self.buf_i = 0;
+
+        Ok(())
+    }
+
+    /// Write a value into the DMA buffer set up with `start_dma`, scaled by channel 1's
+    /// `ScopeScale`. Wraps around at the end of the buffer. Does nothing if DMA isn't started.
+    pub fn push(&mut self, val: f32) {
+        if self.buf_len == 0 {
+            return;
+        }
+
+        let word = self.scale1.to_word(val, self.dac.max_word());
+        // The DMA reads from this buffer concurrently; use a volatile write.
+        unsafe { core::ptr::write_volatile(self.buf_ptr.add(self.buf_i), word) };
+
+        self.buf_i += 1;
+        if self.buf_i == self.buf_len {
+            self.buf_i = 0;
+        }
+    }
+}
+
+#[cfg(not(any(feature = "h7", feature = "wl")))]
+/// Streams variables over SWO, using ITM stimulus ports. Each variable is sent as an `f32`, on
+/// its own port. View with a SWO viewer such as `probe-rs`, `openocd`, or STM32CubeProgrammer's
+/// SWV, and plot it there.
+pub struct SwoScope {
+    pub itm: ITM,
+    /// The stimulus port to use for the first variable. The second variable uses the next port.
+    pub port: usize,
+}
+
+#[cfg(not(any(feature = "h7", feature = "wl")))]
+impl SwoScope {
+    /// Configure the TPIU and ITM for SWO output in NRZ (UART) mode. `traceclkin` is the trace
+    /// clock in Hz; generally the same as HCLK. `baud` is the SWO baud rate; it must be an
+    /// integer divider of `traceclkin`, and match your SWO viewer's setting. `port` must be
+    /// 0 - 30, since the second variable uses the next port.
+    pub fn new(
+        itm: ITM,
+        dcb: &mut DCB,
+        tpiu: &mut TPIU,
+        port: usize,
+        traceclkin: u32,
+        baud: u32,
+    ) -> Self {
+        assert!(port <= 30, "The SWO scope's first port must be 0 - 30.");
+        // TPIU_ACPR's SWOSCALER field is 13 bits.
+        assert!(
+            baud > 0 && baud <= traceclkin && traceclkin / baud <= 0x2000,
+            "The SWO baud rate must be between `traceclkin` / 8,192, and `traceclkin`."
+        );
+
+        // Enable the DWT and ITM blocks.
+        dcb.enable_trace();
+
+        // Enable the trace pin (SWO), in asynchronous mode.
+        let dbgmcu = unsafe { &(*DBGMCU::ptr()) };
+        dbgmcu.cr.modify(|_, w| w.trace_ioen().set_bit());
+
+        unsafe {
+            // Set SWO to NRZ (UART) encoding, and the baud rate prescaler.
+            tpiu.sppr.write(0b10);
+            tpiu.acpr.write(traceclkin / baud - 1);
+            // Disable continuous formatting; pass ITM packets directly.
+            tpiu.ffcr.write(0x100);
+
+            // Unlock the ITM registers, and enable it, with a trace bus ID of 1.
+            itm.lar.write(0xC5AC_CE55);
+            itm.tcr.write((1 << 16) | (1 << 3) | 1);
+            // Enable the stimulus ports used.
+            itm.ter[0].modify(|v| v | (0b11 << port));
+        }
+
+        Self { itm, port }
+    }
+
+    /// Write a variable to the first stimulus port. Blocks until the ITM FIFO is ready.
+    pub fn write(&mut self, val: f32) {
+        let stim = &mut self.itm.stim[self.port];
+        while !stim.is_fifo_ready() {}
+        stim.write_u32(val.to_bits());
+    }
+
+    /// Write a variable to each stimulus port. Run this once per loop iteration.
+    pub fn write_both(&mut self, val1: f32, val2: f32) {
+        self.write(val1);
+
+        let stim = &mut self.itm.stim[self.port + 1];
+        while !stim.is_fifo_ready() {}
+        stim.write_u32(val2.to_bits());
+    }
+}
@@ -9,12 +9,19 @@ use cortex_m::interrupt::free;
 #[cfg(feature = "embedded-hal")]
 use embedded_hal::spi::FullDuplex;
 
+#[cfg(feature = "embedded-hal-1")]
+use embedded_hal_1::spi::{ErrorKind, ErrorType, Operation, SpiBus};
+
+#[cfg(feature = "embedded-hal-1")]
+use crate::{clocks::Clocks, gpio::Pin};
+
 use crate::{
+    gpio::AltFnPin,
     pac::{self, RCC},
     util::RccPeriph,
 };
 
-#[cfg(any(feature = "f3", feature = "l4"))]
+#[cfg(not(feature = "f4"))]
 use crate::util::DmaPeriph;
 
 #[cfg(feature = "g0")]
@@ -22,6 +29,7 @@ use crate::pac::dma as dma_p;
 #[cfg(any(
     feature = "f3",
     feature = "l4",
+    feature = "l5",
     feature = "g4",
     feature = "h7",
     feature = "wb",
@@ -29,7 +37,7 @@ use crate::pac::dma as dma_p;
 ))]
 use crate::pac::dma1 as dma_p;
 
-#[cfg(not(any(feature = "f4", feature = "l5")))]
+#[cfg(not(feature = "f4"))]
 use crate::dma::{self, ChannelCfg, Dma, DmaChannel};
 
 #[cfg(any(feature = "f3", feature = "l4"))]
@@ -37,28 +45,61 @@ use crate::dma::DmaInput;
 
 use cfg_if::cfg_if;
 
-/// SPI error
+/// SPI error. These are returned once per occurrence: `Spi::check_errors` clears the flag
+/// it reports, so the SPI can continue to be used afterwards.
 #[non_exhaustive]
 #[derive(Copy, Clone, Debug)]
 pub enum Error {
-    /// Overrun occurred
+    /// Overrun occurred: A word was received while the RX buffer was full. The word that arrived
+    /// is lost.
     Overrun,
-    /// Mode fault occurred
+    /// Mode fault occurred: NSS was pulled low while in master mode, eg due to another master on
+    /// the bus. The hardware disables the SPI, and switches it to slave mode. It stays that way;
+    /// call `Spi::resume_master` once the bus is free again.
     ModeFault,
-    /// CRC error
+    /// CRC error: The CRC received didn't match the one calculated on the received data, eg due
+    /// to noise on the bus.
     Crc,
+    /// TI frame format error: In TI mode, as a slave, a frame sync pulse arrived while a word
+    /// was being received. The word is corrupt. (FRE; TIFRE on H7)
+    FrameFormat,
+    #[cfg(feature = "h7")]
+    /// Underrun occurred: In slave mode, a word had to be transmitted before one was written to
+    /// the TX FIFO.
+    Underrun,
 }
 
-/// Possible interrupt types. Enable these in CR2. Check and clear with SR. There is no explicit
-/// way to clear these.
+/// Diagnostic returned by `Spi::self_test` when the test fails.
+#[derive(Copy, Clone, Debug)]
+pub enum SelfTestError {
+    /// An SPI error occurred during the transfer.
+    Spi(Error),
+    /// A received word didn't match the one sent. This usually means MOSI isn't jumpered to
+    /// MISO, or there's a problem with the pin configuration or signal integrity.
+    Mismatch { index: usize, sent: u8, received: u8 },
+}
+
+/// Words transferred by `Spi::self_test`. Chosen to exercise each bit in both states, and
+/// to catch lines stuck high or low.
+const SELF_TEST_PATTERN: [u8; 8] = [0x00, 0xff, 0x55, 0xaa, 0x0f, 0xf0, 0x01, 0x80];
+
+/// Possible interrupt types. Enable these in CR2 (IER on H7). Check and clear with SR. There is
+/// no explicit way to clear these, except on H7.
 #[derive(Copy, Clone)]
 pub enum SpiInterrupt {
-    /// Tx buffer empty (TXEIE)
+    /// Tx buffer empty (TXEIE). On H7, the TX FIFO has space for a FIFO threshold's worth of
+    /// frames. (TXPIE)
     TxBufEmpty,
-    /// Rx buffer not empty (RXNEIE)
+    /// Rx buffer not empty (RXNEIE). On H7, the RX FIFO holds a FIFO threshold's worth of frames.
+    /// (RXPIE)
     RxBufNotEmpty,
-    /// Error (ERRIE)
+    /// Error (ERRIE). On H7, this enables the underrun, overrun, CRC, TI frame, and mode fault
+    /// interrupts. (UDRIE, OVRIE, CRCEIE, TIFREIE, and MODFIE)
     Error,
+    #[cfg(feature = "h7")]
+    /// End of transfer: The number of frames set in TSIZE has been transferred, eg by
+    /// `write` or `transfer`. (EOTIE)
+    EndOfTransfer,
 }
 
 /// Set the factor to divide the APB clock by to set baud rate. Sets `SPI_CR1` register, `BR` field.
@@ -131,6 +172,28 @@ pub enum DataSize {
     D32 = 31,
 }
 
+/// A word type for `read_word`, `write_word`, `write_words`, and `transfer_words`. Use the
+/// smallest type that fits the configured data size: `u8` for 4 to 8 bits, `u16` for 9 to 16
+/// bits, and, on H7, `u32` for 17 to 32 bits. Each word is one data register access, and one
+/// frame on the bus.
+pub trait SpiWord: Copy {
+    /// The width of the type, in bits.
+    const BITS: u8;
+}
+
+impl SpiWord for u8 {
+    const BITS: u8 = 8;
+}
+
+impl SpiWord for u16 {
+    const BITS: u8 = 16;
+}
+
+#[cfg(feature = "h7")]
+impl SpiWord for u32 {
+    const BITS: u8 = 32;
+}
+
 #[derive(Clone, Copy)]
 #[repr(u8)]
 /// FIFO reception threshold Sets `SPI_CR2` register, `FRXTH` field.
@@ -141,17 +204,41 @@ pub enum ReceptionThresh {
     D8 = 1,
 }
 
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 /// Select the communication mode between.
 pub enum SpiCommMode {
     FullDuplex,
+    /// A single, bidirectional data line: Connect MOSI to the device's data pin, eg for 3-wire
+    /// sensors and display controllers. Use `transmit` and `receive`, which switch the line's
+    /// direction as required. (BIDIMODE)
     HalfDuplex,
     /// Simplex Transmit only. (Cfg same as Full Duplex, but ignores input)
     TransmitOnly,
-    /// Simplex Receive only.
+    /// Simplex Receive only. Use `receive`. (RXONLY)
     ReceiveOnly,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// The data line direction, in half-duplex mode. Sets CR1 register, BIDIOE field (HDDIR field
+/// on H7).
+pub enum BidiDirection {
+    Receive,
+    Transmit,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(u8)]
+/// The frame format. Sets CR2 register, FRF field (CFG2 register, SP field on H7).
+pub enum FrameFormat {
+    /// Standard SPI.
+    Motorola = 0,
+    /// TI synchronous serial protocol, used by some DACs and codecs. NSS is a frame sync pulse,
+    /// driven by hardware before each data frame, so configure the NSS pin in alternate function
+    /// mode. The clock polarity and phase are fixed by the protocol, so `mode` is ignored, as is
+    /// `slave_select`. Frame sync errors are reported with `Error::FrameFormat`.
+    Ti = 1,
+}
+
 #[derive(Clone, Copy, PartialEq)]
 /// Used for managing NSS / CS pin. Sets CR1 register, SSM field.
 pub enum SlaveSelect {
@@ -174,7 +261,7 @@ pub enum SlaveSelect {
     HardwareOutDisable,
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug)]
 #[repr(u8)]
 /// Clock polarity. Sets CFGR2 register, CPOL field. Stored in the config as a field of `SpiMode`.
 pub enum SpiPolarity {
@@ -184,7 +271,7 @@ pub enum SpiPolarity {
     IdleHigh = 1,
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug)]
 #[repr(u8)]
 /// Clock phase. Sets CFGR2 register, CPHA field. Stored in the config as a field of `SpiMode`.
 pub enum SpiPhase {
@@ -194,7 +281,7 @@ pub enum SpiPhase {
     CaptureOnSecondTransition = 1,
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug)]
 /// SPI mode. Sets CFGR2 reigster, CPOL and CPHA fields.
 pub struct SpiMode {
     /// Clock polarity
@@ -243,10 +330,23 @@ pub struct SpiConfig {
     pub mode: SpiMode,
     pub comm_mode: SpiCommMode,
     pub slave_select: SlaveSelect,
-    /// Data size. Defaults to 8 bits.
+    /// Data size: 4 to 16 bits, or 4 to 32 bits on H7. F4 only supports 8 and 16 bits. For sizes
+    /// over 8 bits, use `write_words` and `transfer_words` with `u16` words. Defaults to 8 bits.
     pub data_size: DataSize,
     /// FIFO reception threshhold. Defaults to 8 bits.
     pub fifo_reception_thresh: ReceptionThresh,
+    /// Defaults to Motorola.
+    pub frame_format: FrameFormat,
+    #[cfg(not(feature = "h7"))]
+    /// Enable hardware CRC calculation, with this polynomial, eg 0x07 for CRC-8. The CRC's
+    /// length follows the data size: 8 bits for data sizes of 8 bits or less, and 16 bits
+    /// otherwise. Use `write_crc` and `transfer_crc` to send and check it. Defaults to `None`:
+    /// disabled. (SPI_CR1, CRCEN and CRCL, and SPI_CRCPR)
+    pub crc_polynomial: Option<u16>,
+    #[cfg(feature = "h7")]
+    /// FIFO threshold, in data frames, 1 - 16: The number of frames transferred per TXP and RXP
+    /// event, and per DMA request. Defaults to 1. (SPI_CFG1, FTHLV)
+    pub fifo_threshold: u8,
     // pub cs_delay: f32,
     // pub swap_miso_mosi: bool,
     // pub suspend_when_inactive: bool,
@@ -260,10 +360,60 @@ impl Default for SpiConfig {
             slave_select: SlaveSelect::Software,
             data_size: DataSize::D8,
             fifo_reception_thresh: ReceptionThresh::D8,
+            frame_format: FrameFormat::Motorola,
+            #[cfg(not(feature = "h7"))]
+            crc_polynomial: None,
+            #[cfg(feature = "h7")]
+            fifo_threshold: 1,
         }
     }
 }
 
+/// SPI configuration, decoded from its registers by `Spi::dump`. Print it with `{:?}`, eg to
+/// attach to a bug report, or to compare against the RM. (With `defmt`, use `Debug2Format`.)
+#[derive(Clone, Copy, Debug)]
+pub struct SpiDump {
+    /// SPI_CR1, SPE
+    pub enabled: bool,
+    /// SPI_CR1, MSTR (SPI_CFG2, MASTER on H7)
+    pub master: bool,
+    /// The factor the SPI's input clock is divided by. SPI_CR1, BR (SPI_CFG1, MBR on H7)
+    pub baud_div: u16,
+    /// SPI_CR1, CPOL and CPHA (SPI_CFG2 on H7)
+    pub mode: SpiMode,
+    /// SPI_CR1, BIDIMODE and RXONLY (SPI_CFG2, COMM on H7). Note that on families other than H7,
+    /// transmit-only mode is configured the same as full duplex, so reads as full duplex.
+    pub comm_mode: SpiCommMode,
+    /// Number of bits in a data frame. SPI_CR2, DS (SPI_CR1, DFF on F4; SPI_CFG1, DSIZE on H7)
+    pub data_bits: u8,
+    /// SPI_CR1, LSBFIRST (SPI_CFG2, LSBFRST on H7)
+    pub lsb_first: bool,
+    /// Software slave management. SPI_CR1, SSM (SPI_CFG2 on H7)
+    pub software_ss: bool,
+    /// SPI_CR2, FRF (SPI_CFG2, SP on H7)
+    pub frame_format: FrameFormat,
+    /// SPI_CR2, TXDMAEN (SPI_CFG1 on H7)
+    pub tx_dma: bool,
+    /// SPI_CR2, RXDMAEN (SPI_CFG1 on H7)
+    pub rx_dma: bool,
+}
+
+/// A pin that can be used as SPI `R`'s SCK signal. Implemented for the tokens of pins with the
+/// signal, along with its alternate function number. See `Spi::new_with_pins`.
+pub trait SckPin<R>: AltFnPin {
+    const AF: u8;
+}
+
+/// A pin that can be used as SPI `R`'s MISO signal. See `SckPin`.
+pub trait MisoPin<R>: AltFnPin {
+    const AF: u8;
+}
+
+/// A pin that can be used as SPI `R`'s MOSI signal. See `SckPin`.
+pub trait MosiPin<R>: AltFnPin {
+    const AF: u8;
+}
+
 /// Represents a Serial Peripheral Interface (SPI) peripheral.
 pub struct Spi<R> {
     pub regs: R,
@@ -333,6 +483,8 @@ where
                     w.cpha().bit(cfg.mode.phase as u8 != 0);
                         w.cpol().bit(cfg.mode.polarity as u8 != 0);
                         w.master().master();
+                        w.ssm().bit(cfg.slave_select == SlaveSelect::Software);
+                        w.ssoe().bit(cfg.slave_select == SlaveSelect::HardwareOutEnable);
                         w.lsbfrst().msbfirst()
                         // w.ssom().bit(config.suspend_when_inactive);
                         // w.ssm().bit(config.managed_cs == false);
@@ -343,8 +495,32 @@ where
                         // w.comm().variant(communication_mode);
                 });
 
+                regs.cfg2.modify(|_, w| {
+                    match cfg.comm_mode {
+                        SpiCommMode::FullDuplex => w.comm().full_duplex(),
+                        SpiCommMode::TransmitOnly => w.comm().transmitter(),
+                        SpiCommMode::ReceiveOnly => w.comm().receiver(),
+                        SpiCommMode::HalfDuplex => w.comm().half_duplex(),
+                    };
+                    match cfg.frame_format {
+                        FrameFormat::Motorola => w.sp().motorola(),
+                        FrameFormat::Ti => w.sp().ti(),
+                    }
+                });
+
+                assert!(
+                    cfg.fifo_threshold >= 1 && cfg.fifo_threshold <= 16,
+                    "The FIFO threshold must be 1 - 16 frames."
+                );
+                regs.cfg1.modify(|_, w| w.fthlv().bits(cfg.fifo_threshold - 1));
+
                 // spe: enable the SPI bus
-                regs.cr1.write(|w| w.ssi().slave_not_selected().spe().enabled());
+                regs.cr1.write(|w| {
+                    // In half-duplex mode, start as transmitter, so the bus is idle.
+                    w.hddir().bit(cfg.comm_mode == SpiCommMode::HalfDuplex);
+                    w.ssi().slave_not_selected();
+                    w.spe().enabled()
+                });
             } else {
                 // L44 RM, section 40.4.7: Configuration of SPI
                 // The configuration procedure is almost the same for master and slave. For specific mode
@@ -354,6 +530,16 @@ where
                 // 1. Write proper GPIO registers: Configure GPIO for MOSI, MISO and SCK pins.
                 // (Handled in GPIO modules and user code)
 
+                // (Step 3c, moved here, since FRF can only be written with SPE = 0.)
+                // c) Set the FRF bit if the TI protocol is required (keep NSSP bit cleared in TI mode).
+                regs.cr2.modify(|_, w| w.frf().bit(cfg.frame_format == FrameFormat::Ti));
+
+                // (Step 4, moved here, since CRCPR can only be written with SPE = 0.)
+                // 4. Write to SPI_CRCPR register: Configure the CRC polynomial if needed.
+                if let Some(poly) = cfg.crc_polynomial {
+                    regs.crcpr.write(|w| unsafe { w.bits(poly as u32) });
+                }
+
                 // 2. Write to the SPI_CR1 register:
                 regs.cr1.modify(|_, w| unsafe {
                     // a) Configure the serial clock baud rate using the BR[2:0] bits (Note: 4)
@@ -367,19 +553,28 @@ where
                     // c) Select simplex or half-duplex mode by configuring RXONLY or BIDIMODE and
                     // BIDIOE (RXONLY and BIDIMODE can't be set at the same time).
                     w.bidimode().bit(cfg.comm_mode == SpiCommMode::HalfDuplex);
+                    // (Start half-duplex mode as transmitter, so the bus is idle.)
+                    w.bidioe().bit(cfg.comm_mode == SpiCommMode::HalfDuplex);
                     w.rxonly().bit(cfg.comm_mode == SpiCommMode::ReceiveOnly);
                     // d) Configure the LSBFIRST bit to define the frame format (Note: 2).
                     w.lsbfirst().clear_bit();
                     // e) Configure the CRCL and CRCEN bits if CRC is needed (while SCK clock signal is
                     // at idle state).
-                    w.crcen().clear_bit();
+                    #[cfg(not(feature = "f4"))]
+                    w.crcl().bit(cfg.data_size as u8 > DataSize::D8 as u8);
+                    w.crcen().bit(cfg.crc_polynomial.is_some());
+                    // (On F4, only 8 and 16-bit data sizes are available, set with DFF.)
+                    #[cfg(feature = "f4")]
+                    w.dff().bit(cfg.data_size as u8 > DataSize::D8 as u8);
                     // f) Configure SSM and SSI (Notes: 2 & 3).
                     w.ssm().bit(cfg.slave_select == SlaveSelect::Software);
                     w.ssi().set_bit(); // todo?
                     // g) Configure the MSTR bit (in multimaster NSS configuration, avoid conflict state on
                     // NSS if master is configured to prevent MODF error).
                     w.mstr().set_bit();
-                    w.spe().set_bit() // Enable SPI
+                    // Enable SPI. In receive-only mode, the master clocks continuously while
+                    // enabled, so we leave it disabled until `receive` is called.
+                    w.spe().bit(cfg.comm_mode != SpiCommMode::ReceiveOnly)
                 });
 
                 // 3. Write to SPI_CR2 register:
@@ -398,12 +593,12 @@ where
                         w.frxth().bit(cfg.fifo_reception_thresh as u8 != 0)
                     });
 
-                // c) Set the FRF bit if the TI protocol is required (keep NSSP bit cleared in TI mode).
+                // c) (Handled before step 2)
                 // d) Set the NSSP bit if the NSS pulse mode between two data units is required (keep
                 // CHPA and TI bits cleared in NSSP mode).
 
                 // f) Initialize LDMA_TX and LDMA_RX bits if DMA is used in packed mode.
-                // 4. Write to SPI_CRCPR register: Configure the CRC polynomial if needed.
+                // 4. (Handled before step 2)
                 // 5. Write proper DMA registers: Configure DMA streams dedicated for SPI Tx and Rx in
                 // DMA registers if the DMA streams are used.
             }
@@ -415,6 +610,26 @@ where
         Spi { regs, cfg }
     }
 
+    /// Initialize an SPI peripheral as with `new`, and configure its SCK, MISO, and MOSI pins with
+    /// the correct alternate function. Pins without this SPI's signal fail to compile. Their tokens
+    /// are consumed, so the pins can't be taken by other drivers.
+    pub fn new_with_pins<SCK: SckPin<R>, MISO: MisoPin<R>, MOSI: MosiPin<R>>(
+        regs: R,
+        sck: SCK,
+        miso: MISO,
+        mosi: MOSI,
+        cfg: SpiConfig,
+        baud_rate: BaudRate,
+    ) -> Self {
+        let result = Self::new(regs, cfg, baud_rate);
+
+        sck.into_alt_fn(SCK::AF);
+        miso.into_alt_fn(MISO::AF);
+        mosi.into_alt_fn(MOSI::AF);
+
+        result
+    }
+
     /// Change the SPI baud rate.
     pub fn reclock(&mut self, baud_rate: BaudRate) {
         self.regs.cr1.modify(|_, w| w.spe().clear_bit());
@@ -422,7 +637,8 @@ where
         #[cfg(not(feature = "h7"))]
         self.regs.cr1.modify(|_, w| unsafe {
             w.br().bits(baud_rate as u8);
-            w.spe().set_bit()
+            // In receive-only mode, enabling the SPI starts the clock; `receive` enables it.
+            w.spe().bit(self.cfg.comm_mode != SpiCommMode::ReceiveOnly)
         });
 
         #[cfg(feature = "h7")]
@@ -474,28 +690,96 @@ where
         }
     }
 
+    /// Check for mode fault, overrun, CRC, TI frame format, and (on H7) underrun errors. If one is flagged, clear
+    /// it using the sequence from the RM, and return it. Otherwise, these flags stay latched,
+    /// and the bus stops working. This is called by `read` and `write_one`; call it directly when
+    /// using DMA, eg from the error interrupt. See L44 RM, section 40.4.10: SPI error flags,
+    /// and H743 RM, section 50.4.14: Error flags.
+    pub fn check_errors(&mut self) -> Result<(), Error> {
+        let sr = self.regs.sr.read();
+
+        cfg_if! {
+            if #[cfg(feature = "h7")] {
+                if sr.modf().bit_is_set() {
+                    // "When MODF is set, the SPE and IOLOCK bits are cleared, and MASTER is reset."
+                    // Leave it that way; the caller decides when it's safe to drive the bus again.
+                    self.regs.ifcr.write(|w| w.modfc().set_bit());
+                    Err(Error::ModeFault)
+                } else if sr.ovr().bit_is_set() {
+                    self.regs.ifcr.write(|w| w.ovrc().set_bit());
+                    Err(Error::Overrun)
+                } else if sr.udr().bit_is_set() {
+                    self.regs.ifcr.write(|w| w.udrc().set_bit());
+                    Err(Error::Underrun)
+                } else if sr.crce().bit_is_set() {
+                    self.regs.ifcr.write(|w| w.crcec().set_bit());
+                    Err(Error::Crc)
+                } else if sr.tifre().bit_is_set() {
+                    self.regs.ifcr.write(|w| w.tifrec().set_bit());
+                    Err(Error::FrameFormat)
+                } else {
+                    Ok(())
+                }
+            } else {
+                if sr.modf().bit_is_set() {
+                    // "Use the following software sequence to clear the MODF bit:
+                    // 1. Make a read or write access to the SPIx_SR register while the MODF bit is set.
+                    // 2. Then write to the SPIx_CR1 register."
+                    // The hardware cleared MSTR and SPE when MODF was set; this write leaves them
+                    // cleared, so the caller decides when it's safe to drive the bus again.
+                    self.regs.cr1.modify(|_, w| w);
+                    Err(Error::ModeFault)
+                } else if sr.ovr().bit_is_set() {
+                    // "Clearing the OVR bit is done by a read access to the SPI_DR register followed
+                    // by a read access to the SPI_SR register."
+                    unsafe { ptr::read_volatile(&self.regs.dr as *const _ as *const u8) };
+                    self.regs.sr.read();
+                    Err(Error::Overrun)
+                } else if sr.crcerr().bit_is_set() {
+                    // CRCERR is cleared by writing 0 to it.
+                    self.regs.sr.modify(|_, w| w.crcerr().clear_bit());
+                    Err(Error::Crc)
+                } else if sr.fre().bit_is_set() {
+                    // FRE is cleared by the SR read above.
+                    Err(Error::FrameFormat)
+                } else {
+                    Ok(())
+                }
+            }
+        }
+    }
+
+    /// Return to master mode, and re-enable the SPI, after `check_errors` reported
+    /// `Error::ModeFault`. Only call this once the other device driving NSS low has released it;
+    /// otherwise, the fault is raised again.
+    pub fn resume_master(&mut self) {
+        cfg_if! {
+            if #[cfg(feature = "h7")] {
+                self.regs.cfg2.modify(|_, w| w.master().master());
+                self.regs.cr1.modify(|_, w| w.spe().enabled());
+            } else {
+                self.regs.cr1.modify(|_, w| {
+                    w.mstr().set_bit();
+                    w.spe().set_bit()
+                });
+            }
+        }
+    }
+
     /// Read a single byte if available, or block until it's available.
     /// See L44 RM, section 40.4.9: Data transmission and reception procedures.
     pub fn read(&mut self) -> nb::Result<u8, Error> {
-        let sr = self.regs.sr.read();
+        self.check_errors()?;
 
         cfg_if! {
             if #[cfg(feature = "h7")] {
-                let crce = sr.crce().bit_is_set();
-                let not_empty = sr.rxp().bit_is_set();
+                let not_empty = self.regs.sr.read().rxp().bit_is_set();
             } else {
-                let crce = sr.crcerr().bit_is_set();
-                let not_empty = sr.rxne().bit_is_set();
+                let not_empty = self.regs.sr.read().rxne().bit_is_set();
             }
         }
 
-        if sr.ovr().bit_is_set() {
-            Err(nb::Error::Other(Error::Overrun))
-        } else if sr.modf().bit_is_set() {
-            Err(nb::Error::Other(Error::ModeFault))
-        } else if crce {
-            Err(nb::Error::Other(Error::Crc))
-        } else if not_empty {
+        if not_empty {
             #[cfg(feature = "h7")]
             // todo: note: H7 can support words beyond u8. (Can others too?)
             let result = unsafe { ptr::read_volatile(&self.regs.rxdr as *const _ as *const u8) };
@@ -510,25 +794,17 @@ where
     /// Write a single byte if available, or block until it's available.
     /// See L44 RM, section 40.4.9: Data transmission and reception procedures.
     pub fn write_one(&mut self, byte: u8) -> nb::Result<(), Error> {
-        let sr = self.regs.sr.read();
+        self.check_errors()?;
 
         cfg_if! {
             if #[cfg(feature = "h7")] {
-                let crce = sr.crce().bit_is_set();
-                let rdy = sr.txp().bit_is_set();
+                let rdy = self.regs.sr.read().txp().bit_is_set();
             } else {
-                let crce = sr.crcerr().bit_is_set();
-                let rdy = sr.txe().bit_is_set();
+                let rdy = self.regs.sr.read().txe().bit_is_set();
             }
         }
 
-        if sr.ovr().bit_is_set() {
-            Err(nb::Error::Other(Error::Overrun))
-        } else if sr.modf().bit_is_set() {
-            Err(nb::Error::Other(Error::ModeFault))
-        } else if crce {
-            Err(nb::Error::Other(Error::Crc))
-        } else if rdy {
+        if rdy {
             cfg_if! {
                 if #[cfg(feature = "h7")] {
                     // todo: note: H7 can support words beyond u8. (Can others too?)
@@ -549,6 +825,28 @@ where
     /// Write multiple bytes on the SPI line, blocking until complete.
     /// See L44 RM, section 40.4.9: Data transmission and reception procedures.
     pub fn write(&mut self, words: &[u8]) -> Result<(), Error> {
+        // In transmit-only mode, the receiver is off, so there's nothing to read.
+        #[cfg(feature = "h7")]
+        let tx_only = self.cfg.comm_mode == SpiCommMode::TransmitOnly;
+
+        #[cfg(feature = "h7")]
+        for chunk in words.chunks(u16::MAX as usize) {
+            self.start_sized(chunk.len() as u16);
+
+            let result = chunk.iter().try_for_each(|word| {
+                nb::block!(self.write_one(*word))?;
+                if !tx_only {
+                    nb::block!(self.read())?;
+                }
+                Ok(())
+            });
+
+            let ended = self.end_sized(result.is_ok());
+            result?;
+            ended?;
+        }
+
+        #[cfg(not(feature = "h7"))]
         for word in words {
             nb::block!(self.write_one(word.clone()))?;
             nb::block!(self.read())?;
@@ -560,6 +858,22 @@ where
     /// Read multiple bytes to a buffer, blocking until complete.
     /// See L44 RM, section 40.4.9: Data transmission and reception procedures.
     pub fn transfer<'w>(&mut self, words: &'w mut [u8]) -> Result<(), Error> {
+        #[cfg(feature = "h7")]
+        for chunk in words.chunks_mut(u16::MAX as usize) {
+            self.start_sized(chunk.len() as u16);
+
+            let result = chunk.iter_mut().try_for_each(|word| {
+                nb::block!(self.write_one(*word))?;
+                *word = nb::block!(self.read())?;
+                Ok(())
+            });
+
+            let ended = self.end_sized(result.is_ok());
+            result?;
+            ended?;
+        }
+
+        #[cfg(not(feature = "h7"))]
         for word in words.iter_mut() {
             nb::block!(self.write_one(word.clone()))?;
             *word = nb::block!(self.read())?;
@@ -568,9 +882,418 @@ where
         Ok(())
     }
 
-    #[cfg(not(any(feature = "g0", feature = "f4", feature = "l5")))]
+    #[cfg(feature = "h7")]
+    /// Start a transaction of `len` frames. The hardware ends it after the last frame, and sets
+    /// EOT. See H743 RM, section 50.4.12: "Procedure for enabling SPI".
+    fn start_sized(&mut self, len: u16) {
+        // TSIZE can only be written with SPE = 0.
+        self.regs.cr1.modify(|_, w| w.spe().clear_bit());
+        self.regs.cr2.modify(|_, w| w.tsize().bits(len));
+        self.regs.cr1.modify(|_, w| w.spe().set_bit());
+        self.regs.cr1.modify(|_, w| w.cstart().started());
+    }
+
+    #[cfg(feature = "h7")]
+    /// End a transaction started with `start_sized`, and return to transactions of unlimited
+    /// size, as used by `write_one` and DMA. If `completed` is false, eg after an error, the
+    /// transaction is aborted, instead of waiting for EOT.
+    fn end_sized(&mut self, completed: bool) -> Result<(), Error> {
+        let result = if completed {
+            self.wait_for(|regs| regs.sr.read().eot().bit_is_set())
+        } else {
+            Ok(())
+        };
+        self.regs.ifcr.write(|w| {
+            w.eotc().set_bit();
+            w.txtfc().set_bit()
+        });
+
+        self.regs.cr1.modify(|_, w| w.spe().clear_bit());
+        self.regs.cr2.modify(|_, w| w.tsize().bits(0));
+        self.regs.cr1.modify(|_, w| w.spe().set_bit());
+
+        result
+    }
+
+    /// Block until `done` returns true, returning early if an error is flagged. Without this,
+    /// an error could leave us waiting forever: Eg, on a mode fault, the hardware disables the
+    /// SPI, so flags like EOT are never set.
+    fn wait_for(
+        &mut self,
+        done: impl Fn(&pac::spi1::RegisterBlock) -> bool,
+    ) -> Result<(), Error> {
+        while !done(&*self.regs) {
+            self.check_errors()?;
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "h7")]
+    /// Lock the SPI's IO configuration (SPI_CFG2, eg master mode, clock polarity and phase, and
+    /// NSS management) against changes, eg by errant code. The lock is released by hardware when
+    /// the SPI is disabled, or on a mode fault. (SPI_CR1, IOLOCK)
+    pub fn lock_io(&mut self) {
+        // IOLOCK can only be written with SPE = 0.
+        self.regs.cr1.modify(|_, w| w.spe().clear_bit());
+        self.regs.cr1.modify(|_, w| w.iolock().set_bit());
+        self.regs.cr1.modify(|_, w| w.spe().set_bit());
+    }
+
+    /// Set the data line direction, in half-duplex mode. Waits for any transmission in progress
+    /// to complete first, so the last frame isn't cut off. The SPI is left disabled in the receive
+    /// direction (except on H7), since the master starts clocking as soon as it's enabled; `receive`
+    /// enables it. You don't need to call this when using `transmit` and `receive`.
+    pub fn set_bidi_direction(&mut self, direction: BidiDirection) -> Result<(), Error> {
+        cfg_if! {
+            if #[cfg(feature = "h7")] {
+                // Transfers started with `transmit` and `receive` are complete when they return.
+                self.regs.cr1.modify(|_, w| w.spe().clear_bit());
+                self.regs.cr1.modify(|_, w| w.hddir().bit(direction == BidiDirection::Transmit));
+                self.regs.cr1.modify(|_, w| w.spe().set_bit());
+            } else {
+                self.wait_for_tx_complete()?;
+
+                self.regs.cr1.modify(|_, w| w.spe().clear_bit());
+                self.regs.cr1.modify(|_, w| w.bidioe().bit(direction == BidiDirection::Transmit));
+
+                if direction == BidiDirection::Transmit {
+                    self.regs.cr1.modify(|_, w| w.spe().set_bit());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    #[cfg(not(feature = "h7"))]
+    /// Wait for the last frame to be sent: TXE = 1, FTLVL = 0, and BSY = 0.
+    fn wait_for_tx_complete(&mut self) -> Result<(), Error> {
+        self.wait_for(|regs| regs.sr.read().txe().bit_is_set())?;
+        #[cfg(not(feature = "f4"))]
+        self.wait_for(|regs| regs.sr.read().ftlvl().bits() == 0)?;
+        self.wait_for(|regs| regs.sr.read().bsy().bit_is_clear())
+    }
+
+    /// Write multiple bytes, without receiving, blocking until complete. In half-duplex mode,
+    /// switches the data line to transmit. In other modes, this is the same as `write`.
+    pub fn transmit(&mut self, words: &[u8]) -> Result<(), Error> {
+        cfg_if! {
+            if #[cfg(feature = "h7")] {
+                // In transmit-only mode (COMM = 01), the receiver is off, so there's nothing to read.
+                match self.cfg.comm_mode {
+                    SpiCommMode::HalfDuplex => self.set_bidi_direction(BidiDirection::Transmit)?,
+                    SpiCommMode::TransmitOnly => (),
+                    _ => return self.write(words),
+                }
+
+                for chunk in words.chunks(u16::MAX as usize) {
+                    self.start_sized(chunk.len() as u16);
+
+                    let result = chunk
+                        .iter()
+                        .try_for_each(|word| nb::block!(self.write_one(*word)));
+
+                    let ended = self.end_sized(result.is_ok());
+                    result?;
+                    ended?;
+                }
+            } else {
+                // RM: "In bidirectional mode, when transmitting (BIDIOE = 1), the receive is not
+                // performed", so there's nothing to read. In transmit-only mode, the receiver is
+                // still active, so `write` discards what it receives to prevent overruns.
+                if self.cfg.comm_mode != SpiCommMode::HalfDuplex {
+                    return self.write(words);
+                }
+
+                self.set_bidi_direction(BidiDirection::Transmit)?;
+
+                for word in words {
+                    nb::block!(self.write_one(*word))?;
+                }
+
+                // Wait for the last frame to be sent, so the caller can safely change direction,
+                // or release CS.
+                self.wait_for_tx_complete()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Read multiple bytes, without transmitting, blocking until complete. For half-duplex and
+    /// receive-only modes. In half-duplex mode, switches the data line to receive, then back to
+    /// transmit, leaving the bus idle.
+    ///
+    /// Except on H7, the master clocks continuously in these modes until the SPI is disabled, so
+    /// this disables it while the last frame is in progress, as described in L44 RM, section 40.4.9:
+    /// "Procedure for disabling the SPI". If an interrupt delays this, extra frames are clocked
+    /// and discarded; some devices may treat them as an extended read.
+    pub fn receive(&mut self, buf: &mut [u8]) -> Result<(), Error> {
+        assert!(
+            self.cfg.comm_mode == SpiCommMode::HalfDuplex
+                || self.cfg.comm_mode == SpiCommMode::ReceiveOnly,
+            "`receive` requires half-duplex or receive-only mode."
+        );
+
+        if buf.is_empty() {
+            return Ok(());
+        }
+
+        let half_duplex = self.cfg.comm_mode == SpiCommMode::HalfDuplex;
+        if half_duplex {
+            self.set_bidi_direction(BidiDirection::Receive)?;
+        }
+
+        cfg_if! {
+            if #[cfg(feature = "h7")] {
+                // TSIZE stops the clock after the last frame.
+                let mut result = Ok(());
+                for chunk in buf.chunks_mut(u16::MAX as usize) {
+                    self.start_sized(chunk.len() as u16);
+
+                    result = chunk.iter_mut().try_for_each(|word| {
+                        *word = nb::block!(self.read())?;
+                        Ok(())
+                    });
+
+                    let ended = self.end_sized(result.is_ok());
+                    result = result.and(ended);
+                    if result.is_err() {
+                        break;
+                    }
+                }
+            } else {
+                // Enabling the SPI starts the clock.
+                self.regs.cr1.modify(|_, w| w.spe().set_bit());
+
+                let last = buf.len() - 1;
+                let result = buf.iter_mut().enumerate().try_for_each(|(i, word)| {
+                    if i == last {
+                        // The last frame is in progress.
+                        self.regs.cr1.modify(|_, w| w.spe().clear_bit());
+                    }
+                    *word = nb::block!(self.read())?;
+                    Ok(())
+                });
+
+                // Make sure the SPI is disabled if we exited early, then discard any extra frames.
+                self.regs.cr1.modify(|_, w| w.spe().clear_bit());
+                while self.regs.sr.read().bsy().bit_is_set() {}
+
+                #[cfg(not(feature = "f4"))]
+                while self.regs.sr.read().frlvl().bits() != 0 {
+                    unsafe { ptr::read_volatile(&self.regs.dr as *const _ as *const u8) };
+                }
+                #[cfg(feature = "f4")]
+                if self.regs.sr.read().rxne().bit_is_set() {
+                    unsafe { ptr::read_volatile(&self.regs.dr as *const _ as *const u8) };
+                }
+            }
+        }
+
+        if half_duplex {
+            let restored = self.set_bidi_direction(BidiDirection::Transmit);
+            return result.and(restored);
+        }
+
+        result
+    }
+
+    /// Read a single word if available. The word type must match the configured data size;
+    /// see `SpiWord`.
+    pub fn read_word<W: SpiWord>(&mut self) -> nb::Result<W, Error> {
+        self.check_errors()?;
+
+        cfg_if! {
+            if #[cfg(feature = "h7")] {
+                let not_empty = self.regs.sr.read().rxp().bit_is_set();
+                let dr = &self.regs.rxdr as *const _ as *const W;
+            } else {
+                let not_empty = self.regs.sr.read().rxne().bit_is_set();
+                let dr = &self.regs.dr as *const _ as *const W;
+            }
+        }
+
+        if not_empty {
+            // The access width determines how many frames are popped from the RX FIFO, so we
+            // access the data register as the word type.
+            Ok(unsafe { ptr::read_volatile(dr) })
+        } else {
+            Err(nb::Error::WouldBlock)
+        }
+    }
+
+    /// Write a single word if there's space. The word type must match the configured data size;
+    /// see `SpiWord`.
+    pub fn write_word<W: SpiWord>(&mut self, word: W) -> nb::Result<(), Error> {
+        self.check_errors()?;
+
+        cfg_if! {
+            if #[cfg(feature = "h7")] {
+                let rdy = self.regs.sr.read().txp().bit_is_set();
+                let dr = &self.regs.txdr as *const _ as *mut W;
+            } else {
+                let rdy = self.regs.sr.read().txe().bit_is_set();
+                let dr = &self.regs.dr as *const _ as *mut W;
+            }
+        }
+
+        if rdy {
+            unsafe { ptr::write_volatile(dr, word) };
+            #[cfg(feature = "h7")]
+            self.regs.cr1.modify(|_, w| w.cstart().started());
+            Ok(())
+        } else {
+            Err(nb::Error::WouldBlock)
+        }
+    }
+
+    /// Write multiple words, blocking until complete. Eg, use `u16` words for 16-bit devices,
+    /// instead of splitting each word into bytes.
+    pub fn write_words<W: SpiWord>(&mut self, words: &[W]) -> Result<(), Error> {
+        self.set_word_size::<W>();
+
+        for word in words {
+            nb::block!(self.write_word(*word))?;
+            nb::block!(self.read_word::<W>())?;
+        }
+
+        Ok(())
+    }
+
+    /// Transfer multiple words, blocking until complete. Each word in `words` is replaced with
+    /// the one received while it was sent.
+    pub fn transfer_words<W: SpiWord>(&mut self, words: &mut [W]) -> Result<(), Error> {
+        self.set_word_size::<W>();
+
+        for word in words.iter_mut() {
+            nb::block!(self.write_word(*word))?;
+            *word = nb::block!(self.read_word())?;
+        }
+
+        Ok(())
+    }
+
+    /// Check that the word type matches the configured data size, and set the RX FIFO threshold
+    /// to match it.
+    fn set_word_size<W: SpiWord>(&mut self) {
+        let data_bits = self.cfg.data_size as u8 + 1;
+        assert!(
+            data_bits <= W::BITS && (W::BITS == 8 || data_bits > W::BITS / 2),
+            "The word type must be the smallest one that fits the SPI data size."
+        );
+
+        // RM: "The RXFIFO threshold must be aligned to the read access size for the SPIx_DR
+        // register." FRXTH = 1 generates RXNE at 8 bits, and 0, at 16 bits.
+        #[cfg(not(any(feature = "f4", feature = "h7")))]
+        self.regs.cr2.modify(|_, w| w.frxth().bit(W::BITS == 8));
+    }
+
+    #[cfg(not(feature = "h7"))]
+    /// Write multiple bytes, followed by the CRC of those bytes, blocking until complete. The
+    /// CRC calculation is then reset, for the next transfer. Requires `crc_polynomial` to be set,
+    /// and 8-bit data. See L44 RM, section 40.4.10: CRC calculation.
+    pub fn write_crc(&mut self, words: &[u8]) -> Result<(), Error> {
+        let mut buf = [0];
+        for (i, word) in words.iter().enumerate() {
+            buf[0] = *word;
+            self.transfer_crc_word(&mut buf, i == words.len() - 1)?;
+        }
+        Ok(())
+    }
+
+    #[cfg(not(feature = "h7"))]
+    /// Transfer multiple bytes, followed by their CRC, blocking until complete. The CRC received
+    /// from the slave is checked against the one calculated on the bytes received; if they don't
+    /// match, returns `Error::Crc`. The CRC calculation is then reset, for the next transfer.
+    /// Requires `crc_polynomial` to be set, and 8-bit data.
+    pub fn transfer_crc(&mut self, words: &mut [u8]) -> Result<(), Error> {
+        let len = words.len();
+        for (i, word) in words.iter_mut().enumerate() {
+            self.transfer_crc_word(core::slice::from_mut(word), i == len - 1)?;
+        }
+        Ok(())
+    }
+
+    #[cfg(not(feature = "h7"))]
+    /// Transfer one word, as part of a CRC transfer. After the last word, send the CRC, receive
+    /// and check the slave's, and reset the CRC calculation. The CRC is also reset on error.
+    fn transfer_crc_word(&mut self, word: &mut [u8], last: bool) -> Result<(), Error> {
+        let result = (|| {
+            nb::block!(self.write_one(word[0]))?;
+
+            if !last {
+                word[0] = nb::block!(self.read())?;
+                return Ok(());
+            }
+
+            // "CRCNEXT bit should be set after the last data is written to the TX buffer", ie
+            // while it's being transmitted.
+            self.regs.cr1.modify(|_, w| w.crcnext().set_bit());
+            word[0] = nb::block!(self.read())?;
+
+            // The received CRC arrives in the RX buffer, like data. The hardware compares it to
+            // its own calculation, and sets CRCERR if they don't match. Wait for BSY directly,
+            // so a CRC error is reported below instead of by `wait_for`.
+            nb::block!(self.read())?;
+            while self.regs.sr.read().bsy().bit_is_set() {}
+
+            self.check_errors()
+        })();
+
+        if last || result.is_err() {
+            self.reset_crc();
+        }
+        result
+    }
+
+    #[cfg(not(feature = "h7"))]
+    /// Reset the CRC calculation, eg after an aborted transfer. The `write_crc` and
+    /// `transfer_crc` methods do this automatically.
+    pub fn reset_crc(&mut self) {
+        // RM: "The CRC values are reset when the CRCEN bit is toggled, which can only be done
+        // while the SPI is disabled."
+        self.regs.cr1.modify(|_, w| w.spe().clear_bit());
+        self.regs.cr1.modify(|_, w| w.crcen().clear_bit());
+        self.regs.cr1.modify(|_, w| w.crcen().set_bit());
+        self.regs.cr1.modify(|_, w| w.spe().set_bit());
+    }
+
+    #[cfg(not(feature = "h7"))]
+    /// Read the CRCs calculated on the transmitted and received data, in that order.
+    /// (SPI_TXCRCR and SPI_RXCRCR)
+    pub fn read_crc(&self) -> (u16, u16) {
+        (
+            self.regs.txcrcr.read().bits() as u16,
+            self.regs.rxcrcr.read().bits() as u16,
+        )
+    }
+
+    /// Run a loopback self-test, eg from production test firmware. The SPI peripheral doesn't
+    /// have an internal loopback mode, so this requires MOSI to be jumpered to MISO. Transfers a
+    /// fixed test pattern in master, full-duplex mode, and checks that each word is received
+    /// as sent.
+    pub fn self_test(&mut self) -> Result<(), SelfTestError> {
+        let mut buf = SELF_TEST_PATTERN;
+        self.transfer(&mut buf).map_err(SelfTestError::Spi)?;
+
+        for (i, (sent, received)) in SELF_TEST_PATTERN.iter().zip(buf.iter()).enumerate() {
+            if sent != received {
+                return Err(SelfTestError::Mismatch {
+                    index: i,
+                    sent: *sent,
+                    received: *received,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    #[cfg(not(any(feature = "g0", feature = "f4")))]
     /// Transmit data using DMA. See L44 RM, section 40.4.9: Communication using DMA.
     /// Note that the `channel` argument has no effect on F3 and L4.
+    /// On WB and WL, the DMAMUX is configured to route this peripheral's request to it.
     pub unsafe fn write_dma<D>(
         &mut self,
         buf: &[u8],
@@ -579,6 +1302,7 @@ where
         dma: &mut Dma<D>,
     ) where
         D: Deref<Target = dma_p::RegisterBlock>,
+        R: DmaPeriph,
     {
         // Static write and read buffers?
         let (ptr, len) = (buf.as_ptr(), buf.len());
@@ -603,6 +1327,8 @@ where
         let channel = R::write_chan();
         #[cfg(feature = "l4")]
         R::write_sel(dma);
+        #[cfg(any(feature = "wb", feature = "wl"))]
+        dma::mux_dma(dma, channel, R::write_input());
 
         #[cfg(feature = "h7")]
         let periph_addr = &self.regs.txdr as *const _ as u32;
@@ -636,11 +1362,16 @@ where
         // 4. Enable the SPI by setting the SPE bit.
         self.regs.cr1.modify(|_, w| w.spe().set_bit());
         // (todo: Should be already set. Should we disable it at the top of this fn just in case?)
+
+        // In master mode, H7 starts the transfer on CSTART.
+        #[cfg(feature = "h7")]
+        self.regs.cr1.modify(|_, w| w.cstart().started());
     }
 
-    #[cfg(not(any(feature = "g0", feature = "f4", feature = "l5")))]
+    #[cfg(not(any(feature = "g0", feature = "f4")))]
     /// Receive data using DMA. See L44 RM, section 40.4.9: Communication using DMA.
-    /// Note thay the `channel` argument has no effect on F3 and L4.
+    /// Note that the `channel` argument has no effect on F3 and L4.
+    /// On WB and WL, the DMAMUX is configured to route this peripheral's request to it.
     pub unsafe fn read_dma<D>(
         &mut self,
         buf: &mut [u8],
@@ -649,6 +1380,7 @@ where
         dma: &mut Dma<D>,
     ) where
         D: Deref<Target = dma_p::RegisterBlock>,
+        R: DmaPeriph,
     {
         // todo: Accept u16 words too.
         let (ptr, len) = (buf.as_mut_ptr(), buf.len());
@@ -664,6 +1396,8 @@ where
         let channel = R::read_chan();
         #[cfg(feature = "l4")]
         R::read_sel(dma);
+        #[cfg(any(feature = "wb", feature = "wl"))]
+        dma::mux_dma(dma, channel, R::read_input());
 
         #[cfg(feature = "h7")]
         let periph_addr = &self.regs.rxdr as *const _ as u32;
@@ -688,12 +1422,110 @@ where
 
         self.regs.cr1.modify(|_, w| w.spe().set_bit());
 
-        // todo: Set rxne or something to start?
+        // In master mode, H7 starts the transfer on CSTART.
+        #[cfg(feature = "h7")]
+        self.regs.cr1.modify(|_, w| w.cstart().started());
     }
 
-    // todo: pub fn transfer_dma()?
+    #[cfg(not(any(feature = "g0", feature = "f4")))]
+    /// Transfer data in full duplex using DMA: Send `write_buf`, and receive into `read_buf`,
+    /// which must be the same length. Completion is signaled by the RX channel, since the last
+    /// word is received after the last one is sent: Enable its transfer complete interrupt, or
+    /// poll `dma.transfer_is_complete(rx_channel)`. Then, call `dma.stop(tx_channel)`, and
+    /// `stop_dma(rx_channel, dma)`, which also disables the SPI's DMA requests. `channel_cfg`
+    /// applies to both channels. See L44 RM, section 40.4.9: Communication using DMA.
+    /// Note that the channel arguments have no effect on F3 and L4.
+    /// On WB and WL, the DMAMUX is configured to route this peripheral's requests to them.
+    pub unsafe fn transfer_dma<D>(
+        &mut self,
+        write_buf: &[u8],
+        read_buf: &mut [u8],
+        tx_channel: DmaChannel,
+        rx_channel: DmaChannel,
+        channel_cfg: ChannelCfg,
+        dma: &mut Dma<D>,
+    ) where
+        D: Deref<Target = dma_p::RegisterBlock>,
+        R: DmaPeriph,
+    {
+        assert_eq!(write_buf.len(), read_buf.len());
+        // The DMA transfer count (NDTR) is 16 bits.
+        assert!(
+            !write_buf.is_empty() && write_buf.len() <= u16::MAX as usize,
+            "DMA transfers must be 1 - 65,535 words long."
+        );
+
+        #[cfg(any(feature = "f3", feature = "l4"))]
+        let (tx_channel, rx_channel) = (R::write_chan(), R::read_chan());
+        #[cfg(feature = "l4")]
+        {
+            R::write_sel(dma);
+            R::read_sel(dma);
+        }
+        #[cfg(any(feature = "wb", feature = "wl"))]
+        {
+            dma::mux_dma(dma, tx_channel, R::write_input());
+            dma::mux_dma(dma, rx_channel, R::read_input());
+        }
+
+        cfg_if! {
+            if #[cfg(feature = "h7")] {
+                let (tx_addr, rx_addr) = (&self.regs.txdr as *const _ as u32, &self.regs.rxdr as *const _ as u32);
+                let num_data = write_buf.len() as u32;
+            } else {
+                let (tx_addr, rx_addr) = (&self.regs.dr as *const _ as u32, &self.regs.dr as *const _ as u32);
+                let num_data = write_buf.len() as u16;
+            }
+        }
+
+        // When starting communication using DMA, to prevent DMA channel management raising
+        // error events, these steps must be followed in order:
+        //
+        // 1. Enable DMA Rx buffer in the RXDMAEN bit in the SPI_CR2 register, if DMA Rx is
+        // used.
+        #[cfg(not(feature = "h7"))]
+        self.regs.cr2.modify(|_, w| w.rxdmaen().set_bit());
+        #[cfg(feature = "h7")]
+        self.regs.cfg1.modify(|_, w| w.rxdmaen().set_bit());
+
+        // 2. Enable DMA streams for Tx and Rx in DMA registers, if the streams are used.
+        dma.cfg_channel(
+            rx_channel,
+            rx_addr,
+            read_buf.as_mut_ptr() as u32,
+            num_data,
+            dma::Direction::ReadFromPeriph,
+            dma::DataSize::S8,
+            dma::DataSize::S8,
+            channel_cfg,
+        );
+
+        dma.cfg_channel(
+            tx_channel,
+            tx_addr,
+            write_buf.as_ptr() as u32,
+            num_data,
+            dma::Direction::ReadFromMem,
+            dma::DataSize::S8,
+            dma::DataSize::S8,
+            channel_cfg,
+        );
+
+        // 3. Enable DMA Tx buffer in the TXDMAEN bit in the SPI_CR2 register, if DMA Tx is used.
+        #[cfg(not(feature = "h7"))]
+        self.regs.cr2.modify(|_, w| w.txdmaen().set_bit());
+        #[cfg(feature = "h7")]
+        self.regs.cfg1.modify(|_, w| w.txdmaen().set_bit());
+
+        // 4. Enable the SPI by setting the SPE bit.
+        self.regs.cr1.modify(|_, w| w.spe().set_bit());
+
+        // In master mode, H7 starts the transfer on CSTART.
+        #[cfg(feature = "h7")]
+        self.regs.cr1.modify(|_, w| w.cstart().started());
+    }
 
-    #[cfg(not(any(feature = "g0", feature = "h7", feature = "f4", feature = "l5")))]
+    #[cfg(not(any(feature = "g0", feature = "h7", feature = "f4")))]
     /// Stop a DMA transfer. Stops the channel, and disables the `txdmaen` and `rxdmaen` bits.
     /// Run this after each transfer completes - you may wish to do this in an interrupt
     /// (eg DMA transfer complete) instead of blocking.
@@ -714,6 +1546,113 @@ where
         })
     }
 
+    #[cfg(feature = "h7")]
+    /// Stop a DMA transfer. Stops the channel, and disables the `txdmaen` and `rxdmaen` bits.
+    /// Run this after each transfer completes - you may wish to do this in an interrupt
+    /// (eg DMA transfer complete) instead of blocking.
+    pub fn stop_dma<D>(&mut self, channel: DmaChannel, dma: &mut Dma<D>)
+    where
+        D: Deref<Target = dma_p::RegisterBlock>,
+    {
+        dma.stop(channel);
+        self.regs.cfg1.modify(|_, w| {
+            w.txdmaen().clear_bit();
+            w.rxdmaen().clear_bit()
+        })
+    }
+
+    /// Read the current configuration from the SPI's registers. Unlike the `SpiConfig` passed
+    /// to the constructor, this reflects any changes made since, including by direct register
+    /// writes.
+    pub fn dump(&self) -> SpiDump {
+        let polarity = |idle_high: bool| {
+            if idle_high {
+                SpiPolarity::IdleHigh
+            } else {
+                SpiPolarity::IdleLow
+            }
+        };
+        let phase = |second: bool| {
+            if second {
+                SpiPhase::CaptureOnSecondTransition
+            } else {
+                SpiPhase::CaptureOnFirstTransition
+            }
+        };
+
+        let frame_format = |ti: bool| {
+            if ti {
+                FrameFormat::Ti
+            } else {
+                FrameFormat::Motorola
+            }
+        };
+
+        cfg_if! {
+            if #[cfg(feature = "h7")] {
+                let cfg1 = self.regs.cfg1.read();
+                let cfg2 = self.regs.cfg2.read();
+
+                let comm_mode = match cfg2.comm().bits() {
+                    0b00 => SpiCommMode::FullDuplex,
+                    0b01 => SpiCommMode::TransmitOnly,
+                    0b10 => SpiCommMode::ReceiveOnly,
+                    _ => SpiCommMode::HalfDuplex,
+                };
+
+                SpiDump {
+                    enabled: self.regs.cr1.read().spe().bit_is_set(),
+                    master: cfg2.master().bit_is_set(),
+                    baud_div: 2 << cfg1.mbr().bits(),
+                    mode: SpiMode {
+                        polarity: polarity(cfg2.cpol().bit_is_set()),
+                        phase: phase(cfg2.cpha().bit_is_set()),
+                    },
+                    comm_mode,
+                    data_bits: cfg1.dsize().bits() + 1,
+                    lsb_first: cfg2.lsbfrst().bit_is_set(),
+                    software_ss: cfg2.ssm().bit_is_set(),
+                    frame_format: frame_format(cfg2.sp().bits() != 0),
+                    tx_dma: cfg1.txdmaen().bit_is_set(),
+                    rx_dma: cfg1.rxdmaen().bit_is_set(),
+                }
+            } else {
+                let cr1 = self.regs.cr1.read();
+                let cr2 = self.regs.cr2.read();
+
+                let comm_mode = if cr1.bidimode().bit_is_set() {
+                    SpiCommMode::HalfDuplex
+                } else if cr1.rxonly().bit_is_set() {
+                    SpiCommMode::ReceiveOnly
+                } else {
+                    SpiCommMode::FullDuplex
+                };
+
+                #[cfg(feature = "f4")]
+                let data_bits = if cr1.dff().bit_is_set() { 16 } else { 8 };
+                #[cfg(not(feature = "f4"))]
+                let data_bits = cr2.ds().bits() + 1;
+
+                SpiDump {
+                    enabled: cr1.spe().bit_is_set(),
+                    master: cr1.mstr().bit_is_set(),
+                    baud_div: 2 << cr1.br().bits(),
+                    mode: SpiMode {
+                        polarity: polarity(cr1.cpol().bit_is_set()),
+                        phase: phase(cr1.cpha().bit_is_set()),
+                    },
+                    comm_mode,
+                    data_bits,
+                    lsb_first: cr1.lsbfirst().bit_is_set(),
+                    software_ss: cr1.ssm().bit_is_set(),
+                    frame_format: frame_format(cr2.frf().bit_is_set()),
+                    tx_dma: cr2.txdmaen().bit_is_set(),
+                    rx_dma: cr2.rxdmaen().bit_is_set(),
+                }
+            }
+        }
+    }
+
     #[cfg(not(feature = "h7"))]
     /// Enable an interrupt. Note that unlike on other peripherals, there's no explicit way to
     /// clear these. RM: "Writing to the transmit data register always clears the TXE bit.
@@ -725,6 +1664,44 @@ where
             SpiInterrupt::Error => w.errie().set_bit(),
         });
     }
+
+    #[cfg(feature = "h7")]
+    /// Enable an interrupt. See H743 RM, section 50.11: SPI interrupts.
+    pub fn enable_interrupt(&mut self, interrupt_type: SpiInterrupt) {
+        self.regs.ier.modify(|_, w| match interrupt_type {
+            SpiInterrupt::TxBufEmpty => w.txpie().set_bit(),
+            SpiInterrupt::RxBufNotEmpty => w.rxpie().set_bit(),
+            SpiInterrupt::Error => {
+                w.udrie().set_bit();
+                w.ovrie().set_bit();
+                w.crceie().set_bit();
+                w.tifreie().set_bit();
+                w.modfie().set_bit()
+            }
+            SpiInterrupt::EndOfTransfer => w.eotie().set_bit(),
+        });
+    }
+
+    #[cfg(feature = "h7")]
+    /// Clear an interrupt flag. TXP and RXP can't be cleared this way: They reflect the FIFO
+    /// levels. For errors, this clears all error flags; to find which occurred, use
+    /// `check_errors` instead.
+    pub fn clear_interrupt(&mut self, interrupt_type: SpiInterrupt) {
+        self.regs.ifcr.write(|w| match interrupt_type {
+            SpiInterrupt::EndOfTransfer => {
+                w.eotc().set_bit();
+                w.txtfc().set_bit()
+            }
+            SpiInterrupt::Error => {
+                w.udrc().set_bit();
+                w.ovrc().set_bit();
+                w.crcec().set_bit();
+                w.tifrec().set_bit();
+                w.modfc().set_bit()
+            }
+            _ => w,
+        });
+    }
 }
 
 #[cfg(feature = "embedded-hal")]
@@ -757,3 +1734,147 @@ impl<R> embedded_hal::blocking::spi::write::Default<u8> for Spi<R> where
     R: Deref<Target = pac::spi1::RegisterBlock> + DmaPeriph + RccPeriph
 {
 }
+
+#[cfg(feature = "embedded-hal-1")]
+impl embedded_hal_1::spi::Error for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Self::Overrun => ErrorKind::Overrun,
+            Self::ModeFault => ErrorKind::ModeFault,
+            Self::FrameFormat => ErrorKind::FrameFormat,
+            _ => ErrorKind::Other,
+        }
+    }
+}
+
+#[cfg(feature = "embedded-hal-1")]
+impl<R> ErrorType for Spi<R>
+where
+    R: Deref<Target = pac::spi1::RegisterBlock> + RccPeriph,
+{
+    type Error = Error;
+}
+
+#[cfg(feature = "embedded-hal-1")]
+impl<R> SpiBus<u8> for Spi<R>
+where
+    R: Deref<Target = pac::spi1::RegisterBlock> + RccPeriph,
+{
+    /// Sends 0s while reading.
+    fn read(&mut self, words: &mut [u8]) -> Result<(), Error> {
+        for word in words.iter_mut() {
+            nb::block!(self.write_one(0))?;
+            *word = nb::block!(Spi::read(self))?;
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, words: &[u8]) -> Result<(), Error> {
+        Spi::write(self, words)
+    }
+
+    /// If `write` is shorter than `read`, sends 0s for the remaining words. If `read` is shorter
+    /// than `write`, the remaining received words are discarded.
+    fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), Error> {
+        for i in 0..read.len().max(write.len()) {
+            nb::block!(self.write_one(write.get(i).copied().unwrap_or(0)))?;
+            let word = nb::block!(Spi::read(self))?;
+            if let Some(r) = read.get_mut(i) {
+                *r = word;
+            }
+        }
+        Ok(())
+    }
+
+    fn transfer_in_place(&mut self, words: &mut [u8]) -> Result<(), Error> {
+        Spi::transfer(self, words)
+    }
+
+    /// Each of the methods above reads every word it sends, so they return after the last word
+    /// is received. This waits for the bus to be idle regardless.
+    fn flush(&mut self) -> Result<(), Error> {
+        #[cfg(not(feature = "h7"))]
+        self.wait_for(|regs| regs.sr.read().bsy().bit_is_clear())?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "embedded-hal-1")]
+/// An SPI device with exclusive use of the bus, using a GPIO pin as CS. Implements the
+/// embedded-hal 1.0 `SpiDevice` trait, which most driver crates take. For buses shared between
+/// several devices, use a crate like `embedded-hal-bus` with `Spi` directly.
+///
+/// Example:
+/// ```
+/// let spi = Spi::new(dp.SPI1, SpiConfig::default(), BaudRate::Div32);
+/// let cs = Pin::new(Port::A, 4, PinMode::Output);
+/// let device = ExclusiveDevice::new(spi, cs, &clock_cfg);
+/// let mut display = SomeDisplayDriver::new(device);
+/// ```
+pub struct ExclusiveDevice<R> {
+    pub spi: Spi<R>,
+    pub cs: Pin,
+    /// Used for `Operation::DelayNs`.
+    sysclk: u32,
+}
+
+#[cfg(feature = "embedded-hal-1")]
+impl<R> ExclusiveDevice<R>
+where
+    R: Deref<Target = pac::spi1::RegisterBlock> + RccPeriph,
+{
+    /// Create the device, and set CS high (inactive). `cs` must be configured as an output.
+    pub fn new(spi: Spi<R>, mut cs: Pin, clocks: &Clocks) -> Self {
+        cs.set_high();
+
+        Self {
+            spi,
+            cs,
+            sysclk: clocks.sysclk(),
+        }
+    }
+
+    /// Release the SPI peripheral and CS pin.
+    pub fn free(self) -> (Spi<R>, Pin) {
+        (self.spi, self.cs)
+    }
+}
+
+#[cfg(feature = "embedded-hal-1")]
+impl<R> ErrorType for ExclusiveDevice<R>
+where
+    R: Deref<Target = pac::spi1::RegisterBlock> + RccPeriph,
+{
+    type Error = Error;
+}
+
+#[cfg(feature = "embedded-hal-1")]
+impl<R> embedded_hal_1::spi::SpiDevice<u8> for ExclusiveDevice<R>
+where
+    R: Deref<Target = pac::spi1::RegisterBlock> + RccPeriph,
+{
+    /// Runs the operations with CS low. CS is set high afterwards, even if an operation fails;
+    /// the first error is returned.
+    fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Error> {
+        self.cs.set_low();
+
+        let result = operations.iter_mut().try_for_each(|op| match op {
+            Operation::Read(words) => SpiBus::read(&mut self.spi, words),
+            Operation::Write(words) => SpiBus::write(&mut self.spi, words),
+            Operation::Transfer(read, write) => SpiBus::transfer(&mut self.spi, read, write),
+            Operation::TransferInPlace(words) => SpiBus::transfer_in_place(&mut self.spi, words),
+            Operation::DelayNs(ns) => {
+                self.spi.flush()?;
+                // The CPU clock is at most SYSCLK, so this delays for at least `ns`.
+                let cycles = *ns as u64 * self.sysclk as u64 / 1_000_000_000 + 1;
+                cortex_m::asm::delay(cycles.min(u32::MAX as u64) as u32);
+                Ok(())
+            }
+        });
+
+        let flushed = self.spi.flush();
+        self.cs.set_high();
+
+        result.and(flushed)
+    }
+}
@@ -0,0 +1,285 @@
+//! Board abstraction for the WL's sub-GHz radio. The radio itself is identical across boards,
+//! but its RF front end isn't: Boards differ in how the RF switch is controlled, whether a TCXO
+//! is fitted, and which of the low-power and high-power PA outputs are connected. A radio driver
+//! takes a `RadioBoard` implementation, so the same driver runs on the Nucleo-WL55 and custom
+//! boards by implementing one small trait.
+//!
+//! This module doesn't include a radio driver; it defines the values a driver passes to the
+//! radio's `SetTcxoMode`, `SetPaConfig`, and `SetTxParams` commands. See RM0453, section 5.8:
+//! "Sub-GHz radio commands".
+//!
+//! Example, for a custom board with only the high-power PA connected, and a 2-pin RF switch:
+//! ```
+//! struct MyBoard {
+//!     tx_en: Pin,
+//!     rx_en: Pin,
+//! }
+//!
+//! impl RadioBoard for MyBoard {
+//!     fn set_rf_mode(&mut self, mode: RfMode) {
+//!         match mode {
+//!             RfMode::Rx => { self.tx_en.set_low(); self.rx_en.set_high() }
+//!             RfMode::TxHighPower => { self.rx_en.set_low(); self.tx_en.set_high() }
+//!             _ => { self.tx_en.set_low(); self.rx_en.set_low() }
+//!         }
+//!     }
+//!
+//!     fn tx_paths(&self) -> TxPaths {
+//!         TxPaths::HighPower
+//!     }
+//! }
+//! ```
+
+use crate::gpio::{Pin, PinMode, Port};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// The RF switch position, set by the driver before each radio operation.
+pub enum RfMode {
+    /// Disconnect the antenna, eg in sleep.
+    Off,
+    Rx,
+    /// Transmit using the low-power PA (RFO_LP), for up to +15dBm.
+    TxLowPower,
+    /// Transmit using the high-power PA (RFO_HP), for up to +22dBm.
+    TxHighPower,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// The PA outputs connected to the antenna on a board.
+pub enum TxPaths {
+    LowPower,
+    HighPower,
+    Both,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(u8)]
+/// The voltage the radio supplies to the TCXO, on PB0-VDDTCXO. (`SetTcxoMode`, TcxoVoltage)
+pub enum TcxoVoltage {
+    V1_6 = 0,
+    V1_7 = 1,
+    V1_8 = 2,
+    V2_2 = 3,
+    V2_4 = 4,
+    V2_7 = 5,
+    V3_0 = 6,
+    V3_3 = 7,
+}
+
+#[derive(Clone, Copy, Debug)]
+/// TCXO configuration, for boards that use a TCXO as the radio's reference, instead of a crystal.
+pub struct TcxoConfig {
+    pub voltage: TcxoVoltage,
+    /// The time the TCXO takes to stabilize after power is applied, in µs, from its datasheet.
+    /// The radio waits this long before starting the HSE32 oscillator.
+    pub startup_us: u32,
+}
+
+impl TcxoConfig {
+    /// The startup time, in the radio's units of 15.625µs, rounded up, for `SetTcxoMode`. This is
+    /// a 24-bit value.
+    pub fn timeout(&self) -> u32 {
+        ((self.startup_us as u64 * 64 + 999) / 1_000).min(0xff_ffff) as u32
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(u8)]
+/// Selects the PA. (`SetPaConfig`, PaSel)
+pub enum PaSel {
+    HighPower = 0,
+    LowPower = 1,
+}
+
+#[derive(Clone, Copy, Debug)]
+/// PA configuration, for `SetPaConfig` and `SetTxParams`. The presets are the optimal settings
+/// for each output power, from RM0453, table 35: "Optimal PA settings"; other combinations
+/// reduce efficiency, or exceed the PA's ratings.
+pub struct PaConfig {
+    /// (`SetPaConfig`, PaDutyCycle)
+    pub duty_cycle: u8,
+    /// (`SetPaConfig`, HpMax)
+    pub hp_max: u8,
+    pub pa_sel: PaSel,
+    /// The power to request, in dBm. (`SetTxParams`, Power) Note that with the presets, the
+    /// output power differs from this; see `output_dbm`.
+    pub power: i8,
+    /// The resulting output power, in dBm, with a matched board.
+    pub output_dbm: i8,
+}
+
+impl PaConfig {
+    /// Low-power PA, +15dBm.
+    pub const LP_15DBM: Self = Self::new(0x07, 0x00, PaSel::LowPower, 14, 15);
+    /// Low-power PA, +14dBm.
+    pub const LP_14DBM: Self = Self::new(0x04, 0x00, PaSel::LowPower, 14, 14);
+    /// Low-power PA, +10dBm.
+    pub const LP_10DBM: Self = Self::new(0x01, 0x00, PaSel::LowPower, 13, 10);
+    /// High-power PA, +22dBm.
+    pub const HP_22DBM: Self = Self::new(0x04, 0x07, PaSel::HighPower, 22, 22);
+    /// High-power PA, +20dBm.
+    pub const HP_20DBM: Self = Self::new(0x03, 0x05, PaSel::HighPower, 22, 20);
+    /// High-power PA, +17dBm.
+    pub const HP_17DBM: Self = Self::new(0x02, 0x03, PaSel::HighPower, 22, 17);
+    /// High-power PA, +14dBm.
+    pub const HP_14DBM: Self = Self::new(0x02, 0x02, PaSel::HighPower, 22, 14);
+
+    const fn new(duty_cycle: u8, hp_max: u8, pa_sel: PaSel, power: i8, output_dbm: i8) -> Self {
+        Self {
+            duty_cycle,
+            hp_max,
+            pa_sel,
+            power,
+            output_dbm,
+        }
+    }
+
+    /// The preset with the highest output power not exceeding `dbm`, using a PA available on
+    /// the board. Prefers the low-power PA where it can reach the power, since it's more
+    /// efficient. Returns `None` if `dbm` is below the lowest preset available.
+    pub fn for_power(dbm: i8, paths: TxPaths) -> Option<Self> {
+        const LP: [PaConfig; 3] = [PaConfig::LP_15DBM, PaConfig::LP_14DBM, PaConfig::LP_10DBM];
+        const HP: [PaConfig; 4] = [
+            PaConfig::HP_22DBM,
+            PaConfig::HP_20DBM,
+            PaConfig::HP_17DBM,
+            PaConfig::HP_14DBM,
+        ];
+
+        let lp = LP.iter().find(|p| p.output_dbm <= dbm);
+        let hp = HP.iter().find(|p| p.output_dbm <= dbm);
+
+        match paths {
+            TxPaths::LowPower => lp,
+            TxPaths::HighPower => hp,
+            TxPaths::Both => match (lp, hp) {
+                (Some(l), Some(h)) if h.output_dbm > l.output_dbm => Some(h),
+                (Some(l), _) => Some(l),
+                (None, h) => h,
+            },
+        }
+        .copied()
+    }
+
+    /// The RF switch mode to transmit with this configuration.
+    pub fn rf_mode(&self) -> RfMode {
+        match self.pa_sel {
+            PaSel::LowPower => RfMode::TxLowPower,
+            PaSel::HighPower => RfMode::TxHighPower,
+        }
+    }
+}
+
+/// Board-specific radio front end control. Implement this for your board, and pass it to the
+/// radio driver.
+pub trait RadioBoard {
+    /// Set the RF switch. Called before each transmission and reception, and when the radio
+    /// sleeps.
+    fn set_rf_mode(&mut self, mode: RfMode);
+
+    /// The TCXO configuration, or `None` if the radio uses a crystal.
+    fn tcxo(&self) -> Option<TcxoConfig> {
+        None
+    }
+
+    /// The PA outputs connected to the antenna.
+    fn tx_paths(&self) -> TxPaths {
+        TxPaths::Both
+    }
+
+    /// The time the RF switch takes to settle after `set_rf_mode`, in µs. The driver waits
+    /// this long before starting a transmission or reception.
+    fn rf_switch_settle_us(&self) -> u32 {
+        0
+    }
+}
+
+/// The Nucleo-WL55JC board. Its RF switch is controlled by PC4 (FE_CTRL1), PC5 (FE_CTRL2),
+/// and PC3 (FE_CTRL3), both PAs are connected, and its TCXO runs at 1.7V. See UM2592:
+/// "STM32WL Nucleo-64 board", section 7.6: "RF".
+pub struct NucleoWl55 {
+    ctrl1: Pin,
+    ctrl2: Pin,
+    ctrl3: Pin,
+}
+
+impl NucleoWl55 {
+    /// Configure the RF switch control pins as outputs, with the switch off.
+    pub fn new() -> Self {
+        let mut result = Self {
+            ctrl1: Pin::new(Port::C, 4, PinMode::Output),
+            ctrl2: Pin::new(Port::C, 5, PinMode::Output),
+            ctrl3: Pin::new(Port::C, 3, PinMode::Output),
+        };
+
+        result.set_rf_mode(RfMode::Off);
+        result
+    }
+}
+
+impl RadioBoard for NucleoWl55 {
+    fn set_rf_mode(&mut self, mode: RfMode) {
+        // (FE_CTRL1, FE_CTRL2, FE_CTRL3)
+        let states = match mode {
+            RfMode::Off => (false, false, false),
+            RfMode::Rx => (true, false, true),
+            RfMode::TxLowPower => (true, true, true),
+            RfMode::TxHighPower => (false, true, true),
+        };
+
+        for (pin, high) in [
+            (&mut self.ctrl1, states.0),
+            (&mut self.ctrl2, states.1),
+            (&mut self.ctrl3, states.2),
+        ] {
+            if high {
+                pin.set_high();
+            } else {
+                pin.set_low();
+            }
+        }
+    }
+
+    fn tcxo(&self) -> Option<TcxoConfig> {
+        Some(TcxoConfig {
+            voltage: TcxoVoltage::V1_7,
+            startup_us: 5_000,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tcxo_timeout() {
+        let timeout = |startup_us| {
+            TcxoConfig {
+                voltage: TcxoVoltage::V1_7,
+                startup_us,
+            }
+            .timeout()
+        };
+
+        assert_eq!(timeout(5_000), 320);
+        // Rounded up.
+        assert_eq!(timeout(1), 1);
+        // Saturates at 24 bits.
+        assert_eq!(timeout(u32::MAX), 0xff_ffff);
+    }
+
+    #[test]
+    fn pa_for_power() {
+        let out = |dbm, paths| PaConfig::for_power(dbm, paths).map(|p| (p.pa_sel, p.output_dbm));
+
+        assert_eq!(out(22, TxPaths::Both), Some((PaSel::HighPower, 22)));
+        assert_eq!(out(16, TxPaths::Both), Some((PaSel::LowPower, 15)));
+        // The low-power PA is preferred at equal power.
+        assert_eq!(out(14, TxPaths::Both), Some((PaSel::LowPower, 14)));
+        assert_eq!(out(16, TxPaths::HighPower), Some((PaSel::HighPower, 14)));
+        assert_eq!(out(30, TxPaths::LowPower), Some((PaSel::LowPower, 15)));
+        assert_eq!(out(12, TxPaths::HighPower), None);
+        assert_eq!(out(9, TxPaths::Both), None);
+    }
+}
@@ -0,0 +1,130 @@
+//! An application-level watchdog supervisor. Each logical task (eg the main loop, a control loop
+//! ISR, and a comms handler) checks in with its own deadline, and the independent watchdog (IWDG)
+//! is only refreshed when every task is alive. This catches partial lock-ups, eg a stalled ISR
+//! or a task stuck waiting on a peripheral, that feeding the watchdog from the main loop alone
+//! would miss.
+//!
+//! Times are in ticks of any monotonic `u32` time source you choose, eg milliseconds from SysTick,
+//! or a timer's `read_count`; wrap-around is handled.
+//!
+//! Example, with 3 tasks, and times in ms:
+//! ```
+//! const MAIN_LOOP: usize = 0;
+//! const CONTROL: usize = 1;
+//! const COMMS: usize = 2;
+//!
+//! static SUPERVISOR: Mutex<RefCell<Option<Supervisor<3>>>> = Mutex::new(RefCell::new(None));
+//!
+//! // In `main`:
+//! supervisor::start_watchdog(0.5);
+//!
+//! free(|cs| {
+//!     SUPERVISOR.borrow(cs).replace(Some(Supervisor::new([50, 5, 1_000], now_ms())));
+//! });
+//!
+//! // In each task:
+//! free(|cs| access_global!(SUPERVISOR, s, cs).check_in(CONTROL, now_ms()));
+//!
+//! // Periodically, eg in the main loop:
+//! free(|cs| {
+//!     if let Err(task) = access_global!(SUPERVISOR, s, cs).feed(now_ms()) {
+//!         // `task` missed its deadline; the watchdog will reset the MCU.
+//!     }
+//! });
+//! ```
+
+use crate::reset;
+
+/// LSI frequency, which clocks the IWDG. This is nominally 40kHz on F3, and 32kHz on the other
+/// supported families, but varies substantially with temperature and between parts; leave margin
+/// in timeouts.
+const LSI_FREQ: f32 = if cfg!(feature = "f3") { 40_000. } else { 32_000. };
+
+/// A task's check-in state.
+#[derive(Clone, Copy)]
+struct Task {
+    /// The maximum time allowed between check-ins, in ticks.
+    deadline: u32,
+    /// The time of the last check-in, in ticks.
+    last_check_in: u32,
+}
+
+/// Supervises `N` tasks, each identified by its index.
+pub struct Supervisor<const N: usize> {
+    tasks: [Task; N],
+}
+
+impl<const N: usize> Supervisor<N> {
+    /// Create a supervisor, with each task's maximum time between check-ins, in ticks. All tasks
+    /// are treated as having checked in at `now`, so each has one full deadline to start.
+    pub fn new(deadlines: [u32; N], now: u32) -> Self {
+        let mut tasks = [Task {
+            deadline: 0,
+            last_check_in: now,
+        }; N];
+
+        for (task, deadline) in tasks.iter_mut().zip(deadlines) {
+            task.deadline = deadline;
+        }
+
+        Self { tasks }
+    }
+
+    /// Record that a task is alive. Panics if `task` is out of range.
+    pub fn check_in(&mut self, task: usize, now: u32) {
+        self.tasks[task].last_check_in = now;
+    }
+
+    /// Returns the index of the first task that has missed its deadline, if any.
+    pub fn overdue_task(&self, now: u32) -> Option<usize> {
+        self.tasks
+            .iter()
+            .position(|t| now.wrapping_sub(t.last_check_in) > t.deadline)
+    }
+
+    /// Refresh the IWDG if all tasks are alive. Otherwise, don't refresh it, and return the index
+    /// of the first overdue task, eg to log before the watchdog resets the MCU. Call this more
+    /// often than the watchdog's timeout.
+    pub fn feed(&mut self, now: u32) -> Result<(), usize> {
+        match self.overdue_task(now) {
+            Some(task) => Err(task),
+            None => {
+                reset::refresh_iwdg();
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Start the independent watchdog, with a timeout in seconds, up to about 32s. Once started, it
+/// can't be stopped, except by a reset. See L4 RM, section 32.3.2: "Window option", and 32.3.6:
+/// "Register access protection".
+pub fn start_watchdog(timeout: f32) {
+    #[cfg(any(feature = "h747cm4", feature = "h747cm7"))]
+    let iwdg = unsafe { &(*crate::pac::IWDG1::ptr()) };
+    #[cfg(not(any(feature = "h747cm4", feature = "h747cm7")))]
+    let iwdg = unsafe { &(*crate::pac::IWDG::ptr()) };
+
+    // Find the smallest prescaler (/4 to /256; PR = 0 to 6) where the 12-bit reload value fits.
+    let mut pr = 0;
+    let mut reload = timeout * LSI_FREQ / 4.;
+    while reload > 4_095. && pr < 6 {
+        pr += 1;
+        reload /= 2.;
+    }
+    let reload = (reload as u32).clamp(1, 4_095);
+
+    // "1. Enable the IWDG by writing 0x0000 CCCC in the IWDG key register (IWDG_KR).
+    // 2. Enable register access by writing 0x0000 5555 in the IWDG key register (IWDG_KR).
+    // 3. Write the prescaler by programming the IWDG prescaler register (IWDG_PR) from 0 to 7.
+    // 4. Write the IWDG reload register (IWDG_RLR).
+    // 5. Wait for the registers to be updated (IWDG_SR = 0x0000 0000).
+    // 6. Refresh the counter value with IWDG_RLR (IWDG_KR = 0x0000 AAAA)."
+    iwdg.kr.write(|w| unsafe { w.bits(0xCCCC) });
+    iwdg.kr.write(|w| unsafe { w.bits(0x5555) });
+    iwdg.pr.write(|w| unsafe { w.bits(pr) });
+    iwdg.rlr.write(|w| unsafe { w.bits(reload) });
+    while iwdg.sr.read().bits() != 0 {}
+
+    reset::refresh_iwdg();
+}
@@ -0,0 +1,143 @@
+//! Hardware-in-loop (HIL) test firmware support. `run` accepts single-character commands from a
+//! host over a U[s]ART (eg the ST-Link virtual COM port on Nucleo boards), runs the corresponding
+//! test, and reports `PASS` or `FAIL` for it. Use this to validate a board, or a new family or
+//! variant, on real hardware. See the `hil_test` example for a complete firmware.
+//!
+//! Tests are closures, so they can use any peripheral, and pins that suit the board. This module
+//! provides tests that aren't covered by the drivers' own `self_test` methods.
+
+use core::ops::Deref;
+
+#[cfg(not(any(feature = "f4", feature = "g0")))]
+use crate::{
+    dma::{ChannelCfg, Dma, DmaChannel, DmaInterrupt},
+    pac::{dma1 as dma_p, spi1},
+    spi::Spi,
+    util::DmaPeriph,
+};
+
+use crate::{
+    gpio::{Pin, PinMode, Port},
+    pac,
+    usart::Usart,
+    util::{BaudPeriph, RccPeriph},
+};
+
+use cortex_m::asm;
+
+/// The command that runs all tests, in order.
+pub const CMD_ALL: u8 = b'*';
+
+/// Pattern transferred by `test_spi_dma`.
+const SPI_PATTERN: [u8; 8] = [0x00, 0xff, 0x55, 0xaa, 0x0f, 0xf0, 0x01, 0x80];
+
+#[cfg(not(any(feature = "f4", feature = "g0")))]
+/// The number of times `test_spi_dma` polls for the transfer to complete, before failing. This is
+/// far longer than transferring `SPI_PATTERN` takes, at any baud rate.
+const SPI_DMA_TIMEOUT_POLLS: u32 = 1_000_000;
+
+/// A test that `run` can perform.
+pub struct Test<'a> {
+    /// The command character that runs this test.
+    pub cmd: u8,
+    /// The name reported to the host, eg `"SPI"`.
+    pub name: &'static str,
+    /// Runs the test, and returns `true` if it passed.
+    pub run: &'a mut dyn FnMut() -> bool,
+}
+
+/// Write a test result to the host, eg `SPI: PASS`.
+pub fn report<R>(host: &mut Usart<R>, name: &str, passed: bool)
+where
+    R: Deref<Target = pac::usart1::RegisterBlock> + RccPeriph + BaudPeriph,
+{
+    host.write(name.as_bytes());
+    host.write(if passed { b": PASS\r\n" } else { b": FAIL\r\n" });
+}
+
+/// Announce readiness to the host, then run tests as their commands arrive, forever. `CMD_ALL`
+/// runs all tests. Unrecognized commands are ignored.
+pub fn run<R>(host: &mut Usart<R>, tests: &mut [Test]) -> !
+where
+    R: Deref<Target = pac::usart1::RegisterBlock> + RccPeriph + BaudPeriph,
+{
+    host.write(b"HIL test ready\r\n");
+
+    loop {
+        let cmd = host.read_one();
+
+        for test in tests.iter_mut() {
+            if cmd == CMD_ALL || cmd == test.cmd {
+                let passed = (test.run)();
+                report(host, test.name, passed);
+            }
+        }
+    }
+}
+
+/// Wait long enough for a pin driving a jumpered input to settle.
+fn settle() {
+    asm::delay(1_000);
+}
+
+/// Check GPIO output and input, using both the `Pin` and `Port` APIs. Requires `output` to be
+/// jumpered to `input`; both must be on `port`.
+pub fn test_gpio(port: Port, output: u8, input: u8) -> bool {
+    let mut output_pin = Pin::new(port, output, PinMode::Output);
+    let input_pin = Pin::new(port, input, PinMode::Input);
+
+    output_pin.set_high();
+    settle();
+    let pin_high = input_pin.is_high();
+
+    output_pin.set_low();
+    settle();
+    let pin_low = input_pin.is_low();
+
+    let mut port = port;
+
+    port.write_masked(1 << output, 1 << output);
+    settle();
+    let port_high = port.read() & (1 << input) != 0;
+
+    port.write_masked(1 << output, 0);
+    settle();
+    let port_low = port.read() & (1 << input) == 0;
+
+    pin_high && pin_low && port_high && port_low
+}
+
+#[cfg(not(any(feature = "f4", feature = "g0")))]
+/// Transfer a test pattern over SPI with DMA, and check it's received as sent. Requires MOSI to be
+/// jumpered to MISO. `rx_channel` and `tx_channel` must be the ones this SPI's requests are on.
+/// Fails if the transfer doesn't complete, eg if a channel is wrong.
+pub fn test_spi_dma<R, D>(
+    spi: &mut Spi<R>,
+    dma: &mut Dma<D>,
+    rx_channel: DmaChannel,
+    tx_channel: DmaChannel,
+) -> bool
+where
+    R: Deref<Target = spi1::RegisterBlock> + RccPeriph + DmaPeriph,
+    D: Deref<Target = dma_p::RegisterBlock>,
+{
+    let mut read_buf = [0; SPI_PATTERN.len()];
+
+    unsafe {
+        spi.read_dma(&mut read_buf, rx_channel, ChannelCfg::default(), dma);
+        spi.write_dma(&SPI_PATTERN, tx_channel, ChannelCfg::default(), dma);
+    }
+
+    let mut polls = 0;
+    while !dma.transfer_is_complete(rx_channel) && polls < SPI_DMA_TIMEOUT_POLLS {
+        polls += 1;
+    }
+    let complete = dma.transfer_is_complete(rx_channel);
+
+    dma.clear_interrupt(rx_channel, DmaInterrupt::TransferComplete);
+    dma.clear_interrupt(tx_channel, DmaInterrupt::TransferComplete);
+    spi.stop_dma(rx_channel, dma);
+    spi.stop_dma(tx_channel, dma);
+
+    complete && read_buf == SPI_PATTERN
+}
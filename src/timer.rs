@@ -5,7 +5,7 @@
 
 // todo: WB and WL should support pwm features
 
-use core::ops::Deref;
+use core::{marker::PhantomData, ops::Deref};
 
 use num_traits::float::Float;
 
@@ -29,18 +29,22 @@ use crate::{
 #[cfg(any(feature = "f3", feature = "l4"))]
 use crate::util::DmaPeriph;
 
+#[cfg(any(feature = "g4", feature = "h7", feature = "wb", feature = "wl"))]
+use crate::{dma::DmaInput, util::TimerDmaPeriph};
+
 #[cfg(feature = "g0")]
 use crate::pac::dma as dma_p;
 #[cfg(any(
     feature = "f3",
     feature = "l4",
+    feature = "l5",
     feature = "g4",
     feature = "h7",
     feature = "wb"
 ))]
 use crate::pac::dma1 as dma_p;
 
-#[cfg(not(any(feature = "f4", feature = "l5")))]
+#[cfg(not(feature = "f4"))]
 use crate::dma::{self, ChannelCfg, Dma, DmaChannel};
 
 #[cfg(any(feature = "f3", feature = "l4"))]
@@ -116,8 +120,33 @@ pub enum TimerInterrupt {
     CaptureCompare4Dma,
 }
 
-/// Output alignment. Sets `TIMx_CR1` register, `CMS` field.
 #[derive(Clone, Copy)]
+/// A timer DMA request, generated by an update or capture/compare event. Sets `TIMx_DIER` register,
+/// `UDE` or `CCxDE` field.
+pub enum TimerDmaRequest {
+    Update,
+    CaptureCompare1,
+    CaptureCompare2,
+    CaptureCompare3,
+    CaptureCompare4,
+}
+
+impl TimerDmaRequest {
+    /// The bit position of this request's enable field, in `TIMx_DIER`. This is the same for all
+    /// timers; we use it since some PACs omit the `CCxDE` fields.
+    fn dier_bit(&self) -> u8 {
+        match self {
+            Self::Update => 8,
+            Self::CaptureCompare1 => 9,
+            Self::CaptureCompare2 => 10,
+            Self::CaptureCompare3 => 11,
+            Self::CaptureCompare4 => 12,
+        }
+    }
+}
+
+/// Output alignment. Sets `TIMx_CR1` register, `CMS` field.
+#[derive(Clone, Copy, Debug)]
 pub enum Alignment {
     /// Edge-aligned mode. The counter counts up or down depending on the direction bit
     /// (DIR).
@@ -136,6 +165,124 @@ pub enum Alignment {
     Center3 = 0b11,
 }
 
+/// A PWM source, abstracted over the peripheral that generates it. Application code, eg LED,
+/// heater, or motor drivers, can accept any `Pwm` implementation generically, instead of a
+/// specific timer. Duty values are in counts, between 0 and `get_max_duty()`.
+///
+/// Currently implemented for general-purpose and advanced-control timers (`Timer`), using their
+/// capture compare channels. todo: LPTIM and HRTIM outputs, once those peripherals are supported.
+///
+/// Example:
+/// ```
+/// fn set_brightness<P: Pwm>(led: &mut P, channel: P::Channel, brightness: f32) {
+///     led.set_duty_ratio(channel, brightness);
+/// }
+/// ```
+pub trait Pwm {
+    /// The type used to select an output of this PWM source, eg `TimChannel`.
+    type Channel: Copy;
+
+    /// Enable an output.
+    fn enable(&mut self, channel: Self::Channel);
+    /// Disable an output.
+    fn disable(&mut self, channel: Self::Channel);
+    /// Get the duty of an output, in counts.
+    fn get_duty(&self, channel: Self::Channel) -> u32;
+    /// Get the duty value corresponding to a 100% duty cycle.
+    fn get_max_duty(&self) -> u32;
+    /// Set the duty of an output, in counts.
+    fn set_duty(&mut self, channel: Self::Channel, duty: u32);
+    /// Enable or disable the duty preload for an output. When enabled, a new duty only takes
+    /// effect at the start of the next period, so an update can't produce a glitch pulse.
+    fn set_preload(&mut self, channel: Self::Channel, preload: bool);
+
+    /// Set the duty of an output, as a ratio of the period, between 0. and 1.
+    fn set_duty_ratio(&mut self, channel: Self::Channel, ratio: f32) {
+        let duty = (self.get_max_duty() as f32 * ratio.clamp(0., 1.)) as u32;
+        self.set_duty(channel, duty);
+    }
+}
+
+/// Applies duty cycle changes to a PWM output at period boundaries, and optionally limits how
+/// fast the duty changes. This suits slow, high-power loads like heaters and pumps, which
+/// shouldn't see glitch pulses or abrupt steps when the duty is updated.
+///
+/// Duty preload is enabled on construction, so each write takes effect at the next update
+/// event. Call `update` from the timer's update interrupt (`TimerInterrupt::Update`); it moves the
+/// duty towards the target by at most `max_step` counts per period. This doesn't own the PWM
+/// source, so the timer's other channels remain usable.
+///
+/// Example:
+/// ```
+/// let mut heater = PwmSlewLimiter::new(&mut timer, TimChannel::C1, Some(20));
+/// heater.set_target_ratio(&timer, 0.6);
+///
+/// // In the update ISR:
+/// timer.clear_interrupt(TimerInterrupt::Update);
+/// heater.update(&mut timer);
+/// ```
+pub struct PwmSlewLimiter<C> {
+    channel: C,
+    /// The largest change in duty, in counts, applied per period. If `None`, the target is
+    /// applied in full at the next update.
+    pub max_step: Option<u32>,
+    current: u32,
+    target: u32,
+}
+
+impl<C: Copy> PwmSlewLimiter<C> {
+    /// Enable duty preload on the output, and start from its current duty.
+    pub fn new<P: Pwm<Channel = C>>(pwm: &mut P, channel: C, max_step: Option<u32>) -> Self {
+        pwm.set_preload(channel, true);
+        let current = pwm.get_duty(channel);
+
+        Self {
+            channel,
+            max_step,
+            current,
+            target: current,
+        }
+    }
+
+    /// Set the duty to move towards, in counts.
+    pub fn set_target(&mut self, duty: u32) {
+        self.target = duty;
+    }
+
+    /// Set the duty to move towards, as a ratio of the period, between 0. and 1.
+    pub fn set_target_ratio<P: Pwm<Channel = C>>(&mut self, pwm: &P, ratio: f32) {
+        self.target = (pwm.get_max_duty() as f32 * ratio.clamp(0., 1.)) as u32;
+    }
+
+    /// The duty being moved towards, in counts.
+    pub fn target(&self) -> u32 {
+        self.target
+    }
+
+    /// The duty most recently written to the output, in counts.
+    pub fn current(&self) -> u32 {
+        self.current
+    }
+
+    /// Step the duty towards the target. Call this once per period, from the timer's update
+    /// interrupt. Returns `true` once the target is reached.
+    pub fn update<P: Pwm<Channel = C>>(&mut self, pwm: &mut P) -> bool {
+        if self.current == self.target {
+            return true;
+        }
+
+        let step = self.max_step.unwrap_or(u32::MAX);
+        self.current = if self.target > self.current {
+            self.current + (self.target - self.current).min(step)
+        } else {
+            self.current - (self.current - self.target).min(step)
+        };
+
+        pwm.set_duty(self.channel, self.current);
+        self.current == self.target
+    }
+}
+
 /// Timer channel
 #[derive(Clone, Copy)]
 pub enum TimChannel {
@@ -148,12 +295,109 @@ pub enum TimChannel {
 
 /// Timer count direction
 #[repr(u8)]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug)]
 pub enum CountDir {
     Up = 0,
     Down = 1,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(u8)]
+/// Slave mode, for timers with a slave mode controller. TIM15 supports only these modes; TIM16
+/// and TIM17 have no slave mode controller, so `set_slave_mode` isn't available on them.
+/// Sets `TIMx_SMCR` register, `SMS` field.
+pub enum SlaveMode {
+    /// The prescaler is clocked directly by the internal clock.
+    Disabled = 0b000,
+    /// Rising edge of the selected trigger input (TRGI) reinitializes the counter, and
+    /// generates an update of the registers.
+    Reset = 0b100,
+    /// The counter clock is enabled when the trigger input (TRGI) is high.
+    Gated = 0b101,
+    /// The counter starts at a rising edge of the trigger TRGI, but isn't reset.
+    Trigger = 0b110,
+    /// Rising edges of the selected trigger (TRGI) clock the counter.
+    ExternalClock1 = 0b111,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(u8)]
+/// Slave mode trigger input. Which timer each internal trigger (ITRx) connects to varies by
+/// timer and MCU; see the "TIMx internal trigger connection" table in the RM.
+/// Sets `TIMx_SMCR` register, `TS` field.
+pub enum SlaveTrigger {
+    /// Internal trigger 0
+    Itr0 = 0b000,
+    /// Internal trigger 1
+    Itr1 = 0b001,
+    /// Internal trigger 2
+    Itr2 = 0b010,
+    /// Internal trigger 3
+    Itr3 = 0b011,
+    /// TI1 edge detector
+    Ti1FEd = 0b100,
+    /// Filtered timer input 1
+    Ti1Fp1 = 0b101,
+    /// Filtered timer input 2
+    Ti2Fp2 = 0b110,
+}
+
+#[cfg(any(feature = "g0", feature = "g4"))]
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// Selects an advanced timer's break input: BRK (set in TIMx_AF1), or BRK2 (set in TIMx_AF2).
+pub enum BreakInput {
+    Bk,
+    Bk2,
+}
+
+#[cfg(any(feature = "g0", feature = "g4"))]
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(u8)]
+/// A signal that drives a break input. Multiple sources can be combined; the break input is
+/// active if any of them is. Comparators must be enabled separately. G0 has COMP1 - 2 (COMP1 - 3
+/// on G0B1 and G0C1); G4 has COMP1 - 7, depending on the variant. Sets `TIMx_AF1` or `TIMx_AF2`,
+/// `BKINE` or `BKCMPxE`.
+pub enum BreakSource {
+    /// The BKIN or BKIN2 pin, in alternate function mode.
+    Pin = 0,
+    Comp1 = 1,
+    Comp2 = 2,
+    #[cfg(any(feature = "g0b1", feature = "g0c1", feature = "g4"))]
+    Comp3 = 3,
+    #[cfg(feature = "g4")]
+    Comp4 = 4,
+    #[cfg(feature = "g4")]
+    Comp5 = 5,
+    #[cfg(feature = "g4")]
+    Comp6 = 6,
+    #[cfg(feature = "g4")]
+    Comp7 = 7,
+}
+
+#[cfg(any(feature = "g0", feature = "g4"))]
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(u8)]
+/// Break input digital filter: The sampling frequency, and the number of consecutive samples
+/// (N) required to validate a transition. Sets `TIMx_BDTR`, `BKF` or `BK2F`.
+pub enum BreakFilter {
+    NoFilter = 0b0000,
+    FckIntN2 = 0b0001,
+    FckIntN4 = 0b0010,
+    FckIntN8 = 0b0011,
+    FdtsDiv2N6 = 0b0100,
+    FdtsDiv2N8 = 0b0101,
+    FdtsDiv4N6 = 0b0110,
+    FdtsDiv4N8 = 0b0111,
+    FdtsDiv8N6 = 0b1000,
+    FdtsDiv8N8 = 0b1001,
+    FdtsDiv16N5 = 0b1010,
+    FdtsDiv16N6 = 0b1011,
+    FdtsDiv16N8 = 0b1100,
+    FdtsDiv32N5 = 0b1101,
+    FdtsDiv32N6 = 0b1110,
+    FdtsDiv32N8 = 0b1111,
+}
+
 /// Capture/Compare selection.
 /// This field defines the direction of the channel (input/output) as well as the used input.
 /// It affects the TIMx_CCMR1 register, CCxS fields.
@@ -307,6 +551,139 @@ pub struct Timer<TIM> {
     pub regs: TIM, // Register block for the specific timer.
     pub cfg: TimerConfig,
     clock_speed: u32, // Associated timer clock speed in Hz.
+    channels_taken: bool,
+}
+
+/// Exclusive ownership of capture/compare channel `C` (1 - 4) of timer `TIM`, checked at compile
+/// time. Take these with `Timer::take_channels`, which only returns tokens for the channels this
+/// timer supports. Tokens can't be copied or cloned, and are consumed when configuring the channel
+/// with `pwm_channel` or `capture_channel`, so using a channel for both PWM and input capture, or
+/// for PWM twice, fails to compile. Release a configured channel with `release`, to reconfigure
+/// it.
+///
+/// Example:
+/// ```ignore
+/// let chans = timer.take_channels().unwrap();
+/// let pwm = timer.pwm_channel(chans.ch1, OutputCompare::Pwm1, 0.5);
+/// // Rejected by the compiler: `chans.ch1` was moved above.
+/// let capture = timer.capture_channel(chans.ch1, CaptureCompare::InputTi1, Polarity::ActiveHigh);
+/// ```
+///
+/// This is opt-in: The methods that take a `TimChannel`, eg `enable_pwm_output`, remain available,
+/// and aren't checked against tokens.
+pub struct ChannelToken<TIM, const C: u8> {
+    _tim: PhantomData<TIM>,
+}
+
+impl<TIM, const C: u8> ChannelToken<TIM, C> {
+    fn new() -> Self {
+        Self { _tim: PhantomData }
+    }
+
+    /// The channel this token represents.
+    pub fn channel(&self) -> TimChannel {
+        match C {
+            1 => TimChannel::C1,
+            2 => TimChannel::C2,
+            3 => TimChannel::C3,
+            #[cfg(not(feature = "wl"))]
+            4 => TimChannel::C4,
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// Tokens for all of a 4-channel timer's capture/compare channels. Move the individual tokens out
+/// of this struct as required.
+pub struct TimChannels<TIM> {
+    pub ch1: ChannelToken<TIM, 1>,
+    pub ch2: ChannelToken<TIM, 2>,
+    pub ch3: ChannelToken<TIM, 3>,
+    #[cfg(not(feature = "wl"))]
+    pub ch4: ChannelToken<TIM, 4>,
+}
+
+/// Tokens for a 2-channel timer's capture/compare channels, eg TIM15. See `TimChannels`.
+pub struct TimChannels2<TIM> {
+    pub ch1: ChannelToken<TIM, 1>,
+    pub ch2: ChannelToken<TIM, 2>,
+}
+
+/// The token for a 1-channel timer's capture/compare channel, eg TIM16 and TIM17. See
+/// `TimChannels`.
+pub struct TimChannels1<TIM> {
+    pub ch1: ChannelToken<TIM, 1>,
+}
+
+/// A channel configured for PWM output, with `Timer::pwm_channel`.
+pub struct PwmChannel<TIM, const C: u8> {
+    token: ChannelToken<TIM, C>,
+}
+
+impl<TIM, const C: u8> PwmChannel<TIM, C> {
+    pub fn channel(&self) -> TimChannel {
+        self.token.channel()
+    }
+
+    /// Release the channel, eg to reconfigure it for input capture. This doesn't change the
+    /// channel's configuration.
+    pub fn release(self) -> ChannelToken<TIM, C> {
+        self.token
+    }
+}
+
+/// A channel configured for input capture, with `Timer::capture_channel`.
+pub struct CaptureChannel<TIM, const C: u8> {
+    token: ChannelToken<TIM, C>,
+}
+
+impl<TIM, const C: u8> CaptureChannel<TIM, C> {
+    pub fn channel(&self) -> TimChannel {
+        self.token.channel()
+    }
+
+    /// Release the channel, eg to reconfigure it for PWM output. This doesn't change the
+    /// channel's configuration.
+    pub fn release(self) -> ChannelToken<TIM, C> {
+        self.token
+    }
+}
+
+#[cfg(any(feature = "g4", feature = "h7", feature = "wb", feature = "wl"))]
+impl<TIM> Timer<TIM>
+where
+    TIM: TimerDmaPeriph,
+{
+    /// The DMAMUX input for one of this timer's DMA requests. Pass this to `dma::mux` to route it to
+    /// a DMA channel, eg for use with `write_dma_duty`.
+    pub fn dma_input(&self, request: TimerDmaRequest) -> DmaInput {
+        TIM::dma_input(request)
+    }
+}
+
+/// Timer configuration, decoded from its registers by `Timer::dump`. Print it with `{:?}`, eg to
+/// attach to a bug report, or to compare against the RM. (With `defmt`, use `Debug2Format`.)
+#[derive(Clone, Copy, Debug)]
+pub struct TimerDump {
+    /// TIMx_CR1, CEN
+    pub enabled: bool,
+    /// TIMx_CR1, DIR
+    pub direction: CountDir,
+    /// TIMx_CR1, CMS
+    pub alignment: Alignment,
+    /// TIMx_CR1, OPM
+    pub one_pulse_mode: bool,
+    /// TIMx_CR1, ARPE
+    pub auto_reload_preload: bool,
+    /// TIMx_PSC
+    pub prescaler: u16,
+    /// TIMx_ARR
+    pub auto_reload: u32,
+    /// TIMx_CNT, at the time of the dump.
+    pub count: u32,
+    /// The update (overflow) frequency in Hz, calculated from PSC, ARR, alignment, and the timer's
+    /// input clock.
+    pub freq: f32,
 }
 
 macro_rules! make_timer {
@@ -341,7 +718,12 @@ macro_rules! make_timer {
                         w.ccds().bit(cfg.capture_compare_dma as u8 != 0)
                     });
 
-                    let mut result = Timer { clock_speed, cfg, regs };
+                    let mut result = Timer {
+                        clock_speed,
+                        cfg,
+                        regs,
+                        channels_taken: false,
+                    };
 
                     result.set_freq(freq).ok();
                     result.set_dir();
@@ -396,6 +778,17 @@ macro_rules! make_timer {
                 }
             }
 
+            /// Enable a DMA request, on update or capture/compare events. Eg, to update a duty cycle
+            /// from a buffer on each update event, or to pace ADC or DAC transfers.
+            pub fn enable_dma_request(&mut self, request: TimerDmaRequest) {
+                self.regs.dier.modify(|r, w| unsafe { w.bits(r.bits() | (1 << request.dier_bit())) });
+            }
+
+            /// Disable a DMA request.
+            pub fn disable_dma_request(&mut self, request: TimerDmaRequest) {
+                self.regs.dier.modify(|r, w| unsafe { w.bits(r.bits() & !(1 << request.dier_bit())) });
+            }
+
             /// Clears interrupt associated with this timer.
             ///
             /// If the interrupt is not cleared, it will immediately retrigger after
@@ -425,6 +818,25 @@ macro_rules! make_timer {
                 }
             }
 
+            /// Wait for the next update event (eg counter overflow), sleeping with `WFE` until it
+            /// occurs. Enables the update interrupt; `interrupt` is this timer's NVIC interrupt,
+            /// which should be left masked. See the `async_wfe` module for details.
+            #[cfg(feature = "async_wfe")]
+            pub async fn wait_update(&mut self, interrupt: pac::Interrupt) {
+                self.enable_interrupt(TimerInterrupt::Update);
+
+                crate::async_wfe::wait_for(Some(interrupt), || {
+                    if self.regs.sr.read().uif().bit_is_set() {
+                        // Clear the flag before the NVIC pending bit is cleared.
+                        self.clear_interrupt(TimerInterrupt::Update);
+                        Some(())
+                    } else {
+                        None
+                    }
+                })
+                .await;
+            }
+
             /// Enable the timer.
             pub fn enable(&mut self) {
                 self.regs.cr1.write(|w| w.cen().set_bit());
@@ -498,6 +910,37 @@ macro_rules! make_timer {
                 self.clear_interrupt(TimerInterrupt::Update);
             }
 
+            /// Read the current configuration from the timer's registers. Unlike the `TimerConfig`
+            /// passed to the constructor, this reflects any changes made since, including by direct
+            /// register writes.
+            pub fn dump(&self) -> TimerDump {
+                let cr1 = self.regs.cr1.read();
+                let (direction, alignment) = self.count_mode();
+
+                let prescaler = self.regs.psc.read().bits() as u16;
+                let auto_reload = self.regs.arr.read().bits();
+
+                // This is the inverse of the calculation in `set_freq`.
+                let mut freq = self.clock_speed as f32
+                    / ((prescaler as f32 + 1.) * (auto_reload as f32 + 1.));
+                match alignment {
+                    Alignment::Edge => (),
+                    _ => freq /= 2.,
+                }
+
+                TimerDump {
+                    enabled: cr1.cen().bit_is_set(),
+                    direction,
+                    alignment,
+                    one_pulse_mode: cr1.opm().bit_is_set(),
+                    auto_reload_preload: cr1.arpe().bit_is_set(),
+                    prescaler,
+                    auto_reload,
+                    count: self.read_count(),
+                    freq,
+                }
+            }
+
             /// Read the current counter value.
             pub fn read_count(&self) -> u32 {
                 // todo: This depends on resolution. We read the whole
@@ -506,6 +949,57 @@ macro_rules! make_timer {
                 self.regs.cnt.read().bits()
             }
 
+            #[cfg(not(feature = "f4"))]
+            /// Enable or disable UIF remapping. When enabled, the update interrupt flag (UIF) is
+            /// copied to bit 31 of the counter register (UIFCPY), so `read_count_with_overflow` reads
+            /// both atomically. On 32-bit timers, this limits the count to 31 bits; set ARR
+            /// accordingly. (TIMx_CR1, UIFREMAP)
+            pub fn set_uif_remap(&mut self, enabled: bool) {
+                // We set this bit directly, since some PACs omit the field. UIFREMAP is at CR1 bit 11.
+                self.regs.cr1.modify(|r, w| unsafe {
+                    w.bits((r.bits() & !(1 << 11)) | ((enabled as u32) << 11))
+                });
+            }
+
+            /// Read the current counter value, and whether an overflow (update event) is pending;
+            /// ie, the update interrupt flag. These are read consistently: if the flag is set, the count
+            /// was read after the overflow. This allows building extended tick counters without races,
+            /// eg, with a 64-bit count whose high bits are incremented in the update ISR:
+            ///
+            /// ```
+            /// let ticks = free(|cs| {
+            ///     let (count, overflow) = timer.read_count_with_overflow();
+            ///     // If the ISR hasn't run yet for a pending overflow, account for it here.
+            ///     let high = OVERFLOWS.borrow(cs).get() + overflow as u64;
+            ///     (high << 16) | count as u64
+            /// });
+            /// ```
+            ///
+            /// If UIF remapping is enabled, with `set_uif_remap`, this is a single register read. Otherwise,
+            /// we read UIF before and after the counter, and retry if it changed in between.
+            pub fn read_count_with_overflow(&self) -> (u32, bool) {
+                let count_mask = $res::MAX as u32;
+
+                #[cfg(not(feature = "f4"))]
+                if self.regs.cr1.read().bits() & (1 << 11) != 0 {
+                    // With UIFREMAP set, bit 31 is UIFCPY, and isn't part of the count.
+                    let cnt = self.regs.cnt.read().bits();
+                    return (cnt & count_mask & 0x7fff_ffff, cnt & (1 << 31) != 0);
+                }
+
+                loop {
+                    let uif_before = self.regs.sr.read().uif().bit_is_set();
+                    let cnt = self.regs.cnt.read().bits();
+                    let uif_after = self.regs.sr.read().uif().bit_is_set();
+
+                    // If UIF was set between the reads, we can't tell if the count was read before
+                    // or after the overflow.
+                    if uif_before == uif_after {
+                        return (cnt & count_mask, uif_after);
+                    }
+                }
+            }
+
 
             /// Enables PWM output for a given channel and output compare, with an initial duty cycle, in Hz.
             pub fn enable_pwm_output(
@@ -522,6 +1016,47 @@ macro_rules! make_timer {
                 self.enable_capture_compare(channel);
             }
 
+            /// Mark the channel tokens as taken, for `take_channels`. Returns `false` if they
+            /// already were. Since the timer owns its register block, which can only be taken
+            /// once, each channel has at most one token.
+            fn claim_channels(&mut self) -> bool {
+                if self.channels_taken {
+                    return false;
+                }
+                self.channels_taken = true;
+                true
+            }
+
+            /// Configure a channel for PWM output, consuming its token. See `enable_pwm_output`.
+            pub fn pwm_channel<const C: u8>(
+                &mut self,
+                token: ChannelToken<pac::$TIMX, C>,
+                compare: OutputCompare,
+                duty: f32,
+            ) -> PwmChannel<pac::$TIMX, C> {
+                self.enable_pwm_output(token.channel(), compare, duty);
+                PwmChannel { token }
+            }
+
+            /// Configure a channel for input capture, consuming its token. Read captured values with
+            /// `get_duty`.
+            pub fn capture_channel<const C: u8>(
+                &mut self,
+                token: ChannelToken<pac::$TIMX, C>,
+                mode: CaptureCompare,
+                polarity: Polarity,
+            ) -> CaptureChannel<pac::$TIMX, C> {
+                let channel = token.channel();
+
+                // CCxS can only be written when the channel is off (CCxE = 0).
+                self.disable_capture_compare(channel);
+                self.set_capture_compare(channel, mode);
+                self.set_polarity(channel, polarity);
+                self.enable_capture_compare(channel);
+
+                CaptureChannel { token }
+            }
+
             /// Return the integer associated with the maximum duty period.
             pub fn get_max_duty(&self) -> $res {
                 #[cfg(feature = "g0")]
@@ -535,7 +1070,7 @@ macro_rules! make_timer {
              /// The main purpose is to be able to re-program part of the timer multiple times without
              /// software overhead, but it can also be used to read several registers in a row, at regular
              /// intervals."
-            #[cfg(not(any(feature = "g0", feature = "f4", feature = "l5", feature = "f3", feature = "l4")))]
+            #[cfg(not(any(feature = "g0", feature = "f4", feature = "f3", feature = "l4")))]
             pub unsafe fn write_dma_burst<D>(
                 &mut self,
                 buf: &[u16],
@@ -643,6 +1178,59 @@ macro_rules! make_timer {
                 );
 
             }
+
+            /// Write a buffer of duty cycle values to a channel's capture/compare register, one per DMA
+            /// request. Eg, with `TimerDmaRequest::Update`, the duty cycle changes each timer period;
+            /// this can be used to generate sine-modulated PWM, or WS2812 LED data. Enables
+            /// `request`. On MCUs that use DMAMUX, route the input from `dma_input()` to
+            /// `dma_channel` first, using `dma::mux`, or `dma::mux_dma` on WB and WL.
+            #[cfg(not(any(feature = "g0", feature = "f4", feature = "f3", feature = "l4")))]
+            pub unsafe fn write_dma_duty<D>(
+                &mut self,
+                buf: &[u16],
+                tim_channel: TimChannel,
+                request: TimerDmaRequest,
+                dma_channel: DmaChannel,
+                channel_cfg: ChannelCfg,
+                dma: &mut Dma<D>,
+            ) where
+                D: Deref<Target = dma_p::RegisterBlock>,
+            {
+                let (ptr, len) = (buf.as_ptr(), buf.len());
+                assert!(
+                    len > 0 && len <= u16::MAX as usize,
+                    "DMA transfers must be 1 - 65,535 words long."
+                );
+
+                let periph_addr = match tim_channel {
+                    TimChannel::C1 => &self.regs.ccr1 as *const _ as u32,
+                    TimChannel::C2 => &self.regs.ccr2 as *const _ as u32,
+                    TimChannel::C3 => &self.regs.ccr3 as *const _ as u32,
+                    #[cfg(not(feature = "wl"))]
+                    TimChannel::C4 => &self.regs.ccr4 as *const _ as u32,
+                };
+
+                #[cfg(feature = "h7")]
+                let num_data = len as u32;
+                #[cfg(not(feature = "h7"))]
+                let num_data = len as u16;
+
+                self.enable_dma_request(request);
+
+                dma.cfg_channel(
+                    dma_channel,
+                    periph_addr,
+                    ptr as u32,
+                    num_data,
+                    dma::Direction::ReadFromMem,
+                    // The sizes must match: In direct mode, H7's DMA uses the peripheral size on the
+                    // memory side too. Half-word writes update the lower half of 32-bit timers' CCR
+                    // registers.
+                    dma::DataSize::S16,
+                    dma::DataSize::S16,
+                    channel_cfg,
+                );
+            }
         }
 
         #[cfg(feature = "embedded-hal")]
@@ -729,18 +1317,84 @@ macro_rules! make_timer {
     }
 }
 
+/// Implement the `Pwm` trait for a timer, using its inherent capture compare methods. This is
+/// called by the `cc_` macros below.
+macro_rules! pwm_impl {
+    ($TIMX:ident, $res:ident) => {
+        impl Pwm for Timer<pac::$TIMX> {
+            type Channel = TimChannel;
+
+            fn enable(&mut self, channel: TimChannel) {
+                Timer::<pac::$TIMX>::enable_capture_compare(self, channel);
+            }
+
+            fn disable(&mut self, channel: TimChannel) {
+                Timer::<pac::$TIMX>::disable_capture_compare(self, channel);
+            }
+
+            fn get_duty(&self, channel: TimChannel) -> u32 {
+                Timer::<pac::$TIMX>::get_duty(self, channel) as u32
+            }
+
+            fn get_max_duty(&self) -> u32 {
+                Timer::<pac::$TIMX>::get_max_duty(self) as u32
+            }
+
+            fn set_duty(&mut self, channel: TimChannel, duty: u32) {
+                Timer::<pac::$TIMX>::set_duty(self, channel, duty as $res);
+            }
+
+            fn set_preload(&mut self, channel: TimChannel, preload: bool) {
+                Timer::<pac::$TIMX>::set_preload(self, channel, preload);
+            }
+        }
+    };
+}
+
 // We use macros to support the varying number of capture compare channels available on
 // different timers.
 // Note that there's lots of DRY between these implementations.
 macro_rules! cc_4_channels {
     ($TIMX:ident, $res:ident) => {
         impl Timer<pac::$TIMX> {
+            /// Take the tokens for this timer's capture/compare channels. Returns `None` if they've
+            /// already been taken. See `ChannelToken`.
+            pub fn take_channels(&mut self) -> Option<TimChannels<pac::$TIMX>> {
+                if !self.claim_channels() {
+                    return None;
+                }
+
+                Some(TimChannels {
+                    ch1: ChannelToken::new(),
+                    ch2: ChannelToken::new(),
+                    ch3: ChannelToken::new(),
+                    #[cfg(not(feature = "wl"))]
+                    ch4: ChannelToken::new(),
+                })
+            }
+
             /// Function that allows us to set direction only on timers that have this option.
             pub fn set_dir(&mut self) {
                 self.regs.cr1.modify(|_, w| w.dir().bit(self.cfg.direction as u8 != 0));
                 self.regs.cr1.modify(|_, w| unsafe { w.cms().bits(self.cfg.alignment as u8) });
             }
 
+            /// Read the counting direction and alignment from CR1. Used by `dump`.
+            fn count_mode(&self) -> (CountDir, Alignment) {
+                let cr1 = self.regs.cr1.read();
+
+                let direction = if cr1.dir().bit_is_set() { CountDir::Down } else { CountDir::Up };
+
+                let alignment = match cr1.cms().bits() {
+                    0b00 => Alignment::Edge,
+                    0b01 => Alignment::Center1,
+                    0b10 => Alignment::Center2,
+                    _ => Alignment::Center3,
+                };
+
+                (direction, alignment)
+            }
+
             /// Enables basic PWM input. TODO: Doesn't work yet.
             /// L4 RM, section 26.3.8
             pub fn _enable_pwm_input(
@@ -1012,17 +1666,38 @@ macro_rules! cc_4_channels {
             }
 
         }
+
+        pwm_impl!($TIMX, $res);
     }
 }
 
 macro_rules! cc_2_channels {
     ($TIMX:ident, $res:ident) => {
         impl Timer<pac::$TIMX> {
+            /// Take the tokens for this timer's capture/compare channels. Returns `None` if they've
+            /// already been taken. See `ChannelToken`.
+            pub fn take_channels(&mut self) -> Option<TimChannels2<pac::$TIMX>> {
+                if !self.claim_channels() {
+                    return None;
+                }
+
+                Some(TimChannels2 {
+                    ch1: ChannelToken::new(),
+                    ch2: ChannelToken::new(),
+                })
+            }
+
             /// Function that allows us to set direction only on timers that have this option.
             fn set_dir(&mut self) {
                 // self.regs.cr1.modify(|_, w| w.dir().bit(self.cfg.direction as u8 != 0));
             }
 
+            /// The counting direction and alignment, for `dump`. These timers' CR1 doesn't have
+            /// DIR and CMS in every PAC, and `set_dir` doesn't write them, so report the config.
+            fn count_mode(&self) -> (CountDir, Alignment) {
+                (self.cfg.direction, self.cfg.alignment)
+            }
+
             /// Enables basic PWM input. TODO: Doesn't work yet.
             /// L4 RM, section 26.3.8
             pub fn _enable_pwm_input(
@@ -1230,15 +1905,34 @@ macro_rules! cc_2_channels {
             }
 
         }
+
+        pwm_impl!($TIMX, $res);
     }
 }
 
 macro_rules! cc_1_channel {
     ($TIMX:ident, $res:ident) => {
         impl Timer<pac::$TIMX> {
+            /// Take the tokens for this timer's capture/compare channels. Returns `None` if they've
+            /// already been taken. See `ChannelToken`.
+            pub fn take_channels(&mut self) -> Option<TimChannels1<pac::$TIMX>> {
+                if !self.claim_channels() {
+                    return None;
+                }
+
+                Some(TimChannels1 {
+                    ch1: ChannelToken::new(),
+                })
+            }
+
             /// Function that allows us to set direction only on timers that have this option.
             fn set_dir(&mut self) {}
 
+            /// The counting direction and alignment, for `dump`. See `cc_2_channels`.
+            fn count_mode(&self) -> (CountDir, Alignment) {
+                (self.cfg.direction, self.cfg.alignment)
+            }
+
             /// Enables basic PWM input. TODO: Doesn't work yet.
             /// L4 RM, section 26.3.8
             pub fn _enable_pwm_input(
@@ -1426,9 +2120,187 @@ macro_rules! cc_1_channel {
             }
 
         }
+
+        pwm_impl!($TIMX, $res);
     }
 }
 
+/// Break, dead-time, and complementary output support, for timers with a break and dead-time
+/// register: The advanced-control timers, and the lightweight timers (TIM15, TIM16, and TIM17);
+/// these are often the only timers left free on small packages. Their outputs stay inactive until
+/// the main output is enabled with `enable_main_output`. See G4 RM, section 30.4: "TIM15/TIM16/TIM17
+/// registers".
+macro_rules! break_dead_time {
+    ($TIMX:ident) => {
+        impl Timer<pac::$TIMX> {
+            /// Enable the main output. (TIMx_BDTR, MOE) This is required for any output on
+            /// this timer, including CH1N. It's cleared in hardware on a break, unless automatic
+            /// output is enabled.
+            pub fn enable_main_output(&mut self) {
+                self.regs.bdtr.modify(|_, w| w.moe().set_bit());
+            }
+
+            /// Disable the main output, forcing the outputs to their idle state.
+            pub fn disable_main_output(&mut self) {
+                self.regs.bdtr.modify(|_, w| w.moe().clear_bit());
+            }
+
+            /// Enable the CH1N complementary output. (TIMx_CCER, CC1NE) Set its polarity with
+            /// `set_complementary_polarity`, and the gap between CH1 and CH1N edges with
+            /// `set_dead_time`.
+            pub fn enable_complementary_output(&mut self) {
+                self.regs.ccer.modify(|_, w| w.cc1ne().set_bit());
+            }
+
+            /// Disable the CH1N complementary output.
+            pub fn disable_complementary_output(&mut self) {
+                self.regs.ccer.modify(|_, w| w.cc1ne().clear_bit());
+            }
+
+            /// Set the dead-time inserted between CH1 and CH1N edges. `dtg` is the raw DTG field;
+            /// for values up to 127, this is the dead-time in timer kernel clock cycles. See the
+            /// TIMx_BDTR, DTG field description in the RM for larger values.
+            /// Must be set before the break and dead-time register is locked.
+            pub fn set_dead_time(&mut self, dtg: u8) {
+                self.regs.bdtr.modify(|_, w| unsafe { w.dtg().bits(dtg) });
+            }
+
+            /// Enable the break input, eg to shut down a power stage on an overcurrent comparator
+            /// or fault pin. On a break, MOE is cleared, and the outputs go to their idle state.
+            /// If `automatic_output` is true, MOE is set again at the next update event once the
+            /// break input is inactive. (TIMx_BDTR, BKE, BKP, and AOE)
+            pub fn enable_break(&mut self, polarity: Polarity, automatic_output: bool) {
+                self.regs.bdtr.modify(|_, w| {
+                    w.bke().set_bit();
+                    // Unlike CCxP, BKP = 1 means active high.
+                    w.bkp().bit(matches!(polarity, Polarity::ActiveHigh));
+                    w.aoe().bit(automatic_output)
+                });
+            }
+
+            /// Disable the break input.
+            pub fn disable_break(&mut self) {
+                self.regs.bdtr.modify(|_, w| w.bke().clear_bit());
+            }
+
+            /// Check if a break has occurred. (TIMx_SR, BIF)
+            pub fn break_occurred(&self) -> bool {
+                self.regs.sr.read().bif().bit_is_set()
+            }
+
+            /// Clear the break flag. The main output must be re-enabled separately, unless
+            /// automatic output is enabled.
+            pub fn clear_break(&mut self) {
+                // rc_w0: Writing the other flags back as read leaves them unaffected, and reserved
+                // bits at their reset value. (A flag set between the read and write is cleared)
+                self.regs.sr.modify(|_, w| w.bif().clear_bit());
+            }
+        }
+    };
+}
+
+/// Break input source, polarity, and filter selection, for advanced-control timers on G0 and G4.
+/// See G4 RM, section 28.3.16: "Using the break function".
+#[cfg(any(feature = "g0", feature = "g4"))]
+macro_rules! break_input {
+    ($TIMX:ident) => {
+        impl Timer<pac::$TIMX> {
+            /// Configure and enable a break input. `sources` replaces any sources previously set
+            /// for this input. `polarity` is the break input's active level, after combining the
+            /// sources. Unlike `enable_break`, this supports BRK2, and comparator sources. Use
+            /// `interconnect::route_comp_to_break` on G4 to invert individual comparator sources.
+            ///
+            /// BDTR fields can't be modified once locked; call this before setting a lock level.
+            pub fn set_break_input(
+                &mut self,
+                input: BreakInput,
+                sources: &[BreakSource],
+                polarity: Polarity,
+                filter: BreakFilter,
+            ) {
+                let has = |source| sources.contains(&source);
+
+                // `TIMx_AF1` and `TIMx_AF2` share a layout, but their fields are named for BRK
+                // and BRK2 respectively.
+                match input {
+                    BreakInput::Bk => self.regs.af1.modify(|_, w| {
+                        w.bkine().bit(has(BreakSource::Pin));
+                        w.bkcmp1e().bit(has(BreakSource::Comp1));
+                        w.bkcmp2e().bit(has(BreakSource::Comp2));
+                        #[cfg(any(feature = "g0b1", feature = "g0c1", feature = "g4"))]
+                        w.bkcmp3e().bit(has(BreakSource::Comp3));
+                        #[cfg(feature = "g4")]
+                        {
+                            w.bkcmp4e().bit(has(BreakSource::Comp4));
+                            w.bkcmp5e().bit(has(BreakSource::Comp5));
+                            w.bkcmp6e().bit(has(BreakSource::Comp6));
+                            w.bkcmp7e().bit(has(BreakSource::Comp7));
+                        }
+                        w
+                    }),
+                    BreakInput::Bk2 => self.regs.af2.modify(|_, w| {
+                        w.bk2ine().bit(has(BreakSource::Pin));
+                        w.bk2cmp1e().bit(has(BreakSource::Comp1));
+                        w.bk2cmp2e().bit(has(BreakSource::Comp2));
+                        #[cfg(any(feature = "g0b1", feature = "g0c1", feature = "g4"))]
+                        w.bk2cmp3e().bit(has(BreakSource::Comp3));
+                        #[cfg(feature = "g4")]
+                        {
+                            w.bk2cmp4e().bit(has(BreakSource::Comp4));
+                            w.bk2cmp5e().bit(has(BreakSource::Comp5));
+                            w.bk2cmp6e().bit(has(BreakSource::Comp6));
+                            w.bk2cmp7e().bit(has(BreakSource::Comp7));
+                        }
+                        w
+                    }),
+                }
+
+                // Unlike CCxP, BKP and BK2P = 1 means active high.
+                let active_high = matches!(polarity, Polarity::ActiveHigh);
+
+                self.regs.bdtr.modify(|_, w| unsafe {
+                    match input {
+                        BreakInput::Bk => {
+                            w.bkf().bits(filter as u8);
+                            w.bke().set_bit();
+                            w.bkp().bit(active_high)
+                        }
+                        BreakInput::Bk2 => {
+                            w.bk2f().bits(filter as u8);
+                            w.bk2e().set_bit();
+                            w.bk2p().bit(active_high)
+                        }
+                    }
+                });
+            }
+
+            /// Disable a break input. Its sources are left configured.
+            pub fn disable_break_input(&mut self, input: BreakInput) {
+                self.regs.bdtr.modify(|_, w| match input {
+                    BreakInput::Bk => w.bke().clear_bit(),
+                    BreakInput::Bk2 => w.bk2e().clear_bit(),
+                });
+            }
+        }
+    };
+}
+
+/// Slave mode controller support. Of the lightweight timers, only TIM15 has one.
+macro_rules! slave_mode {
+    ($TIMX:ident) => {
+        impl Timer<pac::$TIMX> {
+            /// Configure the slave mode controller, eg to start, gate, or reset this timer from
+            /// another timer's TRGO, or from an input pin. (TIMx_SMCR, SMS and TS)
+            pub fn set_slave_mode(&mut self, mode: SlaveMode, trigger: SlaveTrigger) {
+                // TS must only be changed while SMS = 0.
+                self.regs.smcr.modify(|_, w| unsafe { w.sms().bits(0) });
+                self.regs.smcr.modify(|_, w| unsafe { w.ts().bits(trigger as u8) });
+                self.regs.smcr.modify(|_, w| unsafe { w.sms().bits(mode as u8) });
+            }
+        }
+    };
+}
+
 /// Calculate values required to set the timer frequency: `PSC` and `ARR`. This can be
 /// used for initial timer setup, or changing the value later.
 fn calc_freq_vals(freq: f32, clock_speed: u32) -> Result<(u16, u16), ValueError> {
@@ -1608,6 +2480,10 @@ make_timer!(TIM1, tim1, 2, u16);
 // todo: Some variantsl ike H7 have 4 channels on TIM1.
 #[cfg(not(any(feature = "f373")))]
 cc_2_channels!(TIM1, u16);
+#[cfg(not(any(feature = "f373")))]
+break_dead_time!(TIM1);
+#[cfg(any(feature = "g0", feature = "g4"))]
+break_input!(TIM1);
 
 cfg_if! {
     if #[cfg(not(any(
@@ -1684,6 +2560,9 @@ cfg_if! {
         make_timer!(TIM8, tim8, 2, u16);
         // todo: Some issues with field names or something on l562 here.
         cc_1_channel!(TIM8, u16);
+        break_dead_time!(TIM8);
+        #[cfg(feature = "g4")]
+        break_input!(TIM8);
     }
 }
 
@@ -1700,7 +2579,24 @@ cfg_if! {
         feature = "wl"
     )))] {
         make_timer!(TIM15, tim15, 2, u16);
-        // todo: TIM15 on some variant has 2 channels (Eg H7). On others, like L4x3, it appears to be 1.
+        break_dead_time!(TIM15);
+        slave_mode!(TIM15);
+    }
+}
+
+// TIM15 has 2 channels per the RMs, but on some PACs, like L4x3, it appears to have 1.
+cfg_if! {
+    if #[cfg(any(feature = "f3", feature = "g4", feature = "h7"))] {
+        cc_2_channels!(TIM15, u16);
+    } else if #[cfg(not(any(
+        feature = "l5",
+        feature = "f4",
+        feature = "g031",
+        feature = "g041",
+        feature = "g030",
+        feature = "wb",
+        feature = "wl"
+    )))] {
         cc_1_channel!(TIM15, u16);
     }
 }
@@ -1709,6 +2605,8 @@ cfg_if! {
 make_timer!(TIM16, tim16, 2, u16);
 #[cfg(not(feature = "f4"))]
 cc_1_channel!(TIM16, u16);
+#[cfg(not(feature = "f4"))]
+break_dead_time!(TIM16);
 
 cfg_if! {
     if #[cfg(not(any(
@@ -1720,6 +2618,7 @@ cfg_if! {
     )))] {
         make_timer!(TIM17, tim17, 2, u16);
         cc_1_channel!(TIM17, u16);
+        break_dead_time!(TIM17);
     }
 }
 
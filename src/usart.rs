@@ -9,15 +9,19 @@
 
 use crate::{
     clocks::Clocks,
+    gpio::AltFnPin,
     pac::{self, RCC},
     util::{BaudPeriph, RccPeriph},
 };
 
-#[cfg(any(feature = "f3", feature = "l4"))]
+#[cfg(not(feature = "f4"))]
 use crate::util::DmaPeriph;
 
 use core::ops::Deref;
 
+#[cfg(not(any(feature = "g0", feature = "f4")))]
+use core::sync::atomic::{self, Ordering};
+
 use cortex_m::interrupt::free;
 
 #[cfg(feature = "g0")]
@@ -25,6 +29,7 @@ use crate::pac::dma as dma_p;
 #[cfg(any(
     feature = "f3",
     feature = "l4",
+    feature = "l5",
     feature = "g4",
     feature = "h7",
     feature = "wb",
@@ -32,7 +37,7 @@ use crate::pac::dma as dma_p;
 ))]
 use crate::pac::dma1 as dma_p;
 
-#[cfg(not(any(feature = "f4", feature = "l5")))]
+#[cfg(not(feature = "f4"))]
 use crate::dma::{self, Dma, DmaChannel};
 
 #[cfg(any(feature = "f3", feature = "l4"))]
@@ -44,11 +49,14 @@ use embedded_hal::{
     serial::{Read, Write},
 };
 
+#[cfg(feature = "embedded-io")]
+use embedded_io::{ErrorKind, ErrorType, ReadReady, WriteReady};
+
 use cfg_if::cfg_if;
 
 // todo: Prescaler (USART_PRESC) register on v3 (L5, G, H etc)
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug)]
 #[repr(u8)]
 /// The number of stop bits. (USART_CR2, STOP)
 pub enum StopBits {
@@ -58,7 +66,7 @@ pub enum StopBits {
     S1_5 = 0b11,
 }
 
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 /// Parity control enable/disable, and even/odd selection (USART_CR1, PCE and PS)
 pub enum Parity {
     EnabledEven,
@@ -66,11 +74,14 @@ pub enum Parity {
     Disabled,
 }
 
-#[derive(Clone, Copy)]
-/// The length of word to transmit and receive. (USART_CR1, M)
+#[derive(Clone, Copy, Debug)]
+/// The length of word to transmit and receive, including the parity bit, if parity is enabled.
+/// Eg, for 8 data bits with parity, use `W9`. (USART_CR1, M1 and M0)
 pub enum WordLen {
     W8,
+    /// Use `write_u16` and `read_u16` for 9-bit data.
     W9,
+    /// Not available on F4.
     W7,
 }
 
@@ -84,17 +95,33 @@ impl WordLen {
             Self::W7 => (1, 0),
         }
     }
+
+    /// The number of bits in the word, including parity.
+    fn len(&self) -> u8 {
+        match self {
+            Self::W8 => 8,
+            Self::W9 => 9,
+            Self::W7 => 7,
+        }
+    }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug)]
 #[repr(u8)]
-/// Set Oversampling16 or Oversampling8 modes.
+/// Set Oversampling16 or Oversampling8 modes. (USART_CR1, OVER8)
 pub enum OverSampling {
+    /// Oversampling by 16. Allows baud rates up to the USART kernel clock / 16, and is more
+    /// tolerant of clock deviation.
     O16 = 0,
+    /// Oversampling by 8. Allows baud rates up to the USART kernel clock / 8, eg 12.5Mbps from
+    /// a 100Mhz kernel clock, at the cost of lower tolerance to clock deviation. Not available on
+    /// LPUART.
     O8 = 1,
 }
 
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// IrDA SIR encoder/decoder mode, for driving infrared transceivers. (USART_CR3, IREN and IRLP)
+/// IrDA supports baud rates up to 115,200. See G4 RM, section 37.5.18: USART IrDA SIR ENDEC block.
 pub enum IrdaMode {
     /// "IrDA mode disabled
     None,
@@ -104,6 +131,192 @@ pub enum IrdaMode {
     LowPower,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(u8)]
+/// LIN break detection length: The number of consecutive low bits that are detected as a break.
+/// (USART_CR2, LBDL)
+pub enum LinBreakLen {
+    /// 10-bit break detection
+    B10 = 0,
+    /// 11-bit break detection
+    B11 = 1,
+}
+
+#[derive(Clone, Copy, Debug)]
+/// Smartcard (ISO 7816-3) mode configuration. In this mode, the USART communicates half-duplex
+/// on its TX pin, which must be configured as open-drain with a pull-up. Use 9-bit words with
+/// parity enabled, (ie 8 data bits, and the parity bit), and 1.5 stop bits; `Usart::new` panics
+/// otherwise. See G4 RM, section 37.5.17: USART Smartcard mode.
+pub struct SmartcardConfig {
+    /// Output a clock to the card on the CK pin. (USART_CR2, CLKEN)
+    pub clock_output: bool,
+    /// The USART's input clock is divided by twice this to generate the card clock, ie
+    /// f_CK = f_USART / (2 * prescaler). Must be between 1 and 31. (USART_GTPR, PSC)
+    pub prescaler: u8,
+    /// Guard time, in baud clock periods: The TC flag is delayed by this after a transmission.
+    /// (USART_GTPR, GT)
+    pub guard_time: u8,
+    /// Transmit a NACK when a parity error is received. (USART_CR3, NACK)
+    pub nack: bool,
+    #[cfg(not(feature = "f4"))]
+    /// The number of times to retransmit a word that the card NACKs, or to wait for a retransmission
+    /// of a received word with a parity error, between 0 and 7. If retries are exhausted, a framing
+    /// error is flagged on transmit, and a parity error on receive. 0 disables automatic
+    /// retransmission. (USART_CR3, SCARCNT)
+    pub retries: u8,
+}
+
+impl Default for SmartcardConfig {
+    fn default() -> Self {
+        Self {
+            clock_output: true,
+            prescaler: 1,
+            guard_time: 0,
+            nack: true,
+            #[cfg(not(feature = "f4"))]
+            retries: 3,
+        }
+    }
+}
+
+#[cfg(not(feature = "f4"))]
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(u8)]
+/// The active level of the RS-485 driver enable (DE) signal. (USART_CR3, DEP)
+pub enum DePolarity {
+    /// DE is high while transmitting.
+    ActiveHigh = 0,
+    /// DE is low while transmitting.
+    ActiveLow = 1,
+}
+
+#[cfg(not(feature = "f4"))]
+#[derive(Clone, Copy, Debug)]
+/// RS-485 driver enable configuration. In this mode, the USART drives its DE output while
+/// transmitting, to switch an external transceiver between transmit and receive without software
+/// timing. DE shares its pin, and alternate function, with RTS: Configure the RTS pin in
+/// alternate function mode, using the AF listed for USARTx_RTS_DE in the datasheet.
+/// See G4 RM, section 37.5.21: RS232 hardware flow control and RS485 driver enable.
+pub struct Rs485Config {
+    /// Defaults to active high.
+    pub polarity: DePolarity,
+    /// The time between DE activation and the start of the first start bit, in sample time
+    /// units (1/16 or 1/8 bit time, depending on oversampling). Must be 31 or lower.
+    /// (USART_CR1, DEAT)
+    pub assertion_time: u8,
+    /// The time between the end of the last stop bit and DE deactivation, in sample time units.
+    /// Must be 31 or lower. (USART_CR1, DEDT)
+    pub deassertion_time: u8,
+}
+
+#[cfg(not(feature = "f4"))]
+impl Default for Rs485Config {
+    fn default() -> Self {
+        Self {
+            polarity: DePolarity::ActiveHigh,
+            assertion_time: 0,
+            deassertion_time: 0,
+        }
+    }
+}
+
+#[cfg(any(feature = "g4", feature = "l5", feature = "h7", feature = "wb", feature = "wl"))]
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(u8)]
+/// FIFO threshold, as a fraction of the 8-word FIFO depth. The threshold flag (and interrupt, if
+/// enabled) is set when the TX FIFO drops to, or the RX FIFO reaches, this level.
+/// (USART_CR3, TXFTCFG and RXFTCFG)
+pub enum FifoThreshold {
+    /// 1/8 of the FIFO depth
+    T1_8 = 0b000,
+    /// 1/4 of the FIFO depth
+    T1_4 = 0b001,
+    /// 1/2 of the FIFO depth
+    T1_2 = 0b010,
+    /// 3/4 of the FIFO depth
+    T3_4 = 0b011,
+    /// 7/8 of the FIFO depth
+    T7_8 = 0b100,
+    /// The FIFO is empty (TX), or full (RX).
+    Full = 0b101,
+}
+
+#[cfg(not(feature = "f4"))]
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(u8)]
+/// How a muted receiver wakes up, in multiprocessor mode. (USART_CR1, WAKE)
+pub enum WakeMethod {
+    /// Wake when the line goes idle. The receiver is muted again with `enter_mute`.
+    IdleLine = 0,
+    /// Wake when a word with its MSB set, and an address matching ours, is received. The
+    /// receiver mutes itself again on receiving an address word that doesn't match.
+    AddressMark = 1,
+}
+
+#[cfg(not(feature = "f4"))]
+#[derive(Clone, Copy, Debug)]
+/// Multiprocessor communication configuration, for multi-drop networks, eg RS-485, where one
+/// controller addresses several nodes. The receiver is muted until the wake condition occurs:
+/// While muted, it sets no receive flags, and raises no receive interrupts. See G4 RM, section
+/// 37.5.10: USART multiprocessor communication.
+pub struct MultiprocessorConfig {
+    /// Defaults to address mark.
+    pub wake: WakeMethod,
+    /// This node's address. In address mark mode, an address word is one with its MSB set;
+    /// the remaining bits are compared to this. (USART_CR2, ADD)
+    pub address: u8,
+    /// Compare 7 address bits, instead of 4. (With 8-bit words, the 7 bits below the MSB.)
+    /// Defaults to false. (USART_CR2, ADDM7)
+    pub address_7bit: bool,
+}
+
+#[cfg(not(feature = "f4"))]
+impl Default for MultiprocessorConfig {
+    fn default() -> Self {
+        Self {
+            wake: WakeMethod::AddressMark,
+            address: 0,
+            address_7bit: false,
+        }
+    }
+}
+
+#[cfg(not(feature = "f4"))]
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(u8)]
+/// The character pattern auto baud rate detection measures. (USART_CR2, ABRMOD) The first
+/// character received after a detection request must match it. See G4 RM, section 37.5.8:
+/// "USART auto baud rate detection".
+pub enum AutoBaudMode {
+    /// Measure the start bit. The character must start with a 1 bit, eg any odd character.
+    StartBit = 0b00,
+    /// Measure between falling edges. The character must start with 10xx bits.
+    FallingEdge = 0b01,
+    /// The character must be 0x7F.
+    Frame0x7F = 0b10,
+    /// The character must be 0x55.
+    Frame0x55 = 0b11,
+}
+
+/// Diagnostic returned by `Usart::self_test` when the test fails.
+#[derive(Clone, Copy, Debug)]
+pub enum SelfTestError {
+    /// A reception error occurred.
+    Usart(Error),
+    /// A word wasn't received in time. This usually means TX isn't jumpered to RX, or the
+    /// pins aren't configured for the USART's alternate function.
+    Timeout { index: usize },
+    /// A received word didn't match the one sent.
+    Mismatch { index: usize, sent: u8, received: u8 },
+}
+
+/// Words transmitted by `Usart::self_test`.
+const SELF_TEST_PATTERN: [u8; 8] = [0x00, 0xff, 0x55, 0xaa, 0x0f, 0xf0, 0x01, 0x80];
+
+/// Number of status register polls `Usart::self_test` waits for each word before timing out.
+/// This is generous for any baud rate over a few hundred bps, at typical core clock speeds.
+const SELF_TEST_TIMEOUT: u32 = 1_000_000;
+
 #[cfg(not(feature = "f4"))]
 #[derive(Clone, Copy)]
 /// The type of USART interrupt to configure. Reference the USART_ISR register.
@@ -117,11 +330,25 @@ pub enum UsartInterrupt {
     Overrun,
     ParityError,
     ReadNotEmpty,
+    /// No new word has started within the receiver timeout, after the last word's stop bit.
+    /// Configure it with `set_receiver_timeout`. (RTOIE)
     ReceiverTimeout,
     #[cfg(not(any(feature = "f3", feature = "l4")))] // todo: PAC ommission?
     Tcbgt,
     TransmissionComplete,
     TransmitEmpty,
+    #[cfg(any(feature = "g4", feature = "l5", feature = "h7", feature = "wb", feature = "wl"))]
+    /// The TX FIFO has reached its threshold. (TXFTIE)
+    TxFifoThreshold,
+    #[cfg(any(feature = "g4", feature = "l5", feature = "h7", feature = "wb", feature = "wl"))]
+    /// The RX FIFO has reached its threshold. (RXFTIE)
+    RxFifoThreshold,
+    #[cfg(any(feature = "g4", feature = "l5", feature = "h7", feature = "wb", feature = "wl"))]
+    /// The TX FIFO is empty. (TXFEIE)
+    TxFifoEmpty,
+    #[cfg(any(feature = "g4", feature = "l5", feature = "h7", feature = "wb", feature = "wl"))]
+    /// The RX FIFO is full. (RXFFIE)
+    RxFifoFull,
 }
 
 /// Configuration for Usart. Can be used with default::Default.
@@ -136,6 +363,49 @@ pub struct UsartConfig {
     pub parity: Parity,
     /// IrDA mode: Enables this protocol, which is used to communicate with IR devices.
     pub irda_mode: IrdaMode,
+    /// IrDA low-power mode prescaler. The USART's input clock is divided by this to generate the
+    /// low-power pulse frequency, which should be between 1.42 and 2.12Mhz. Eg, 8 with a 16Mhz
+    /// clock. Must be nonzero. Ignored unless `irda_mode` is `LowPower`. Defaults to 1.
+    /// (USART_GTPR, PSC)
+    pub irda_prescaler: u8,
+    /// Smartcard (ISO 7816) mode. Defaults to `None`: disabled.
+    pub smartcard: Option<SmartcardConfig>,
+    /// LIN mode, with the break detection length. Use 8-bit words, with no parity, and 1 stop bit.
+    /// Enable the `LineBreak` interrupt to be notified of received breaks, and send breaks with
+    /// `send_break`. Defaults to `None`: disabled.
+    pub lin_mode: Option<LinBreakLen>,
+    #[cfg(not(feature = "f4"))]
+    /// RS-485 driver enable mode. Defaults to `None`: disabled.
+    pub rs485: Option<Rs485Config>,
+    #[cfg(not(feature = "f4"))]
+    /// Multiprocessor mode, which mutes the receiver until it's addressed. Not compatible with
+    /// the `CharDetect` interrupt, which shares the address register. Defaults to `None`: disabled.
+    pub multiprocessor: Option<MultiprocessorConfig>,
+    #[cfg(not(feature = "f4"))]
+    /// Swap the TX and RX pins' functions, eg to fix a board layout mistake. Defaults to false.
+    /// (USART_CR2, SWAP)
+    pub swap_pins: bool,
+    #[cfg(not(feature = "f4"))]
+    /// Invert the TX pin's logic levels, eg for an inverted-logic transceiver. Defaults to false.
+    /// (USART_CR2, TXINV)
+    pub tx_inverted: bool,
+    #[cfg(not(feature = "f4"))]
+    /// Invert the RX pin's logic levels. Defaults to false. (USART_CR2, RXINV)
+    pub rx_inverted: bool,
+    #[cfg(not(feature = "f4"))]
+    /// Invert the data bits, including parity, but not the start and stop bits. Defaults to
+    /// false. (USART_CR2, DATAINV)
+    pub data_inverted: bool,
+    #[cfg(any(feature = "g4", feature = "l5", feature = "h7", feature = "wb", feature = "wl"))]
+    /// Enable the 8-word TX and RX FIFOs. This allows high baud rates to tolerate more interrupt
+    /// latency without overruns. Defaults to disabled.
+    pub fifo_enabled: bool,
+    #[cfg(any(feature = "g4", feature = "l5", feature = "h7", feature = "wb", feature = "wl"))]
+    /// TX FIFO threshold, for the `TxFifoThreshold` interrupt. Defaults to 1/8.
+    pub tx_fifo_thresh: FifoThreshold,
+    #[cfg(any(feature = "g4", feature = "l5", feature = "h7", feature = "wb", feature = "wl"))]
+    /// RX FIFO threshold, for the `RxFifoThreshold` interrupt. Defaults to 1/8.
+    pub rx_fifo_thresh: FifoThreshold,
 }
 
 impl Default for UsartConfig {
@@ -146,15 +416,173 @@ impl Default for UsartConfig {
             oversampling: OverSampling::O16,
             parity: Parity::Disabled,
             irda_mode: IrdaMode::None,
+            irda_prescaler: 1,
+            smartcard: None,
+            lin_mode: None,
+            #[cfg(not(feature = "f4"))]
+            rs485: None,
+            #[cfg(not(feature = "f4"))]
+            multiprocessor: None,
+            #[cfg(not(feature = "f4"))]
+            swap_pins: false,
+            #[cfg(not(feature = "f4"))]
+            tx_inverted: false,
+            #[cfg(not(feature = "f4"))]
+            rx_inverted: false,
+            #[cfg(not(feature = "f4"))]
+            data_inverted: false,
+            #[cfg(any(feature = "g4", feature = "l5", feature = "h7", feature = "wb", feature = "wl"))]
+            fifo_enabled: false,
+            #[cfg(any(feature = "g4", feature = "l5", feature = "h7", feature = "wb", feature = "wl"))]
+            tx_fifo_thresh: FifoThreshold::T1_8,
+            #[cfg(any(feature = "g4", feature = "l5", feature = "h7", feature = "wb", feature = "wl"))]
+            rx_fifo_thresh: FifoThreshold::T1_8,
         }
     }
 }
 
+/// USART configuration, decoded from its registers by `Usart::dump`. Print it with `{:?}`, eg
+/// to attach to a bug report, or to compare against the RM. (With `defmt`, use `Debug2Format`.)
+#[derive(Clone, Copy, Debug)]
+pub struct UsartDump {
+    /// USART_CR1, UE
+    pub enabled: bool,
+    /// USART_CR1, TE
+    pub tx_enabled: bool,
+    /// USART_CR1, RE
+    pub rx_enabled: bool,
+    /// The baud rate, calculated from USART_BRR and the USART's input clock.
+    pub baud: u32,
+    /// USART_CR1, M1 and M0
+    pub word_len: WordLen,
+    /// USART_CR2, STOP
+    pub stop_bits: StopBits,
+    /// USART_CR1, PCE and PS
+    pub parity: Parity,
+    /// USART_CR1, OVER8
+    pub oversampling: OverSampling,
+    /// USART_CR3, IREN and IRLP
+    pub irda_mode: IrdaMode,
+    /// USART_CR3, SCEN
+    pub smartcard: bool,
+    /// USART_CR2, LINEN
+    pub lin_mode: bool,
+    /// USART_CR3, DMAT
+    pub tx_dma: bool,
+    /// USART_CR3, DMAR
+    pub rx_dma: bool,
+    #[cfg(not(feature = "f4"))]
+    /// USART_CR3, DEM
+    pub rs485: bool,
+    #[cfg(not(feature = "f4"))]
+    /// USART_CR1, MME
+    pub multiprocessor: bool,
+    #[cfg(not(feature = "f4"))]
+    /// USART_CR2, SWAP
+    pub swap_pins: bool,
+    #[cfg(not(feature = "f4"))]
+    /// USART_CR2, TXINV
+    pub tx_inverted: bool,
+    #[cfg(not(feature = "f4"))]
+    /// USART_CR2, RXINV
+    pub rx_inverted: bool,
+    #[cfg(not(feature = "f4"))]
+    /// USART_CR2, DATAINV
+    pub data_inverted: bool,
+    #[cfg(any(feature = "g4", feature = "l5", feature = "h7", feature = "wb", feature = "wl"))]
+    /// USART_CR1, FIFOEN
+    pub fifo_enabled: bool,
+}
+
+cfg_if! {
+    if #[cfg(feature = "wl")] {
+        use pac::LPUART as LPUART1;
+    } else if #[cfg(not(any(
+        feature = "f3",
+        feature = "f4",
+        feature = "g030",
+        feature = "g050",
+        feature = "g070",
+        feature = "g0b0"
+    )))] {
+        use pac::LPUART1;
+    }
+}
+
+#[cfg(not(any(
+    feature = "f3",
+    feature = "f4",
+    feature = "g030",
+    feature = "g050",
+    feature = "g070",
+    feature = "g0b0"
+)))]
+/// The PAC gives LPUART its own register block type, although its layout is a subset of USART's.
+/// Wrap it in this to use it with `Usart`. Example:
+/// `let mut uart = Usart::new(Lpuart1(dp.LPUART1), 9_600, Default::default(), &clock_cfg);`
+/// LPUART doesn't support oversampling by 8, IrDA, LIN, smartcard, or synchronous modes. Select
+/// its kernel clock using the `lpuart_src` field of `Clocks`; HSI16 or LSE allow it to run in
+/// Stop mode.
+pub struct Lpuart1(pub LPUART1);
+
+#[cfg(not(any(
+    feature = "f3",
+    feature = "f4",
+    feature = "g030",
+    feature = "g050",
+    feature = "g070",
+    feature = "g0b0"
+)))]
+impl Deref for Lpuart1 {
+    type Target = pac::usart1::RegisterBlock;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*(LPUART1::ptr() as *const pac::usart1::RegisterBlock) }
+    }
+}
+
+#[cfg(any(feature = "g0b1", feature = "g0c1"))]
+/// Wraps LPUART2, for use with `Usart`. See `Lpuart1` for details.
+pub struct Lpuart2(pub pac::LPUART2);
+
+#[cfg(any(feature = "g0b1", feature = "g0c1"))]
+impl Deref for Lpuart2 {
+    type Target = pac::usart1::RegisterBlock;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*(pac::LPUART2::ptr() as *const pac::usart1::RegisterBlock) }
+    }
+}
+
+#[cfg(not(any(feature = "g0", feature = "f4")))]
+/// State for reception into a circular DMA buffer; see `Usart::read_dma_circular`.
+struct CircularRx {
+    channel: DmaChannel,
+    /// The buffer's address. (Stored as an integer, so `Usart` remains `Send`)
+    buf_addr: usize,
+    buf_len: usize,
+    /// The index in the buffer of the next byte `read_available` will return.
+    read_pos: usize,
+}
+
+/// A pin that can be used as U[s]ART `R`'s TX signal. Implemented for the tokens of pins with
+/// the signal, along with its alternate function number. See `Usart::new_with_pins`.
+pub trait TxPin<R>: AltFnPin {
+    const AF: u8;
+}
+
+/// A pin that can be used as U[s]ART `R`'s RX signal. See `TxPin`.
+pub trait RxPin<R>: AltFnPin {
+    const AF: u8;
+}
+
 /// Represents the USART peripheral, for serial communications.
 pub struct Usart<R> {
     pub regs: R,
     baud: u32,
     config: UsartConfig,
+    #[cfg(not(any(feature = "g0", feature = "f4")))]
+    circular_rx: Option<CircularRx>,
 }
 
 impl<R> Usart<R>
@@ -170,7 +598,13 @@ where
             R::en_reset(rcc);
         });
 
-        let mut result = Self { regs, baud, config };
+        let mut result = Self {
+            regs,
+            baud,
+            config,
+            #[cfg(not(any(feature = "g0", feature = "f4")))]
+            circular_rx: None,
+        };
 
         // This should already be disabled on power up, but disable here just in case;
         // some bits can't be set with USART enabled.
@@ -183,9 +617,15 @@ where
         // Set up transmission. See L44 RM, section 38.5.2: "Character Transmission Procedures".
         // 1. Program the M bits in USART_CR1 to define the word length.
 
+        #[cfg(feature = "f4")]
+        if let WordLen::W7 = result.config.word_len {
+            panic!("7-bit words aren't available on F4.");
+        }
+
         let word_len_bits = result.config.word_len.bits();
         result.regs.cr1.modify(|_, w| {
-            w.over8().bit(result.config.oversampling as u8 != 0);
+            // OVER8 is reserved on LPUART.
+            w.over8().bit(result.config.oversampling as u8 != 0 && !R::low_power());
             w.pce().bit(result.config.parity != Parity::Disabled);
             cfg_if! {
                 if #[cfg(not(any(feature = "f3", feature = "f4", feature = "wl")))] {
@@ -198,8 +638,9 @@ where
             }
         });
 
-        // todo: Workaround due to a PAC bug, where M0 is missing.
-        #[cfg(any(feature = "f3", feature = "f4"))]
+        // todo: Workaround due to a PAC bug, where M0 is missing. (And on WL, where the M fields
+        // todo: are named differently)
+        #[cfg(any(feature = "f3", feature = "f4", feature = "wl"))]
         result.regs.cr1.write(|w| unsafe {
             w.bits(
                 result.regs.cr1.read().bits()
@@ -209,26 +650,39 @@ where
         });
 
         // 2. Select the desired baud rate using the USART_BRR register.
-        result.set_baud(baud, clock_cfg);
+        result
+            .set_baud(baud, clock_cfg)
+            .expect("Baud rate out of range for the U[S]ART's kernel clock.");
         // 3. Program the number of stop bits in USART_CR2.
         result
             .regs
             .cr2
             .modify(|_, w| unsafe { w.stop().bits(result.config.stop_bits as u8) });
-        // 4. Enable the USART by writing the UE bit in USART_CR1 register to 1.
-        result.regs.cr1.modify(|_, w| w.ue().set_bit());
-        // 5. Select DMA enable (DMAT[R]] in USART_CR3 if multibuffer communication is to take
-        // place. Configure the DMA register as explained in multibuffer communication.
-        // (Handled in `read_dma()` and `write_dma()`)
-        // 6. Set the TE bit in USART_CR1 to send an idle frame as first transmission.
-        // 6. Set the RE bit USART_CR1. This enables the receiver which begins searching for a
-        // start bit.
 
-        result.regs.cr1.modify(|_, w| {
-            w.te().set_bit();
-            w.re().set_bit()
+        // Pin swapping and inversion can only be configured while UE = 0.
+        #[cfg(not(feature = "f4"))]
+        result.regs.cr2.modify(|_, w| {
+            w.swap().bit(result.config.swap_pins);
+            w.txinv().bit(result.config.tx_inverted);
+            w.rxinv().bit(result.config.rx_inverted);
+            w.datainv().bit(result.config.data_inverted)
         });
 
+        // FIFO mode, and its thresholds, can only be configured while UE = 0. See G4 RM, section
+        // 37.5.3: USART FIFOs and thresholds.
+        #[cfg(any(feature = "g4", feature = "l5", feature = "h7", feature = "wb", feature = "wl"))]
+        {
+            result
+                .regs
+                .cr1
+                .modify(|_, w| w.fifoen().bit(result.config.fifo_enabled));
+            result.regs.cr3.modify(|_, w| unsafe {
+                w.txftcfg().bits(result.config.tx_fifo_thresh as u8);
+                w.rxftcfg().bits(result.config.rx_fifo_thresh as u8)
+            });
+        }
+
+        // IREN, and the bits IrDA requires to be cleared, can only be written with UE = 0.
         match result.config.irda_mode {
             // See G4 RM, section 37.5.18: USART IrDA SIR ENDEC block
             // " IrDA mode is selected by setting the IREN bit in the USART_CR3 register. In IrDA mode,
@@ -236,6 +690,19 @@ where
             // • LINEN, STOP and CLKEN bits in the USART_CR2 register,
             IrdaMode::None => (),
             _ => {
+                if R::low_power() {
+                    panic!("LPUART doesn't support IrDA mode.");
+                }
+                // A prescaler value of 0 is reserved in IrDA mode. In normal mode, it must be 1.
+                let psc = match result.config.irda_mode {
+                    IrdaMode::LowPower => {
+                        assert!(result.config.irda_prescaler != 0);
+                        result.config.irda_prescaler
+                    }
+                    _ => 1,
+                };
+                result.regs.gtpr.modify(|_, w| unsafe { w.psc().bits(psc) });
+
                 result.regs.cr2.modify(|_, w| unsafe {
                     w.linen().clear_bit();
                     w.stop().bits(0);
@@ -252,48 +719,195 @@ where
             }
         }
 
+        // See G4 RM, section 37.5.17: USART Smartcard mode. These bits can only be written with
+        // UE = 0.
+        if let Some(sc) = result.config.smartcard {
+            if R::low_power() {
+                panic!("LPUART doesn't support smartcard mode.");
+            }
+            // "The Smartcard mode implements a single wire half duplex communication protocol.
+            // 9 bits data frame as 8 data bits and parity with 1.5 stop bits."
+            if !matches!(result.config.word_len, WordLen::W9)
+                || result.config.parity == Parity::Disabled
+                || !matches!(result.config.stop_bits, StopBits::S1_5)
+            {
+                panic!("Smartcard mode requires 9-bit words, with parity, and 1.5 stop bits.");
+            }
+            assert!(sc.prescaler >= 1 && sc.prescaler <= 31);
+
+            result.regs.gtpr.modify(|_, w| unsafe {
+                w.gt().bits(sc.guard_time);
+                w.psc().bits(sc.prescaler)
+            });
+
+            // "Smartcard mode is selected by setting the SCEN bit in the USART_CR3 register. In
+            // Smartcard mode, the following bits must be kept cleared:
+            // • LINEN bit in the USART_CR2 register,
+            // • HDSEL and IREN bits in the USART_CR3 register.
+            // Moreover, the CLKEN bit may be set in order to provide a clock to the smartcard."
+            result.regs.cr2.modify(|_, w| {
+                w.linen().clear_bit();
+                w.clken().bit(sc.clock_output)
+            });
+
+            result.regs.cr3.modify(|_, w| {
+                w.hdsel().clear_bit();
+                w.iren().clear_bit();
+                w.nack().bit(sc.nack);
+                w.scen().set_bit()
+            });
+
+            #[cfg(not(feature = "f4"))]
+            {
+                assert!(sc.retries <= 7);
+                result
+                    .regs
+                    .cr3
+                    .modify(|_, w| unsafe { w.scarcnt().bits(sc.retries) });
+            }
+        }
+
+        // See G4 RM, section 37.5.14: USART LIN (local interconnection network) mode. These bits
+        // can only be written with UE = 0.
+        if let Some(break_len) = result.config.lin_mode {
+            if R::low_power() {
+                panic!("LPUART doesn't support LIN mode.");
+            }
+            if result.config.irda_mode != IrdaMode::None || result.config.smartcard.is_some() {
+                panic!("LIN mode can't be used with IrDA or smartcard modes.");
+            }
+
+            // "The LIN mode is selected by setting the LINEN bit in the USART_CR2 register. In LIN
+            // mode, the following bits must be kept cleared:
+            // • STOP[1:0] and CLKEN in the USART_CR2 register,
+            // • SCEN, HDSEL and IREN in the USART_CR3 register."
+            result.regs.cr2.modify(|_, w| unsafe {
+                w.clken().clear_bit();
+                w.stop().bits(0);
+                w.lbdl().bit(break_len as u8 != 0);
+                w.linen().set_bit()
+            });
+
+            result.regs.cr3.modify(|_, w| {
+                w.scen().clear_bit();
+                w.hdsel().clear_bit();
+                w.iren().clear_bit()
+            });
+        }
+
+        // See G4 RM, section 37.5.21: "RS485 Driver Enable". These bits can only be written with
+        // UE = 0.
+        #[cfg(not(feature = "f4"))]
+        if let Some(de) = result.config.rs485 {
+            assert!(de.assertion_time <= 31 && de.deassertion_time <= 31);
+
+            // todo: Field names for DEAT and DEDT are inconsistent across PACs, so we set them
+            // todo directly. DEAT is at CR1 bits 21:25, and DEDT is at bits 16:20.
+            result.regs.cr1.modify(|r, w| unsafe {
+                w.bits(
+                    (r.bits() & !(0x3ff << 16))
+                        | ((de.assertion_time as u32) << 21)
+                        | ((de.deassertion_time as u32) << 16),
+                )
+            });
+
+            result.regs.cr3.modify(|_, w| {
+                w.dep().bit(de.polarity as u8 != 0);
+                w.dem().set_bit()
+            });
+        }
+
+        // See G4 RM, section 37.5.10: "USART multiprocessor communication". ADD, ADDM7, WAKE and
+        // MME can only be written with UE = 0.
+        #[cfg(not(feature = "f4"))]
+        if let Some(mp) = result.config.multiprocessor {
+            // todo: ADD is split into 2 fields on some PACs, so we set it directly. ADD is at CR2
+            // todo bits 24:31, and ADDM7 is at bit 4. WAKE is at CR1 bit 11, and MME at bit 13.
+            result.regs.cr2.modify(|r, w| unsafe {
+                w.bits(
+                    (r.bits() & !((0xff << 24) | (1 << 4)))
+                        | ((mp.address as u32) << 24)
+                        | ((mp.address_7bit as u32) << 4),
+                )
+            });
+            result.regs.cr1.modify(|r, w| unsafe {
+                w.bits((r.bits() & !(1 << 11)) | ((mp.wake as u32) << 11) | (1 << 13))
+            });
+        }
+
+        // 4. Enable the USART by writing the UE bit in USART_CR1 register to 1.
+        result.regs.cr1.modify(|_, w| w.ue().set_bit());
+        // 5. Select DMA enable (DMAT[R]] in USART_CR3 if multibuffer communication is to take
+        // place. Configure the DMA register as explained in multibuffer communication.
+        // (Handled in `read_dma()` and `write_dma()`)
+        // 6. Set the TE bit in USART_CR1 to send an idle frame as first transmission.
+        // 6. Set the RE bit USART_CR1. This enables the receiver which begins searching for a
+        // start bit.
+
+        result.regs.cr1.modify(|_, w| {
+            w.te().set_bit();
+            w.re().set_bit()
+        });
+
         result
     }
 
-    /// Set the BAUD rate. Called during init, and can be called later to change BAUD
-    /// during program execution.
-    pub fn set_baud(&mut self, baud: u32, clock_cfg: &Clocks) {
-        let originally_enabled = self.regs.cr1.read().ue().bit_is_set();
+    /// Initialize a U[s]ART peripheral as with `new`, and configure its TX and RX pins with the
+    /// correct alternate function. Pins without this U[s]ART's signal fail to compile. Their tokens
+    /// are consumed, so the pins can't be taken by other drivers.
+    pub fn new_with_pins<TX: TxPin<R>, RX: RxPin<R>>(
+        regs: R,
+        tx: TX,
+        rx: RX,
+        baud: u32,
+        config: UsartConfig,
+        clock_cfg: &Clocks,
+    ) -> Self {
+        let result = Self::new(regs, baud, config, clock_cfg);
+
+        tx.into_alt_fn(TX::AF);
+        rx.into_alt_fn(RX::AF);
 
-        if originally_enabled {
-            self.regs.cr1.modify(|_, w| w.ue().clear_bit());
-            while self.regs.cr1.read().ue().bit_is_set() {}
-        }
+        result
+    }
 
+    /// Set the BAUD rate. Called during init, and can be called later to change BAUD
+    /// during program execution. Returns `Error::BaudRate`, leaving the rate unchanged, if `baud`
+    /// can't be generated from the U[S]ART's kernel clock with the oversampling setting.
+    pub fn set_baud(&mut self, baud: u32, clock_cfg: &Clocks) -> Result<(), Error> {
         // To set BAUD rate, see L4 RM section 38.5.4: "USART baud rate generation".
         // todo: This assumes the USART clock is APB1 or 2 depending on which USART.
         // todo: Take into account the selectable USART clock in both
         // todo util::baud implementation, and `clocks` module.
-        let fclk = R::baud(clock_cfg);
+        let brr = brr_val(
+            R::baud(clock_cfg),
+            baud,
+            self.config.oversampling,
+            R::low_power(),
+        )?;
 
-        let usart_div = match self.config.oversampling {
-            OverSampling::O16 => fclk / baud,
-            OverSampling::O8 => 2 * fclk / baud,
-        };
+        let originally_enabled = self.regs.cr1.read().ue().bit_is_set();
 
-        // USARTDIV is an unsigned fixed point number that is coded on the USART_BRR register.
-        // • When OVER8 = 0, BRR = USARTDIV.
-        // • When OVER8 = 1
-        // – BRR[2:0] = USARTDIV[3:0] shifted 1 bit to the right.
-        // – BRR[3] must be kept cleared.
-        // – BRR[15:4] = USARTDIV[15:4]
-        // todo: BRR needs to be modified per the above if on oversampling 8.
+        if originally_enabled {
+            self.regs.cr1.modify(|_, w| w.ue().clear_bit());
+            while self.regs.cr1.read().ue().bit_is_set() {}
+        }
 
-        self.regs.brr.write(|w| unsafe { w.bits(usart_div as u32) });
+        self.regs.brr.write(|w| unsafe { w.bits(brr) });
 
         self.baud = baud;
 
         if originally_enabled {
             self.regs.cr1.modify(|_, w| w.ue().set_bit());
         }
+
+        Ok(())
     }
 
     /// Transmit data, as a sequence of u8. See L44 RM, section 38.5.2: "Character transmission procedure"
+    ///
+    /// In FIFO mode, TXE reads as TXFNF (TX FIFO not full), so words are queued back-to-back
+    /// while the FIFO has room, instead of one per shift register transfer.
     pub fn write(&mut self, data: &[u8]) {
         // 7. Write the data to send in the USART_TDR register (this clears the TXE bit). Repeat this
         // for each data to be transmitted in case of single buffer.
@@ -302,8 +916,7 @@ where
             if #[cfg(not(feature = "f4"))] {
                 for word in data {
                     while self.regs.isr.read().txe().bit_is_clear() {}
-                    // todo: how does this work with a 9 bit words? Presumably you'd need to make `data`
-                    // todo take `&u16`.
+                    // For 9-bit words, use `write_u16`.
                     self.regs
                         .tdr
                         .modify(|_, w| unsafe { w.tdr().bits(*word as u16) });
@@ -331,16 +944,18 @@ where
     }
 
     /// Receive data into a u8 buffer. See L44 RM, section 38.5.3: "Character reception procedure"
+    ///
+    /// In FIFO mode, RXNE reads as RXFNE (RX FIFO not empty); each read of RDR pops a word.
     pub fn read(&mut self, buf: &mut [u8]) {
         for i in 0..buf.len() {
             // Wait for the next bit
             cfg_if! {
                 if #[cfg(not(feature = "f4"))] {
                     while self.regs.isr.read().rxne().bit_is_clear() {}
-                    buf[i] = self.regs.rdr.read().rdr().bits() as u8;
+                    buf[i] = (self.regs.rdr.read().rdr().bits() & self.data_mask()) as u8;
                 } else {
                     while self.regs.sr.read().rxne().bit_is_clear() {}
-                    buf[i] = self.regs.dr.read().dr().bits() as u8;
+                    buf[i] = (self.regs.dr.read().dr().bits() & self.data_mask()) as u8;
                 }
             }
         }
@@ -360,6 +975,59 @@ where
         // reception of the next character to avoid an overrun error
     }
 
+    /// Transmit 9-bit data, as a sequence of u16. Bits above the data length are ignored. Use
+    /// this with `WordLen::W9`, and parity disabled. For other word lengths, this is equivalent
+    /// to `write`.
+    pub fn write_u16(&mut self, data: &[u16]) {
+        let mask = self.data_mask();
+
+        cfg_if! {
+            if #[cfg(not(feature = "f4"))] {
+                for word in data {
+                    while self.regs.isr.read().txe().bit_is_clear() {}
+                    self.regs
+                        .tdr
+                        .modify(|_, w| unsafe { w.tdr().bits(*word & mask) });
+                }
+                while self.regs.isr.read().tc().bit_is_clear() {}
+            } else {
+                for word in data {
+                    while self.regs.sr.read().txe().bit_is_clear() {}
+                    self.regs
+                        .dr
+                        .modify(|_, w| unsafe { w.dr().bits(*word & mask) });
+                }
+                while self.regs.sr.read().tc().bit_is_clear() {}
+            }
+        }
+    }
+
+    /// Receive 9-bit data into a u16 buffer. Use this with `WordLen::W9`, and parity disabled.
+    /// If parity is enabled, the parity bit is masked out of each word.
+    pub fn read_u16(&mut self, buf: &mut [u16]) {
+        let mask = self.data_mask();
+
+        for word in buf.iter_mut() {
+            cfg_if! {
+                if #[cfg(not(feature = "f4"))] {
+                    while self.regs.isr.read().rxne().bit_is_clear() {}
+                    *word = self.regs.rdr.read().rdr().bits() & mask;
+                } else {
+                    while self.regs.sr.read().rxne().bit_is_clear() {}
+                    *word = self.regs.dr.read().dr().bits() & mask;
+                }
+            }
+        }
+    }
+
+    /// A mask of the data bits in each word. "When receiving with the parity enabled, the value
+    /// read in the MSB bit is the received parity bit." (G4 RM, section 37.5.11: USART parity
+    /// control)
+    fn data_mask(&self) -> u16 {
+        let data_bits = self.config.word_len.len() - (self.config.parity != Parity::Disabled) as u8;
+        (1 << data_bits) - 1
+    }
+
     /// Read a single word, without confirming if it's ready. This is useful in async concepts,
     /// when you know word is ready to be read.
     pub fn read_one(&mut self) -> u8 {
@@ -372,12 +1040,192 @@ where
         }
     }
 
-    #[cfg(not(any(feature = "g0", feature = "h7", feature = "f4", feature = "l5")))]
+    #[cfg(any(feature = "g4", feature = "l5", feature = "h7", feature = "wb", feature = "wl"))]
+    /// Queue as much of `data` in the TX FIFO as fits, without blocking. Returns the number of
+    /// words queued. Use this from the `TxFifoThreshold` or `TxFifoEmpty` interrupt handler
+    /// to refill the FIFO, for one interrupt per several words. Requires `fifo_enabled`.
+    /// See G4 RM, section 37.5.3: USART FIFOs and thresholds.
+    pub fn write_fifo(&mut self, data: &[u8]) -> usize {
+        let mut written = 0;
+
+        for word in data {
+            // TXFNF shares its bit with TXE.
+            if self.regs.isr.read().txe().bit_is_clear() {
+                break;
+            }
+            self.regs
+                .tdr
+                .modify(|_, w| unsafe { w.tdr().bits(*word as u16) });
+            written += 1;
+        }
+
+        written
+    }
+
+    #[cfg(any(feature = "g4", feature = "l5", feature = "h7", feature = "wb", feature = "wl"))]
+    /// Read all words currently in the RX FIFO into `buf`, up to its length, without blocking.
+    /// Returns the number of words read. Use this from the `RxFifoThreshold` or `RxFifoFull`
+    /// interrupt handler; reading the FIFO below its threshold clears those flags. Requires
+    /// `fifo_enabled`.
+    pub fn read_fifo(&mut self, buf: &mut [u8]) -> usize {
+        let mut read = 0;
+
+        for word in buf.iter_mut() {
+            // RXFNE shares its bit with RXNE.
+            if self.regs.isr.read().rxne().bit_is_clear() {
+                break;
+            }
+            *word = self.regs.rdr.read().rdr().bits() as u8;
+            read += 1;
+        }
+
+        read
+    }
+
+    /// Read a single word, sleeping with `WFE` until it's received. Enables the RXNE interrupt;
+    /// `interrupt` is this U[S]ART's NVIC interrupt, which should be left masked. See the
+    /// `async_wfe` module for details.
+    #[cfg(all(feature = "async_wfe", not(feature = "f4")))]
+    pub async fn read_async(&mut self, interrupt: pac::Interrupt) -> u8 {
+        self.enable_interrupt(UsartInterrupt::ReadNotEmpty);
+
+        let regs = &self.regs;
+        crate::async_wfe::wait_for(Some(interrupt), || {
+            if regs.isr.read().rxne().bit_is_set() {
+                // Reading the data register clears RXNE, before the NVIC pending bit is cleared.
+                Some(regs.rdr.read().rdr().bits() as u8)
+            } else {
+                None
+            }
+        })
+        .await
+    }
+
+    /// Run a loopback self-test, eg from production test firmware. Transmits a fixed test
+    /// pattern, and checks that each word is received as sent. If `internal` is true, the USART is
+    /// temporarily placed in single-wire half-duplex mode (USART_CR3, HDSEL), where TX and RX are
+    /// connected internally; this requires no jumper, and verifies the peripheral and TX pin
+    /// configuration. If false, TX must be jumpered to RX, which also verifies the RX pin.
+    pub fn self_test(&mut self, internal: bool) -> Result<(), SelfTestError> {
+        if internal {
+            // HDSEL can only be written when the USART is disabled.
+            self.regs.cr1.modify(|_, w| w.ue().clear_bit());
+            while self.regs.cr1.read().ue().bit_is_set() {}
+            self.regs.cr3.modify(|_, w| w.hdsel().set_bit());
+            self.regs.cr1.modify(|_, w| w.ue().set_bit());
+        }
+
+        // Discard any stale received word, so it's not mistaken for part of the pattern.
+        self.read_one();
+
+        let mut result = Ok(());
+
+        for (i, sent) in SELF_TEST_PATTERN.iter().enumerate() {
+            self.write(&[*sent]);
+
+            let mut received = None;
+            for _ in 0..SELF_TEST_TIMEOUT {
+                match self.check_rx_errors() {
+                    Err(e) => {
+                        result = Err(SelfTestError::Usart(e));
+                        break;
+                    }
+                    Ok(true) => {
+                        received = Some(self.read_one());
+                        break;
+                    }
+                    Ok(false) => (),
+                }
+            }
+
+            if result.is_err() {
+                break;
+            }
+
+            match received {
+                Some(r) if r != *sent => {
+                    result = Err(SelfTestError::Mismatch {
+                        index: i,
+                        sent: *sent,
+                        received: r,
+                    });
+                    break;
+                }
+                Some(_) => (),
+                None => {
+                    result = Err(SelfTestError::Timeout { index: i });
+                    break;
+                }
+            }
+        }
+
+        if internal {
+            self.regs.cr1.modify(|_, w| w.ue().clear_bit());
+            while self.regs.cr1.read().ue().bit_is_set() {}
+            self.regs.cr3.modify(|_, w| w.hdsel().clear_bit());
+            self.regs.cr1.modify(|_, w| w.ue().set_bit());
+        }
+
+        result
+    }
+
+    /// Check the reception error flags, clearing any that are set. Returns `Ok(true)` if a word
+    /// is ready to be read. Used by `self_test`, and the `embedded-io` implementations.
+    fn check_rx_errors(&mut self) -> Result<bool, Error> {
+        cfg_if! {
+            if #[cfg(not(feature = "f4"))] {
+                let isr = self.regs.isr.read();
+
+                if isr.ore().bit_is_set() {
+                    self.regs.icr.write(|w| w.orecf().set_bit());
+                    Err(Error::Overrun)
+                } else if isr.fe().bit_is_set() {
+                    self.regs.icr.write(|w| w.fecf().set_bit());
+                    Err(Error::Framing)
+                } else if isr.nf().bit_is_set() {
+                    self.regs.icr.write(|w| w.ncf().set_bit());
+                    Err(Error::Noise)
+                } else if isr.pe().bit_is_set() {
+                    self.regs.icr.write(|w| w.pecf().set_bit());
+                    Err(Error::Parity)
+                } else {
+                    Ok(isr.rxne().bit_is_set())
+                }
+            } else {
+                let sr = self.regs.sr.read();
+
+                // On F4, these flags are cleared by reading SR, then DR.
+                let err = if sr.ore().bit_is_set() {
+                    Some(Error::Overrun)
+                } else if sr.fe().bit_is_set() {
+                    Some(Error::Framing)
+                } else if sr.nf().bit_is_set() {
+                    Some(Error::Noise)
+                } else if sr.pe().bit_is_set() {
+                    Some(Error::Parity)
+                } else {
+                    None
+                };
+
+                match err {
+                    Some(e) => {
+                        self.regs.dr.read();
+                        Err(e)
+                    }
+                    None => Ok(sr.rxne().bit_is_set()),
+                }
+            }
+        }
+    }
+
+    #[cfg(not(any(feature = "g0", feature = "h7", feature = "f4")))]
     /// Transmit data using DMA. (L44 RM, section 38.5.15)
-    /// Note that the `channel` argument is only used on F3 and L4.
+    /// Note that the `channel` argument has no effect on F3 and L4.
+    /// On WB and WL, the DMAMUX is configured to route this peripheral's request to it.
     pub unsafe fn write_dma<D>(&mut self, buf: &[u8], channel: DmaChannel, dma: &mut Dma<D>)
     where
         D: Deref<Target = dma_p::RegisterBlock>,
+        R: DmaPeriph,
     {
         let (ptr, len) = (buf.as_ptr(), buf.len());
 
@@ -388,6 +1236,8 @@ where
         let channel = R::write_chan();
         #[cfg(feature = "l4")]
         R::write_sel(dma);
+        #[cfg(any(feature = "wb", feature = "wl"))]
+        dma::mux_dma(dma, channel, R::write_input());
 
         // todo: Pri and Circular as args?
 
@@ -439,12 +1289,14 @@ where
         self.regs.cr3.modify(|_, w| w.dmat().set_bit());
     }
 
-    #[cfg(not(any(feature = "g0", feature = "f4", feature = "l5")))]
+    #[cfg(not(any(feature = "g0", feature = "f4")))]
     /// Receive data using DMA. (L44 RM, section 38.5.15)
-    /// Note that the `channel` argument is only used on F3 and L4.
+    /// Note that the `channel` argument has no effect on F3 and L4.
+    /// On WB and WL, the DMAMUX is configured to route this peripheral's request to it.
     pub unsafe fn read_dma<D>(&mut self, buf: &mut [u8], channel: DmaChannel, dma: &mut Dma<D>)
     where
         D: Deref<Target = dma_p::RegisterBlock>,
+        R: DmaPeriph,
     {
         let (ptr, len) = (buf.as_mut_ptr(), buf.len());
 
@@ -452,6 +1304,8 @@ where
         let channel = R::read_chan();
         #[cfg(feature = "l4")]
         R::read_sel(dma);
+        #[cfg(any(feature = "wb", feature = "wl"))]
+        dma::mux_dma(dma, channel, R::read_input());
 
         #[cfg(feature = "h7")]
         let num_data = len as u32;
@@ -491,6 +1345,174 @@ where
         // controller generates an interrupt on the DMA channel interrupt vector.
     }
 
+    #[cfg(not(any(feature = "g0", feature = "f4")))]
+    /// Receive continuously into `buf`, using a circular DMA channel, and enable the Idle line
+    /// interrupt. This suits protocols with variable-length frames, like MAVLink and NMEA: The
+    /// Idle interrupt fires when the line goes quiet after a burst; in its handler, call
+    /// `clear_interrupt(UsartInterrupt::Idle)`, then `read_available` to fetch the burst.
+    ///
+    /// `buf` must outlive the reception, and be larger than the data that may arrive between
+    /// `read_available` calls; older bytes are overwritten once it wraps. For long bursts, also
+    /// call `read_available` from the DMA channel's half transfer and transfer complete
+    /// interrupts. Stop reception with `dma.stop(channel)`. `buf` must be 1 - 65,535 bytes long.
+    /// Note that the `channel` argument has no effect on F3 and L4.
+    pub unsafe fn read_dma_circular<D>(
+        &mut self,
+        buf: &mut [u8],
+        channel: DmaChannel,
+        dma: &mut Dma<D>,
+    ) where
+        D: Deref<Target = dma_p::RegisterBlock>,
+        R: DmaPeriph,
+    {
+        let (ptr, len) = (buf.as_mut_ptr(), buf.len());
+
+        // `read_available` tracks positions modulo the length, and the DMA counter is 16 bits.
+        assert!(
+            len >= 1 && len <= u16::MAX as usize,
+            "The circular buffer must be 1 - 65,535 bytes."
+        );
+
+        #[cfg(any(feature = "f3", feature = "l4"))]
+        let channel = R::read_chan();
+        #[cfg(feature = "l4")]
+        R::read_sel(dma);
+        #[cfg(any(feature = "wb", feature = "wl"))]
+        dma::mux_dma(dma, channel, R::read_input());
+
+        #[cfg(feature = "h7")]
+        let num_data = len as u32;
+        #[cfg(not(feature = "h7"))]
+        let num_data = len as u16;
+
+        dma.cfg_channel(
+            channel,
+            &self.regs.rdr as *const _ as u32,
+            ptr as u32,
+            num_data,
+            dma::Direction::ReadFromPeriph,
+            dma::DataSize::S8,
+            dma::DataSize::S8,
+            dma::ChannelCfg {
+                circular: dma::Circular::Enabled,
+                ..Default::default()
+            },
+        );
+
+        self.circular_rx = Some(CircularRx {
+            channel,
+            buf_addr: ptr as usize,
+            buf_len: len,
+            read_pos: 0,
+        });
+
+        self.regs.icr.write(|w| w.idlecf().set_bit());
+        // IDLEIE, unlike some of the bits set in `enable_interrupt`, can be written with UE = 1.
+        self.regs.cr1.modify(|_, w| w.idleie().set_bit());
+        self.regs.cr3.modify(|_, w| w.dmar().set_bit());
+    }
+
+    #[cfg(not(any(feature = "g0", feature = "f4")))]
+    /// Copy bytes received by `read_dma_circular` since the last call into `buf`, and return how
+    /// many were copied. Any that don't fit in `buf` are returned by the next call. Returns 0 if
+    /// circular reception isn't active.
+    pub fn read_available<D>(&mut self, buf: &mut [u8], dma: &Dma<D>) -> usize
+    where
+        D: Deref<Target = dma_p::RegisterBlock>,
+    {
+        let rx = match self.circular_rx.as_mut() {
+            Some(rx) => rx,
+            None => return 0,
+        };
+
+        // The DMA's write position. The counter reloads from 0 to the buffer length at the end
+        // of each pass, so a reading of 0 is the same position as the buffer's start.
+        let write_pos = (rx.buf_len - dma.transfer_remaining(rx.channel) as usize) % rx.buf_len;
+
+        let available = (write_pos + rx.buf_len - rx.read_pos) % rx.buf_len;
+        let count = available.min(buf.len());
+
+        // Make sure we read the buffer after the counter.
+        atomic::compiler_fence(Ordering::SeqCst);
+
+        let src = rx.buf_addr as *const u8;
+        for (i, word) in buf.iter_mut().take(count).enumerate() {
+            *word = unsafe { core::ptr::read_volatile(src.add((rx.read_pos + i) % rx.buf_len)) };
+        }
+
+        rx.read_pos = (rx.read_pos + count) % rx.buf_len;
+        count
+    }
+
+    #[cfg(not(feature = "f4"))]
+    /// Detect the baud rate, using the auto baud rate hardware, and reconfigure the USART to use
+    /// it. Blocks until the host sends a character matching `mode`. The character used for
+    /// detection is then available to read, with `read_one`. Returns the detected baud rate.
+    /// Not supported on LPUART, or on some UARTs; check your RM.
+    pub fn detect_baud(&mut self, mode: AutoBaudMode, clock_cfg: &Clocks) -> Result<u32, Error> {
+        if R::low_power() {
+            panic!("LPUART doesn't support auto baud rate detection.");
+        }
+
+        // ABRMOD can only be written with UE = 0.
+        self.regs.cr1.modify(|_, w| w.ue().clear_bit());
+        while self.regs.cr1.read().ue().bit_is_set() {}
+
+        self.regs.cr2.modify(|_, w| unsafe {
+            w.abrmod().bits(mode as u8);
+            w.abren().set_bit()
+        });
+
+        self.regs.cr1.modify(|_, w| w.ue().set_bit());
+
+        // Clear ABRF, and any previous result, to start a new detection.
+        self.regs.rqr.write(|w| w.abrrq().set_bit());
+
+        // "If the auto baud rate operation is completed successfully, the ABRF flag is set and BRR
+        // is updated. ... If the auto baud rate operation is not successful, the ABRE flag is set."
+        // ABRF is set in both cases.
+        while self.regs.isr.read().abrf().bit_is_clear() {}
+
+        let result = if self.regs.isr.read().abre().bit_is_set() {
+            Err(Error::AutoBaud)
+        } else {
+            self.baud = self.read_baud(clock_cfg);
+            Ok(self.baud)
+        };
+
+        // We leave ABREN set: Detection only runs again on a new request.
+        result
+    }
+
+    /// Send a break character: A frame of all low bits, followed by a stop bit. In LIN mode, this
+    /// is 13 low bits, and starts a LIN frame. The break is sent after any ongoing transmission
+    /// completes. See G4 RM, section 37.5.14: USART LIN mode.
+    pub fn send_break(&mut self) {
+        cfg_if! {
+            if #[cfg(feature = "f4")] {
+                self.regs.cr1.modify(|_, w| w.sbk().set_bit());
+            } else {
+                self.regs.rqr.write(|w| w.sbkrq().set_bit());
+            }
+        }
+    }
+
+    #[cfg(not(feature = "f4"))]
+    /// In multiprocessor mode, mute the receiver until it's woken by an idle line, or a word
+    /// with a matching address, depending on the wake method. Eg, call this after receiving a
+    /// frame addressed to this node. (USART_RQR, MMRQ)
+    pub fn enter_mute(&mut self) {
+        // MMRQ is at RQR bit 2.
+        self.regs.rqr.write(|w| unsafe { w.bits(1 << 2) });
+    }
+
+    #[cfg(not(feature = "f4"))]
+    /// Returns true if the receiver is muted, in multiprocessor mode. (USART_ISR, RWU)
+    pub fn is_muted(&self) -> bool {
+        // RWU is at ISR bit 19.
+        self.regs.isr.read().bits() & (1 << 19) != 0
+    }
+
     /// Flush the transmit buffer.
     pub fn flush(&self) {
         #[cfg(not(feature = "f4"))]
@@ -562,11 +1584,54 @@ where
             UsartInterrupt::TransmitEmpty => {
                 self.regs.cr1.modify(|_, w| w.txeie().set_bit());
             }
+            #[cfg(any(feature = "g4", feature = "l5", feature = "h7", feature = "wb", feature = "wl"))]
+            UsartInterrupt::TxFifoThreshold => {
+                self.regs.cr3.modify(|_, w| w.txftie().set_bit());
+            }
+            #[cfg(any(feature = "g4", feature = "l5", feature = "h7", feature = "wb", feature = "wl"))]
+            UsartInterrupt::RxFifoThreshold => {
+                self.regs.cr3.modify(|_, w| w.rxftie().set_bit());
+            }
+            #[cfg(any(feature = "g4", feature = "l5", feature = "h7", feature = "wb", feature = "wl"))]
+            UsartInterrupt::TxFifoEmpty => {
+                self.regs.cr1.modify(|_, w| w.txfeie().set_bit());
+            }
+            #[cfg(any(feature = "g4", feature = "l5", feature = "h7", feature = "wb", feature = "wl"))]
+            UsartInterrupt::RxFifoFull => {
+                self.regs.cr1.modify(|_, w| w.rxffie().set_bit());
+            }
         }
 
         self.regs.cr1.modify(|_, w| w.ue().set_bit());
     }
 
+    #[cfg(not(feature = "f4"))]
+    /// Enable the receiver timeout, in bit durations (eg 35 for the Modbus RTU 3.5 character gap,
+    /// with 10-bit frames). The timeout restarts at the end of each received word's stop bit, and
+    /// the `ReceiverTimeout` flag (and interrupt, if enabled) is set if no new start bit arrives
+    /// in time. Unlike the `Idle` interrupt, which triggers after one idle frame, this lets you
+    /// detect frame boundaries by a gap of any length, up to 2^24 - 1 bit durations.
+    ///
+    /// Not available on LPUART, or on some USARTs of some MCUs; check the "USART features"
+    /// table in your RM. See G4 RM, section 37.5.8: "Receiver timeout".
+    pub fn set_receiver_timeout(&mut self, bit_durations: u32) {
+        if R::low_power() {
+            panic!("LPUART doesn't support the receiver timeout.");
+        }
+        assert!(bit_durations <= 0xff_ffff);
+
+        self.regs
+            .rtor
+            .modify(|_, w| unsafe { w.rto().bits(bit_durations) });
+        self.regs.cr2.modify(|_, w| w.rtoen().set_bit());
+    }
+
+    #[cfg(not(feature = "f4"))]
+    /// Disable the receiver timeout.
+    pub fn disable_receiver_timeout(&mut self) {
+        self.regs.cr2.modify(|_, w| w.rtoen().clear_bit());
+    }
+
     #[cfg(not(feature = "f4"))]
     /// Clears the interrupt pending flag for a specific type of interrupt.
     pub fn clear_interrupt(&mut self, interrupt: UsartInterrupt) {
@@ -587,13 +1652,173 @@ where
             UsartInterrupt::Tcbgt => self.regs.icr.write(|w| w.tcbgtc().set_bit()),
             UsartInterrupt::TransmissionComplete => self.regs.icr.write(|w| w.tccf().set_bit()),
             UsartInterrupt::TransmitEmpty => self.regs.rqr.write(|w| w.txfrq().set_bit()),
+            #[cfg(any(feature = "g4", feature = "l5", feature = "h7", feature = "wb", feature = "wl"))]
+            UsartInterrupt::TxFifoEmpty => self.regs.icr.write(|w| w.txfecf().set_bit()),
+            // The threshold and RX-full flags are cleared by hardware, as the FIFO level changes.
+            #[cfg(any(feature = "g4", feature = "l5", feature = "h7", feature = "wb", feature = "wl"))]
+            UsartInterrupt::TxFifoThreshold
+            | UsartInterrupt::RxFifoThreshold
+            | UsartInterrupt::RxFifoFull => (),
+        }
+    }
+
+    /// Calculate the baud rate from USART_BRR and the USART's input clock. This is the inverse of
+    /// the calculation in `set_baud`.
+    fn read_baud(&self, clock_cfg: &Clocks) -> u32 {
+        baud_from_brr(
+            R::baud(clock_cfg),
+            self.regs.brr.read().bits(),
+            self.regs.cr1.read().over8().bit_is_set(),
+            R::low_power(),
+        )
+    }
+
+    /// Read the current configuration from the USART's registers. Unlike the `UsartConfig` passed
+    /// to the constructor, this reflects any changes made since, including by direct register
+    /// writes.
+    pub fn dump(&self, clock_cfg: &Clocks) -> UsartDump {
+        let cr1 = self.regs.cr1.read();
+        let cr2 = self.regs.cr2.read();
+        let cr3 = self.regs.cr3.read();
+
+        // We read M0 and M1 from the raw bits, due to the PAC bug described in `new`.
+        let word_len = match ((cr1.bits() >> 28) & 1, (cr1.bits() >> 12) & 1) {
+            (0, 0) => WordLen::W8,
+            (0, _) => WordLen::W9,
+            _ => WordLen::W7,
+        };
+
+        let stop_bits = match cr2.stop().bits() {
+            0b00 => StopBits::S1,
+            0b01 => StopBits::S0_5,
+            0b10 => StopBits::S2,
+            _ => StopBits::S1_5,
+        };
+
+        let parity = if cr1.pce().bit_is_clear() {
+            Parity::Disabled
+        } else if cr1.ps().bit_is_set() {
+            Parity::EnabledOdd
+        } else {
+            Parity::EnabledEven
+        };
+
+        let oversampling = if cr1.over8().bit_is_set() {
+            OverSampling::O8
+        } else {
+            OverSampling::O16
+        };
+
+        let irda_mode = if cr3.iren().bit_is_clear() {
+            IrdaMode::None
+        } else if cr3.irlp().bit_is_set() {
+            IrdaMode::LowPower
+        } else {
+            IrdaMode::Normal
+        };
+
+        let baud = self.read_baud(clock_cfg);
+
+        UsartDump {
+            enabled: cr1.ue().bit_is_set(),
+            tx_enabled: cr1.te().bit_is_set(),
+            rx_enabled: cr1.re().bit_is_set(),
+            baud,
+            word_len,
+            stop_bits,
+            parity,
+            oversampling,
+            irda_mode,
+            smartcard: cr3.scen().bit_is_set(),
+            lin_mode: cr2.linen().bit_is_set(),
+            tx_dma: cr3.dmat().bit_is_set(),
+            rx_dma: cr3.dmar().bit_is_set(),
+            #[cfg(not(feature = "f4"))]
+            rs485: cr3.dem().bit_is_set(),
+            #[cfg(not(feature = "f4"))]
+            multiprocessor: cr1.bits() & (1 << 13) != 0,
+            #[cfg(not(feature = "f4"))]
+            swap_pins: cr2.swap().bit_is_set(),
+            #[cfg(not(feature = "f4"))]
+            tx_inverted: cr2.txinv().bit_is_set(),
+            #[cfg(not(feature = "f4"))]
+            rx_inverted: cr2.rxinv().bit_is_set(),
+            #[cfg(not(feature = "f4"))]
+            data_inverted: cr2.datainv().bit_is_set(),
+            #[cfg(any(feature = "g4", feature = "l5", feature = "h7", feature = "wb", feature = "wl"))]
+            fifo_enabled: cr1.fifoen().bit_is_set(),
+        }
+    }
+}
+
+/// Calculate the BRR value for `baud`, from the U[S]ART's kernel clock frequency, `fclk`. See
+/// `Usart::set_baud`.
+fn brr_val(
+    fclk: u32,
+    baud: u32,
+    oversampling: OverSampling,
+    low_power: bool,
+) -> Result<u32, Error> {
+    if baud == 0 {
+        return Err(Error::BaudRate);
+    }
+
+    let brr = if low_power {
+        // LPUART uses a different calculation, with no oversampling setting: BRR = 256 x fclk
+        // / baud. BRR must be at least 0x300, and fits in 20 bits. (L4 RM, "LPUART baud rate
+        // generation")
+        let brr = (256 * fclk as u64 / baud as u64) as u32;
+        if brr < 0x300 || brr >= 1 << 20 {
+            return Err(Error::BaudRate);
+        }
+        brr
+    } else {
+        // Round to the nearest divider, to minimize baud error at high rates, where the
+        // divider is small.
+        let usart_div = match oversampling {
+            OverSampling::O16 => (fclk + baud / 2) / baud,
+            OverSampling::O8 => (2 * fclk + baud / 2) / baud,
+        };
+
+        // "USARTDIV must be greater than or equal to 16d."
+        if usart_div < 16 || usart_div > 0xffff {
+            return Err(Error::BaudRate);
+        }
+
+        // USARTDIV is an unsigned fixed point number that is coded on the USART_BRR register.
+        // • When OVER8 = 0, BRR = USARTDIV.
+        // • When OVER8 = 1
+        // – BRR[2:0] = USARTDIV[3:0] shifted 1 bit to the right.
+        // – BRR[3] must be kept cleared.
+        // – BRR[15:4] = USARTDIV[15:4]
+        match oversampling {
+            OverSampling::O16 => usart_div,
+            OverSampling::O8 => (usart_div & !0xf) | ((usart_div & 0xf) >> 1),
         }
+    };
+
+    Ok(brr)
+}
+
+/// Calculate the baud rate from a BRR value, and the U[S]ART's kernel clock frequency. This is
+/// the inverse of `brr_val`.
+fn baud_from_brr(fclk: u32, brr: u32, over8: bool, low_power: bool) -> u32 {
+    if brr == 0 {
+        0
+    } else if low_power {
+        (256 * fclk as u64 / brr as u64) as u32
+    } else if over8 {
+        // Undo the BRR encoding described in `brr_val`.
+        let usart_div = (brr & !0xf) | ((brr & 0b111) << 1);
+        2 * fclk / usart_div
+    } else {
+        fclk / brr
     }
 }
 
 /// Serial error
 #[non_exhaustive]
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug)]
 pub enum Error {
     /// Framing error
     Framing,
@@ -603,6 +1828,12 @@ pub enum Error {
     Overrun,
     /// Parity check error
     Parity,
+    /// Auto baud rate detection failed: The detected rate was out of range, or the
+    /// character was too short.
+    AutoBaud,
+    /// The requested baud rate can't be generated from the kernel clock, with this
+    /// oversampling setting.
+    BaudRate,
 }
 
 #[cfg(feature = "embedded-hal")]
@@ -708,3 +1939,179 @@ where
         Ok(())
     }
 }
+
+#[cfg(feature = "embedded-io")]
+impl embedded_io::Error for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Self::Framing | Self::Noise | Self::Parity => ErrorKind::InvalidData,
+            _ => ErrorKind::Other,
+        }
+    }
+}
+
+#[cfg(feature = "embedded-io")]
+impl<R> ErrorType for Usart<R>
+where
+    R: Deref<Target = pac::usart1::RegisterBlock> + RccPeriph + BaudPeriph,
+{
+    type Error = Error;
+}
+
+#[cfg(feature = "embedded-io")]
+impl<R> Usart<R>
+where
+    R: Deref<Target = pac::usart1::RegisterBlock> + RccPeriph + BaudPeriph,
+{
+    /// TXE, or TXFNF in FIFO mode.
+    fn tx_ready(&self) -> bool {
+        #[cfg(not(feature = "f4"))]
+        return self.regs.isr.read().txe().bit_is_set();
+        #[cfg(feature = "f4")]
+        return self.regs.sr.read().txe().bit_is_set();
+    }
+}
+
+#[cfg(feature = "embedded-io")]
+impl<R> embedded_io::Read for Usart<R>
+where
+    R: Deref<Target = pac::usart1::RegisterBlock> + RccPeriph + BaudPeriph,
+{
+    /// Block until at least one word is received, then read any others that are ready (eg in
+    /// the RX FIFO), without blocking further.
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        while !self.check_rx_errors()? {}
+
+        let mut read = 0;
+        while read < buf.len() && self.check_rx_errors()? {
+            buf[read] = self.read_one();
+            read += 1;
+        }
+
+        Ok(read)
+    }
+}
+
+#[cfg(feature = "embedded-io")]
+impl<R> ReadReady for Usart<R>
+where
+    R: Deref<Target = pac::usart1::RegisterBlock> + RccPeriph + BaudPeriph,
+{
+    fn read_ready(&mut self) -> Result<bool, Error> {
+        self.check_rx_errors()
+    }
+}
+
+#[cfg(feature = "embedded-io")]
+impl<R> embedded_io::Write for Usart<R>
+where
+    R: Deref<Target = pac::usart1::RegisterBlock> + RccPeriph + BaudPeriph,
+{
+    /// Block until the transmitter can accept a word, then write as many as it can accept
+    /// (eg to fill the TX FIFO), without blocking further.
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        while !self.tx_ready() {}
+
+        let mut written = 0;
+        while written < buf.len() && self.tx_ready() {
+            cfg_if! {
+                if #[cfg(not(feature = "f4"))] {
+                    self.regs
+                        .tdr
+                        .modify(|_, w| unsafe { w.tdr().bits(buf[written] as u16) });
+                } else {
+                    self.regs
+                        .dr
+                        .modify(|_, w| unsafe { w.dr().bits(buf[written] as u16) });
+                }
+            }
+            written += 1;
+        }
+
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        #[cfg(not(feature = "f4"))]
+        while self.regs.isr.read().tc().bit_is_clear() {}
+        #[cfg(feature = "f4")]
+        while self.regs.sr.read().tc().bit_is_clear() {}
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "embedded-io")]
+impl<R> WriteReady for Usart<R>
+where
+    R: Deref<Target = pac::usart1::RegisterBlock> + RccPeriph + BaudPeriph,
+{
+    fn write_ready(&mut self) -> Result<bool, Error> {
+        Ok(self.tx_ready())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn brr_rm_examples() {
+        // L4 RM, section 38.5.4: "USART baud rate generation", examples 1 and 2.
+        assert_eq!(
+            brr_val(8_000_000, 9_600, OverSampling::O16, false).ok(),
+            Some(0x341)
+        );
+        assert_eq!(
+            brr_val(8_000_000, 9_600, OverSampling::O8, false).ok(),
+            Some(0x681)
+        );
+    }
+
+    #[test]
+    fn brr_round_trip() {
+        for (fclk, baud) in [
+            (80_000_000, 115_200),
+            (80_000_000, 9_600),
+            (170_000_000, 1_000_000),
+            (16_000_000, 57_600),
+        ] {
+            for (oversampling, over8) in [(OverSampling::O16, false), (OverSampling::O8, true)] {
+                let brr = brr_val(fclk, baud, oversampling, false).unwrap();
+                // BRR[3] must be kept cleared with OVER8.
+                assert!(!over8 || brr & 0b1000 == 0);
+
+                let actual = baud_from_brr(fclk, brr, over8, false);
+                assert!((actual as f32 / baud as f32 - 1.).abs() < 0.01);
+            }
+        }
+    }
+
+    #[test]
+    fn brr_lpuart() {
+        let brr = brr_val(32_768, 9_600, OverSampling::O16, true).unwrap();
+        assert_eq!(brr, 873);
+        assert_eq!(baud_from_brr(32_768, brr, false, true), 9_608);
+
+        // BRR must be at least 0x300.
+        assert!(brr_val(32_768, 38_400, OverSampling::O16, true).is_err());
+    }
+
+    #[test]
+    fn brr_out_of_range() {
+        assert!(brr_val(80_000_000, 0, OverSampling::O16, false).is_err());
+        // USARTDIV below 16.
+        assert!(brr_val(16_000_000, 2_000_000, OverSampling::O16, false).is_err());
+        assert!(brr_val(16_000_000, 2_000_000, OverSampling::O8, false).is_ok());
+        // USARTDIV above 0xffff.
+        assert!(brr_val(170_000_000, 1_200, OverSampling::O16, false).is_err());
+    }
+}
@@ -17,6 +17,32 @@ use crate::pac::DMA1;
 // #[cfg(not(any(feature = "f4", feature = "l5")))]
 #[cfg(any(feature = "f3", feature = "l4"))]
 use crate::dma::{self, Dma, DmaChannel, DmaInput};
+#[cfg(any(
+    feature = "l5",
+    feature = "g4",
+    feature = "h7",
+    feature = "wb",
+    feature = "wl"
+))]
+use crate::dma::DmaInput;
+
+#[cfg(not(any(feature = "f4", feature = "wb", feature = "g0")))]
+use crate::dac::DacChannel;
+
+#[cfg(any(feature = "g4", feature = "h7", feature = "wb", feature = "wl"))]
+use crate::timer::TimerDmaRequest;
+
+#[cfg(not(any(
+    feature = "f3",
+    feature = "f4",
+    feature = "g030",
+    feature = "g050",
+    feature = "g070",
+    feature = "g0b0"
+)))]
+use crate::usart::Lpuart1;
+#[cfg(any(feature = "g0b1", feature = "g0c1"))]
+use crate::usart::Lpuart2;
 
 #[cfg(not(any(
     feature = "f401",
@@ -143,9 +169,19 @@ macro_rules! rcc_en_reset {
     };
 }
 
+#[derive(Clone, Copy, Debug)]
+/// Used when attempting to connect a source to a destination it isn't wired to, eg a trigger to
+/// an ADC it isn't connected to. Re-exported by the `adc` and `interconnect` modules.
+pub struct RouteError {}
+
 // todo: This trait is currently a one-off for usart
 pub trait BaudPeriph {
     fn baud(clock_cfg: &Clocks) -> u32;
+
+    /// LPUART uses a different baud rate calculation from USART.
+    fn low_power() -> bool {
+        false
+    }
 }
 
 impl BaudPeriph for pac::USART1 {
@@ -178,6 +214,38 @@ impl BaudPeriph for pac::USART3 {
     }
 }
 
+#[cfg(not(any(
+    feature = "f3",
+    feature = "f4",
+    feature = "g030",
+    feature = "g050",
+    feature = "g070",
+    feature = "g0b0"
+)))]
+impl BaudPeriph for Lpuart1 {
+    fn baud(clock_cfg: &Clocks) -> u32 {
+        #[cfg(feature = "h7")]
+        return clock_cfg.apb4();
+        #[cfg(not(feature = "h7"))]
+        return clock_cfg.lpuart();
+    }
+
+    fn low_power() -> bool {
+        true
+    }
+}
+
+#[cfg(any(feature = "g0b1", feature = "g0c1"))]
+impl BaudPeriph for Lpuart2 {
+    fn baud(clock_cfg: &Clocks) -> u32 {
+        clock_cfg.lpuart()
+    }
+
+    fn low_power() -> bool {
+        true
+    }
+}
+
 // todo: This trait is currently a one-off for adc, and isn't currently used.
 pub trait VrefPeriph {
     fn vref(clock_cfg: &Clocks) -> u32;
@@ -417,6 +485,38 @@ impl RccPeriph for pac::USART3 {
     }
 }
 
+#[cfg(not(any(
+    feature = "f3",
+    feature = "f4",
+    feature = "g030",
+    feature = "g050",
+    feature = "g070",
+    feature = "g0b0"
+)))]
+impl RccPeriph for Lpuart1 {
+    fn en_reset(rcc: &RegisterBlock) {
+        cfg_if::cfg_if! {
+            if #[cfg(feature = "g0")] {
+                rcc_en_reset!(apb1, lpuart1, rcc);
+            } else if #[cfg(feature = "h7")] {
+                rcc_en_reset!(apb4, lpuart1, rcc);
+            } else {
+                // On APB1 register 2, which `rcc_en_reset` doesn't handle.
+                rcc.apb1enr2.modify(|_, w| w.lpuart1en().set_bit());
+                rcc.apb1rstr2.modify(|_, w| w.lpuart1rst().set_bit());
+                rcc.apb1rstr2.modify(|_, w| w.lpuart1rst().clear_bit());
+            }
+        }
+    }
+}
+
+#[cfg(any(feature = "g0b1", feature = "g0c1"))]
+impl RccPeriph for Lpuart2 {
+    fn en_reset(rcc: &RegisterBlock) {
+        rcc_en_reset!(apb1, lpuart2, rcc);
+    }
+}
+
 // todo: USART 4 and 5.
 
 #[cfg(not(any(
@@ -489,11 +589,49 @@ cfg_if::cfg_if! {
     }
 }
 
+cfg_if::cfg_if! {
+    if #[cfg(feature = "g0")] {
+        impl RccPeriph for pac::DMA {
+            fn en_reset(rcc: &RegisterBlock) {
+                rcc_en_reset!(ahb1, dma, rcc);
+            }
+        }
+    } else if #[cfg(feature = "f3")] {
+        // F3 doesn't have DMA reset bits.
+        impl RccPeriph for pac::DMA1 {
+            fn en_reset(rcc: &RegisterBlock) {
+                rcc.ahbenr.modify(|_, w| w.dma1en().set_bit());
+            }
+        }
+
+        #[cfg(not(any(feature = "f301", feature = "f3x4")))]
+        impl RccPeriph for pac::DMA2 {
+            fn en_reset(rcc: &RegisterBlock) {
+                rcc.ahbenr.modify(|_, w| w.dma2en().set_bit());
+            }
+        }
+    } else {
+        impl RccPeriph for pac::DMA1 {
+            fn en_reset(rcc: &RegisterBlock) {
+                rcc_en_reset!(ahb1, dma1, rcc);
+            }
+        }
+
+        impl RccPeriph for pac::DMA2 {
+            fn en_reset(rcc: &RegisterBlock) {
+                rcc_en_reset!(ahb1, dma2, rcc);
+            }
+        }
+    }
+}
+
 // todo: APB1LR2 on L5, and AHB4 on H7. Fix it. (I2C4)
 // I2cDevice::Four => {
 
-// todo: DMA2 support.
-#[cfg(any(feature = "f3", feature = "l4"))]
+/// Used to find a peripheral's DMA requests. On F3 and L4, these are hard-wired to DMA1 channels.
+/// On WB and WL, they're DMAMUX inputs, which can be routed to any channel on DMA1 or DMA2.
+// todo: DMA2 support on F3 and L4.
+#[cfg(not(feature = "f4"))]
 pub trait DmaPeriph {
     #[cfg(any(feature = "f3", feature = "l4"))]
     fn read_chan() -> DmaChannel;
@@ -503,8 +641,56 @@ pub trait DmaPeriph {
     fn read_sel<D: Deref<Target = dma_p::RegisterBlock>>(dma: &mut Dma<D>);
     #[cfg(feature = "l4")]
     fn write_sel<D: Deref<Target = dma_p::RegisterBlock>>(dma: &mut Dma<D>);
+    #[cfg(any(feature = "wb", feature = "wl"))]
+    fn read_input() -> DmaInput;
+    #[cfg(any(feature = "wb", feature = "wl"))]
+    fn write_input() -> DmaInput;
 }
 
+// Other families don't look up DMA requests using this trait; the channel passed to DMA methods
+// is used as-is. Implement it for all peripherals, so those methods remain available.
+#[cfg(not(any(
+    feature = "f3",
+    feature = "l4",
+    feature = "f4",
+    feature = "wb",
+    feature = "wl"
+)))]
+impl<T> DmaPeriph for T {}
+
+/// Implement `DmaPeriph` for a peripheral on WB or WL, using its DMAMUX inputs.
+#[cfg(any(feature = "wb", feature = "wl"))]
+macro_rules! dma_periph_mux {
+    ($periph:ty, $rx:ident, $tx:ident) => {
+        impl DmaPeriph for $periph {
+            fn read_input() -> DmaInput {
+                DmaInput::$rx
+            }
+
+            fn write_input() -> DmaInput {
+                DmaInput::$tx
+            }
+        }
+    };
+}
+
+#[cfg(any(feature = "wb", feature = "wl"))]
+dma_periph_mux!(pac::SPI1, Spi1Rx, Spi1Tx);
+#[cfg(any(feature = "wb", feature = "wl"))]
+dma_periph_mux!(pac::SPI2, Spi2Rx, Spi2Tx);
+#[cfg(any(feature = "wb", feature = "wl"))]
+dma_periph_mux!(pac::I2C1, I2c1Rx, I2c1Tx);
+#[cfg(feature = "wl")]
+dma_periph_mux!(pac::I2C2, I2c2Rx, I2c2Tx);
+#[cfg(any(feature = "wb", feature = "wl"))]
+dma_periph_mux!(pac::I2C3, I2c3Rx, I2c3Tx);
+#[cfg(any(feature = "wb", feature = "wl"))]
+dma_periph_mux!(pac::USART1, Usart1Rx, Usart1Tx);
+#[cfg(feature = "wl")]
+dma_periph_mux!(pac::USART2, Usart2Rx, Usart2Tx);
+#[cfg(any(feature = "wb", feature = "wl"))]
+dma_periph_mux!(Lpuart1, Lpuart1Rx, Lpuart1Tx);
+
 #[cfg(any(feature = "f3", feature = "l4"))]
 impl DmaPeriph for pac::I2C1 {
     #[cfg(any(feature = "f3", feature = "l4"))]
@@ -689,7 +875,62 @@ impl DmaPeriph for pac::USART3 {
     }
 }
 
-// We currently only set up DAC1 DMA, and it's split by channels, not device.
+/// Used to find a DAC's DMA requests. Unlike other peripherals, these are split by channel, not
+/// device, so this is separate from `DmaPeriph`. On F3 and L4, the request is hard-wired to a DMA1
+/// channel. On MCUs that use DMAMUX, it's the input to route to the channel you pick. Returns an
+/// error for channels the DAC doesn't have, eg channel 2 of G4's DAC2.
+// todo: DAC2 on F3.
+#[cfg(not(any(feature = "f4", feature = "wb", feature = "g0")))]
+pub trait DacChannelDma {
+    fn dma_input(channel: DacChannel) -> Result<DmaInput, RouteError>;
+}
+
+#[cfg(not(any(feature = "f4", feature = "wb", feature = "g0")))]
+impl DacChannelDma for DAC1 {
+    fn dma_input(channel: DacChannel) -> Result<DmaInput, RouteError> {
+        Ok(match channel {
+            #[cfg(not(feature = "h7"))]
+            DacChannel::C1 => DmaInput::Dac1Ch1,
+            #[cfg(all(not(feature = "h7"), not(feature = "wl")))]
+            DacChannel::C2 => DmaInput::Dac1Ch2,
+            #[cfg(feature = "h7")]
+            DacChannel::C1 => DmaInput::DacCh1,
+            #[cfg(feature = "h7")]
+            DacChannel::C2 => DmaInput::DacCh2,
+        })
+    }
+}
+
+#[cfg(feature = "g4")]
+impl DacChannelDma for pac::DAC2 {
+    /// DAC2 only has one channel.
+    fn dma_input(channel: DacChannel) -> Result<DmaInput, RouteError> {
+        match channel {
+            DacChannel::C1 => Ok(DmaInput::Dac2Ch1),
+            DacChannel::C2 => Err(RouteError {}),
+        }
+    }
+}
+
+#[cfg(feature = "g4")]
+impl DacChannelDma for pac::DAC3 {
+    fn dma_input(channel: DacChannel) -> Result<DmaInput, RouteError> {
+        Ok(match channel {
+            DacChannel::C1 => DmaInput::Dac3Ch1,
+            DacChannel::C2 => DmaInput::Dac3Ch2,
+        })
+    }
+}
+
+#[cfg(feature = "g4")]
+impl DacChannelDma for pac::DAC4 {
+    fn dma_input(channel: DacChannel) -> Result<DmaInput, RouteError> {
+        Ok(match channel {
+            DacChannel::C1 => DmaInput::Dac4Ch1,
+            DacChannel::C2 => DmaInput::Dac4Ch2,
+        })
+    }
+}
 
 // todo: Use thsi approach for USART and SAI. When you un-macro them, ADC and Timer as well.
 
@@ -746,3 +987,59 @@ impl DmaPeriph for pac::ADC2 {
 }
 
 // L4 and F3 only have DMA on ADC 1 and 2.
+
+/// Used to find a timer's DMA requests. Like the DAC's, these are split by event, not device: each
+/// timer has one for the update event, and one for each capture/compare channel.
+// todo: L5 and G0; their DMAMUX request IDs don't match `DmaInput`'s G4 values.
+#[cfg(any(feature = "g4", feature = "h7", feature = "wb", feature = "wl"))]
+pub trait TimerDmaPeriph {
+    fn dma_input(request: TimerDmaRequest) -> DmaInput;
+}
+
+/// Implement `TimerDmaPeriph` for a timer, using its update and capture/compare DMAMUX inputs.
+#[cfg(any(feature = "g4", feature = "h7", feature = "wb", feature = "wl"))]
+macro_rules! timer_dma_periph {
+    ($tim:ident, $up:ident $(, $cc:ident => $input:ident)*) => {
+        impl TimerDmaPeriph for pac::$tim {
+            fn dma_input(request: TimerDmaRequest) -> DmaInput {
+                match request {
+                    TimerDmaRequest::Update => DmaInput::$up,
+                    $(TimerDmaRequest::$cc => DmaInput::$input,)*
+                    #[allow(unreachable_patterns)]
+                    _ => panic!("This timer doesn't have a DMA request for this channel."),
+                }
+            }
+        }
+    };
+}
+
+#[cfg(any(feature = "g4", feature = "h7", feature = "wb", feature = "wl"))]
+timer_dma_periph!(TIM1, Tim1Up,
+    CaptureCompare1 => Tim1Ch1, CaptureCompare2 => Tim1Ch2,
+    CaptureCompare3 => Tim1Ch3, CaptureCompare4 => Tim1Ch4);
+#[cfg(any(feature = "g4", feature = "h7", feature = "wb", feature = "wl"))]
+timer_dma_periph!(TIM2, Tim2Up,
+    CaptureCompare1 => Tim2Ch1, CaptureCompare2 => Tim2Ch2,
+    CaptureCompare3 => Tim2Ch3, CaptureCompare4 => Tim2Ch4);
+#[cfg(any(feature = "g4", feature = "h7"))]
+timer_dma_periph!(TIM3, Tim3Up,
+    CaptureCompare1 => Tim3Ch1, CaptureCompare2 => Tim3Ch2,
+    CaptureCompare3 => Tim3Ch3, CaptureCompare4 => Tim3Ch4);
+// H7 has no TIM4_CH4 DMA request.
+#[cfg(feature = "h7")]
+timer_dma_periph!(TIM4, Tim4Up,
+    CaptureCompare1 => Tim4Ch1, CaptureCompare2 => Tim4Ch2, CaptureCompare3 => Tim4Ch3);
+#[cfg(feature = "h7")]
+timer_dma_periph!(TIM5, Tim5Up,
+    CaptureCompare1 => Tim5Ch1, CaptureCompare2 => Tim5Ch2,
+    CaptureCompare3 => Tim5Ch3, CaptureCompare4 => Tim5Ch4);
+#[cfg(any(feature = "g4", feature = "h7"))]
+timer_dma_periph!(TIM8, Tim8Up,
+    CaptureCompare1 => Tim8Ch1, CaptureCompare2 => Tim8Ch2,
+    CaptureCompare3 => Tim8Ch3, CaptureCompare4 => Tim8Ch4);
+#[cfg(any(feature = "g4", feature = "h7"))]
+timer_dma_periph!(TIM15, Tim15Up, CaptureCompare1 => Tim15Ch1);
+#[cfg(any(feature = "g4", feature = "h7", feature = "wb", feature = "wl"))]
+timer_dma_periph!(TIM16, Tim16Up, CaptureCompare1 => Tim16Ch1);
+#[cfg(any(feature = "g4", feature = "h7", feature = "wb", feature = "wl"))]
+timer_dma_periph!(TIM17, Tim17Up, CaptureCompare1 => Tim17Ch1);
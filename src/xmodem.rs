@@ -0,0 +1,428 @@
+//! An XMODEM and YMODEM receiver, for accepting firmware images over a serial port. Received
+//! data is written directly to flash, so bootloaders built on this HAL can be updated using
+//! only a terminal program (eg Tera Term, or `sz` / `sb`) on the host.
+//!
+//! Supports XMODEM-CRC, with 128 and 1024-byte blocks (XMODEM-1K), and single-file YMODEM
+//! batches. Packets are checked with the CRC peripheral, using the CRC-16/XMODEM algorithm.
+//! Received data is buffered a flash page at a time; each page is erased, then written, once
+//! it's full.
+//!
+//! Example, receiving into flash starting at page 32 (0x0801_0000):
+//! ```
+//! let mut crc = dp.CRC.crc(&mut dp.RCC);
+//! let mut flash = Flash::new(dp.FLASH);
+//!
+//! let cfg = XmodemConfig {
+//!     start_page: 32,
+//!     max_pages: 96,
+//!     ymodem: false,
+//! };
+//!
+//! match xmodem::receive(&mut uart, &mut crc, &mut flash, &cfg) {
+//!     Ok(len) => defmt::println!("Received {} bytes", len),
+//!     Err(e) => defmt::println!("Transfer failed: {:?}", e),
+//! }
+//! ```
+
+use core::ops::Deref;
+
+use crate::{
+    crc::{Config, Crc, Polynomial},
+    flash::{self, Flash, PAGE_SIZE},
+    pac,
+    usart::Usart,
+    util::{BaudPeriph, RccPeriph},
+};
+
+const SOH: u8 = 0x01;
+const STX: u8 = 0x02;
+const EOT: u8 = 0x04;
+const ACK: u8 = 0x06;
+const NAK: u8 = 0x15;
+const CAN: u8 = 0x18;
+/// Sent instead of NAK, to request CRC mode from the sender.
+const CRC_MODE: u8 = b'C';
+
+/// Number of status register polls to wait for each byte before timing out. This is on the order
+/// of a second, at typical core clock speeds.
+const BYTE_TIMEOUT: u32 = 5_000_000;
+
+/// The number of times to request the transfer start, before giving up.
+const MAX_START_ATTEMPTS: u8 = 60;
+
+/// The number of consecutive bad packets to accept before cancelling.
+const MAX_RETRIES: u8 = 10;
+
+/// XMODEM receiver configuration.
+pub struct XmodemConfig {
+    /// The flash page to write the first received data to.
+    pub start_page: usize,
+    /// The maximum number of pages to write. If the transfer is larger, it's cancelled.
+    pub max_pages: usize,
+    /// Use the YMODEM protocol instead of XMODEM. YMODEM sends the file's size, so the last page
+    /// isn't padded with the sender's fill bytes.
+    pub ymodem: bool,
+}
+
+#[derive(Clone, Copy, Debug)]
+/// Reasons a transfer failed. The sender is notified of cancellations with CAN bytes.
+pub enum XmodemError {
+    /// The sender didn't start the transfer, or stopped responding.
+    Timeout,
+    /// The sender cancelled the transfer.
+    Cancelled,
+    /// Too many consecutive packets failed their CRC check, or were out of sequence.
+    TooManyErrors,
+    /// The transfer doesn't fit in `max_pages`.
+    TooLarge,
+    /// A YMODEM header block couldn't be parsed.
+    InvalidHeader,
+    /// Erasing or writing flash failed.
+    Flash(flash::Error),
+}
+
+/// Accumulates received data, and writes it to flash a page at a time.
+struct PageWriter<'a> {
+    flash: &'a mut Flash,
+    page: usize,
+    end_page: usize,
+    buf: [u8; PAGE_SIZE],
+    len: usize,
+}
+
+impl<'a> PageWriter<'a> {
+    fn push(&mut self, mut data: &[u8]) -> Result<(), XmodemError> {
+        while !data.is_empty() {
+            let n = (PAGE_SIZE - self.len).min(data.len());
+            self.buf[self.len..self.len + n].copy_from_slice(&data[..n]);
+            self.len += n;
+            data = &data[n..];
+
+            if self.len == PAGE_SIZE {
+                self.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the buffered data to the current page, padding it with erased (0xff) bytes.
+    fn flush(&mut self) -> Result<(), XmodemError> {
+        if self.len == 0 {
+            return Ok(());
+        }
+        if self.page >= self.end_page {
+            return Err(XmodemError::TooLarge);
+        }
+
+        for byte in &mut self.buf[self.len..] {
+            *byte = 0xff;
+        }
+
+        let mut words = [0; PAGE_SIZE / 8];
+        for (word, chunk) in words.iter_mut().zip(self.buf.chunks_exact(8)) {
+            *word = u64::from_le_bytes(chunk.try_into().unwrap());
+        }
+
+        self.flash
+            .erase_page(self.page)
+            .map_err(XmodemError::Flash)?;
+        self.flash
+            .write_page(self.page, &words)
+            .map_err(XmodemError::Flash)?;
+
+        self.page += 1;
+        self.len = 0;
+        Ok(())
+    }
+}
+
+/// A received packet, or control byte.
+enum Packet {
+    /// A data block, with its sequence number, and length. (128 or 1024)
+    Block(u8, usize),
+    Eot,
+    Cancel,
+    /// A packet that failed its checks, and should be NAKed.
+    Bad,
+}
+
+/// Receive a transfer, and write it to flash. Blocks until the transfer completes, fails, or
+/// the sender doesn't start one in about a minute. Returns the number of bytes received. For
+/// XMODEM, this includes the sender's padding of the last block.
+pub fn receive<R>(
+    usart: &mut Usart<R>,
+    crc: &mut Crc,
+    flash: &mut Flash,
+    cfg: &XmodemConfig,
+) -> Result<usize, XmodemError>
+where
+    R: Deref<Target = pac::usart1::RegisterBlock> + RccPeriph + BaudPeriph,
+{
+    // CRC-16/XMODEM: Polynomial 0x1021, initial value 0, no reflection, and no output XOR.
+    crc.set_config(
+        &Config::new()
+            .polynomial(Polynomial::bits16_unchecked(0x1021))
+            .initial_value(0),
+    );
+
+    let mut writer = PageWriter {
+        flash,
+        page: cfg.start_page,
+        end_page: cfg.start_page + cfg.max_pages,
+        buf: [0; PAGE_SIZE],
+        len: 0,
+    };
+
+    let mut block = [0; 1_024];
+
+    // With YMODEM, block 0 is a header containing the file name and size.
+    let mut file_size = None;
+    if cfg.ymodem {
+        let len = match start(usart, crc, &mut block)? {
+            Packet::Block(0, len) => len,
+            Packet::Block(..) => {
+                cancel(usart);
+                return Err(XmodemError::InvalidHeader);
+            }
+            _ => return Err(XmodemError::Cancelled),
+        };
+        file_size = Some(parse_ymodem_size(&block[..len])?);
+        usart.write(&[ACK]);
+    }
+
+    let mut expected: u8 = 1;
+    let mut received = 0;
+
+    // The first data block arrives in response to the 'C' that starts the transfer. Its
+    // sequence number is checked against `expected` below, as for the others.
+    let mut packet = start(usart, crc, &mut block)?;
+
+    let mut retries = 0;
+
+    loop {
+        match packet {
+            Packet::Block(seq, len) if seq == expected => {
+                let len = match file_size {
+                    Some(size) => len.min(size - received.min(size)),
+                    None => len,
+                };
+                if let Err(e) = writer.push(&block[..len]) {
+                    cancel(usart);
+                    return Err(e);
+                }
+                received += len;
+                expected = expected.wrapping_add(1);
+                retries = 0;
+                usart.write(&[ACK]);
+            }
+            // The sender didn't receive our ACK, and resent the previous block. Before the first
+            // data block, that's only possible for a YMODEM header.
+            Packet::Block(seq, _)
+                if seq == expected.wrapping_sub(1) && (received > 0 || cfg.ymodem) =>
+            {
+                usart.write(&[ACK])
+            }
+            Packet::Block(..) | Packet::Bad => {
+                retries += 1;
+                if retries > MAX_RETRIES {
+                    cancel(usart);
+                    return Err(XmodemError::TooManyErrors);
+                }
+                usart.write(&[NAK]);
+            }
+            Packet::Eot => break,
+            Packet::Cancel => return Err(XmodemError::Cancelled),
+        }
+
+        packet = read_packet(usart, crc, &mut block)?;
+    }
+
+    if cfg.ymodem {
+        // YMODEM senders expect the first EOT to be NAKed, and resend it.
+        usart.write(&[NAK]);
+        match read_packet(usart, crc, &mut block)? {
+            Packet::Eot => (),
+            _ => return Err(XmodemError::TooManyErrors),
+        }
+    }
+    usart.write(&[ACK]);
+
+    if cfg.ymodem {
+        // End the batch: The sender responds to 'C' with an empty header block.
+        usart.write(&[CRC_MODE]);
+        if let Packet::Block(0, _) = read_packet(usart, crc, &mut block)? {
+            usart.write(&[ACK]);
+        }
+    }
+
+    writer.flush()?;
+
+    Ok(received)
+}
+
+/// Request CRC mode, until the sender starts the transfer with a block, or ends it (EOT)
+/// instead, eg for an empty file. Returns that packet; the caller checks the block's sequence
+/// number.
+fn start<R>(
+    usart: &mut Usart<R>,
+    crc: &mut Crc,
+    block: &mut [u8; 1_024],
+) -> Result<Packet, XmodemError>
+where
+    R: Deref<Target = pac::usart1::RegisterBlock> + RccPeriph + BaudPeriph,
+{
+    for _ in 0..MAX_START_ATTEMPTS {
+        usart.write(&[CRC_MODE]);
+
+        match read_packet(usart, crc, block) {
+            Ok(p @ (Packet::Block(..) | Packet::Eot)) => return Ok(p),
+            Ok(Packet::Cancel) => return Err(XmodemError::Cancelled),
+            // Nothing, or noise, received; try again.
+            Ok(Packet::Bad) | Err(XmodemError::Timeout) => (),
+            Err(e) => return Err(e),
+        }
+    }
+
+    Err(XmodemError::Timeout)
+}
+
+/// Read a packet into `block`, and check its sequence number complement and CRC.
+fn read_packet<R>(
+    usart: &mut Usart<R>,
+    crc: &mut Crc,
+    block: &mut [u8; 1_024],
+) -> Result<Packet, XmodemError>
+where
+    R: Deref<Target = pac::usart1::RegisterBlock> + RccPeriph + BaudPeriph,
+{
+    let len = match read_byte(usart)? {
+        SOH => 128,
+        STX => 1_024,
+        EOT => return Ok(Packet::Eot),
+        CAN => return Ok(Packet::Cancel),
+        _ => {
+            purge(usart);
+            return Ok(Packet::Bad);
+        }
+    };
+
+    let seq = read_byte(usart)?;
+    let seq_complement = read_byte(usart)?;
+
+    for byte in block[..len].iter_mut() {
+        *byte = read_byte(usart)?;
+    }
+
+    let crc_received = u16::from_be_bytes([read_byte(usart)?, read_byte(usart)?]);
+
+    crc.update(&block[..len]);
+    let crc_calculated = crc.finish() as u16;
+
+    if seq != !seq_complement || crc_received != crc_calculated {
+        purge(usart);
+        return Ok(Packet::Bad);
+    }
+
+    Ok(Packet::Block(seq, len))
+}
+
+/// Read a byte, or time out.
+fn read_byte<R>(usart: &mut Usart<R>) -> Result<u8, XmodemError>
+where
+    R: Deref<Target = pac::usart1::RegisterBlock> + RccPeriph + BaudPeriph,
+{
+    for _ in 0..BYTE_TIMEOUT {
+        let isr = usart.regs.isr.read();
+
+        // Clear overrun, so reception continues; the packet fails its CRC check, and is resent.
+        if isr.ore().bit_is_set() {
+            usart.regs.icr.write(|w| w.orecf().set_bit());
+        }
+
+        if isr.rxne().bit_is_set() {
+            return Ok(usart.read_one());
+        }
+    }
+
+    Err(XmodemError::Timeout)
+}
+
+/// Discard bytes until the line is quiet, so we resynchronize with the sender after an error.
+fn purge<R>(usart: &mut Usart<R>)
+where
+    R: Deref<Target = pac::usart1::RegisterBlock> + RccPeriph + BaudPeriph,
+{
+    while read_byte(usart).is_ok() {}
+}
+
+/// Tell the sender we're cancelling the transfer.
+fn cancel<R>(usart: &mut Usart<R>)
+where
+    R: Deref<Target = pac::usart1::RegisterBlock> + RccPeriph + BaudPeriph,
+{
+    usart.write(&[CAN, CAN]);
+}
+
+/// Parse the file size from a YMODEM header block: The file name, a null byte, then the size
+/// in decimal, followed by a space or null.
+fn parse_ymodem_size(block: &[u8]) -> Result<usize, XmodemError> {
+    let name_end = block
+        .iter()
+        .position(|b| *b == 0)
+        .ok_or(XmodemError::InvalidHeader)?;
+
+    let mut size: usize = 0;
+    let mut digits = 0;
+
+    for byte in &block[name_end + 1..] {
+        match byte {
+            b'0'..=b'9' => {
+                size = size
+                    .checked_mul(10)
+                    .and_then(|s| s.checked_add((byte - b'0') as usize))
+                    .ok_or(XmodemError::InvalidHeader)?;
+                digits += 1;
+            }
+            _ => break,
+        }
+    }
+
+    if digits == 0 {
+        return Err(XmodemError::InvalidHeader);
+    }
+
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ymodem_size() {
+        assert_eq!(
+            parse_ymodem_size(b"firmware.bin\x0012345 14100000000 100644\x00").ok(),
+            Some(12_345)
+        );
+        assert_eq!(parse_ymodem_size(b"a\x000\x00").ok(), Some(0));
+    }
+
+    #[test]
+    fn ymodem_size_invalid() {
+        // No null after the file name.
+        assert!(matches!(
+            parse_ymodem_size(b"firmware.bin"),
+            Err(XmodemError::InvalidHeader)
+        ));
+        // No size.
+        assert!(matches!(
+            parse_ymodem_size(b"firmware.bin\x00 \x00"),
+            Err(XmodemError::InvalidHeader)
+        ));
+        // Overflows usize.
+        assert!(matches!(
+            parse_ymodem_size(b"a\x00999999999999999999999999999999\x00"),
+            Err(XmodemError::InvalidHeader)
+        ));
+    }
+}